@@ -0,0 +1,177 @@
+//! Content-addressed storage. File contents live once in `blobs`, keyed by their hex SHA-256,
+//! and `documents`, `document_versions` and `listing_photos` refer to them through their
+//! `hash` column. Triggers keep `blobs.ref_count` up to date and drop a blob once nothing
+//! refers to it any more.
+
+use crate::document::sha256_hex;
+use crate::DB_POOL;
+use serde::Serialize;
+use sqlx::{Row, SqliteConnection, SqlitePool};
+
+/// Tables whose rows reference a blob by hash
+const REFERENCING_TABLES: [&str; 3] = ["documents", "document_versions", "listing_photos"];
+
+/// Tables older versions of the app stored contents in inline, in a `data` column
+const INLINE_DATA_TABLES: [&str; 2] = ["documents", "document_versions"];
+
+#[derive(Serialize)]
+pub struct BlobStats {
+  blob_count: i64,
+  /// Bytes actually stored
+  stored_bytes: i64,
+  /// Bytes that would be stored without deduplication
+  referenced_bytes: i64,
+}
+
+#[derive(Serialize)]
+pub struct GarbageCollection {
+  deleted: u64,
+  bytes_freed: i64,
+}
+
+/// How much space deduplication saves
+#[tauri::command]
+pub async fn get_blob_stats() -> Result<BlobStats, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let row = sqlx::query(
+    r#"
+    SELECT COUNT(*) AS blob_count,
+      COALESCE(SUM(size), 0) AS stored_bytes,
+      COALESCE(SUM(size * ref_count), 0) AS referenced_bytes
+    FROM blobs
+    "#,
+  )
+  .fetch_one(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch blob stats: {}", e))?;
+
+  Ok(BlobStats {
+    blob_count: row.try_get("blob_count").unwrap_or_default(),
+    stored_bytes: row.try_get("stored_bytes").unwrap_or_default(),
+    referenced_bytes: row.try_get("referenced_bytes").unwrap_or_default(),
+  })
+}
+
+/// Recount blob references from scratch and delete blobs nothing refers to. With `vacuum`
+/// the database file is compacted afterwards so the freed space is returned to the disk.
+#[tauri::command]
+pub async fn collect_garbage_blobs(vacuum: bool) -> Result<GarbageCollection, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let collected = recount_references(pool)
+    .await
+    .map_err(|e| format!("Failed to collect unused blobs: {}", e))?;
+
+  if vacuum {
+    sqlx::query("VACUUM")
+      .execute(pool)
+      .await
+      .map_err(|e| format!("Failed to compact database: {}", e))?;
+  }
+
+  Ok(collected)
+}
+
+/// Store `data` under its hash unless an identical blob already exists. Returns the hash.
+/// The blob starts without references, so insert the row referring to it in the same
+/// transaction.
+pub(crate) async fn store_blob(conn: &mut SqliteConnection, data: &[u8]) -> Result<String, String> {
+  let hash = sha256_hex(data);
+
+  sqlx::query("INSERT INTO blobs (hash, data, size) VALUES (?, ?, ?) ON CONFLICT(hash) DO NOTHING")
+    .bind(&hash)
+    .bind(data)
+    .bind(data.len() as i64)
+    .execute(conn)
+    .await
+    .map_err(|e| format!("Failed to store blob: {}", e))?;
+
+  Ok(hash)
+}
+
+/// Move contents stored inline by older versions of the app into `blobs`
+pub async fn migrate_inline_data(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+  let mut migrated = false;
+
+  for table in INLINE_DATA_TABLES {
+    let ids: Vec<i64> = sqlx::query_scalar(&format!(
+      "SELECT id FROM {} WHERE data IS NOT NULL AND length(data) > 0",
+      table
+    ))
+    .fetch_all(pool)
+    .await?;
+
+    // One row at a time, so large vaults are not loaded into memory at once
+    for id in ids {
+      let data: Vec<u8> = sqlx::query_scalar(&format!("SELECT data FROM {} WHERE id = ?", table))
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+
+      let mut tx = pool.begin().await?;
+      let hash = store_blob(&mut *tx, &data)
+        .await
+        .map_err(sqlx::Error::Protocol)?;
+      sqlx::query(&format!(
+        "UPDATE {} SET hash = ?, data = NULL WHERE id = ?",
+        table
+      ))
+      .bind(&hash)
+      .bind(id)
+      .execute(&mut *tx)
+      .await?;
+      tx.commit().await?;
+
+      migrated = true;
+    }
+  }
+
+  // Rows that already had a hash did not go through the reference triggers
+  if migrated {
+    recount_references(pool).await?;
+    println!("Moved inline file contents to content-addressed storage");
+  }
+
+  Ok(())
+}
+
+async fn recount_references(pool: &SqlitePool) -> Result<GarbageCollection, sqlx::Error> {
+  let mut tx = pool.begin().await?;
+
+  let references: Vec<String> = REFERENCING_TABLES
+    .iter()
+    .map(|table| {
+      format!(
+        "(SELECT COUNT(*) FROM {} t WHERE t.hash = blobs.hash)",
+        table
+      )
+    })
+    .collect();
+  let references = references.join(" + ");
+
+  let row = sqlx::query(&format!(
+    "SELECT COUNT(*) AS deleted, COALESCE(SUM(size), 0) AS bytes_freed FROM blobs WHERE {} = 0",
+    references
+  ))
+  .fetch_one(&mut *tx)
+  .await?;
+  let deleted: i64 = row.try_get("deleted")?;
+  let bytes_freed: i64 = row.try_get("bytes_freed")?;
+
+  sqlx::query(&format!("DELETE FROM blobs WHERE {} = 0", references))
+    .execute(&mut *tx)
+    .await?;
+  sqlx::query(&format!("UPDATE blobs SET ref_count = {}", references))
+    .execute(&mut *tx)
+    .await?;
+
+  tx.commit().await?;
+
+  Ok(GarbageCollection {
+    deleted: deleted as u64,
+    bytes_freed,
+  })
+}
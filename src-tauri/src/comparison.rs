@@ -0,0 +1,530 @@
+use crate::costs::{load_listing_costs, summarize_costs, ListingCostSummary};
+use crate::listings::fetch_listings_by_ids;
+use crate::Listing;
+use crate::DB_POOL;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeSet;
+
+/// Which direction counts as "better" when ranking a numeric field
+#[derive(Clone, Copy)]
+enum Better {
+  Lower,
+  Higher,
+}
+
+#[derive(Serialize)]
+pub struct ComparedListing {
+  id: i64,
+  address: String,
+}
+
+#[derive(Serialize)]
+pub struct ComparisonCell {
+  listing_id: i64,
+  value: serde_json::Value,
+  display: String,
+  best: bool,
+  worst: bool,
+}
+
+#[derive(Serialize)]
+pub struct ComparisonRow {
+  field: String,
+  label: String,
+  cells: Vec<ComparisonCell>,
+  all_equal: bool,
+}
+
+#[derive(Serialize)]
+pub struct ListingItems {
+  listing_id: i64,
+  items: Vec<String>,
+}
+
+/// Amenities/utilities across the compared listings expressed as set operations
+#[derive(Serialize)]
+pub struct SetComparison {
+  /// Present in every listing (intersection)
+  common: Vec<String>,
+  /// Present in at least one listing (union)
+  all: Vec<String>,
+  /// Items only this listing has
+  unique: Vec<ListingItems>,
+  /// Items other listings have but this one lacks
+  missing: Vec<ListingItems>,
+}
+
+#[derive(Serialize)]
+pub struct Affordability {
+  listing_id: i64,
+  monthly_total: f64,
+  monthly_income: Option<f64>,
+  rent_to_income_ratio: Option<f64>,
+  meets_minimum_income: Option<bool>,
+  meets_three_times_rent: Option<bool>,
+  affordable: Option<bool>,
+}
+
+#[derive(Serialize)]
+pub struct ListingComparison {
+  listings: Vec<ComparedListing>,
+  rows: Vec<ComparisonRow>,
+  amenities: SetComparison,
+  utilities: SetComparison,
+  affordability: Vec<Affordability>,
+}
+
+/// Compare listings side by side, field by field
+#[tauri::command]
+pub async fn compare_listings(ids: Vec<i64>) -> Result<ListingComparison, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  if ids.len() < 2 {
+    return Err("Select at least two listings to compare".to_string());
+  }
+
+  let listings = fetch_listings_by_ids(pool, &ids).await?;
+  let mut costs = Vec::with_capacity(listings.len());
+  for listing in &listings {
+    let model = load_listing_costs(pool, listing.id.unwrap_or_default()).await?;
+    costs.push(summarize_costs(listing, model.as_ref()));
+  }
+
+  let monthly_income: Option<i64> =
+    sqlx::query_scalar("SELECT monthly_income FROM profile WHERE id = 1")
+      .fetch_optional(pool)
+      .await
+      .map_err(|e| format!("Failed to fetch monthly income: {}", e))?
+      .flatten();
+
+  Ok(build_comparison(
+    &listings,
+    &costs,
+    monthly_income
+      .filter(|income| *income > 0)
+      .map(|i| i as f64),
+  ))
+}
+
+/// Render the comparison of the given listings as a PDF
+#[tauri::command]
+pub async fn export_listing_comparison_pdf(ids: Vec<i64>) -> Result<Vec<u8>, String> {
+  use crate::helpers::pdf_docs::{table_to_pdf, CellHighlight, PdfTableRow};
+
+  let comparison = compare_listings(ids).await?;
+
+  let columns: Vec<String> = comparison
+    .listings
+    .iter()
+    .map(|l| l.address.clone())
+    .collect();
+
+  let mut rows: Vec<PdfTableRow> = comparison
+    .rows
+    .iter()
+    .map(|row| PdfTableRow {
+      label: row.label.clone(),
+      cells: row
+        .cells
+        .iter()
+        .map(|cell| {
+          let highlight = if cell.best {
+            CellHighlight::Best
+          } else if cell.worst {
+            CellHighlight::Worst
+          } else {
+            CellHighlight::None
+          };
+          (cell.display.clone(), highlight)
+        })
+        .collect(),
+    })
+    .collect();
+
+  rows.push(PdfTableRow {
+    label: "Rent / income".to_string(),
+    cells: comparison
+      .affordability
+      .iter()
+      .map(|a| {
+        let text = match a.rent_to_income_ratio {
+          Some(ratio) => format!("{:.0}%", ratio * 100.0),
+          None => "-".to_string(),
+        };
+        let highlight = match a.affordable {
+          Some(true) => CellHighlight::Best,
+          Some(false) => CellHighlight::Worst,
+          None => CellHighlight::None,
+        };
+        (text, highlight)
+      })
+      .collect(),
+  });
+
+  for (label, set) in [
+    ("Amenities only here", &comparison.amenities),
+    ("Utilities only here", &comparison.utilities),
+  ] {
+    rows.push(PdfTableRow {
+      label: label.to_string(),
+      cells: set
+        .unique
+        .iter()
+        .map(|u| (u.items.join(", "), CellHighlight::None))
+        .collect(),
+    });
+  }
+
+  table_to_pdf("Listing Comparison", &columns, &rows)
+    .map_err(|e| format!("Failed to render comparison PDF: {}", e))
+}
+
+fn build_comparison(
+  listings: &[Listing],
+  costs: &[ListingCostSummary],
+  monthly_income: Option<f64>,
+) -> ListingComparison {
+  let cost_of = |listing: &Listing| costs.iter().find(|c| Some(c.listing_id) == listing.id);
+
+  let mut rows = vec![
+    numeric_row(listings, "price_rent", "Rent", Better::Lower, |l| {
+      Some(l.price_rent)
+    }),
+    numeric_row(
+      listings,
+      "monthly_total",
+      "Monthly total",
+      Better::Lower,
+      |l| cost_of(l).map(|c| c.effective_monthly),
+    ),
+    numeric_row(
+      listings,
+      "move_in_total",
+      "Move-in cash",
+      Better::Lower,
+      |l| cost_of(l).map(|c| c.move_in_total),
+    ),
+    numeric_row(
+      listings,
+      "first_year_total",
+      "First-year total",
+      Better::Lower,
+      |l| cost_of(l).map(|c| c.first_year_total),
+    ),
+    numeric_row(
+      listings,
+      "upfront_fees",
+      "Upfront fees",
+      Better::Lower,
+      |l| l.upfront_fees,
+    ),
+    numeric_row(listings, "bedrooms", "Bedrooms", Better::Higher, |l| {
+      l.bedrooms.map(f64::from)
+    }),
+    numeric_row(listings, "bathrooms", "Bathrooms", Better::Higher, |l| {
+      l.bathrooms
+    }),
+    numeric_row(
+      listings,
+      "square_footage",
+      "Square footage",
+      Better::Higher,
+      |l| l.square_footage.map(f64::from),
+    ),
+    numeric_row(
+      listings,
+      "price_per_sqft",
+      "Rent per sq ft",
+      Better::Lower,
+      |l| match l.square_footage {
+        Some(sqft) if sqft > 0 => Some(l.price_rent / sqft as f64),
+        _ => None,
+      },
+    ),
+    numeric_row(
+      listings,
+      "credit_score_min",
+      "Minimum credit score",
+      Better::Lower,
+      |l| l.credit_score_min.map(f64::from),
+    ),
+    numeric_row(
+      listings,
+      "minimum_income",
+      "Minimum income",
+      Better::Lower,
+      |l| l.minimum_income,
+    ),
+    numeric_row(
+      listings,
+      "amenities_count",
+      "Amenities",
+      Better::Higher,
+      |l| Some(parse_list(&l.amenities).len() as f64),
+    ),
+    numeric_row(
+      listings,
+      "utilities_count",
+      "Utilities included",
+      Better::Higher,
+      |l| Some(parse_list(&l.utilities).len() as f64),
+    ),
+  ];
+
+  rows.push(text_row(listings, "housing_type", "Housing type", |l| {
+    l.housing_type.clone()
+  }));
+  rows.push(text_row(listings, "lease_type", "Lease type", |l| {
+    l.lease_type.clone()
+  }));
+  rows.push(text_row(listings, "furnishing", "Furnishing", |l| {
+    l.furnishing.clone()
+  }));
+  rows.push(text_row(listings, "pet_policy", "Pet policy", |l| {
+    l.pet_policy.clone()
+  }));
+  rows.push(text_row(
+    listings,
+    "references_required",
+    "References required",
+    |l| {
+      l.references_required
+        .map(|r| if r { "Yes" } else { "No" }.to_string())
+    },
+  ));
+
+  let affordability = listings
+    .iter()
+    .map(|listing| {
+      let monthly_total = cost_of(listing).map_or(listing.price_rent, |c| c.effective_monthly);
+      affordability(listing, monthly_total, monthly_income)
+    })
+    .collect();
+
+  ListingComparison {
+    listings: listings
+      .iter()
+      .map(|l| ComparedListing {
+        id: l.id.unwrap_or_default(),
+        address: l.address.clone(),
+      })
+      .collect(),
+    rows,
+    amenities: set_comparison(listings, |l| parse_list(&l.amenities)),
+    utilities: set_comparison(listings, |l| parse_list(&l.utilities)),
+    affordability,
+  }
+}
+
+fn affordability(listing: &Listing, monthly_total: f64, income: Option<f64>) -> Affordability {
+  let meets_minimum_income = income.map(|income| {
+    listing
+      .minimum_income
+      .map_or(true, |minimum| income >= minimum)
+  });
+  // Most landlords ask for a gross monthly income of at least 3x the rent
+  let meets_three_times_rent = income.map(|income| income >= listing.price_rent * 3.0);
+
+  Affordability {
+    listing_id: listing.id.unwrap_or_default(),
+    monthly_total,
+    monthly_income: income,
+    // Headline rent, as landlords' income rules use it; fees and utilities are in monthly_total
+    rent_to_income_ratio: income.map(|income| listing.price_rent / income),
+    meets_minimum_income,
+    meets_three_times_rent,
+    affordable: meets_minimum_income
+      .zip(meets_three_times_rent)
+      .map(|(a, b)| a && b),
+  }
+}
+
+fn numeric_row(
+  listings: &[Listing],
+  field: &str,
+  label: &str,
+  better: Better,
+  value: impl Fn(&Listing) -> Option<f64>,
+) -> ComparisonRow {
+  let values: Vec<Option<f64>> = listings.iter().map(&value).collect();
+  let present: Vec<f64> = values.iter().flatten().copied().collect();
+
+  let min = present.iter().copied().fold(f64::INFINITY, f64::min);
+  let max = present.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+  // Only flag best/worst when at least two listings differ on this field
+  let rank = present.len() >= 2 && (max - min).abs() > f64::EPSILON;
+  let (best, worst) = match better {
+    Better::Lower => (min, max),
+    Better::Higher => (max, min),
+  };
+
+  let cells = listings
+    .iter()
+    .zip(&values)
+    .map(|(listing, v)| ComparisonCell {
+      listing_id: listing.id.unwrap_or_default(),
+      value: v.map_or(serde_json::Value::Null, |v| json!(v)),
+      display: v.map_or_else(|| "-".to_string(), format_number),
+      best: rank && v.is_some_and(|v| v == best),
+      worst: rank && v.is_some_and(|v| v == worst),
+    })
+    .collect();
+
+  ComparisonRow {
+    field: field.to_string(),
+    label: label.to_string(),
+    cells,
+    all_equal: !rank && present.len() == values.len(),
+  }
+}
+
+fn text_row(
+  listings: &[Listing],
+  field: &str,
+  label: &str,
+  value: impl Fn(&Listing) -> Option<String>,
+) -> ComparisonRow {
+  let values: Vec<Option<String>> = listings
+    .iter()
+    .map(|l| value(l).filter(|v| !v.trim().is_empty()))
+    .collect();
+  let all_equal = values.windows(2).all(|w| w[0] == w[1]);
+
+  let cells = listings
+    .iter()
+    .zip(values)
+    .map(|(listing, v)| ComparisonCell {
+      listing_id: listing.id.unwrap_or_default(),
+      display: v.clone().unwrap_or_else(|| "-".to_string()),
+      value: v.map_or(serde_json::Value::Null, serde_json::Value::String),
+      best: false,
+      worst: false,
+    })
+    .collect();
+
+  ComparisonRow {
+    field: field.to_string(),
+    label: label.to_string(),
+    cells,
+    all_equal,
+  }
+}
+
+fn set_comparison(listings: &[Listing], items: impl Fn(&Listing) -> Vec<String>) -> SetComparison {
+  let sets: Vec<BTreeSet<String>> = listings
+    .iter()
+    .map(|l| items(l).into_iter().collect())
+    .collect();
+
+  let all: BTreeSet<String> = sets.iter().flatten().cloned().collect();
+  let common: BTreeSet<String> = all
+    .iter()
+    .filter(|item| sets.iter().all(|s| s.contains(*item)))
+    .cloned()
+    .collect();
+
+  let mut unique = Vec::new();
+  let mut missing = Vec::new();
+  for (i, (listing, set)) in listings.iter().zip(&sets).enumerate() {
+    let others: BTreeSet<&String> = sets
+      .iter()
+      .enumerate()
+      .filter(|(j, _)| *j != i)
+      .flat_map(|(_, s)| s.iter())
+      .collect();
+
+    unique.push(ListingItems {
+      listing_id: listing.id.unwrap_or_default(),
+      items: set
+        .iter()
+        .filter(|item| !others.contains(item))
+        .cloned()
+        .collect(),
+    });
+    missing.push(ListingItems {
+      listing_id: listing.id.unwrap_or_default(),
+      items: all.difference(set).cloned().collect(),
+    });
+  }
+
+  SetComparison {
+    common: common.into_iter().collect(),
+    all: all.into_iter().collect(),
+    unique,
+    missing,
+  }
+}
+
+/// Parse a JSON array column such as `amenities`, falling back to a comma separated list
+pub(crate) fn parse_list(raw: &Option<String>) -> Vec<String> {
+  let Some(raw) = raw.as_deref().map(str::trim).filter(|r| !r.is_empty()) else {
+    return Vec::new();
+  };
+
+  let items = serde_json::from_str::<Vec<String>>(raw)
+    .unwrap_or_else(|_| raw.split(',').map(str::to_string).collect());
+
+  items
+    .into_iter()
+    .map(|item| item.trim().to_string())
+    .filter(|item| !item.is_empty())
+    .collect()
+}
+
+fn format_number(value: f64) -> String {
+  if value.fract() == 0.0 {
+    format!("{:.0}", value)
+  } else {
+    format!("{:.2}", value)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn listing(price_rent: f64, minimum_income: Option<f64>) -> Listing {
+    Listing {
+      id: Some(1),
+      price_rent,
+      minimum_income,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn rent_to_income_uses_headline_rent() {
+    let result = affordability(&listing(1500.0, None), 1850.0, Some(6000.0));
+    assert_eq!(result.rent_to_income_ratio, Some(0.25));
+    assert_eq!(result.monthly_total, 1850.0);
+  }
+
+  #[test]
+  fn three_times_rent_rule_uses_headline_rent() {
+    // 3x the rent is met even though 3x the monthly total is not
+    let result = affordability(&listing(2000.0, None), 2300.0, Some(6000.0));
+    assert_eq!(result.meets_three_times_rent, Some(true));
+    assert_eq!(result.affordable, Some(true));
+
+    let result = affordability(&listing(2001.0, None), 2001.0, Some(6000.0));
+    assert_eq!(result.meets_three_times_rent, Some(false));
+    assert_eq!(result.affordable, Some(false));
+  }
+
+  #[test]
+  fn minimum_income_is_checked() {
+    let result = affordability(&listing(1000.0, Some(7000.0)), 1000.0, Some(6000.0));
+    assert_eq!(result.meets_minimum_income, Some(false));
+    assert_eq!(result.meets_three_times_rent, Some(true));
+    assert_eq!(result.affordable, Some(false));
+  }
+
+  #[test]
+  fn unknown_income_leaves_affordability_open() {
+    let result = affordability(&listing(1000.0, Some(3000.0)), 1000.0, None);
+    assert_eq!(result.rent_to_income_ratio, None);
+    assert_eq!(result.affordable, None);
+  }
+}
@@ -0,0 +1,363 @@
+use crate::listings::fetch_listings_by_ids;
+use crate::Listing;
+use crate::ListingCosts;
+use crate::DB_POOL;
+use serde::Serialize;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+pub struct CostLineItem {
+  label: String,
+  amount: f64,
+  /// "move_in", "monthly" or "concession"
+  kind: String,
+  refundable: bool,
+  /// Set on rent paid in advance at move-in
+  #[serde(skip)]
+  prepaid_month: Option<PrepaidMonth>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PrepaidMonth {
+  First,
+  Last,
+}
+
+#[derive(Serialize)]
+pub struct ListingCostSummary {
+  pub listing_id: i64,
+  pub rent: f64,
+  /// Rent plus every recurring monthly charge, before concessions
+  pub monthly_recurring: f64,
+  /// Concessions spread evenly over the lease
+  pub concession_monthly_credit: f64,
+  pub effective_monthly: f64,
+  pub move_in_total: f64,
+  /// Part of the move-in cash that is refundable (deposits)
+  pub refundable_deposits: f64,
+  /// Everything paid over the first twelve months, or over the whole lease if it is shorter
+  pub first_year_total: f64,
+  pub lease_length_months: i32,
+  pub has_cost_model: bool,
+  line_items: Vec<CostLineItem>,
+}
+
+/// Get the cost model of a listing, if one has been entered
+#[tauri::command]
+pub async fn get_listing_costs(listing_id: i64) -> Result<Option<ListingCosts>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  load_listing_costs(pool, listing_id).await
+}
+
+/// Create or replace the cost model of a listing
+#[tauri::command]
+pub async fn set_listing_costs(costs: ListingCosts) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  if let Some(raw) = costs.utility_costs.as_deref() {
+    serde_json::from_str::<BTreeMap<String, f64>>(raw).map_err(|e| {
+      format!(
+        "Invalid utility costs, expected {{\"name\": amount}}: {}",
+        e
+      )
+    })?;
+  }
+  if costs.lease_length_months.is_some_and(|months| months <= 0) {
+    return Err("Lease length must be at least one month".to_string());
+  }
+
+  sqlx::query(
+    r#"
+    INSERT INTO listing_costs (
+      listing_id, security_deposit, first_month_due, last_month_due, application_fee,
+      broker_fee, pet_deposit, pet_rent, parking_monthly, utility_costs,
+      lease_length_months, months_free
+    ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+    ON CONFLICT(listing_id) DO UPDATE SET
+      security_deposit = excluded.security_deposit,
+      first_month_due = excluded.first_month_due,
+      last_month_due = excluded.last_month_due,
+      application_fee = excluded.application_fee,
+      broker_fee = excluded.broker_fee,
+      pet_deposit = excluded.pet_deposit,
+      pet_rent = excluded.pet_rent,
+      parking_monthly = excluded.parking_monthly,
+      utility_costs = excluded.utility_costs,
+      lease_length_months = excluded.lease_length_months,
+      months_free = excluded.months_free
+    "#,
+  )
+  .bind(costs.listing_id)
+  .bind(costs.security_deposit)
+  .bind(costs.first_month_due)
+  .bind(costs.last_month_due)
+  .bind(costs.application_fee)
+  .bind(costs.broker_fee)
+  .bind(costs.pet_deposit)
+  .bind(costs.pet_rent)
+  .bind(costs.parking_monthly)
+  .bind(&costs.utility_costs)
+  .bind(costs.lease_length_months)
+  .bind(costs.months_free)
+  .execute(pool)
+  .await
+  .map_err(|e| format!("Failed to save listing costs: {}", e))?;
+
+  Ok(())
+}
+
+/// Compute move-in cash, effective monthly cost and first-year total for a listing
+#[tauri::command]
+pub async fn compute_listing_costs(listing_id: i64) -> Result<ListingCostSummary, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let listing = fetch_listings_by_ids(pool, &[listing_id])
+    .await?
+    .pop()
+    .ok_or_else(|| format!("No listing found with id {}", listing_id))?;
+  let costs = load_listing_costs(pool, listing_id).await?;
+
+  Ok(summarize_costs(&listing, costs.as_ref()))
+}
+
+pub(crate) async fn load_listing_costs(
+  pool: &SqlitePool,
+  listing_id: i64,
+) -> Result<Option<ListingCosts>, String> {
+  let row = sqlx::query("SELECT * FROM listing_costs WHERE listing_id = ?")
+    .bind(listing_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch listing costs: {}", e))?;
+
+  Ok(row.as_ref().map(listing_costs_from_row))
+}
+
+fn listing_costs_from_row(row: &SqliteRow) -> ListingCosts {
+  ListingCosts {
+    listing_id: row.try_get("listing_id").unwrap_or_default(),
+    security_deposit: row.try_get("security_deposit").ok(),
+    first_month_due: row.try_get("first_month_due").ok(),
+    last_month_due: row.try_get("last_month_due").ok(),
+    application_fee: row.try_get("application_fee").ok(),
+    broker_fee: row.try_get("broker_fee").ok(),
+    pet_deposit: row.try_get("pet_deposit").ok(),
+    pet_rent: row.try_get("pet_rent").ok(),
+    parking_monthly: row.try_get("parking_monthly").ok(),
+    utility_costs: row.try_get("utility_costs").ok(),
+    lease_length_months: row.try_get("lease_length_months").ok(),
+    months_free: row.try_get("months_free").ok(),
+    updated_at: row.try_get("updated_at").ok(),
+  }
+}
+
+/// Build the cost breakdown of a listing. Without a cost model only the rent and the
+/// listing's `upfront_fees` are known, on a 12-month lease with the first month due up front.
+/// `upfront_fees` is a lump sum for the fees the cost model itemizes, so it only counts when
+/// no fees are itemized.
+pub(crate) fn summarize_costs(
+  listing: &Listing,
+  costs: Option<&ListingCosts>,
+) -> ListingCostSummary {
+  let default_costs = ListingCosts::default();
+  let c = costs.unwrap_or(&default_costs);
+  let rent = listing.price_rent;
+  let lease_length_months = c.lease_length_months.filter(|m| *m > 0).unwrap_or(12);
+
+  let prepaid = |label: &str, month: PrepaidMonth| CostLineItem {
+    label: label.to_string(),
+    amount: rent,
+    kind: "move_in".to_string(),
+    refundable: false,
+    prepaid_month: Some(month),
+  };
+  let mut line_items = Vec::new();
+  if rent != 0.0 {
+    if c.first_month_due.unwrap_or(true) {
+      line_items.push(prepaid("First month's rent", PrepaidMonth::First));
+    }
+    if c.last_month_due.unwrap_or(false) {
+      line_items.push(prepaid("Last month's rent", PrepaidMonth::Last));
+    }
+  }
+
+  let mut add = |label: &str, amount: Option<f64>, kind: &str, refundable: bool| {
+    if let Some(amount) = amount.filter(|a| *a != 0.0) {
+      line_items.push(CostLineItem {
+        label: label.to_string(),
+        amount,
+        kind: kind.to_string(),
+        refundable,
+        prepaid_month: None,
+      });
+    }
+  };
+
+  add("Security deposit", c.security_deposit, "move_in", true);
+  add("Pet deposit", c.pet_deposit, "move_in", true);
+  add("Application fee", c.application_fee, "move_in", false);
+  add("Broker fee", c.broker_fee, "move_in", false);
+  let has_itemized_fees = [c.application_fee, c.broker_fee]
+    .into_iter()
+    .any(|fee| fee.is_some_and(|fee| fee != 0.0));
+  if !has_itemized_fees {
+    add("Other upfront fees", listing.upfront_fees, "move_in", false);
+  }
+
+  add("Rent", Some(rent), "monthly", false);
+  add("Pet rent", c.pet_rent, "monthly", false);
+  add("Parking", c.parking_monthly, "monthly", false);
+  let utility_costs: BTreeMap<String, f64> = c
+    .utility_costs
+    .as_deref()
+    .and_then(|raw| serde_json::from_str(raw).ok())
+    .unwrap_or_default();
+  for (utility, amount) in &utility_costs {
+    add(utility, Some(*amount), "monthly", false);
+  }
+
+  let concession_total = c.months_free.unwrap_or(0.0) * rent;
+  add(
+    "Rent concession",
+    Some(-concession_total),
+    "concession",
+    false,
+  );
+
+  let sum = |kind: &str, refundable_only: bool| -> f64 {
+    line_items
+      .iter()
+      .filter(|item| item.kind == kind && (!refundable_only || item.refundable))
+      .map(|item| item.amount)
+      .sum()
+  };
+
+  let move_in_total = sum("move_in", false);
+  let refundable_deposits = sum("move_in", true);
+  let monthly_recurring = sum("monthly", false);
+  let concession_monthly_credit = concession_total / lease_length_months as f64;
+  let effective_monthly = monthly_recurring - concession_monthly_credit;
+
+  // Prepaid rent for a month counted below is only added once. The last month of a lease longer
+  // than a year falls outside those months, so paying it up front adds to the first year.
+  let prepaid_rent = line_items
+    .iter()
+    .filter(|item| match item.prepaid_month {
+      Some(PrepaidMonth::First) => true,
+      Some(PrepaidMonth::Last) => lease_length_months <= 12,
+      None => false,
+    })
+    .map(|item| item.amount)
+    .sum::<f64>();
+  let months_in_first_year = lease_length_months.min(12) as f64;
+  let first_year_total = (move_in_total - prepaid_rent)
+    + (monthly_recurring - concession_monthly_credit) * months_in_first_year;
+
+  ListingCostSummary {
+    listing_id: listing.id.unwrap_or_default(),
+    rent,
+    monthly_recurring,
+    concession_monthly_credit,
+    effective_monthly,
+    move_in_total,
+    refundable_deposits,
+    first_year_total,
+    lease_length_months,
+    has_cost_model: costs.is_some(),
+    line_items,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn listing(price_rent: f64, upfront_fees: Option<f64>) -> Listing {
+    Listing {
+      id: Some(1),
+      price_rent,
+      upfront_fees,
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn without_a_cost_model_assumes_a_year_with_the_first_month_up_front() {
+    let summary = summarize_costs(&listing(1000.0, Some(250.0)), None);
+    assert_eq!(summary.move_in_total, 1250.0);
+    assert_eq!(summary.effective_monthly, 1000.0);
+    assert_eq!(summary.first_year_total, 12_250.0);
+    assert!(!summary.has_cost_model);
+  }
+
+  #[test]
+  fn short_leases_count_only_their_own_months() {
+    let costs = ListingCosts {
+      lease_length_months: Some(6),
+      months_free: Some(1.0),
+      security_deposit: Some(1000.0),
+      ..Default::default()
+    };
+    let summary = summarize_costs(&listing(1200.0, None), Some(&costs));
+    assert_eq!(summary.concession_monthly_credit, 200.0);
+    assert_eq!(summary.effective_monthly, 1000.0);
+    // Deposit, then six months of rent less the free month
+    assert_eq!(summary.first_year_total, 1000.0 + 6.0 * 1200.0 - 1200.0);
+  }
+
+  #[test]
+  fn long_leases_count_twelve_months() {
+    let costs = ListingCosts {
+      lease_length_months: Some(24),
+      months_free: Some(2.0),
+      pet_rent: Some(50.0),
+      ..Default::default()
+    };
+    let summary = summarize_costs(&listing(1200.0, None), Some(&costs));
+    assert_eq!(summary.monthly_recurring, 1250.0);
+    assert_eq!(summary.concession_monthly_credit, 100.0);
+    assert_eq!(summary.first_year_total, 12.0 * 1150.0);
+  }
+
+  #[test]
+  fn prepaid_last_month_counts_once_within_the_first_year() {
+    let mut costs = ListingCosts {
+      lease_length_months: Some(12),
+      last_month_due: Some(true),
+      ..Default::default()
+    };
+    let summary = summarize_costs(&listing(1000.0, None), Some(&costs));
+    assert_eq!(summary.move_in_total, 2000.0);
+    assert_eq!(summary.first_year_total, 12_000.0);
+
+    // The 13th month is paid at move-in on top of the first twelve
+    costs.lease_length_months = Some(13);
+    let summary = summarize_costs(&listing(1000.0, None), Some(&costs));
+    assert_eq!(summary.move_in_total, 2000.0);
+    assert_eq!(summary.first_year_total, 13_000.0);
+  }
+
+  #[test]
+  fn upfront_fees_only_count_without_itemized_fees() {
+    let itemized = ListingCosts {
+      application_fee: Some(50.0),
+      broker_fee: Some(1200.0),
+      ..Default::default()
+    };
+    let summary = summarize_costs(&listing(1200.0, Some(1250.0)), Some(&itemized));
+    assert_eq!(summary.move_in_total, 1200.0 + 50.0 + 1200.0);
+
+    let deposits_only = ListingCosts {
+      security_deposit: Some(1200.0),
+      ..Default::default()
+    };
+    let summary = summarize_costs(&listing(1200.0, Some(100.0)), Some(&deposits_only));
+    assert_eq!(summary.move_in_total, 1200.0 + 1200.0 + 100.0);
+  }
+}
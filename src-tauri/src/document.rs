@@ -1,8 +1,23 @@
+use crate::blobs::store_blob;
+use crate::document_text::index_document_text;
+use crate::document_validity::validate_document_dates;
+use crate::form_templates::apply_saved_template;
+use crate::helpers::images::sanitize_image;
+use crate::helpers::mime::{self, canonical_mime, claim_matches, sniff_mime};
+use crate::helpers::pdf_docs::{
+  PacketContent, PacketDocument, PdfBuildMonitor, PdfBuildStage, PdfRenderOptions,
+};
+use crate::packet::PacketStampOptions;
+use crate::pdf_jobs::PdfJob;
 use crate::Document;
+use crate::DocumentMeta;
 use crate::DB_POOL;
 use serde::{Deserialize, Serialize};
-use sqlx::Row;
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
 use std::fs;
+use std::sync::Arc;
+use tauri::ipc::Response;
 
 #[derive(Serialize, Deserialize)]
 pub struct FileBlob {
@@ -10,54 +25,337 @@ pub struct FileBlob {
   mime_type: String,
 }
 
+/// Why a document was rejected. Serialized for the frontend as
+/// `{ kind, message, claimed, detected, document_id }`.
+#[derive(Debug)]
+pub enum DocumentError {
+  Database(String),
+  EmptyFile,
+  /// Native executables and scripts are never stored
+  Executable,
+  /// The content could not be identified as any supported type
+  Unrecognized {
+    claimed: Option<String>,
+  },
+  /// The content was identified but is not an accepted document type
+  DisallowedType {
+    detected: String,
+  },
+  /// The content does not match the type it was labeled with
+  MimeMismatch {
+    claimed: String,
+    detected: String,
+  },
+  /// An identical file is already stored as another document
+  Duplicate {
+    document_id: i64,
+    name: String,
+  },
+}
+
+impl std::fmt::Display for DocumentError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      DocumentError::Database(e) => write!(f, "{}", e),
+      DocumentError::EmptyFile => write!(f, "The file is empty"),
+      DocumentError::Executable => write!(f, "Executable files cannot be stored as documents"),
+      DocumentError::Unrecognized { .. } => write!(f, "The file type could not be recognized"),
+      DocumentError::DisallowedType { detected } => {
+        write!(f, "Files of type {} are not accepted", detected)
+      }
+      DocumentError::MimeMismatch { claimed, detected } => write!(
+        f,
+        "The file is labeled {} but its contents are {}",
+        claimed, detected
+      ),
+      DocumentError::Duplicate { name, .. } => {
+        write!(f, "An identical file is already stored as '{}'", name)
+      }
+    }
+  }
+}
+
+impl Serialize for DocumentError {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::SerializeMap;
+
+    let (kind, claimed, detected) = match self {
+      DocumentError::Database(_) => ("database", None, None),
+      DocumentError::EmptyFile => ("empty_file", None, None),
+      DocumentError::Executable => ("executable", None, None),
+      DocumentError::Duplicate { .. } => ("duplicate", None, None),
+      DocumentError::Unrecognized { claimed } => ("unrecognized", claimed.as_deref(), None),
+      DocumentError::DisallowedType { detected } => {
+        ("disallowed_type", None, Some(detected.as_str()))
+      }
+      DocumentError::MimeMismatch { claimed, detected } => (
+        "mime_mismatch",
+        Some(claimed.as_str()),
+        Some(detected.as_str()),
+      ),
+    };
+
+    let document_id = match self {
+      DocumentError::Duplicate { document_id, .. } => Some(*document_id),
+      _ => None,
+    };
+
+    let mut map = serializer.serialize_map(Some(5))?;
+    map.serialize_entry("kind", kind)?;
+    map.serialize_entry("message", &self.to_string())?;
+    map.serialize_entry("claimed", &claimed)?;
+    map.serialize_entry("detected", &detected)?;
+    map.serialize_entry("document_id", &document_id)?;
+    map.end()
+  }
+}
+
+impl From<&str> for DocumentError {
+  fn from(e: &str) -> Self {
+    DocumentError::Database(e.to_string())
+  }
+}
+
+impl From<String> for DocumentError {
+  fn from(e: String) -> Self {
+    DocumentError::Database(e)
+  }
+}
+
+/// Identify a document from its contents and check it against the claimed type.
+/// Returns the detected MIME type to store.
+pub fn validate_document_content(
+  claimed: Option<&str>,
+  data: &[u8],
+) -> Result<&'static str, DocumentError> {
+  if data.is_empty() {
+    return Err(DocumentError::EmptyFile);
+  }
+
+  let detected = match sniff_mime(data) {
+    Some(mime::EXECUTABLE) => return Err(DocumentError::Executable),
+    Some(detected) => detected,
+    // Plain text has no magic bytes; accept it only when labeled as such
+    None
+      if claimed.map(canonical_mime).as_deref() == Some(mime::TEXT)
+        && !data.contains(&0)
+        && std::str::from_utf8(data).is_ok() =>
+    {
+      mime::TEXT
+    }
+    None => {
+      return Err(DocumentError::Unrecognized {
+        claimed: claimed.map(str::to_string),
+      })
+    }
+  };
+
+  if !mime::ALLOWED_DOCUMENT_TYPES.contains(&detected) {
+    return Err(DocumentError::DisallowedType {
+      detected: detected.to_string(),
+    });
+  }
+
+  if let Some(claimed) = claimed {
+    if !claim_matches(claimed, detected) {
+      return Err(DocumentError::MimeMismatch {
+        claimed: claimed.to_string(),
+        detected: detected.to_string(),
+      });
+    }
+  }
+
+  Ok(detected)
+}
+
+#[derive(Deserialize, Default)]
+pub struct DocumentQuery {
+  /// Case-insensitive match on the document name
+  search: Option<String>,
+  document_type: Option<String>,
+  limit: Option<i64>,
+  offset: Option<i64>,
+}
+
+/// List document metadata. Document contents are fetched separately with `get_document_data`.
 #[tauri::command]
-pub async fn get_documents() -> Result<Vec<Document>, String> {
+pub async fn list_documents(query: Option<DocumentQuery>) -> Result<Vec<DocumentMeta>, String> {
   let pool_guard = DB_POOL.read().await;
   let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+  let query = query.unwrap_or_default();
+
   let rows = sqlx::query(
     r#"
-        SELECT *
-        FROM documents
-        "#,
+    SELECT id, name, document_type, reminder_date, mime_type, claimed_mime_type, size, page_count,
+      hash, issued_on, expires_on, updated_at,
+      (SELECT has_text_layer FROM blob_text t WHERE t.hash = documents.hash) AS has_text_layer
+    FROM documents
+    WHERE (?1 IS NULL OR name LIKE '%' || ?1 || '%')
+      AND (?2 IS NULL OR document_type = ?2)
+    ORDER BY updated_at DESC, id DESC
+    LIMIT ?3 OFFSET ?4
+    "#,
   )
+  .bind(query.search.filter(|s| !s.trim().is_empty()))
+  .bind(query.document_type)
+  .bind(query.limit.unwrap_or(-1))
+  .bind(query.offset.unwrap_or(0))
   .fetch_all(pool)
   .await
   .map_err(|e| format!("Failed to fetch documents: {}", e))?;
 
   let mut documents = Vec::new();
   for row in rows {
-    documents.push(Document {
-      id: row.try_get("id").ok(),
+    documents.push(DocumentMeta {
+      id: row.try_get("id").unwrap_or_default(),
       name: row.try_get("name").unwrap_or_default(),
       document_type: row.try_get("document_type").unwrap_or_default(),
       reminder_date: row.try_get("reminder_date").ok(),
       mime_type: row.try_get("mime_type").ok(),
-      data: row.try_get::<Vec<u8>, _>("data").ok(),
+      claimed_mime_type: row.try_get("claimed_mime_type").ok(),
+      size: row.try_get("size").ok(),
+      page_count: row.try_get("page_count").ok(),
+      hash: row.try_get("hash").ok(),
+      issued_on: row.try_get("issued_on").ok(),
+      expires_on: row.try_get("expires_on").ok(),
       updated_at: row.try_get("updated_at").ok(),
+      has_text_layer: row.try_get("has_text_layer").ok().flatten(),
     });
   }
 
   Ok(documents)
 }
 
+/// Fetch the contents of a document as raw bytes. `offset`/`length` select a byte range so
+/// large files can be read in chunks.
+#[tauri::command]
+pub async fn get_document_data(
+  document_id: i64,
+  offset: Option<i64>,
+  length: Option<i64>,
+) -> Result<Response, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let offset = offset.unwrap_or(0);
+  if offset < 0 || length.is_some_and(|l| l < 0) {
+    return Err("Invalid byte range".to_string());
+  }
+
+  // substr() is 1-based and reads only the requested part of the blob
+  let data: Option<Vec<u8>> = sqlx::query_scalar(
+    r#"
+    SELECT substr(b.data, ?, COALESCE(?, b.size))
+    FROM documents d
+    LEFT JOIN blobs b ON b.hash = d.hash
+    WHERE d.id = ?
+    "#,
+  )
+  .bind(offset + 1)
+  .bind(length)
+  .bind(document_id)
+  .fetch_optional(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch document data: {}", e))?
+  .ok_or_else(|| format!("No document found with id {}", document_id))?;
+
+  Ok(Response::new(data.unwrap_or_default()))
+}
+
+/// Store a new document. When an identical file is already stored this fails with
+/// `DocumentError::Duplicate`, so the caller can link the existing document instead;
+/// pass `allow_duplicate` to add it anyway. Either way the contents are stored only once.
+/// PDFs are added to the text index, and a PDF form with a saved template is also filled from
+/// it, see `apply_saved_template`.
 #[tauri::command]
-pub async fn add_document(document: Document) -> Result<i64, String> {
+pub async fn add_document(
+  app: tauri::AppHandle,
+  document: Document,
+  allow_duplicate: Option<bool>,
+) -> Result<i64, DocumentError> {
   let pool_guard = DB_POOL.read().await;
   let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+  let data = document.data.unwrap_or_default();
+  let mime_type = validate_document_content(document.mime_type.as_deref(), &data)?;
+  let (data, mime_type) = sanitize_document_content(data, mime_type).await?;
+  let (size, page_count, hash) = document_metadata(Some(mime_type), &data);
+  validate_document_dates(
+    document.issued_on.as_deref(),
+    document.expires_on.as_deref(),
+  )?;
+
+  if !allow_duplicate.unwrap_or(false) {
+    let existing = sqlx::query("SELECT id, name FROM documents WHERE hash = ? ORDER BY id LIMIT 1")
+      .bind(&hash)
+      .fetch_optional(pool)
+      .await
+      .map_err(|e| format!("Failed to check for duplicate documents: {}", e))?;
+
+    if let Some(existing) = existing {
+      return Err(DocumentError::Duplicate {
+        document_id: existing.try_get("id").unwrap_or_default(),
+        name: existing.try_get("name").unwrap_or_default(),
+      });
+    }
+  }
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+  store_blob(&mut *tx, &data).await?;
+
   let result = sqlx::query(
     r#"
-    INSERT INTO documents (name, document_type, reminder_date, mime_type, data) VALUES (?, ?, ?, ?, ?)
+    INSERT INTO documents (
+      name, document_type, reminder_date, mime_type, claimed_mime_type, size, page_count, hash,
+      issued_on, expires_on, version_uploaded_at
+    ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
     "#,
   )
   .bind(&document.name)
   .bind(&document.document_type)
   .bind(&document.reminder_date)
+  .bind(mime_type)
   .bind(&document.mime_type)
-  .bind(&document.data)
-  .execute(pool)
+  .bind(size)
+  .bind(page_count)
+  .bind(&hash)
+  .bind(&document.issued_on)
+  .bind(&document.expires_on)
+  .execute(&mut *tx)
   .await
   .map_err(|e| format!("Failed to insert document: {}", e))?;
-  Ok(result.last_insert_rowid())
+
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to insert document: {}", e))?;
+
+  let document_id = result.last_insert_rowid();
+  if mime_type == mime::PDF {
+    index_document_text(pool, document_id).await;
+    apply_saved_template(&app, pool, document_id, data).await;
+  }
+  Ok(document_id)
+}
+
+/// Store images upright and without location or camera metadata. Other content is kept as
+/// uploaded. Returns the content to store and its type, which changes if an image had to be
+/// re-encoded.
+pub(crate) async fn sanitize_document_content(
+  data: Vec<u8>,
+  mime_type: &'static str,
+) -> Result<(Vec<u8>, &'static str), String> {
+  if !mime_type.starts_with("image/") {
+    return Ok((data, mime_type));
+  }
+
+  let data = tokio::task::spawn_blocking(move || sanitize_image(&data))
+    .await
+    .map_err(|e| format!("Image processing task failed: {}", e))?
+    .map_err(|e| format!("Failed to process image: {}", e))?;
+  let mime_type = sniff_mime(&data).unwrap_or(mime_type);
+  Ok((data, mime_type))
 }
 
 #[tauri::command]
@@ -76,7 +374,11 @@ pub async fn read_file_as_blob(file_path: String) -> Result<FileBlob, String> {
     file_contents.len()
   );
 
-  let mime_type = get_mime_type(&file_path);
+  // Trust the contents over the extension
+  let mime_type = sniff_mime(&file_contents)
+    .filter(|detected| *detected != mime::EXECUTABLE)
+    .map(str::to_string)
+    .unwrap_or_else(|| get_mime_type(&file_path));
   println!("Detected MIME type: {}", mime_type);
 
   Ok(FileBlob {
@@ -97,6 +399,93 @@ pub async fn delete_document(document_id: i64) -> Result<(), String> {
   Ok(())
 }
 
+/// Fill in size, page count and hash for documents stored before these columns existed
+pub async fn backfill_document_metadata(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+  let ids: Vec<i64> = sqlx::query_scalar("SELECT id FROM documents WHERE hash IS NULL")
+    .fetch_all(pool)
+    .await?;
+
+  for id in ids {
+    let row = sqlx::query(
+      r#"
+      SELECT d.mime_type, COALESCE(d.data, b.data) AS data
+      FROM documents d
+      LEFT JOIN blobs b ON b.hash = d.hash
+      WHERE d.id = ?
+      "#,
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+    let mime_type: Option<String> = row.try_get("mime_type").ok();
+    let data: Vec<u8> = row.try_get("data").unwrap_or_default();
+    let (size, page_count, hash) = document_metadata(mime_type.as_deref(), &data);
+
+    sqlx::query("UPDATE documents SET size = ?, page_count = ?, hash = ? WHERE id = ?")
+      .bind(size)
+      .bind(page_count)
+      .bind(&hash)
+      .bind(id)
+      .execute(pool)
+      .await?;
+  }
+
+  // Documents stored before content sniffing only have the type they were labeled with
+  let ids: Vec<i64> =
+    sqlx::query_scalar("SELECT id FROM documents WHERE claimed_mime_type IS NULL")
+      .fetch_all(pool)
+      .await?;
+
+  for id in ids {
+    let row = sqlx::query(
+      r#"
+      SELECT d.mime_type, COALESCE(d.data, b.data) AS data
+      FROM documents d
+      LEFT JOIN blobs b ON b.hash = d.hash
+      WHERE d.id = ?
+      "#,
+    )
+    .bind(id)
+    .fetch_one(pool)
+    .await?;
+    let claimed: String = row.try_get("mime_type").unwrap_or_default();
+    let data: Vec<u8> = row.try_get("data").unwrap_or_default();
+    let detected = sniff_mime(&data)
+      .map(str::to_string)
+      .unwrap_or_else(|| claimed.clone());
+
+    sqlx::query("UPDATE documents SET mime_type = ?, claimed_mime_type = ? WHERE id = ?")
+      .bind(&detected)
+      .bind(&claimed)
+      .bind(id)
+      .execute(pool)
+      .await?;
+  }
+
+  Ok(())
+}
+
+/// Size in bytes, page count (when it can be determined) and hex SHA-256 of a document
+pub(crate) fn document_metadata(
+  mime_type: Option<&str>,
+  data: &[u8],
+) -> (i64, Option<i64>, String) {
+  use crate::helpers::images::image_page_count;
+  use crate::helpers::pdf_docs::pdf_page_count;
+
+  let page_count = match mime_type {
+    Some("application/pdf") => pdf_page_count(data).map(i64::from),
+    Some(mime) if mime.starts_with("image/") => image_page_count(data).ok().map(i64::from),
+    _ => None,
+  };
+
+  (data.len() as i64, page_count, sha256_hex(data))
+}
+
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+  hex::encode(Sha256::digest(data))
+}
+
 fn get_mime_type(file_path: &str) -> String {
   let extension = file_path.split('.').last().unwrap_or("").to_lowercase();
 
@@ -110,6 +499,9 @@ fn get_mime_type(file_path: &str) -> String {
     "gif" => "image/gif".to_string(),
     "bmp" => "image/bmp".to_string(),
     "webp" => "image/webp".to_string(),
+    "heic" => "image/heic".to_string(),
+    "heif" | "hif" => "image/heif".to_string(),
+    "tif" | "tiff" => "image/tiff".to_string(),
     "svg" => "image/svg+xml".to_string(),
     "mp4" => "video/mp4".to_string(),
     "avi" => "video/x-msvideo".to_string(),
@@ -133,71 +525,133 @@ fn get_mime_type(file_path: &str) -> String {
 
 #[tauri::command]
 pub async fn test_pdf_generation() -> Result<String, String> {
-  use crate::helpers::pdf_docs::create_test_pdf;
-  use std::env;
+  use crate::helpers::pdf_docs::{create_test_pdf, pdf_page_count};
 
-  let temp_dir = env::temp_dir();
-  let test_pdf_path = temp_dir.join("test_output.pdf");
-
-  create_test_pdf(&test_pdf_path).map_err(|e| format!("Failed to create test PDF: {}", e))?;
+  // Generated in memory and checked by parsing it back; nothing is written to disk
+  let pdf = tokio::task::spawn_blocking(create_test_pdf)
+    .await
+    .map_err(|e| format!("Test PDF task failed: {}", e))?;
+  let pages =
+    pdf_page_count(&pdf).ok_or_else(|| "Generated test PDF could not be parsed".to_string())?;
 
-  Ok(format!("Test PDF created at: {}", test_pdf_path.display()))
+  Ok(format!(
+    "Test PDF generated: {} bytes, {} page(s)",
+    pdf.len(),
+    pages
+  ))
 }
 
+/// Append documents to `first_pdf`, each with an outline entry. Merging happens on this machine;
+/// the SASE merge API is only used when `merge_remotely` is set, and then needs `jwt_token`.
+/// `stamp` adds page numbers, a running header or a watermark to every page, as on packets.
+/// Progress is reported as `pdf-job-progress` events for `job_id`, which `cancel_pdf_job` stops.
 #[tauri::command]
 pub async fn build_pdf_with_sase_api(
+  app: tauri::AppHandle,
   first_pdf: Vec<u8>,
   ids_in_order: Vec<i64>,
-  jwt_token: String,
+  jwt_token: Option<String>,
+  listing_id: Option<i64>,
+  merge_remotely: Option<bool>,
+  stamp: Option<PacketStampOptions>,
+  render_options: Option<PdfRenderOptions>,
+  job_id: Option<String>,
 ) -> Result<Vec<u8>, String> {
-  use crate::helpers::pdf_docs::merge_mixed_to_pdf_via_sase_api_with_pdfs;
+  use crate::helpers::pdf_docs::{merge_documents, merge_documents_via_sase_api};
+  use crate::helpers::pdf_size::apply_size_budget;
+  use crate::packet::{apply_stamp, listing_stamp};
 
-  let pool_guard = DB_POOL.read().await;
-  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+  let render_options = render_options.unwrap_or_default();
+  render_options.validate().map_err(|e| e.to_string())?;
+  let job = PdfJob::start(app, job_id)?;
+  let monitor: Arc<dyn PdfBuildMonitor> = job.clone();
 
-  let mut additional_blobs = Vec::new();
-  let mut additional_pdfs = Vec::new();
-  let mut image_headers = Vec::new();
-  let mut pdf_headers = Vec::new();
+  job
+    .run(async move {
+      let pool_guard = DB_POOL.read().await;
+      let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
 
-  for doc_id in &ids_in_order {
-    let row = sqlx::query("SELECT data, mime_type, name FROM documents WHERE id = ?")
-      .bind(doc_id)
-      .fetch_optional(pool)
-      .await
-      .map_err(|e| format!("Database error: {}", e))?;
-
-    if let Some(row) = row {
-      let data: Vec<u8> = row.get("data");
-      let mime_type: String = row.get("mime_type");
-      let name: String = row.get("name");
-
-      if mime_type.starts_with("image/") {
-        additional_blobs.push(data);
-        image_headers.push(name);
-      } else if mime_type == "application/pdf" {
-        additional_pdfs.push(data);
-        pdf_headers.push(name);
+      let documents =
+        load_packet_documents(pool, &ids_in_order, listing_id, monitor.as_ref()).await?;
+      let stamp = match stamp {
+        Some(options) => listing_stamp(pool, listing_id, options).await?,
+        None => None,
+      };
+
+      if merge_remotely.unwrap_or(false) {
+        let jwt_token = jwt_token.ok_or("Merging with the SASE API requires a JWT token")?;
+        let merged = merge_documents_via_sase_api(
+          first_pdf,
+          documents,
+          &render_options,
+          &jwt_token,
+          monitor.clone(),
+        )
+        .await
+        .map_err(|e| format!("Failed to merge PDFs via SASE API: {}", e))?;
+        return tokio::task::spawn_blocking(move || {
+          apply_stamp(merged, stamp.as_ref(), monitor.as_ref())
+        })
+        .await
+        .map_err(|e| format!("PDF stamp task failed: {}", e))?
+        .map_err(|e| format!("Failed to stamp PDF: {}", e));
       }
-    }
-  }
 
-  let mut all_headers = image_headers;
-  all_headers.extend(pdf_headers);
+      tokio::task::spawn_blocking(move || {
+        let merged = merge_documents(first_pdf, documents, &render_options, monitor.as_ref())?;
+        // Stamped before the budget, so it covers the stamps too
+        let merged = apply_stamp(merged, stamp.as_ref(), monitor.as_ref())?;
+        apply_size_budget(merged, render_options.target_size_bytes, monitor.as_ref())
+      })
+      .await
+      .map_err(|e| format!("PDF merge task failed: {}", e))?
+      .map_err(|e| format!("Failed to merge PDFs: {}", e))
+    })
+    .await
+}
+
+/// Load documents in the given order for merging into a PDF, honoring the listing's pinned
+/// versions. Deleted documents are skipped.
+pub(crate) async fn load_packet_documents(
+  pool: &SqlitePool,
+  ids_in_order: &[i64],
+  listing_id: Option<i64>,
+  monitor: &dyn PdfBuildMonitor,
+) -> Result<Vec<PacketDocument>, String> {
+  use crate::document_versions::resolve_document;
 
-  println!(
-    "Collected {} total headers: {:?}",
-    all_headers.len(),
-    all_headers
-  );
+  let mut documents = Vec::new();
+  for (i, doc_id) in ids_in_order.iter().enumerate() {
+    monitor.check_cancelled().map_err(|e| e.to_string())?;
+    monitor.report(PdfBuildStage::LoadingDocument {
+      index: i + 1,
+      total: ids_in_order.len(),
+    });
 
-  merge_mixed_to_pdf_via_sase_api_with_pdfs(
-    first_pdf,
-    additional_blobs,
-    additional_pdfs,
-    all_headers,
-    &jwt_token,
-  )
-  .await
-  .map_err(|e| format!("Failed to merge PDFs via SASE API: {}", e))
+    // Listings may pin an older version of a document; everything else uses the latest
+    let Some(resolved) = resolve_document(pool, *doc_id, listing_id).await? else {
+      continue;
+    };
+
+    // Route by the actual contents; a wrong label must not drop a document from the packet
+    let content = match sniff_mime(&resolved.data) {
+      Some(mime::PDF) => PacketContent::Pdf(resolved.data),
+      Some(detected) if detected.starts_with("image/") => PacketContent::Image(resolved.data),
+      detected => {
+        return Err(format!(
+          "Document '{}' cannot be added to a PDF (type {})",
+          resolved.name,
+          detected.unwrap_or("unknown")
+        ))
+      }
+    };
+
+    documents.push(PacketDocument {
+      title: resolved.name,
+      subtitle: Some(resolved.document_type).filter(|t| !t.is_empty()),
+      content,
+    });
+  }
+
+  Ok(documents)
 }
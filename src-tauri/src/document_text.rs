@@ -0,0 +1,183 @@
+//! Full-text search over the contents of PDF documents. Text is extracted per page into the
+//! `blob_text_pages` FTS5 index, keyed like the contents by blob hash, so copies and versions
+//! of the same file are indexed once and the text goes when the blob does.
+
+use crate::helpers::mime;
+use crate::helpers::pdf_text::{extract_pdf_text, has_text_layer};
+use crate::DB_POOL;
+use serde::Serialize;
+use sqlx::{Row, SqlitePool};
+
+const DEFAULT_SEARCH_LIMIT: i64 = 50;
+
+/// A page of a document that matches a text search
+#[derive(Serialize)]
+pub struct DocumentTextMatch {
+  document_id: i64,
+  name: String,
+  document_type: String,
+  /// 1-based
+  page: i64,
+  /// Text around the match, with the matched words in `[` and `]`
+  snippet: String,
+}
+
+/// Search the text of all PDF documents. Pages match any word of `query` and the ones with
+/// more, and rarer, of the words rank first, so "the statement showing the March deposit"
+/// finds the statement without the exact wording.
+#[tauri::command]
+pub async fn search_document_text(
+  query: String,
+  limit: Option<i64>,
+) -> Result<Vec<DocumentTextMatch>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let Some(expression) = match_expression(&query) else {
+    return Ok(Vec::new());
+  };
+
+  let rows = sqlx::query(
+    r#"
+    SELECT d.id, d.name, d.document_type, p.page,
+      snippet(blob_text_pages, 0, '[', ']', '...', 16) AS snippet
+    FROM blob_text_pages p
+    JOIN documents d ON d.hash = p.hash
+    WHERE blob_text_pages MATCH ?
+    ORDER BY p.rank, d.id, p.page
+    LIMIT ?
+    "#,
+  )
+  .bind(expression)
+  .bind(limit.unwrap_or(DEFAULT_SEARCH_LIMIT))
+  .fetch_all(pool)
+  .await
+  .map_err(|e| format!("Failed to search documents: {}", e))?;
+
+  Ok(
+    rows
+      .iter()
+      .map(|row| DocumentTextMatch {
+        document_id: row.try_get("id").unwrap_or_default(),
+        name: row.try_get("name").unwrap_or_default(),
+        document_type: row.try_get("document_type").unwrap_or_default(),
+        page: row.try_get("page").unwrap_or_default(),
+        snippet: row.try_get("snippet").unwrap_or_default(),
+      })
+      .collect(),
+  )
+}
+
+/// Extract the text of a PDF document into the search index, unless its contents already are.
+/// Problems are logged rather than failing the upload.
+pub(crate) async fn index_document_text(pool: &SqlitePool, document_id: i64) {
+  if let Err(e) = index_document(pool, document_id).await {
+    println!("Failed to index text of document {}: {}", document_id, e);
+  }
+}
+
+/// Index the text of PDF documents stored before the text index existed
+pub async fn backfill_document_text(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+  let ids: Vec<i64> = sqlx::query_scalar(
+    r#"
+    SELECT id FROM documents
+    WHERE mime_type = ? AND hash IS NOT NULL
+      AND hash NOT IN (SELECT hash FROM blob_text)
+    "#,
+  )
+  .bind(mime::PDF)
+  .fetch_all(pool)
+  .await?;
+
+  // One document at a time, so large vaults are not loaded into memory at once
+  for id in ids {
+    index_document_text(pool, id).await;
+  }
+
+  Ok(())
+}
+
+async fn index_document(pool: &SqlitePool, document_id: i64) -> Result<(), String> {
+  let row = sqlx::query(
+    r#"
+    SELECT d.hash, b.data
+    FROM documents d
+    JOIN blobs b ON b.hash = d.hash
+    WHERE d.id = ? AND d.mime_type = ?
+      AND NOT EXISTS (SELECT 1 FROM blob_text t WHERE t.hash = d.hash)
+    "#,
+  )
+  .bind(document_id)
+  .bind(mime::PDF)
+  .fetch_optional(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch document: {}", e))?;
+  let Some(row) = row else {
+    return Ok(());
+  };
+  let hash: String = row.try_get("hash").unwrap_or_default();
+  let data: Vec<u8> = row.try_get("data").unwrap_or_default();
+
+  let extracted = tokio::task::spawn_blocking(move || extract_pdf_text(&data))
+    .await
+    .map_err(|e| format!("Text extraction task failed: {}", e))?;
+  // Unreadable PDFs are recorded too, so they are not retried on every start
+  let (pages, error) = match extracted {
+    Ok(pages) => (pages, None),
+    Err(e) => (Vec::new(), Some(e.to_string())),
+  };
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+  // The blob may have been indexed, or released, while the text was being extracted
+  let inserted = sqlx::query(
+    r#"
+    INSERT INTO blob_text (hash, page_count, has_text_layer, error)
+    SELECT ?, ?, ?, ? WHERE EXISTS (SELECT 1 FROM blobs WHERE hash = ?)
+    ON CONFLICT(hash) DO NOTHING
+    "#,
+  )
+  .bind(&hash)
+  .bind(pages.len() as i64)
+  // Whether an unreadable PDF has text is unknown, not false
+  .bind(error.is_none().then(|| has_text_layer(&pages)))
+  .bind(&error)
+  .bind(&hash)
+  .execute(&mut *tx)
+  .await
+  .map_err(|e| format!("Failed to save document text: {}", e))?;
+  if inserted.rows_affected() == 0 {
+    return Ok(());
+  }
+
+  for (index, text) in pages.iter().enumerate() {
+    if text.is_empty() {
+      continue;
+    }
+    sqlx::query("INSERT INTO blob_text_pages (text, hash, page) VALUES (?, ?, ?)")
+      .bind(text)
+      .bind(&hash)
+      .bind(index as i64 + 1)
+      .execute(&mut *tx)
+      .await
+      .map_err(|e| format!("Failed to save document text: {}", e))?;
+  }
+
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to save document text: {}", e))
+}
+
+/// FTS5 query matching any word of free text. Words are quoted so punctuation and operator
+/// words like AND or NOT in the query are searched for rather than parsed.
+fn match_expression(query: &str) -> Option<String> {
+  let words: Vec<String> = query
+    .split(|c: char| !c.is_alphanumeric())
+    .filter(|word| !word.is_empty())
+    .map(|word| format!("\"{}\"", word))
+    .collect();
+  (!words.is_empty()).then(|| words.join(" OR "))
+}
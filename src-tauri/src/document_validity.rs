@@ -0,0 +1,239 @@
+use crate::DocumentValidityRule;
+use crate::DB_POOL;
+use serde::Serialize;
+use sqlx::Row;
+
+/// Validity periods seeded when the rules table is first created, in days after issue
+pub const DEFAULT_VALIDITY_RULES: [(&str, i64); 6] = [
+  ("Pay Stub", 30),
+  ("Proof of Income", 60),
+  ("Bank Statement", 60),
+  ("Credit Report", 30),
+  ("Employment Verification", 30),
+  ("Background Check", 90),
+];
+
+#[derive(Serialize)]
+pub struct ExpiringDocument {
+  document_id: i64,
+  name: String,
+  document_type: String,
+  issued_on: Option<String>,
+  /// Explicit `expires_on`, or the issue date plus the validity period of the document type
+  valid_until: String,
+  /// "expires_on", "issued_on", or "uploaded" when the rule had to fall back to the upload date
+  basis: String,
+  /// Negative once the document has expired
+  days_remaining: i64,
+  expired: bool,
+}
+
+/// Set when a document was issued and, for documents with a printed expiry such as IDs,
+/// when it expires. Dates are `YYYY-MM-DD`.
+#[tauri::command]
+pub async fn set_document_dates(
+  document_id: i64,
+  issued_on: Option<String>,
+  expires_on: Option<String>,
+) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  validate_document_dates(issued_on.as_deref(), expires_on.as_deref())?;
+
+  let result = sqlx::query("UPDATE documents SET issued_on = ?, expires_on = ? WHERE id = ?")
+    .bind(&issued_on)
+    .bind(&expires_on)
+    .bind(document_id)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to update document dates: {}", e))?;
+
+  if result.rows_affected() == 0 {
+    return Err(format!("No document found with id {}", document_id));
+  }
+
+  Ok(())
+}
+
+#[tauri::command]
+pub async fn get_document_validity_rules() -> Result<Vec<DocumentValidityRule>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let rows = sqlx::query("SELECT * FROM document_validity_rules ORDER BY document_type")
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch document validity rules: {}", e))?;
+
+  let mut rules = Vec::new();
+  for row in rows {
+    rules.push(DocumentValidityRule {
+      document_type: row.try_get("document_type").unwrap_or_default(),
+      valid_days: row.try_get("valid_days").unwrap_or_default(),
+      updated_at: row.try_get("updated_at").ok(),
+    });
+  }
+
+  Ok(rules)
+}
+
+/// Set how many days documents of a type stay valid after they were issued.
+/// `valid_days: None` removes the rule so documents of that type never expire by default.
+#[tauri::command]
+pub async fn set_document_validity_rule(
+  document_type: String,
+  valid_days: Option<i64>,
+) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let document_type = document_type.trim();
+  if document_type.is_empty() {
+    return Err("Document type is required".to_string());
+  }
+
+  match valid_days {
+    Some(days) if days <= 0 => {
+      return Err(format!(
+        "Validity period must be positive, got {} days",
+        days
+      ))
+    }
+    Some(days) => {
+      sqlx::query(
+        r#"
+        INSERT INTO document_validity_rules (document_type, valid_days) VALUES (?, ?)
+        ON CONFLICT(document_type) DO UPDATE SET valid_days = excluded.valid_days
+        "#,
+      )
+      .bind(document_type)
+      .bind(days)
+      .execute(pool)
+      .await
+      .map_err(|e| format!("Failed to save document validity rule: {}", e))?;
+    }
+    None => {
+      sqlx::query("DELETE FROM document_validity_rules WHERE document_type = ?")
+        .bind(document_type)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to delete document validity rule: {}", e))?;
+    }
+  }
+
+  Ok(())
+}
+
+/// Documents that are expired or expire within `within_days`, soonest first.
+/// Documents without an expiry date or a validity rule for their type are never included.
+#[tauri::command]
+pub async fn get_expiring_documents(within_days: i64) -> Result<Vec<ExpiringDocument>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  if within_days < 0 {
+    return Err("within_days must not be negative".to_string());
+  }
+
+  let rows = sqlx::query(
+    r#"
+    SELECT *, CAST(julianday(valid_until) - julianday(date('now', 'localtime')) AS INTEGER)
+      AS days_remaining
+    FROM (
+      SELECT d.id, d.name, d.document_type, d.issued_on,
+        COALESCE(
+          d.expires_on,
+          date(
+            COALESCE(d.issued_on, date(d.version_uploaded_at)),
+            '+' || r.valid_days || ' days'
+          )
+        ) AS valid_until,
+        CASE
+          WHEN d.expires_on IS NOT NULL THEN 'expires_on'
+          WHEN d.issued_on IS NOT NULL THEN 'issued_on'
+          ELSE 'uploaded'
+        END AS basis
+      FROM documents d
+      LEFT JOIN document_validity_rules r ON r.document_type = d.document_type
+    )
+    WHERE valid_until IS NOT NULL
+      AND valid_until <= date('now', 'localtime', '+' || ? || ' days')
+    ORDER BY valid_until, id
+    "#,
+  )
+  .bind(within_days)
+  .fetch_all(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch expiring documents: {}", e))?;
+
+  let mut documents = Vec::new();
+  for row in rows {
+    let days_remaining: i64 = row.try_get("days_remaining").unwrap_or_default();
+    documents.push(ExpiringDocument {
+      document_id: row.try_get("id").unwrap_or_default(),
+      name: row.try_get("name").unwrap_or_default(),
+      document_type: row.try_get("document_type").unwrap_or_default(),
+      issued_on: row.try_get("issued_on").ok(),
+      valid_until: row.try_get("valid_until").unwrap_or_default(),
+      basis: row.try_get("basis").unwrap_or_default(),
+      days_remaining,
+      expired: days_remaining < 0,
+    });
+  }
+
+  Ok(documents)
+}
+
+/// Check that document dates are valid `YYYY-MM-DD` dates and in order
+pub(crate) fn validate_document_dates(
+  issued_on: Option<&str>,
+  expires_on: Option<&str>,
+) -> Result<(), String> {
+  for date in [issued_on, expires_on].into_iter().flatten() {
+    if !is_iso_date(date) {
+      return Err(format!("Invalid date '{}', expected YYYY-MM-DD", date));
+    }
+  }
+
+  if let (Some(issued_on), Some(expires_on)) = (issued_on, expires_on) {
+    // ISO dates compare correctly as strings
+    if expires_on < issued_on {
+      return Err(format!(
+        "Expiry date {} is before the issue date {}",
+        expires_on, issued_on
+      ));
+    }
+  }
+
+  Ok(())
+}
+
+fn is_iso_date(date: &str) -> bool {
+  let parts: Vec<&str> = date.split('-').collect();
+  let [year, month, day] = parts[..] else {
+    return false;
+  };
+  if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+    return false;
+  }
+
+  let (Ok(year), Ok(month), Ok(day)) = (
+    year.parse::<u32>(),
+    month.parse::<u32>(),
+    day.parse::<u32>(),
+  ) else {
+    return false;
+  };
+
+  let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+  let days_in_month = match month {
+    1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+    4 | 6 | 9 | 11 => 30,
+    2 if leap => 29,
+    2 => 28,
+    _ => return false,
+  };
+
+  (1..=days_in_month).contains(&day)
+}
@@ -0,0 +1,482 @@
+use crate::blobs::store_blob;
+use crate::document::{
+  document_metadata, sanitize_document_content, validate_document_content, DocumentError,
+};
+use crate::document_text::index_document_text;
+use crate::helpers::mime::{self, sniff_mime};
+use crate::helpers::redact::{redact_image, redact_pdf, RedactionRegion};
+use crate::DocumentVersion;
+use crate::DB_POOL;
+use serde::Serialize;
+use sqlx::{Row, Sqlite, SqlitePool, Transaction};
+use tauri::ipc::Response;
+
+#[derive(Serialize)]
+pub struct DocumentPin {
+  document_id: i64,
+  version: i64,
+}
+
+/// Content of one document version as it is resolved for use
+pub(crate) struct ResolvedDocument {
+  pub name: String,
+  pub document_type: String,
+  pub data: Vec<u8>,
+}
+
+/// Upload a new file for an existing document, keeping the current one as history.
+/// Returns the new version number.
+#[tauri::command]
+pub async fn replace_document(
+  document_id: i64,
+  data: Vec<u8>,
+  mime_type: Option<String>,
+  note: Option<String>,
+) -> Result<i64, DocumentError> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let detected = validate_document_content(mime_type.as_deref(), &data)?;
+  let (data, detected) = sanitize_document_content(data, detected).await?;
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+  let version = replace_content(
+    &mut tx,
+    document_id,
+    &data,
+    detected,
+    mime_type.as_deref(),
+    note.as_deref(),
+  )
+  .await?;
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to replace document: {}", e))?;
+
+  index_document_text(pool, document_id).await;
+  Ok(version)
+}
+
+/// All versions of a document, newest first. The current version is flagged `is_current`.
+#[tauri::command]
+pub async fn list_document_versions(document_id: i64) -> Result<Vec<DocumentVersion>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let rows = sqlx::query(
+    r#"
+    SELECT id AS document_id, COALESCE(version, 1) AS version, mime_type, size, page_count, hash,
+      version_note AS note, version_uploaded_at AS uploaded_at, 1 AS is_current
+    FROM documents
+    WHERE id = ?1
+    UNION ALL
+    SELECT document_id, version, mime_type, size, page_count, hash, note, uploaded_at,
+      0 AS is_current
+    FROM document_versions
+    WHERE document_id = ?1
+    ORDER BY version DESC
+    "#,
+  )
+  .bind(document_id)
+  .fetch_all(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch document versions: {}", e))?;
+
+  if rows.is_empty() {
+    return Err(format!("No document found with id {}", document_id));
+  }
+
+  let mut versions = Vec::new();
+  for row in rows {
+    versions.push(DocumentVersion {
+      document_id: row.try_get("document_id").unwrap_or_default(),
+      version: row.try_get("version").unwrap_or_default(),
+      mime_type: row.try_get("mime_type").ok(),
+      size: row.try_get("size").ok(),
+      page_count: row.try_get("page_count").ok(),
+      hash: row.try_get("hash").ok(),
+      note: row.try_get("note").ok(),
+      uploaded_at: row.try_get("uploaded_at").ok(),
+      is_current: row.try_get("is_current").unwrap_or(false),
+    });
+  }
+
+  Ok(versions)
+}
+
+/// Contents of a specific version of a document
+#[tauri::command]
+pub async fn get_document_version_data(document_id: i64, version: i64) -> Result<Response, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let resolved = load_document_version(pool, document_id, Some(version))
+    .await?
+    .ok_or_else(|| format!("Document {} has no version {}", document_id, version))?;
+
+  Ok(Response::new(resolved.data))
+}
+
+/// Black out regions of a document, removing the content underneath, and save the result as a
+/// new version. The original stays in the version history. Returns the new version number.
+#[tauri::command]
+pub async fn redact_document(
+  document_id: i64,
+  regions: Vec<RedactionRegion>,
+  note: Option<String>,
+) -> Result<i64, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let row = sqlx::query(
+    r#"
+    SELECT b.data, COALESCE(d.version, 1) AS version
+    FROM documents d
+    LEFT JOIN blobs b ON b.hash = d.hash
+    WHERE d.id = ?
+    "#,
+  )
+  .bind(document_id)
+  .fetch_optional(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch document: {}", e))?
+  .ok_or_else(|| format!("No document found with id {}", document_id))?;
+  let data: Vec<u8> = row.try_get("data").unwrap_or_default();
+  let version: i64 = row.try_get("version").unwrap_or(1);
+
+  let region_count = regions.len();
+  let redacted = tokio::task::spawn_blocking(move || match sniff_mime(&data) {
+    Some(mime::PDF) => redact_pdf(&data, &regions),
+    Some(detected) if detected.starts_with("image/") => redact_image(&data, &regions),
+    detected => Err(anyhow::anyhow!(
+      "Documents of type {} cannot be redacted",
+      detected.unwrap_or("unknown")
+    )),
+  })
+  .await
+  .map_err(|e| format!("Redaction task failed: {}", e))?
+  .map_err(|e| format!("Failed to redact document: {}", e))?;
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+  // Don't replace a version uploaded while the redaction was running
+  let current: Option<i64> =
+    sqlx::query_scalar("SELECT COALESCE(version, 1) FROM documents WHERE id = ?")
+      .bind(document_id)
+      .fetch_optional(&mut *tx)
+      .await
+      .map_err(|e| format!("Failed to fetch document version: {}", e))?;
+  if current != Some(version) {
+    return Err("The document changed while it was being redacted; try again".to_string());
+  }
+
+  let note = note.unwrap_or_else(|| {
+    format!(
+      "Redacted {} {}",
+      region_count,
+      if region_count == 1 {
+        "region"
+      } else {
+        "regions"
+      }
+    )
+  });
+  let new_version = replace_content(
+    &mut tx,
+    document_id,
+    &redacted,
+    sniff_mime(&redacted).unwrap_or("application/octet-stream"),
+    None,
+    Some(&note),
+  )
+  .await?;
+
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to save redacted document: {}", e))?;
+
+  index_document_text(pool, document_id).await;
+  Ok(new_version)
+}
+
+/// Make an older version current again. The restored content becomes a new version, so
+/// nothing is lost. Returns the new version number.
+#[tauri::command]
+pub async fn restore_document_version(document_id: i64, version: i64) -> Result<i64, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+  let row = sqlx::query(
+    r#"
+    SELECT b.data, v.mime_type, v.claimed_mime_type
+    FROM document_versions v
+    LEFT JOIN blobs b ON b.hash = v.hash
+    WHERE v.document_id = ? AND v.version = ?
+    "#,
+  )
+  .bind(document_id)
+  .bind(version)
+  .fetch_optional(&mut *tx)
+  .await
+  .map_err(|e| format!("Failed to fetch document version: {}", e))?
+  .ok_or_else(|| {
+    format!(
+      "Document {} has no previous version {}",
+      document_id, version
+    )
+  })?;
+
+  let data: Vec<u8> = row.try_get("data").unwrap_or_default();
+  let mime_type: Option<String> = row.try_get("mime_type").ok();
+  let claimed_mime_type: Option<String> = row.try_get("claimed_mime_type").ok();
+  let note = format!("Restored from version {}", version);
+
+  let new_version = replace_content(
+    &mut tx,
+    document_id,
+    &data,
+    mime_type.as_deref().unwrap_or("application/octet-stream"),
+    claimed_mime_type.as_deref(),
+    Some(&note),
+  )
+  .await?;
+
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to restore document version: {}", e))?;
+
+  index_document_text(pool, document_id).await;
+  Ok(new_version)
+}
+
+/// Delete the oldest previous versions of a document, keeping the `keep` most recent ones.
+/// Versions pinned by a listing are always kept. Returns the number of versions deleted.
+#[tauri::command]
+pub async fn prune_document_versions(document_id: i64, keep: i64) -> Result<u64, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let result = sqlx::query(
+    r#"
+    DELETE FROM document_versions
+    WHERE document_id = ?1
+      AND version NOT IN (
+        SELECT version FROM document_versions
+        WHERE document_id = ?1
+        ORDER BY version DESC
+        LIMIT ?2
+      )
+      AND version NOT IN (
+        SELECT version FROM listing_document_pins WHERE document_id = ?1
+      )
+    "#,
+  )
+  .bind(document_id)
+  .bind(keep.max(0))
+  .execute(pool)
+  .await
+  .map_err(|e| format!("Failed to prune document versions: {}", e))?;
+
+  Ok(result.rows_affected())
+}
+
+/// Pin a listing to a specific version of a document, or follow the latest with `version: None`
+#[tauri::command]
+pub async fn pin_listing_document_version(
+  listing_id: i64,
+  document_id: i64,
+  version: Option<i64>,
+) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  match version {
+    Some(version) => {
+      if load_document_version(pool, document_id, Some(version))
+        .await?
+        .is_none()
+      {
+        return Err(format!(
+          "Document {} has no version {}",
+          document_id, version
+        ));
+      }
+
+      sqlx::query(
+        r#"
+        INSERT INTO listing_document_pins (listing_id, document_id, version) VALUES (?, ?, ?)
+        ON CONFLICT(listing_id, document_id) DO UPDATE SET version = excluded.version
+        "#,
+      )
+      .bind(listing_id)
+      .bind(document_id)
+      .bind(version)
+      .execute(pool)
+      .await
+      .map_err(|e| format!("Failed to pin document version: {}", e))?;
+    }
+    None => {
+      sqlx::query("DELETE FROM listing_document_pins WHERE listing_id = ? AND document_id = ?")
+        .bind(listing_id)
+        .bind(document_id)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Failed to unpin document version: {}", e))?;
+    }
+  }
+
+  Ok(())
+}
+
+#[tauri::command]
+pub async fn get_listing_document_pins(listing_id: i64) -> Result<Vec<DocumentPin>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let rows =
+    sqlx::query("SELECT document_id, version FROM listing_document_pins WHERE listing_id = ?")
+      .bind(listing_id)
+      .fetch_all(pool)
+      .await
+      .map_err(|e| format!("Failed to fetch document pins: {}", e))?;
+
+  Ok(
+    rows
+      .iter()
+      .map(|row| DocumentPin {
+        document_id: row.try_get("document_id").unwrap_or_default(),
+        version: row.try_get("version").unwrap_or_default(),
+      })
+      .collect(),
+  )
+}
+
+/// Resolve the document content to use, honoring a listing's pinned version if there is one
+pub(crate) async fn resolve_document(
+  pool: &SqlitePool,
+  document_id: i64,
+  listing_id: Option<i64>,
+) -> Result<Option<ResolvedDocument>, String> {
+  let pinned: Option<i64> = match listing_id {
+    Some(listing_id) => sqlx::query_scalar(
+      "SELECT version FROM listing_document_pins WHERE listing_id = ? AND document_id = ?",
+    )
+    .bind(listing_id)
+    .bind(document_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch document pin: {}", e))?,
+    None => None,
+  };
+
+  match load_document_version(pool, document_id, pinned).await? {
+    Some(resolved) => Ok(Some(resolved)),
+    // A pruned pin falls back to the latest version
+    None if pinned.is_some() => load_document_version(pool, document_id, None).await,
+    None => Ok(None),
+  }
+}
+
+/// Load the given version of a document, or the current one for `None`
+async fn load_document_version(
+  pool: &SqlitePool,
+  document_id: i64,
+  version: Option<i64>,
+) -> Result<Option<ResolvedDocument>, String> {
+  let row = sqlx::query(
+    r#"
+    SELECT d.name, d.document_type, b.data,
+      CASE WHEN ?2 IS NULL OR COALESCE(d.version, 1) = ?2 THEN 1 ELSE v.id IS NOT NULL END AS found
+    FROM documents d
+    LEFT JOIN document_versions v ON v.document_id = d.id AND v.version = ?2
+    LEFT JOIN blobs b ON b.hash = CASE
+      WHEN ?2 IS NULL OR COALESCE(d.version, 1) = ?2 THEN d.hash
+      ELSE v.hash
+    END
+    WHERE d.id = ?1
+    "#,
+  )
+  .bind(document_id)
+  .bind(version)
+  .fetch_optional(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch document: {}", e))?;
+
+  Ok(row.and_then(|row| {
+    let found: bool = row.try_get("found").unwrap_or(false);
+    found.then(|| ResolvedDocument {
+      name: row.try_get("name").unwrap_or_default(),
+      document_type: row.try_get("document_type").unwrap_or_default(),
+      data: row.try_get("data").unwrap_or_default(),
+    })
+  }))
+}
+
+/// Move the current content of a document into its history and store `data` as the next version
+async fn replace_content(
+  tx: &mut Transaction<'_, Sqlite>,
+  document_id: i64,
+  data: &[u8],
+  mime_type: &str,
+  claimed_mime_type: Option<&str>,
+  note: Option<&str>,
+) -> Result<i64, String> {
+  let archived = sqlx::query(
+    r#"
+    INSERT INTO document_versions (
+      document_id, version, mime_type, claimed_mime_type, size, page_count, hash, note, uploaded_at
+    )
+    SELECT id, COALESCE(version, 1), mime_type, claimed_mime_type, size, page_count, hash,
+      version_note, version_uploaded_at
+    FROM documents
+    WHERE id = ?
+    "#,
+  )
+  .bind(document_id)
+  .execute(&mut **tx)
+  .await
+  .map_err(|e| format!("Failed to archive current document version: {}", e))?;
+
+  if archived.rows_affected() == 0 {
+    return Err(format!("No document found with id {}", document_id));
+  }
+
+  let (size, page_count, hash) = document_metadata(Some(mime_type), data);
+  store_blob(&mut **tx, data).await?;
+
+  sqlx::query(
+    r#"
+    UPDATE documents
+    SET mime_type = ?, claimed_mime_type = ?, size = ?, page_count = ?, hash = ?,
+      version = COALESCE(version, 1) + 1, version_note = ?, version_uploaded_at = CURRENT_TIMESTAMP
+    WHERE id = ?
+    "#,
+  )
+  .bind(mime_type)
+  .bind(claimed_mime_type)
+  .bind(size)
+  .bind(page_count)
+  .bind(&hash)
+  .bind(note)
+  .bind(document_id)
+  .execute(&mut **tx)
+  .await
+  .map_err(|e| format!("Failed to store new document version: {}", e))?;
+
+  sqlx::query_scalar("SELECT version FROM documents WHERE id = ?")
+    .bind(document_id)
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|e| format!("Failed to read document version: {}", e))
+}
@@ -0,0 +1,1097 @@
+use crate::blobs::store_blob;
+use crate::document::document_metadata;
+use crate::document_text::index_document_text;
+use crate::form_templates::{find_template, store_template, FormTemplate};
+use crate::helpers::mime::{self, sniff_mime};
+use crate::helpers::pdf_forms::{fill_form, read_form, FormField, FormFieldKind, PdfForm};
+use crate::DB_POOL;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqlitePool};
+use std::collections::{BTreeMap, HashMap};
+
+/// Where a form field gets its value from
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FieldMapping {
+  /// A value from the applicant's data, e.g. `profile.phone`, `income_sources[0].employer_name`,
+  /// `additional_info.pets` or `listing.address`
+  Path {
+    path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transform: Option<ValueTransform>,
+  },
+  /// A fixed value
+  Literal { value: String },
+  /// Leave the field as it is
+  Skip,
+}
+
+/// How a value is rewritten before it goes into a field
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ValueTransform {
+  /// Reformat a `YYYY-MM-DD` date with a pattern made of `YYYY`, `YY`, `MM`, `M`, `DD` and `D`,
+  /// e.g. `MM/DD/YYYY`
+  Date {
+    format: String,
+  },
+  /// Put the digits of a phone number into the `#` of a pattern such as `(###) ###-####`
+  Phone {
+    format: String,
+  },
+  Uppercase,
+}
+
+impl ValueTransform {
+  pub fn validate(&self) -> Result<(), String> {
+    match self {
+      ValueTransform::Date { format } => {
+        if !format.contains(['Y', 'M', 'D']) {
+          return Err(format!("Date format \"{}\" has no YYYY, MM or DD", format));
+        }
+      }
+      ValueTransform::Phone { format } => {
+        if !format.contains('#') {
+          return Err(format!(
+            "Phone format \"{}\" has no # for the digits",
+            format
+          ));
+        }
+      }
+      ValueTransform::Uppercase => {}
+    }
+    Ok(())
+  }
+
+  /// Values that don't fit the transform, such as a date that isn't `YYYY-MM-DD`, are kept as
+  /// they are
+  pub fn apply(&self, value: &str) -> String {
+    match self {
+      ValueTransform::Date { format } => format_date(value, format),
+      ValueTransform::Phone { format } => format_phone(value, format),
+      ValueTransform::Uppercase => value.to_uppercase(),
+    }
+    .unwrap_or_else(|| value.to_string())
+  }
+}
+
+impl FieldMapping {
+  pub fn validate(&self) -> Result<(), String> {
+    match self {
+      FieldMapping::Path { path, transform } => {
+        if path.trim().is_empty() {
+          return Err("A field mapping has an empty path".to_string());
+        }
+        transform.as_ref().map_or(Ok(()), ValueTransform::validate)
+      }
+      FieldMapping::Literal { .. } | FieldMapping::Skip => Ok(()),
+    }
+  }
+}
+
+fn format_date(value: &str, format: &str) -> Option<String> {
+  let date = value.trim().get(..10)?;
+  let mut parts = date.splitn(3, '-').map(|part| part.parse::<u32>().ok());
+  let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+  if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+    return None;
+  }
+
+  let mut result = String::new();
+  let mut rest = format;
+  while let Some(c) = rest.chars().next() {
+    let (text, token) = if rest.starts_with("YYYY") {
+      (format!("{:04}", year), 4)
+    } else if rest.starts_with("YY") {
+      (format!("{:02}", year % 100), 2)
+    } else if rest.starts_with("MM") {
+      (format!("{:02}", month), 2)
+    } else if rest.starts_with("DD") {
+      (format!("{:02}", day), 2)
+    } else if c == 'M' {
+      (month.to_string(), 1)
+    } else if c == 'D' {
+      (day.to_string(), 1)
+    } else {
+      (c.to_string(), c.len_utf8())
+    };
+    result.push_str(&text);
+    rest = &rest[token..];
+  }
+  Some(result)
+}
+
+fn format_phone(value: &str, format: &str) -> Option<String> {
+  let mut digits: Vec<char> = value.chars().filter(char::is_ascii_digit).collect();
+  let slots = format.matches('#').count();
+  // Drop the country code of North American numbers written as +1 or 1-...
+  if digits.len() == slots + 1 && digits.first() == Some(&'1') {
+    digits.remove(0);
+  }
+  if digits.len() != slots {
+    return None;
+  }
+
+  let mut digits = digits.into_iter();
+  Some(
+    format
+      .chars()
+      .map(|c| {
+        if c == '#' {
+          digits.next().unwrap_or(c)
+        } else {
+          c
+        }
+      })
+      .collect(),
+  )
+}
+
+/// Why a field got the mapping it has
+#[derive(Serialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum MappingOrigin {
+  /// Given with the request
+  Request,
+  /// From the saved template for this form
+  Saved,
+  /// Guessed from the field name
+  Guessed,
+  /// Nothing fits, or the field cannot be filled
+  Unmapped,
+}
+
+/// How to fill a form
+#[derive(Deserialize, Default)]
+pub struct FormMappingProfile {
+  /// Listing the application is for, which `listing.*` paths read from
+  listing_id: Option<i64>,
+  /// Mappings by fully qualified field name. They take precedence over the form's saved template
+  /// and over guesses from field names.
+  #[serde(default)]
+  mappings: HashMap<String, FieldMapping>,
+  /// Only fill fields that have a mapping, instead of guessing from the names of the others
+  #[serde(default)]
+  skip_guesses: bool,
+  /// Draw the values into the pages and remove the form fields, so nothing stays editable
+  #[serde(default)]
+  flatten: bool,
+  /// Save `mappings` to the form's template, so they apply whenever it is filled again
+  #[serde(default)]
+  remember: bool,
+  /// Name of the new document; defaults to the original name with "(filled)"
+  name: Option<String>,
+}
+
+impl FormMappingProfile {
+  /// Fill only what the form's saved template maps
+  pub(crate) fn saved_only() -> Self {
+    FormMappingProfile {
+      skip_guesses: true,
+      ..Default::default()
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct MappedField {
+  name: String,
+  kind: FormFieldKind,
+  mapping: Option<FieldMapping>,
+  origin: MappingOrigin,
+  /// Value that is or would be written; `None` leaves the field as it is
+  value: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct FormPreview {
+  fingerprint: String,
+  page_count: u32,
+  /// Name of the saved template for this form, if there is one
+  template_name: Option<String>,
+  fields: Vec<MappedField>,
+}
+
+#[derive(Serialize)]
+pub struct FilledForm {
+  /// The new document holding the filled form
+  pub(crate) document_id: i64,
+  pub(crate) fingerprint: String,
+  fields: Vec<MappedField>,
+}
+
+/// List the fields of a fillable PDF document with the values `fill_pdf_form` would write
+#[tauri::command]
+pub async fn inspect_pdf_form(
+  document_id: i64,
+  mapping_profile: Option<FormMappingProfile>,
+) -> Result<FormPreview, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let profile = mapping_profile.unwrap_or_default();
+  let plan = plan_form(pool, document_id, &profile).await?;
+
+  Ok(FormPreview {
+    fingerprint: plan.fingerprint,
+    page_count: plan.page_count,
+    template_name: plan.template.map(|template| template.name),
+    fields: plan.fields,
+  })
+}
+
+/// Fill the AcroForm fields of a PDF document from the profile, income sources, additional info
+/// and listing, and save the result as a new document. Fields are mapped by the request, then by
+/// the saved template for the same form, then by guesses from their names.
+#[tauri::command]
+pub async fn fill_pdf_form(
+  document_id: i64,
+  mapping_profile: Option<FormMappingProfile>,
+) -> Result<FilledForm, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  fill_document(pool, document_id, mapping_profile.unwrap_or_default()).await
+}
+
+pub(crate) async fn fill_document(
+  pool: &SqlitePool,
+  document_id: i64,
+  profile: FormMappingProfile,
+) -> Result<FilledForm, String> {
+  let plan = plan_form(pool, document_id, &profile).await?;
+
+  let values: HashMap<String, String> = plan
+    .fields
+    .iter()
+    .filter_map(|field| Some((field.name.clone(), field.value.clone()?)))
+    .collect();
+  if values.is_empty() {
+    return Err("None of the form fields could be filled".to_string());
+  }
+
+  let flatten = profile.flatten;
+  let data = plan.data;
+  let filled = tokio::task::spawn_blocking(move || fill_form(&data, &values, flatten))
+    .await
+    .map_err(|e| format!("Form filling task failed: {}", e))?
+    .map_err(|e| format!("Failed to fill form: {}", e))?;
+
+  let name = profile
+    .name
+    .as_deref()
+    .map(str::trim)
+    .filter(|name| !name.is_empty())
+    .map(str::to_string)
+    .unwrap_or_else(|| format!("{} (filled)", plan.name));
+  let (size, page_count, hash) = document_metadata(Some(mime::PDF), &filled);
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+  store_blob(&mut *tx, &filled).await?;
+
+  let result = sqlx::query(
+    r#"
+    INSERT INTO documents (
+      name, document_type, mime_type, size, page_count, hash, version_uploaded_at
+    ) VALUES (?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP)
+    "#,
+  )
+  .bind(&name)
+  .bind(&plan.document_type)
+  .bind(mime::PDF)
+  .bind(size)
+  .bind(page_count)
+  .bind(&hash)
+  .execute(&mut *tx)
+  .await
+  .map_err(|e| format!("Failed to save filled form: {}", e))?;
+
+  if profile.remember && !profile.mappings.is_empty() {
+    let mut template = plan.template.unwrap_or_else(|| FormTemplate {
+      fingerprint: plan.fingerprint.clone(),
+      name: plan.name.clone(),
+      page_count: plan.page_count,
+      field_names: plan.field_names,
+      mappings: BTreeMap::new(),
+      auto_fill: true,
+      updated_at: None,
+    });
+    template.mappings.extend(profile.mappings);
+    store_template(&mut *tx, &template).await?;
+  }
+
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to save filled form: {}", e))?;
+
+  let document_id = result.last_insert_rowid();
+  index_document_text(pool, document_id).await;
+  Ok(FilledForm {
+    document_id,
+    fingerprint: plan.fingerprint,
+    fields: plan.fields,
+  })
+}
+
+/// A form document with the value chosen for each of its fields
+struct FormPlan {
+  name: String,
+  document_type: String,
+  data: Vec<u8>,
+  fingerprint: String,
+  page_count: u32,
+  field_names: Vec<String>,
+  /// The form's saved template before this request
+  template: Option<FormTemplate>,
+  fields: Vec<MappedField>,
+}
+
+async fn plan_form(
+  pool: &SqlitePool,
+  document_id: i64,
+  profile: &FormMappingProfile,
+) -> Result<FormPlan, String> {
+  for mapping in profile.mappings.values() {
+    mapping.validate()?;
+  }
+
+  let row = sqlx::query(
+    r#"
+    SELECT d.name, d.document_type, b.data
+    FROM documents d
+    LEFT JOIN blobs b ON b.hash = d.hash
+    WHERE d.id = ?
+    "#,
+  )
+  .bind(document_id)
+  .fetch_optional(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch document: {}", e))?
+  .ok_or_else(|| format!("No document found with id {}", document_id))?;
+  let data: Vec<u8> = row.try_get("data").unwrap_or_default();
+  let (data, form) = read_document_form(data).await?;
+
+  let template = find_template(pool, &form.fingerprint).await?;
+  let saved = template
+    .as_ref()
+    .map(|template| template.mappings.clone())
+    .unwrap_or_default();
+  let values = form_data(pool, profile.listing_id).await?;
+
+  let fields = form
+    .fields
+    .iter()
+    .map(|field| map_field(field, profile, &saved, &values))
+    .collect();
+
+  Ok(FormPlan {
+    name: row.try_get("name").unwrap_or_default(),
+    document_type: row.try_get("document_type").unwrap_or_default(),
+    data,
+    fingerprint: form.fingerprint,
+    page_count: form.page_count,
+    field_names: form.fields.into_iter().map(|field| field.name).collect(),
+    template,
+    fields,
+  })
+}
+
+/// Read the form fields of a PDF document, handing its content back for filling
+pub(crate) async fn read_document_form(data: Vec<u8>) -> Result<(Vec<u8>, PdfForm), String> {
+  if sniff_mime(&data) != Some(mime::PDF) {
+    return Err("Only PDF documents can have form fields".to_string());
+  }
+
+  let (data, form) = tokio::task::spawn_blocking(move || {
+    let form = read_form(&data);
+    (data, form)
+  })
+  .await
+  .map_err(|e| format!("Form reading task failed: {}", e))?;
+  let form = form.map_err(|e| format!("Failed to read form: {}", e))?;
+  Ok((data, form))
+}
+
+fn map_field(
+  field: &FormField,
+  profile: &FormMappingProfile,
+  saved: &BTreeMap<String, FieldMapping>,
+  values: &BTreeMap<String, String>,
+) -> MappedField {
+  let (mapping, origin) = if !field.kind.is_fillable() || field.read_only {
+    (None, MappingOrigin::Unmapped)
+  } else if let Some(mapping) = profile.mappings.get(&field.name) {
+    (Some(mapping.clone()), MappingOrigin::Request)
+  } else if let Some(mapping) = saved.get(&field.name) {
+    (Some(mapping.clone()), MappingOrigin::Saved)
+  } else if let Some(path) = guess_path(field, values).filter(|_| !profile.skip_guesses) {
+    let mapping = FieldMapping::Path {
+      path,
+      transform: None,
+    };
+    (Some(mapping), MappingOrigin::Guessed)
+  } else {
+    (None, MappingOrigin::Unmapped)
+  };
+
+  let value = match &mapping {
+    Some(FieldMapping::Path { path, transform }) => values.get(path).map(|value| match transform {
+      Some(transform) => transform.apply(value),
+      None => value.clone(),
+    }),
+    Some(FieldMapping::Literal { value }) => Some(value.clone()),
+    Some(FieldMapping::Skip) | None => None,
+  };
+
+  MappedField {
+    name: field.name.clone(),
+    kind: field.kind,
+    mapping,
+    origin,
+    value,
+  }
+}
+
+/// Everything a form can be filled with, by path. Blank values are left out.
+async fn form_data(
+  pool: &SqlitePool,
+  listing_id: Option<i64>,
+) -> Result<BTreeMap<String, String>, String> {
+  let mut values = BTreeMap::new();
+
+  let profile = sqlx::query("SELECT * FROM profile WHERE id = 1")
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch profile: {}", e))?;
+  if let Some(row) = profile {
+    for column in [
+      "fullname",
+      "date_of_birth",
+      "gender",
+      "phone",
+      "email",
+      "address",
+    ] {
+      if let Ok(Some(value)) = row.try_get::<Option<String>, _>(column) {
+        values.insert(format!("profile.{}", column), value);
+      }
+    }
+    if let Ok(Some(income)) = row.try_get::<Option<i64>, _>("monthly_income") {
+      if income > 0 {
+        values.insert("profile.monthly_income".to_string(), income.to_string());
+        values.insert(
+          "profile.annual_income".to_string(),
+          (income * 12).to_string(),
+        );
+      }
+    }
+  }
+
+  // Forms usually ask for the parts of the name and address separately
+  if let Some(fullname) = values.get("profile.fullname").cloned() {
+    let parts: Vec<&str> = fullname.split_whitespace().collect();
+    if parts.len() >= 2 {
+      values.insert("profile.first_name".to_string(), parts[0].to_string());
+      values.insert(
+        "profile.last_name".to_string(),
+        parts[parts.len() - 1].to_string(),
+      );
+      if parts.len() > 2 {
+        values.insert(
+          "profile.middle_name".to_string(),
+          parts[1..parts.len() - 1].join(" "),
+        );
+      }
+    }
+  }
+  if let Some(address) = values.get("profile.address").cloned() {
+    for (part, value) in address_parts(&address) {
+      values.insert(format!("profile.{}", part), value);
+    }
+  }
+
+  let income_sources = sqlx::query("SELECT * FROM income_sources ORDER BY id")
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch income sources: {}", e))?;
+  for (i, row) in income_sources.iter().enumerate() {
+    for column in [
+      "source",
+      "employer_name",
+      "job_title",
+      "employment_length",
+      "employer_contact",
+    ] {
+      if let Ok(Some(value)) = row.try_get::<Option<String>, _>(column) {
+        values.insert(format!("income_sources[{}].{}", i, column), value);
+      }
+    }
+  }
+
+  let additional_info = sqlx::query("SELECT label, value FROM additional_info ORDER BY id")
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch additional info: {}", e))?;
+  for row in additional_info {
+    let label: String = row.try_get("label").unwrap_or_default();
+    let value: String = row.try_get("value").unwrap_or_default();
+    let key = words(&label).join("_");
+    if !key.is_empty() {
+      values.insert(format!("additional_info.{}", key), value);
+    }
+  }
+
+  if let Some(listing_id) = listing_id {
+    let listing = sqlx::query(
+      r#"
+      SELECT l.*, c.security_deposit, c.application_fee, c.lease_length_months
+      FROM listings l
+      LEFT JOIN listing_costs c ON c.listing_id = l.id
+      WHERE l.id = ?
+      "#,
+    )
+    .bind(listing_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch listing: {}", e))?
+    .ok_or_else(|| format!("No listing found with id {}", listing_id))?;
+
+    for column in [
+      "address",
+      "contact_email",
+      "contact_phone",
+      "housing_type",
+      "lease_type",
+      "pet_policy",
+    ] {
+      if let Ok(Some(value)) = listing.try_get::<Option<String>, _>(column) {
+        values.insert(format!("listing.{}", column), value);
+      }
+    }
+    for column in [
+      "price_rent",
+      "upfront_fees",
+      "security_deposit",
+      "application_fee",
+      "bathrooms",
+    ] {
+      if let Ok(Some(amount)) = listing.try_get::<Option<f64>, _>(column) {
+        let amount = if column == "bathrooms" {
+          amount.to_string()
+        } else {
+          format!("{:.2}", amount)
+        };
+        values.insert(format!("listing.{}", column), amount);
+      }
+    }
+    for column in ["bedrooms", "square_footage", "lease_length_months"] {
+      if let Ok(Some(number)) = listing.try_get::<Option<i64>, _>(column) {
+        values.insert(format!("listing.{}", column), number.to_string());
+      }
+    }
+  }
+
+  let today: String = sqlx::query_scalar("SELECT date('now', 'localtime')")
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Failed to read the current date: {}", e))?;
+  values.insert("today".to_string(), today);
+
+  values.retain(|_, value| !value.trim().is_empty());
+  Ok(values)
+}
+
+/// Street, city, state and ZIP of a US style address such as
+/// "12 Oak St, Apt 4, Springfield, IL 62704"
+fn address_parts(address: &str) -> Vec<(&'static str, String)> {
+  let parts: Vec<&str> = address
+    .split(',')
+    .map(str::trim)
+    .filter(|part| !part.is_empty())
+    .collect();
+  if parts.len() < 3 {
+    return Vec::new();
+  }
+
+  let region = parts[parts.len() - 1];
+  let mut result = vec![
+    ("street", parts[..parts.len() - 2].join(", ")),
+    ("city", parts[parts.len() - 2].to_string()),
+  ];
+  match region.rsplit_once(' ') {
+    Some((state, zip)) if zip.chars().any(|c| c.is_ascii_digit()) => {
+      result.push(("state", state.trim().to_string()));
+      result.push(("zip", zip.to_string()));
+    }
+    _ => result.push(("state", region.to_string())),
+  }
+  result
+}
+
+/// Lower case words of a field name or label, splitting camelCase and digits
+fn words(text: &str) -> Vec<String> {
+  let mut words = Vec::new();
+  let mut word = String::new();
+  let mut previous: Option<char> = None;
+  for c in text.chars() {
+    let boundary = match previous {
+      Some(p) => {
+        !c.is_alphanumeric()
+          || (p.is_lowercase() && c.is_uppercase())
+          || (p.is_ascii_digit() != c.is_ascii_digit())
+      }
+      None => false,
+    };
+    if boundary && !word.is_empty() {
+      words.push(std::mem::take(&mut word));
+    }
+    if c.is_alphanumeric() {
+      word.extend(c.to_lowercase());
+    }
+    previous = Some(c);
+  }
+  if !word.is_empty() {
+    words.push(word);
+  }
+  words
+}
+
+/// A guess at what a field asks for. Every group needs a word in the field name; fields with any
+/// of the `unless` words are about something else.
+struct FieldRule {
+  groups: &'static [&'static [&'static str]],
+  unless: &'static [&'static str],
+  path: &'static str,
+}
+
+/// Words that mean a field is about someone other than the applicant
+const OTHER_PEOPLE: [&str; 15] = [
+  "landlord",
+  "reference",
+  "emergency",
+  "spouse",
+  "guarantor",
+  "cosigner",
+  "coapplicant",
+  "occupant",
+  "parent",
+  "relative",
+  "owner",
+  "manager",
+  "previous",
+  "prior",
+  "former",
+];
+
+/// Words that mean a field is about the applicant's employer rather than the applicant
+const EMPLOYER: [&str; 4] = ["employer", "company", "business", "work"];
+
+/// Words also matched at the end of a longer word, as in "firstname" or "homephone"
+const SUFFIX_WORDS: [&str; 8] = [
+  "name", "phone", "mail", "address", "date", "number", "income", "birth",
+];
+
+/// In order of precedence
+const FIELD_RULES: &[FieldRule] = &[
+  FieldRule {
+    groups: &[&["birth", "dob", "birthdate", "birthday"]],
+    unless: &[],
+    path: "profile.date_of_birth",
+  },
+  FieldRule {
+    groups: &[&["first", "given", "fname"], &["name", "fname"]],
+    unless: &[],
+    path: "profile.first_name",
+  },
+  FieldRule {
+    groups: &[&["middle"], &["name", "initial"]],
+    unless: &[],
+    path: "profile.middle_name",
+  },
+  FieldRule {
+    groups: &[
+      &["last", "surname", "family", "lname"],
+      &["name", "surname", "lname"],
+    ],
+    unless: &[],
+    path: "profile.last_name",
+  },
+  FieldRule {
+    groups: &[
+      &["length", "long", "years", "duration", "since"],
+      &["employ", "job", "work"],
+    ],
+    unless: &[],
+    path: "income_sources[0].employment_length",
+  },
+  FieldRule {
+    groups: &[
+      &["employer", "company", "work", "business", "supervisor"],
+      &["phone", "tel", "contact"],
+    ],
+    unless: &[],
+    path: "income_sources[0].employer_contact",
+  },
+  FieldRule {
+    groups: &[&["employer", "company", "business"]],
+    unless: &["address", "email", "fax"],
+    path: "income_sources[0].employer_name",
+  },
+  FieldRule {
+    groups: &[&["occupation", "position", "job", "title"]],
+    unless: &[],
+    path: "income_sources[0].job_title",
+  },
+  FieldRule {
+    groups: &[&["income"], &["source", "type"]],
+    unless: &[],
+    path: "income_sources[0].source",
+  },
+  FieldRule {
+    groups: &[
+      &["income", "salary", "wage", "wages", "earnings"],
+      &["annual", "annually", "yearly", "year"],
+    ],
+    unless: &[],
+    path: "profile.annual_income",
+  },
+  FieldRule {
+    groups: &[&["income", "salary", "wage", "wages", "earnings"]],
+    unless: &[],
+    path: "profile.monthly_income",
+  },
+  FieldRule {
+    groups: &[
+      &[
+        "property",
+        "premises",
+        "unit",
+        "apartment",
+        "rental",
+        "leased",
+        "dwelling",
+      ],
+      &["address", "location"],
+    ],
+    unless: &["current", "present", "previous", "prior"],
+    path: "listing.address",
+  },
+  FieldRule {
+    groups: &[&["deposit"]],
+    unless: &["pet"],
+    path: "listing.security_deposit",
+  },
+  FieldRule {
+    groups: &[&["application"], &["fee"]],
+    unless: &[],
+    path: "listing.application_fee",
+  },
+  FieldRule {
+    groups: &[
+      &["lease", "term"],
+      &["term", "length", "months", "duration"],
+    ],
+    unless: &[],
+    path: "listing.lease_length_months",
+  },
+  FieldRule {
+    groups: &[&["rent"]],
+    unless: &[
+      "current", "present", "previous", "prior", "history", "address",
+    ],
+    path: "listing.price_rent",
+  },
+  FieldRule {
+    groups: &[&["bedrooms", "bedroom", "beds"]],
+    unless: &[],
+    path: "listing.bedrooms",
+  },
+  FieldRule {
+    groups: &[&["email", "mail"]],
+    unless: &[],
+    path: "profile.email",
+  },
+  FieldRule {
+    groups: &[&["phone", "tel", "cell", "mobile"]],
+    unless: &["fax"],
+    path: "profile.phone",
+  },
+  FieldRule {
+    groups: &[&["street"]],
+    unless: &[],
+    path: "profile.street",
+  },
+  FieldRule {
+    groups: &[&["city", "town"]],
+    unless: &[],
+    path: "profile.city",
+  },
+  FieldRule {
+    groups: &[&["state", "province"]],
+    unless: &[],
+    path: "profile.state",
+  },
+  FieldRule {
+    groups: &[&["zip", "postal", "postcode"]],
+    unless: &[],
+    path: "profile.zip",
+  },
+  FieldRule {
+    groups: &[&["address", "residence"]],
+    unless: &[],
+    path: "profile.address",
+  },
+  FieldRule {
+    groups: &[&["gender", "sex"]],
+    unless: &[],
+    path: "profile.gender",
+  },
+  FieldRule {
+    groups: &[&["name", "applicant"]],
+    unless: &[
+      "signature",
+      "sign",
+      "signed",
+      "employer",
+      "company",
+      "business",
+      "property",
+    ],
+    path: "profile.fullname",
+  },
+  FieldRule {
+    groups: &[&["date", "dated", "today"]],
+    unless: &[
+      "birth",
+      "move",
+      "movein",
+      "start",
+      "end",
+      "hire",
+      "hired",
+      "expiration",
+      "expires",
+      "lease",
+    ],
+    path: "today",
+  },
+];
+
+fn word_matches(word: &str, wanted: &str) -> bool {
+  word == wanted
+    || (wanted.len() >= 3 && word.starts_with(wanted))
+    || (SUFFIX_WORDS.contains(&wanted) && word.ends_with(wanted))
+}
+
+/// Guess the path a text or choice field should be filled from by the words in its name
+fn guess_path(field: &FormField, values: &BTreeMap<String, String>) -> Option<String> {
+  if !matches!(field.kind, FormFieldKind::Text | FormFieldKind::Choice) {
+    return None;
+  }
+
+  // Use the last part of hierarchical names such as "form1[0].page1[0].FirstName[0]"
+  let leaf = field.name.rsplit('.').next().unwrap_or(&field.name);
+  let field_words = words(leaf.split('[').next().unwrap_or(leaf));
+  let has = |wanted: &str| field_words.iter().any(|word| word_matches(word, wanted));
+
+  // Additional info the user entered under the same label as the field
+  let compact = field_words.join("_");
+  let additional_info = format!("additional_info.{}", compact);
+  if values.contains_key(&additional_info) {
+    return Some(additional_info);
+  }
+
+  // "Employer 2" is about the second income source
+  let index = field_words
+    .last()
+    .and_then(|word| word.parse::<usize>().ok())
+    .filter(|n| (1..=9).contains(n))
+    .map(|n| n - 1);
+
+  let rule = FIELD_RULES.iter().find(|rule| {
+    let about_profile = rule.path.starts_with("profile.");
+    let about_applicant = about_profile || rule.path.starts_with("income_sources");
+    let excluded = rule.unless.iter().any(|w| has(w))
+      || (about_applicant && OTHER_PEOPLE.iter().any(|w| has(w)))
+      || (about_profile && EMPLOYER.iter().any(|w| has(w)));
+    rule.groups.iter().all(|group| group.iter().any(|w| has(w))) && !excluded
+  });
+  if let Some(rule) = rule {
+    return Some(match index {
+      Some(index) if rule.path.starts_with("income_sources[0]") => {
+        rule.path.replacen("[0]", &format!("[{}]", index), 1)
+      }
+      _ => rule.path.to_string(),
+    });
+  }
+
+  // Labels that are part of a longer field name, e.g. "Pets" in "Do you have pets"
+  values
+    .keys()
+    .filter_map(|key| key.strip_prefix("additional_info."))
+    .filter(|label| label.len() >= 4)
+    .find(|label| compact.contains(*label))
+    .map(|label| format!("additional_info.{}", label))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn text_field(name: &str) -> FormField {
+    FormField {
+      name: name.to_string(),
+      kind: FormFieldKind::Text,
+      value: None,
+      options: Vec::new(),
+      read_only: false,
+    }
+  }
+
+  fn guess(name: &str) -> Option<String> {
+    guess_path(&text_field(name), &BTreeMap::new())
+  }
+
+  #[test]
+  fn dates_follow_the_format() {
+    assert_eq!(
+      format_date("1990-04-07", "MM/DD/YYYY").as_deref(),
+      Some("04/07/1990")
+    );
+    assert_eq!(
+      format_date("1990-04-07", "M/D/YY").as_deref(),
+      Some("4/7/90")
+    );
+    assert_eq!(
+      format_date("2024-12-31T10:00:00Z", "DD.MM.YYYY").as_deref(),
+      Some("31.12.2024")
+    );
+    assert_eq!(format_date("1990-13-07", "MM/DD/YYYY"), None);
+    assert_eq!(format_date("04/07/1990", "MM/DD/YYYY"), None);
+    assert_eq!(format_date("1990-04", "MM/DD/YYYY"), None);
+  }
+
+  #[test]
+  fn phones_fill_the_format() {
+    assert_eq!(
+      format_phone("555.123.4567", "(###) ###-####").as_deref(),
+      Some("(555) 123-4567")
+    );
+    assert_eq!(
+      format_phone("+1 555 123 4567", "###-###-####").as_deref(),
+      Some("555-123-4567")
+    );
+    assert_eq!(format_phone("123 4567", "(###) ###-####"), None);
+    assert_eq!(format_phone("+44 20 7946 0958", "(###) ###-####"), None);
+  }
+
+  #[test]
+  fn addresses_split_into_parts() {
+    let parts = |address| -> Vec<(&str, String)> { address_parts(address) };
+    assert_eq!(
+      parts("12 Oak St, Apt 4, Springfield, IL 62704"),
+      [
+        ("street", "12 Oak St, Apt 4".to_string()),
+        ("city", "Springfield".to_string()),
+        ("state", "IL".to_string()),
+        ("zip", "62704".to_string()),
+      ]
+    );
+    assert_eq!(
+      parts("1 Main St, Portland, Oregon"),
+      [
+        ("street", "1 Main St".to_string()),
+        ("city", "Portland".to_string()),
+        ("state", "Oregon".to_string()),
+      ]
+    );
+    assert!(parts("1 Main St, Portland").is_empty());
+  }
+
+  #[test]
+  fn words_split_on_case_digits_and_punctuation() {
+    assert_eq!(words("applicantFirstName"), ["applicant", "first", "name"]);
+    assert_eq!(words("Employer2 Phone"), ["employer", "2", "phone"]);
+    assert_eq!(words("E-mail_Address"), ["e", "mail", "address"]);
+    assert_eq!(words("DOB"), ["dob"]);
+    assert!(words("  ").is_empty());
+  }
+
+  #[test]
+  fn fields_are_guessed_from_their_names() {
+    let cases = [
+      ("form1[0].page1[0].FirstName[0]", Some("profile.first_name")),
+      ("Date of Birth", Some("profile.date_of_birth")),
+      ("Home Phone", Some("profile.phone")),
+      ("E-mail", Some("profile.email")),
+      ("Monthly Rent", Some("listing.price_rent")),
+      ("Current Rent", None),
+      ("Applicant Signature Date", Some("today")),
+      ("Move-in Date", None),
+      ("Signature", None),
+    ];
+    for (name, path) in cases {
+      assert_eq!(guess(name).as_deref(), path, "{}", name);
+    }
+  }
+
+  #[test]
+  fn other_people_and_employers_are_not_the_applicant() {
+    let cases = [
+      ("Landlord Name", None),
+      ("Landlord Phone", None),
+      ("Reference 1 Name", None),
+      ("Emergency Contact Phone", None),
+      ("Spouse Employer", None),
+      ("Employer Name", Some("income_sources[0].employer_name")),
+      ("Employer Phone", Some("income_sources[0].employer_contact")),
+      ("Employer Address", None),
+      ("Company Email", None),
+      ("Work City", None),
+      ("Work Street", None),
+    ];
+    for (name, path) in cases {
+      assert_eq!(guess(name).as_deref(), path, "{}", name);
+    }
+  }
+
+  #[test]
+  fn numbered_fields_pick_their_income_source() {
+    assert_eq!(
+      guess("Employer 2").as_deref(),
+      Some("income_sources[1].employer_name")
+    );
+    assert_eq!(
+      guess("Occupation3").as_deref(),
+      Some("income_sources[2].job_title")
+    );
+    assert_eq!(guess("Phone 2").as_deref(), Some("profile.phone"));
+  }
+
+  #[test]
+  fn additional_info_labels_win() {
+    let values: BTreeMap<String, String> = [
+      ("additional_info.pets", "1 cat"),
+      ("additional_info.first_name", "Sam"),
+    ]
+    .into_iter()
+    .map(|(k, v)| (k.to_string(), v.to_string()))
+    .collect();
+    let guess = |name| guess_path(&text_field(name), &values);
+    assert_eq!(
+      guess("First Name").as_deref(),
+      Some("additional_info.first_name")
+    );
+    assert_eq!(
+      guess("Do you have pets").as_deref(),
+      Some("additional_info.pets")
+    );
+
+    let mut signature = text_field("Pets");
+    signature.kind = FormFieldKind::Signature;
+    assert_eq!(guess_path(&signature, &values), None);
+  }
+}
@@ -0,0 +1,327 @@
+use crate::form_fill::{fill_document, read_document_form, FieldMapping, FormMappingProfile};
+use crate::helpers::pdf_forms::form_fingerprint;
+use crate::DB_POOL;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, SqliteConnection, SqlitePool};
+use std::collections::{BTreeMap, HashMap};
+use tauri::{AppHandle, Emitter};
+
+/// Event emitted with a [`FormTemplateApplied`] payload when an uploaded PDF is filled from its
+/// saved template
+pub const FORM_TEMPLATE_APPLIED_EVENT: &str = "form-template-applied";
+
+/// Version of the exported template format
+const TEMPLATE_FORMAT_VERSION: u32 = 1;
+
+/// Field mappings the user approved for a fillable PDF form. Forms are recognized by their
+/// fingerprint, a hash of the field names and page count, so the template applies to every copy
+/// of the same form.
+#[derive(Serialize, Clone)]
+pub struct FormTemplate {
+  pub(crate) fingerprint: String,
+  pub(crate) name: String,
+  pub(crate) page_count: u32,
+  pub(crate) field_names: Vec<String>,
+  pub(crate) mappings: BTreeMap<String, FieldMapping>,
+  /// Fill the form as soon as a copy of it is uploaded
+  pub(crate) auto_fill: bool,
+  pub(crate) updated_at: Option<String>,
+}
+
+/// A template as it is exported and imported. The fingerprint is left out and computed again on
+/// import, so a file that was edited by hand still matches the form it describes.
+#[derive(Serialize, Deserialize)]
+struct SharedFormTemplate {
+  version: u32,
+  name: String,
+  page_count: u32,
+  field_names: Vec<String>,
+  mappings: BTreeMap<String, FieldMapping>,
+}
+
+#[derive(Serialize, Clone)]
+struct FormTemplateApplied {
+  /// The uploaded form
+  document_id: i64,
+  /// The new document holding the filled form
+  filled_document_id: i64,
+  fingerprint: String,
+  template_name: String,
+}
+
+#[tauri::command]
+pub async fn get_form_templates() -> Result<Vec<FormTemplate>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let rows = sqlx::query("SELECT * FROM form_templates ORDER BY name COLLATE NOCASE")
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch form templates: {}", e))?;
+  rows.iter().map(template_from_row).collect()
+}
+
+/// Save the mappings the user approved for the form in a PDF document as its template, replacing
+/// the mappings of an existing template for the same form
+#[tauri::command]
+pub async fn save_form_template(
+  document_id: i64,
+  name: Option<String>,
+  mappings: HashMap<String, FieldMapping>,
+  auto_fill: Option<bool>,
+) -> Result<FormTemplate, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let row = sqlx::query(
+    r#"
+    SELECT d.name, b.data
+    FROM documents d
+    LEFT JOIN blobs b ON b.hash = d.hash
+    WHERE d.id = ?
+    "#,
+  )
+  .bind(document_id)
+  .fetch_optional(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch document: {}", e))?
+  .ok_or_else(|| format!("No document found with id {}", document_id))?;
+  let data: Vec<u8> = row.try_get("data").unwrap_or_default();
+  let (_, form) = read_document_form(data).await?;
+
+  let existing = find_template(pool, &form.fingerprint).await?;
+  let name = name
+    .as_deref()
+    .map(str::trim)
+    .filter(|name| !name.is_empty())
+    .map(str::to_string)
+    .or_else(|| existing.as_ref().map(|template| template.name.clone()))
+    .unwrap_or_else(|| row.try_get("name").unwrap_or_default());
+  let auto_fill = auto_fill
+    .or_else(|| existing.as_ref().map(|template| template.auto_fill))
+    .unwrap_or(true);
+
+  let template = FormTemplate {
+    fingerprint: form.fingerprint,
+    name,
+    page_count: form.page_count,
+    field_names: form.fields.into_iter().map(|field| field.name).collect(),
+    mappings: mappings.into_iter().collect(),
+    auto_fill,
+    updated_at: None,
+  };
+  validate_template(&template)?;
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+  store_template(&mut *tx, &template).await?;
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to save form template: {}", e))?;
+  find_template(pool, &template.fingerprint)
+    .await?
+    .ok_or_else(|| "Form template was not saved".to_string())
+}
+
+#[tauri::command]
+pub async fn delete_form_template(fingerprint: String) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+  sqlx::query("DELETE FROM form_templates WHERE fingerprint = ?")
+    .bind(&fingerprint)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to delete form template: {}", e))?;
+  Ok(())
+}
+
+/// A template as JSON, to share with someone applying to the same property manager
+#[tauri::command]
+pub async fn export_form_template(fingerprint: String) -> Result<String, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let template = find_template(pool, &fingerprint)
+    .await?
+    .ok_or_else(|| format!("No form template found with fingerprint {}", fingerprint))?;
+  let shared = SharedFormTemplate {
+    version: TEMPLATE_FORMAT_VERSION,
+    name: template.name,
+    page_count: template.page_count,
+    field_names: template.field_names,
+    mappings: template.mappings,
+  };
+  serde_json::to_string_pretty(&shared)
+    .map_err(|e| format!("Failed to export form template: {}", e))
+}
+
+/// Save a template exported with `export_form_template`, replacing one for the same form
+#[tauri::command]
+pub async fn import_form_template(json: String) -> Result<FormTemplate, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let shared: SharedFormTemplate =
+    serde_json::from_str(&json).map_err(|e| format!("Not a form template: {}", e))?;
+  if shared.version > TEMPLATE_FORMAT_VERSION {
+    return Err(format!(
+      "Form template version {} is newer than this app supports",
+      shared.version
+    ));
+  }
+
+  let template = FormTemplate {
+    fingerprint: form_fingerprint(
+      shared.field_names.iter().map(String::as_str),
+      shared.page_count,
+    ),
+    name: shared.name.trim().to_string(),
+    page_count: shared.page_count,
+    field_names: shared.field_names,
+    mappings: shared.mappings,
+    auto_fill: true,
+    updated_at: None,
+  };
+  if template.name.is_empty() {
+    return Err("Form template has no name".to_string());
+  }
+  validate_template(&template)?;
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+  store_template(&mut *tx, &template).await?;
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to save form template: {}", e))?;
+  find_template(pool, &template.fingerprint)
+    .await?
+    .ok_or_else(|| "Form template was not saved".to_string())
+}
+
+/// Fill a newly uploaded PDF from the saved template for its form, if there is one that should
+/// be applied automatically. Problems are logged rather than failing the upload.
+pub(crate) async fn apply_saved_template(
+  app: &AppHandle,
+  pool: &SqlitePool,
+  document_id: i64,
+  data: Vec<u8>,
+) {
+  match fill_from_saved_template(pool, document_id, data).await {
+    Ok(Some(applied)) => {
+      if let Err(e) = app.emit(FORM_TEMPLATE_APPLIED_EVENT, applied) {
+        println!(
+          "Failed to emit form template event for document {}: {}",
+          document_id, e
+        );
+      }
+    }
+    Ok(None) => {}
+    Err(e) => println!(
+      "Failed to apply form template to document {}: {}",
+      document_id, e
+    ),
+  }
+}
+
+async fn fill_from_saved_template(
+  pool: &SqlitePool,
+  document_id: i64,
+  data: Vec<u8>,
+) -> Result<Option<FormTemplateApplied>, String> {
+  // PDFs without form fields are the common case and not an error
+  let Ok((_, form)) = read_document_form(data).await else {
+    return Ok(None);
+  };
+  let template = match find_template(pool, &form.fingerprint).await? {
+    Some(template) if template.auto_fill && !template.mappings.is_empty() => template,
+    _ => return Ok(None),
+  };
+
+  let filled = fill_document(pool, document_id, FormMappingProfile::saved_only()).await?;
+  Ok(Some(FormTemplateApplied {
+    document_id,
+    filled_document_id: filled.document_id,
+    fingerprint: filled.fingerprint,
+    template_name: template.name,
+  }))
+}
+
+pub(crate) async fn find_template(
+  pool: &SqlitePool,
+  fingerprint: &str,
+) -> Result<Option<FormTemplate>, String> {
+  let row = sqlx::query("SELECT * FROM form_templates WHERE fingerprint = ?")
+    .bind(fingerprint)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch form template: {}", e))?;
+  row.as_ref().map(template_from_row).transpose()
+}
+
+/// Insert a template or replace the one for the same form
+pub(crate) async fn store_template(
+  conn: &mut SqliteConnection,
+  template: &FormTemplate,
+) -> Result<(), String> {
+  let field_names = serde_json::to_string(&template.field_names)
+    .map_err(|e| format!("Failed to serialize form field names: {}", e))?;
+  let mappings = serde_json::to_string(&template.mappings)
+    .map_err(|e| format!("Failed to serialize form mappings: {}", e))?;
+
+  sqlx::query(
+    r#"
+    INSERT INTO form_templates (fingerprint, name, page_count, field_names, mappings, auto_fill)
+    VALUES (?, ?, ?, ?, ?, ?)
+    ON CONFLICT(fingerprint) DO UPDATE SET
+      name = excluded.name,
+      mappings = excluded.mappings,
+      auto_fill = excluded.auto_fill,
+      updated_at = CURRENT_TIMESTAMP
+    "#,
+  )
+  .bind(&template.fingerprint)
+  .bind(&template.name)
+  .bind(template.page_count)
+  .bind(field_names)
+  .bind(mappings)
+  .bind(template.auto_fill)
+  .execute(conn)
+  .await
+  .map_err(|e| format!("Failed to save form template: {}", e))?;
+  Ok(())
+}
+
+fn template_from_row(row: &sqlx::sqlite::SqliteRow) -> Result<FormTemplate, String> {
+  let field_names: String = row.try_get("field_names").unwrap_or_default();
+  let mappings: String = row.try_get("mappings").unwrap_or_default();
+
+  Ok(FormTemplate {
+    fingerprint: row.try_get("fingerprint").unwrap_or_default(),
+    name: row.try_get("name").unwrap_or_default(),
+    page_count: row.try_get("page_count").unwrap_or_default(),
+    field_names: serde_json::from_str(&field_names)
+      .map_err(|e| format!("Saved form field names are invalid: {}", e))?,
+    mappings: serde_json::from_str(&mappings)
+      .map_err(|e| format!("Saved form mappings are invalid: {}", e))?,
+    auto_fill: row.try_get("auto_fill").unwrap_or(true),
+    updated_at: row.try_get("updated_at").ok(),
+  })
+}
+
+/// Every mapping must be for one of the form's fields and have a usable transform
+fn validate_template(template: &FormTemplate) -> Result<(), String> {
+  if template.field_names.is_empty() {
+    return Err("Form template has no fields".to_string());
+  }
+  for (field, mapping) in &template.mappings {
+    if !template.field_names.contains(field) {
+      return Err(format!("The form has no field named \"{}\"", field));
+    }
+    mapping.validate()?;
+  }
+  Ok(())
+}
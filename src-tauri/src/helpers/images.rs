@@ -0,0 +1,749 @@
+use super::mime::{sniff_mime, HEIC, HEIF, TIFF};
+use anyhow::Result;
+use image::metadata::Orientation;
+use image::{DynamicImage, GrayImage, ImageDecoder, ImageFormat, ImageReader, RgbImage, RgbaImage};
+use std::io::Cursor;
+use tiff::decoder::{Decoder as TiffDecoder, DecodingResult};
+use tiff::tags::Tag;
+
+/// Longest edge of the small thumbnail used in listing cards and galleries
+pub const THUMBNAIL_SMALL: u32 = 256;
+/// Longest edge of the medium thumbnail used in detail views
+pub const THUMBNAIL_MEDIUM: u32 = 1024;
+
+/// Decode an image blob and apply its EXIF orientation, so phone photos come out upright.
+/// Multi-page TIFFs give their first page; use [`decode_pages`] for all of them.
+pub fn decode_oriented(blob: &[u8]) -> Result<DynamicImage> {
+  if matches!(sniff_mime(blob), Some(HEIC | HEIF)) {
+    return decode_heif(blob);
+  }
+
+  let mut decoder = ImageReader::new(Cursor::new(blob))
+    .with_guessed_format()
+    .map_err(|e| anyhow::anyhow!("Failed to read image: {}", e))?
+    .into_decoder()
+    .map_err(|e| anyhow::anyhow!("Unsupported image format: {}", e))?;
+
+  let orientation = decoder
+    .orientation()
+    .map_err(|e| anyhow::anyhow!("Failed to read image orientation: {}", e))?;
+
+  let mut image = DynamicImage::from_decoder(decoder)
+    .map_err(|e| anyhow::anyhow!("Failed to decode image: {}", e))?;
+  image.apply_orientation(orientation);
+
+  Ok(image)
+}
+
+/// Decode every page of an image blob, upright. Multi-page TIFFs such as scanner and fax output
+/// give one image per page; every other format gives a single image.
+pub fn decode_pages(blob: &[u8]) -> Result<Vec<DynamicImage>> {
+  if sniff_mime(blob) != Some(TIFF) {
+    return Ok(vec![decode_oriented(blob)?]);
+  }
+
+  let mut decoder = TiffDecoder::new(Cursor::new(blob))
+    .map_err(|e| anyhow::anyhow!("Failed to read TIFF: {}", e))?;
+  let mut pages = Vec::new();
+  loop {
+    let page = decode_tiff_page(&mut decoder)
+      .map_err(|e| anyhow::anyhow!("TIFF page {}: {}", pages.len() + 1, e))?;
+    pages.push(page);
+
+    if !decoder.more_images() {
+      break;
+    }
+    decoder
+      .next_image()
+      .map_err(|e| anyhow::anyhow!("Failed to read TIFF page {}: {}", pages.len() + 1, e))?;
+  }
+
+  Ok(pages)
+}
+
+/// Number of pages in an image blob, counted without decoding any pixels
+pub fn image_page_count(blob: &[u8]) -> Result<u32> {
+  if sniff_mime(blob) != Some(TIFF) {
+    return Ok(1);
+  }
+
+  let mut decoder = TiffDecoder::new(Cursor::new(blob))
+    .map_err(|e| anyhow::anyhow!("Failed to read TIFF: {}", e))?;
+  let mut pages = 1;
+  while decoder.more_images() {
+    decoder
+      .next_image()
+      .map_err(|e| anyhow::anyhow!("Failed to read TIFF page {}: {}", pages + 1, e))?;
+    pages += 1;
+  }
+  Ok(pages)
+}
+
+/// Decode the TIFF page the decoder is positioned on and apply its orientation tag
+fn decode_tiff_page(decoder: &mut TiffDecoder<Cursor<&[u8]>>) -> Result<DynamicImage> {
+  let (width, height) = decoder
+    .dimensions()
+    .map_err(|e| anyhow::anyhow!("Failed to read dimensions: {}", e))?;
+  let color_type = decoder
+    .colortype()
+    .map_err(|e| anyhow::anyhow!("Failed to read color type: {}", e))?;
+  let orientation = decoder
+    .find_tag_unsigned::<u16>(Tag::Orientation)
+    .ok()
+    .flatten()
+    .and_then(|value| Orientation::from_exif(value.min(255) as u8))
+    .unwrap_or(Orientation::NoTransforms);
+
+  let data = decoder
+    .read_image()
+    .map_err(|e| anyhow::anyhow!("Failed to decode: {}", e))?;
+  let invalid = || anyhow::anyhow!("Pixel data does not match the page size");
+
+  let mut image = match (color_type, data) {
+    // Bilevel scans pack eight pixels per byte, each row padded to a whole byte
+    (tiff::ColorType::Gray(1), DecodingResult::U8(bits)) => {
+      let row_bytes = (width as usize).div_ceil(8);
+      let mut pixels = Vec::with_capacity(width as usize * height as usize);
+      for row in bits.chunks_exact(row_bytes).take(height as usize) {
+        for x in 0..width as usize {
+          let bit = (row[x / 8] >> (7 - x % 8)) & 1;
+          pixels.push(if bit == 1 { 255 } else { 0 });
+        }
+      }
+      DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, pixels).ok_or_else(invalid)?)
+    }
+    (tiff::ColorType::Gray(8), DecodingResult::U8(pixels)) => {
+      DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, pixels).ok_or_else(invalid)?)
+    }
+    (tiff::ColorType::Gray(16), DecodingResult::U16(pixels)) => DynamicImage::ImageLuma16(
+      image::ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?,
+    ),
+    (tiff::ColorType::GrayA(8), DecodingResult::U8(pixels)) => DynamicImage::ImageLumaA8(
+      image::ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?,
+    ),
+    (tiff::ColorType::RGB(8), DecodingResult::U8(pixels)) => {
+      DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels).ok_or_else(invalid)?)
+    }
+    (tiff::ColorType::RGB(16), DecodingResult::U16(pixels)) => DynamicImage::ImageRgb16(
+      image::ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?,
+    ),
+    (tiff::ColorType::RGBA(8), DecodingResult::U8(pixels)) => {
+      DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, pixels).ok_or_else(invalid)?)
+    }
+    (tiff::ColorType::RGBA(16), DecodingResult::U16(pixels)) => DynamicImage::ImageRgba16(
+      image::ImageBuffer::from_raw(width, height, pixels).ok_or_else(invalid)?,
+    ),
+    (tiff::ColorType::CMYK(8), DecodingResult::U8(pixels)) => {
+      let rgb = pixels
+        .chunks_exact(4)
+        .flat_map(|cmyk| {
+          let k = 255 - cmyk[3] as u16;
+          [0, 1, 2].map(|i| ((255 - cmyk[i] as u16) * k / 255) as u8)
+        })
+        .collect();
+      DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, rgb).ok_or_else(invalid)?)
+    }
+    (color_type, _) => {
+      return Err(anyhow::anyhow!(
+        "Unsupported TIFF color type {:?}",
+        color_type
+      ))
+    }
+  };
+  image.apply_orientation(orientation);
+
+  Ok(image)
+}
+
+/// Decode the primary image of a HEIC/HEIF file. libheif applies the rotation and mirroring
+/// stored in the file, so the result is upright.
+#[cfg(feature = "heic")]
+fn decode_heif(blob: &[u8]) -> Result<DynamicImage> {
+  use libheif_rs::{ColorSpace, HeifContext, LibHeif, RgbChroma};
+
+  let lib_heif = LibHeif::new();
+  let context = HeifContext::read_from_bytes(blob)
+    .map_err(|e| anyhow::anyhow!("Failed to read HEIC image: {}", e))?;
+  let handle = context
+    .primary_image_handle()
+    .map_err(|e| anyhow::anyhow!("HEIC file has no primary image: {}", e))?;
+
+  let has_alpha = handle.has_alpha_channel();
+  let chroma = if has_alpha {
+    RgbChroma::Rgba
+  } else {
+    RgbChroma::Rgb
+  };
+  let decoded = lib_heif
+    .decode(&handle, ColorSpace::Rgb(chroma), None)
+    .map_err(|e| anyhow::anyhow!("Failed to decode HEIC image: {}", e))?;
+  let plane = decoded
+    .planes()
+    .interleaved
+    .ok_or_else(|| anyhow::anyhow!("HEIC image has no interleaved RGB plane"))?;
+
+  // Rows may be padded past the last pixel
+  let row_len = plane.width as usize * if has_alpha { 4 } else { 3 };
+  let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+  for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+    pixels.extend_from_slice(&row[..row_len]);
+  }
+
+  let invalid = || anyhow::anyhow!("HEIC pixel data does not match the image size");
+  Ok(if has_alpha {
+    DynamicImage::ImageRgba8(
+      RgbaImage::from_raw(plane.width, plane.height, pixels).ok_or_else(invalid)?,
+    )
+  } else {
+    DynamicImage::ImageRgb8(
+      RgbImage::from_raw(plane.width, plane.height, pixels).ok_or_else(invalid)?,
+    )
+  })
+}
+
+#[cfg(not(feature = "heic"))]
+fn decode_heif(_blob: &[u8]) -> Result<DynamicImage> {
+  Err(anyhow::anyhow!(
+    "HEIC/HEIF images are not supported in this build; convert them to JPEG first"
+  ))
+}
+
+/// Encode an image as a baseline JPEG. Alpha is dropped and no metadata is written.
+pub fn encode_jpeg(image: &DynamicImage, quality: u8) -> Result<Vec<u8>> {
+  let mut bytes = Vec::new();
+  image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, quality)
+    .encode_image(&image.to_rgb8())
+    .map_err(|e| anyhow::anyhow!("Failed to encode JPEG: {}", e))?;
+  Ok(bytes)
+}
+
+/// Write pages as a Deflate-compressed multi-page TIFF, grayscale pages as grayscale and the
+/// rest as RGB. Alpha is dropped and no metadata is written.
+pub fn encode_tiff_pages(pages: &[DynamicImage]) -> Result<Vec<u8>> {
+  use tiff::encoder::compression::DeflateLevel;
+  use tiff::encoder::{colortype, Compression, TiffEncoder};
+
+  let mut bytes = Vec::new();
+  let mut encoder = TiffEncoder::new(Cursor::new(&mut bytes))
+    .map_err(|e| anyhow::anyhow!("Failed to create TIFF: {}", e))?
+    .with_compression(Compression::Deflate(DeflateLevel::Balanced));
+  for (i, page) in pages.iter().enumerate() {
+    let written = if page.color().has_color() {
+      let rgb = page.to_rgb8();
+      encoder.write_image::<colortype::RGB8>(rgb.width(), rgb.height(), rgb.as_raw())
+    } else {
+      let gray = page.to_luma8();
+      encoder.write_image::<colortype::Gray8>(gray.width(), gray.height(), gray.as_raw())
+    };
+    written.map_err(|e| anyhow::anyhow!("Failed to encode TIFF page {}: {}", i + 1, e))?;
+  }
+  Ok(bytes)
+}
+
+/// Downscale an image so its longest edge is at most `max_edge` and encode it as JPEG
+pub fn jpeg_thumbnail(image: &DynamicImage, max_edge: u32) -> Result<Vec<u8>> {
+  let thumbnail = if image.width() > max_edge || image.height() > max_edge {
+    image.thumbnail(max_edge, max_edge)
+  } else {
+    image.clone()
+  };
+  encode_jpeg(&thumbnail, 80)
+}
+
+/// MIME type of an image blob, based on its magic bytes
+pub fn image_mime_type(blob: &[u8]) -> Option<&'static str> {
+  image::guess_format(blob)
+    .ok()
+    .map(|format| format.to_mime_type())
+}
+
+/// Remove metadata that can identify where or by whom a photo was taken (EXIF including GPS,
+/// XMP, IPTC and comments) and bake the EXIF orientation into the pixels. Metadata is removed
+/// from JPEG, PNG and WebP files without re-encoding them; images that need rotating are
+/// re-encoded, JPEGs as JPEG and everything else as PNG. BMPs are converted to PNG, which
+/// every viewer shows and is far smaller. HEIC/HEIF photos are converted to JPEG, or rejected
+/// in builds without the `heic` feature, and TIFFs are rewritten page by page, as their
+/// metadata sits among the image data. Other formats are returned unchanged.
+pub fn sanitize_image(blob: &[u8]) -> Result<Vec<u8>> {
+  match sniff_mime(blob) {
+    Some(HEIC | HEIF) => return encode_jpeg(&decode_oriented(blob)?, 92),
+    Some(TIFF) => return encode_tiff_pages(&decode_pages(blob)?),
+    _ => {}
+  }
+
+  let format = image::guess_format(blob).ok();
+  if !matches!(
+    format,
+    Some(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Bmp)
+  ) {
+    return Ok(blob.to_vec());
+  }
+
+  let orientation = ImageReader::new(Cursor::new(blob))
+    .with_guessed_format()
+    .ok()
+    .and_then(|reader| reader.into_decoder().ok())
+    .and_then(|mut decoder| decoder.orientation().ok());
+
+  let stripped = match (orientation, format) {
+    (Some(Orientation::NoTransforms), Some(ImageFormat::Jpeg)) => strip_jpeg_metadata(blob),
+    (Some(Orientation::NoTransforms), Some(ImageFormat::Png)) => strip_png_metadata(blob),
+    (Some(Orientation::NoTransforms), Some(ImageFormat::WebP)) => strip_webp_metadata(blob),
+    _ => None,
+  };
+  if let Some(stripped) = stripped {
+    return Ok(stripped);
+  }
+
+  // Rotated, or not parseable enough to strip in place: a re-encoded image has no metadata
+  let image = decode_oriented(blob)?;
+  if format == Some(ImageFormat::Jpeg) {
+    return encode_jpeg(&image, 92);
+  }
+  let mut bytes = Vec::new();
+  image
+    .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+    .map_err(|e| anyhow::anyhow!("Failed to encode PNG: {}", e))?;
+  Ok(bytes)
+}
+
+/// Drop APP1 (EXIF, XMP), APP3-APP13, APP15 and comment segments, keeping JFIF, ICC profiles
+/// and the Adobe color transform marker. Anything after the end of the image is dropped too,
+/// such as the secondary images of a multi-picture (MPF) file, each with EXIF of its own.
+/// Returns `None` for malformed files.
+fn strip_jpeg_metadata(blob: &[u8]) -> Option<Vec<u8>> {
+  let mut out = blob.get(..2)?.to_vec();
+  let mut pos = 2;
+
+  loop {
+    if *blob.get(pos)? != 0xFF {
+      return None;
+    }
+    let marker = *blob.get(pos + 1)?;
+    match marker {
+      // Fill bytes before a marker
+      0xFF => {
+        pos += 1;
+        continue;
+      }
+      // End of image
+      0xD9 => {
+        out.extend_from_slice(&blob[pos..pos + 2]);
+        return Some(out);
+      }
+      // Markers without a length
+      0x01 | 0xD0..=0xD7 => {
+        out.extend_from_slice(&blob[pos..pos + 2]);
+        pos += 2;
+        continue;
+      }
+      _ => {}
+    }
+
+    let length = u16::from_be_bytes([*blob.get(pos + 2)?, *blob.get(pos + 3)?]) as usize;
+    let segment = blob.get(pos..pos + 2 + length)?;
+    let keep = match marker {
+      0xE2 => segment.get(4..16) == Some(b"ICC_PROFILE\0".as_slice()),
+      0xE1 | 0xE3..=0xED | 0xEF | 0xFE => false,
+      _ => true,
+    };
+    if keep {
+      out.extend_from_slice(segment);
+    }
+    pos += 2 + length;
+
+    // Start of scan: image data follows, up to the next marker other than a stuffed 0xFF00
+    // or a restart marker. Progressive JPEGs have several scans.
+    if marker == 0xDA {
+      let start = pos;
+      while *blob.get(pos)? != 0xFF || matches!(*blob.get(pos + 1)?, 0x00 | 0xD0..=0xD7) {
+        pos += 1;
+      }
+      out.extend_from_slice(&blob[start..pos]);
+    }
+  }
+}
+
+/// Drop eXIf, text and timestamp chunks. Returns `None` for malformed files.
+fn strip_png_metadata(blob: &[u8]) -> Option<Vec<u8>> {
+  let mut out = blob.get(..8)?.to_vec();
+  let mut pos = 8;
+
+  while pos < blob.len() {
+    let length = u32::from_be_bytes(blob.get(pos..pos + 4)?.try_into().ok()?) as usize;
+    let chunk = blob.get(pos..pos + 12 + length)?;
+    if !matches!(
+      &chunk[4..8],
+      b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME"
+    ) {
+      out.extend_from_slice(chunk);
+    }
+    pos += 12 + length;
+  }
+
+  Some(out)
+}
+
+/// Drop EXIF and XMP chunks and clear their flags in the extended header. Returns `None` for
+/// malformed files.
+fn strip_webp_metadata(blob: &[u8]) -> Option<Vec<u8>> {
+  let mut out = blob.get(..12)?.to_vec();
+  let mut pos = 12;
+
+  while pos < blob.len() {
+    let fourcc = blob.get(pos..pos + 4)?;
+    let length = u32::from_le_bytes(blob.get(pos + 4..pos + 8)?.try_into().ok()?) as usize;
+    // Chunks are padded to an even size
+    let chunk = blob.get(pos..(pos + 8 + length + length % 2).min(blob.len()))?;
+    match fourcc {
+      b"EXIF" | b"XMP " => {}
+      b"VP8X" => {
+        let mut chunk = chunk.to_vec();
+        // Flags: XMP is 0x04, EXIF is 0x08
+        *chunk.get_mut(8)? &= !0x0C;
+        out.extend_from_slice(&chunk);
+      }
+      _ => out.extend_from_slice(chunk),
+    }
+    pos += 8 + length + length % 2;
+  }
+
+  let riff_size = (out.len() - 8) as u32;
+  out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+  Some(out)
+}
+
+/// Crop away a uniform background around a photographed or scanned document, such as the
+/// table a card was photographed on. Returns the image unchanged if no clear border is found.
+pub fn auto_crop_borders(image: &DynamicImage) -> DynamicImage {
+  let luma = image.to_luma8();
+  let (width, height) = luma.dimensions();
+  if width < 16 || height < 16 {
+    return image.clone();
+  }
+
+  // Background is whatever the corners have in common
+  let corners = [
+    luma.get_pixel(0, 0)[0],
+    luma.get_pixel(width - 1, 0)[0],
+    luma.get_pixel(0, height - 1)[0],
+    luma.get_pixel(width - 1, height - 1)[0],
+  ];
+  let background = corners.iter().map(|&c| c as u32).sum::<u32>() / 4;
+  if corners
+    .iter()
+    .any(|&c| (c as i32 - background as i32).abs() > 24)
+  {
+    return image.clone();
+  }
+
+  // A line belongs to the border while almost all of its pixels match the background
+  let differs = |x: u32, y: u32| (luma.get_pixel(x, y)[0] as i32 - background as i32).abs() > 40;
+  let row_is_border = |y: u32| (0..width).filter(|&x| differs(x, y)).count() < width as usize / 50;
+  let column_is_border =
+    |x: u32| (0..height).filter(|&y| differs(x, y)).count() < height as usize / 50;
+
+  let top = (0..height).find(|&y| !row_is_border(y)).unwrap_or(0);
+  let bottom = (0..height)
+    .rev()
+    .find(|&y| !row_is_border(y))
+    .unwrap_or(height - 1);
+  let left = (0..width).find(|&x| !column_is_border(x)).unwrap_or(0);
+  let right = (0..width)
+    .rev()
+    .find(|&x| !column_is_border(x))
+    .unwrap_or(width - 1);
+
+  if right <= left || bottom <= top {
+    return image.clone();
+  }
+  let (crop_width, crop_height) = (right - left + 1, bottom - top + 1);
+  // Keep the result if it looks like a document, not a speck or the whole frame
+  if crop_width * crop_height < width * height / 4 || (crop_width == width && crop_height == height)
+  {
+    return image.clone();
+  }
+
+  // Leave a little room so edges of the document aren't clipped
+  let padding = (width.min(height) / 100).max(1);
+  let x = left.saturating_sub(padding);
+  let y = top.saturating_sub(padding);
+  let crop_width = (crop_width + 2 * padding).min(width - x);
+  let crop_height = (crop_height + 2 * padding).min(height - y);
+  image.crop_imm(x, y, crop_width, crop_height)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::helpers::pdf_docs::{blobs_to_pdf, page_geometry, PageSize, PdfRenderOptions};
+  use image::Rgb;
+
+  const RED: Rgb<u8> = Rgb([255, 0, 0]);
+  const GREEN: Rgb<u8> = Rgb([0, 255, 0]);
+  const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+
+  /// 48x32 photo as stored, before its orientation is applied: red in the top left corner,
+  /// green in the top right corner
+  fn stored_photo() -> DynamicImage {
+    DynamicImage::ImageRgb8(RgbImage::from_fn(48, 32, |x, y| match (x, y) {
+      (0..16, 0..16) => RED,
+      (32.., 0..16) => GREEN,
+      _ => WHITE,
+    }))
+  }
+
+  /// EXIF segment with just an orientation tag
+  fn exif(orientation: u8) -> Vec<u8> {
+    let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08".to_vec();
+    exif.extend_from_slice(&[0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0]);
+    exif.extend_from_slice(&[0, 0, 0, 0]);
+    exif
+  }
+
+  /// JPEG taken with the given EXIF orientation, followed by an MPF secondary image carrying
+  /// EXIF of its own
+  fn jpeg_fixture(orientation: u8) -> Vec<u8> {
+    let photo = encode_jpeg(&stored_photo(), 95).unwrap();
+    let exif = exif(orientation);
+
+    let mut jpeg = photo[..2].to_vec();
+    jpeg.extend_from_slice(&[0xFF, 0xE1]);
+    jpeg.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+    jpeg.extend_from_slice(&exif);
+    jpeg.extend_from_slice(b"\xFF\xE2\0\x0AMPF\0\0\0\0\0");
+    jpeg.extend_from_slice(&photo[2..]);
+
+    jpeg.extend_from_slice(b"\xFF\xD8\xFF\xE1\0\x10");
+    jpeg.extend_from_slice(&exif[..14]);
+    jpeg.extend_from_slice(b"\xFF\xD9");
+    jpeg
+  }
+
+  fn png_fixture(orientation: u8) -> Vec<u8> {
+    let mut png = Vec::new();
+    stored_photo()
+      .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+      .unwrap();
+
+    let data = &exif(orientation)[6..];
+    let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+    chunk.extend_from_slice(b"eXIf");
+    chunk.extend_from_slice(data);
+    chunk.extend_from_slice(&crc32(&chunk[4..]).to_be_bytes());
+    // After the signature and IHDR
+    png.splice(33..33, chunk);
+    png
+  }
+
+  fn webp_fixture(orientation: u8) -> Vec<u8> {
+    let mut simple = Vec::new();
+    stored_photo()
+      .write_to(&mut Cursor::new(&mut simple), ImageFormat::WebP)
+      .unwrap();
+
+    let mut vp8x = b"VP8X\x0A\0\0\0\x08\0\0\0".to_vec();
+    vp8x.extend_from_slice(&47u32.to_le_bytes()[..3]);
+    vp8x.extend_from_slice(&31u32.to_le_bytes()[..3]);
+    let exif = &exif(orientation)[6..];
+    let mut exif_chunk = b"EXIF".to_vec();
+    exif_chunk.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+    exif_chunk.extend_from_slice(exif);
+
+    let mut webp = simple[..12].to_vec();
+    webp.extend_from_slice(&vp8x);
+    webp.extend_from_slice(&simple[12..]);
+    webp.extend_from_slice(&exif_chunk);
+    let riff_size = (webp.len() - 8) as u32;
+    webp[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    webp
+  }
+
+  fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+      crc ^= byte as u32;
+      for _ in 0..8 {
+        crc = if crc & 1 == 1 {
+          (crc >> 1) ^ 0xEDB8_8320
+        } else {
+          crc >> 1
+        };
+      }
+    }
+    !crc
+  }
+
+  /// Displayed size, and the colors of the top left, top right, bottom right and bottom left
+  /// corners, for each EXIF orientation
+  fn expected(orientation: u8) -> ((u32, u32), [Rgb<u8>; 4]) {
+    let corners = match orientation {
+      1 => [RED, GREEN, WHITE, WHITE],
+      2 => [GREEN, RED, WHITE, WHITE],
+      3 => [WHITE, WHITE, RED, GREEN],
+      4 => [WHITE, WHITE, GREEN, RED],
+      5 => [RED, WHITE, WHITE, GREEN],
+      6 => [WHITE, RED, GREEN, WHITE],
+      7 => [WHITE, GREEN, RED, WHITE],
+      8 => [GREEN, WHITE, WHITE, RED],
+      _ => unreachable!(),
+    };
+    let size = if orientation >= 5 { (32, 48) } else { (48, 32) };
+    (size, corners)
+  }
+
+  fn assert_upright(image: &DynamicImage, orientation: u8) {
+    let ((width, height), corners) = expected(orientation);
+    assert_eq!(
+      (image.width(), image.height()),
+      (width, height),
+      "orientation {}",
+      orientation
+    );
+
+    let rgb = image.to_rgb8();
+    let points = [
+      (4, 4),
+      (width - 5, 4),
+      (width - 5, height - 5),
+      (4, height - 5),
+    ];
+    for ((x, y), expected) in points.into_iter().zip(corners) {
+      let pixel = rgb.get_pixel(x, y);
+      let close = pixel
+        .0
+        .iter()
+        .zip(expected.0)
+        .all(|(&a, b)| a.abs_diff(b) < 48);
+      assert!(
+        close,
+        "orientation {}: pixel ({}, {}) is {:?}, expected {:?}",
+        orientation, x, y, pixel, expected
+      );
+    }
+  }
+
+  /// Segment markers before the image data of a JPEG
+  fn jpeg_markers(jpeg: &[u8]) -> Vec<u8> {
+    let mut markers = Vec::new();
+    let mut pos = 2;
+    while jpeg[pos + 1] != 0xDA {
+      markers.push(jpeg[pos + 1]);
+      pos += 2 + u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+    }
+    markers
+  }
+
+  fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+      .windows(needle.len())
+      .any(|window| window == needle)
+  }
+
+  #[test]
+  fn decoding_applies_every_orientation() {
+    for orientation in 1..=8 {
+      let image = decode_oriented(&jpeg_fixture(orientation)).unwrap();
+      assert_upright(&image, orientation);
+    }
+  }
+
+  #[test]
+  fn sanitized_jpegs_are_upright_without_metadata() {
+    for orientation in 1..=8 {
+      let sanitized = sanitize_image(&jpeg_fixture(orientation)).unwrap();
+
+      assert_eq!(image::guess_format(&sanitized).unwrap(), ImageFormat::Jpeg);
+      assert!(!jpeg_markers(&sanitized).contains(&0xE1));
+      assert!(!contains(&sanitized, b"Exif"));
+      assert!(!contains(&sanitized, b"MPF"));
+      assert!(sanitized.ends_with(b"\xFF\xD9"));
+      // Nothing is left to rotate it a second time
+      assert_upright(&decode_oriented(&sanitized).unwrap(), orientation);
+    }
+  }
+
+  #[test]
+  fn sanitized_pngs_and_webps_have_no_exif() {
+    for orientation in [1, 6] {
+      let png = sanitize_image(&png_fixture(orientation)).unwrap();
+      assert_eq!(image::guess_format(&png).unwrap(), ImageFormat::Png);
+      assert!(!contains(&png, b"eXIf"));
+      assert_upright(&decode_oriented(&png).unwrap(), orientation);
+
+      let webp = sanitize_image(&webp_fixture(orientation)).unwrap();
+      assert!(!contains(&webp, b"EXIF"));
+      assert_upright(&decode_oriented(&webp).unwrap(), orientation);
+    }
+  }
+
+  #[test]
+  fn sanitized_tiffs_keep_their_pages_without_tags() {
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    let mut tiff = Vec::new();
+    let mut encoder = TiffEncoder::new(Cursor::new(&mut tiff)).unwrap();
+    for page in [stored_photo(), stored_photo().rotate90()] {
+      let rgb = page.to_rgb8();
+      let mut image = encoder
+        .new_image::<colortype::RGB8>(rgb.width(), rgb.height())
+        .unwrap();
+      image.encoder().write_tag(Tag::Artist, "Jane Doe").unwrap();
+      image
+        .encoder()
+        .write_tag(Tag::ImageDescription, "Taken at 12 Oak St")
+        .unwrap();
+      image.write_data(rgb.as_raw()).unwrap();
+    }
+
+    let sanitized = sanitize_image(&tiff).unwrap();
+    assert!(!contains(&sanitized, b"Jane Doe"));
+    assert!(!contains(&sanitized, b"Oak St"));
+    let pages = decode_pages(&sanitized).unwrap();
+    assert_eq!(pages.len(), 2);
+    assert_upright(&pages[0], 1);
+    assert_upright(&pages[1], 6);
+  }
+
+  #[test]
+  fn pdf_pages_follow_the_orientation() {
+    let options = PdfRenderOptions {
+      page_size: PageSize::Image,
+      margin_mm: 0.0,
+      ..Default::default()
+    };
+
+    for orientation in 1..=8 {
+      let pdf = blobs_to_pdf(&[jpeg_fixture(orientation)], &options).unwrap();
+      let doc = lopdf::Document::load_mem(&pdf).unwrap();
+      let page_id = doc.page_iter().next().unwrap();
+      let (width, height, _) = page_geometry(&doc, page_id).unwrap().display_transform();
+      assert_eq!(
+        width > height,
+        orientation < 5,
+        "orientation {}: page is {}x{}",
+        orientation,
+        width,
+        height
+      );
+
+      let image = doc
+        .objects
+        .values()
+        .filter_map(|object| object.as_stream().ok())
+        .find(|stream| stream.dict.get(b"Subtype").and_then(|s| s.as_name()).ok() == Some(b"Image"))
+        .unwrap();
+      let embedded = match image.filters().unwrap_or_default().as_slice() {
+        [b"DCTDecode"] => image::load_from_memory(&image.content).unwrap(),
+        _ => {
+          let (width, height) = expected(orientation).0;
+          let pixels = image
+            .decompressed_content()
+            .unwrap_or(image.content.clone());
+          DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels).unwrap())
+        }
+      };
+      assert_upright(&embedded, orientation);
+    }
+  }
+}
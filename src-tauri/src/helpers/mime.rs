@@ -0,0 +1,234 @@
+/// MIME types accepted as documents
+pub const ALLOWED_DOCUMENT_TYPES: &[&str] =
+  &[PDF, JPEG, PNG, GIF, WEBP, HEIC, HEIF, TIFF, BMP, DOCX, TEXT];
+
+pub const PDF: &str = "application/pdf";
+pub const JPEG: &str = "image/jpeg";
+pub const PNG: &str = "image/png";
+pub const GIF: &str = "image/gif";
+pub const WEBP: &str = "image/webp";
+pub const HEIC: &str = "image/heic";
+pub const HEIF: &str = "image/heif";
+pub const TIFF: &str = "image/tiff";
+pub const BMP: &str = "image/bmp";
+pub const DOCX: &str = "application/vnd.openxmlformats-officedocument.wordprocessingml.document";
+pub const ZIP: &str = "application/zip";
+pub const TEXT: &str = "text/plain";
+pub const EXECUTABLE: &str = "application/x-executable";
+
+/// Detect the type of a file from its leading magic bytes
+pub fn sniff_mime(data: &[u8]) -> Option<&'static str> {
+  if is_executable(data) {
+    return Some(EXECUTABLE);
+  }
+
+  if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+    return Some(JPEG);
+  }
+  if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+    return Some(PNG);
+  }
+  if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+    return Some(GIF);
+  }
+  if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+    return Some(WEBP);
+  }
+  // Classic TIFF, then BigTIFF as written by some scanners for large multi-page files
+  if data.starts_with(b"II*\0")
+    || data.starts_with(b"MM\0*")
+    || data.starts_with(b"II+\0")
+    || data.starts_with(b"MM\0+")
+  {
+    return Some(TIFF);
+  }
+  // Bitmap file header, then the size of one of the known DIB headers
+  if data.starts_with(b"BM")
+    && data
+      .get(14..18)
+      .map(|size| u32::from_le_bytes([size[0], size[1], size[2], size[3]]))
+      .is_some_and(|size| matches!(size, 12 | 16 | 40 | 52 | 56 | 64 | 108 | 124))
+  {
+    return Some(BMP);
+  }
+  if let Some(mime) = sniff_iso_bmff(data) {
+    return Some(mime);
+  }
+  if data.starts_with(b"PK\x03\x04") {
+    return Some(sniff_zip(data));
+  }
+
+  // PDF headers may be preceded by junk bytes, the spec allows up to 1024. Checked last, as
+  // images can carry the same bytes in their metadata.
+  if data[..data.len().min(1024)]
+    .windows(5)
+    .any(|w| w == b"%PDF-")
+  {
+    return Some(PDF);
+  }
+
+  None
+}
+
+/// Native executables, which are never accepted as documents. Scripts are plain text and are
+/// only ever stored as such, so they are not treated as executables.
+pub fn is_executable(data: &[u8]) -> bool {
+  const MAGICS: &[&[u8]] = &[
+    b"\x7fELF",          // Linux ELF
+    b"\xfe\xed\xfa\xce", // Mach-O 32-bit
+    b"\xfe\xed\xfa\xcf", // Mach-O 64-bit
+    b"\xce\xfa\xed\xfe", // Mach-O 32-bit, little endian
+    b"\xcf\xfa\xed\xfe", // Mach-O 64-bit, little endian
+    b"\xca\xfe\xba\xbe", // Mach-O universal binary
+  ];
+  MAGICS.iter().any(|magic| data.starts_with(magic)) || is_windows_pe(data)
+}
+
+/// "MZ" alone also starts plenty of text files, so a Windows executable is only recognized by
+/// the PE signature its DOS header points to
+fn is_windows_pe(data: &[u8]) -> bool {
+  if !data.starts_with(b"MZ") {
+    return false;
+  }
+  let Some(offset) = data.get(0x3C..0x40) else {
+    return false;
+  };
+  let offset = u32::from_le_bytes([offset[0], offset[1], offset[2], offset[3]]) as usize;
+  offset
+    .checked_add(4)
+    .and_then(|end| data.get(offset..end))
+    .is_some_and(|signature| signature == b"PE\0\0")
+}
+
+/// HEIC/HEIF are ISO base media files identified by the brand in their `ftyp` box
+fn sniff_iso_bmff(data: &[u8]) -> Option<&'static str> {
+  if data.len() < 12 || &data[4..8] != b"ftyp" {
+    return None;
+  }
+
+  let box_size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+  let box_end = box_size.clamp(12, data.len());
+  let major = &data[8..12];
+  // Compatible brands follow the major brand and the minor version
+  let compatible = data.get(16..box_end).unwrap_or(&[]);
+  let brands = std::iter::once(major).chain(compatible.chunks_exact(4));
+
+  let mut heif = false;
+  for brand in brands {
+    match brand {
+      b"heic" | b"heix" | b"heim" | b"heis" | b"hevc" | b"hevx" => return Some(HEIC),
+      b"mif1" | b"mif2" | b"msf1" | b"heif" => heif = true,
+      _ => {}
+    }
+  }
+  heif.then_some(HEIF)
+}
+
+/// Tell DOCX apart from other ZIP archives by the `word/document.xml` part every DOCX has,
+/// named in a local file header or a central directory entry
+fn sniff_zip(data: &[u8]) -> &'static str {
+  const DOCUMENT_PART: &[u8] = b"word/document.xml";
+  // Signature, then where the name length and the name are within the header
+  const HEADERS: [(&[u8], usize, usize); 2] = [(b"PK\x03\x04", 26, 30), (b"PK\x01\x02", 28, 46)];
+
+  let names_document_part = |start: usize, name_length_at: usize, name_at: usize| {
+    let Some(length) = data.get(start + name_length_at..start + name_length_at + 2) else {
+      return false;
+    };
+    let length = u16::from_le_bytes([length[0], length[1]]) as usize;
+    data.get(start + name_at..start + name_at + length) == Some(DOCUMENT_PART)
+  };
+  let is_docx = data.windows(4).enumerate().any(|(start, window)| {
+    HEADERS.iter().any(|(signature, name_length_at, name_at)| {
+      window == *signature && names_document_part(start, *name_length_at, *name_at)
+    })
+  });
+
+  if is_docx {
+    DOCX
+  } else {
+    ZIP
+  }
+}
+
+/// Normalize common aliases so claimed and detected types can be compared
+pub fn canonical_mime(mime: &str) -> String {
+  let mime = mime
+    .split(';')
+    .next()
+    .unwrap_or("")
+    .trim()
+    .to_ascii_lowercase();
+  match mime.as_str() {
+    "image/jpg" | "image/pjpeg" => JPEG.to_string(),
+    "image/x-png" => PNG.to_string(),
+    "image/tif" => TIFF.to_string(),
+    "image/heic-sequence" => HEIC.to_string(),
+    "image/heif-sequence" => HEIF.to_string(),
+    "application/x-pdf" => PDF.to_string(),
+    "application/x-zip-compressed" => ZIP.to_string(),
+    _ => mime,
+  }
+}
+
+/// Whether a claimed type is consistent with the detected one. Generic claims such as
+/// `application/octet-stream` carry no information and are always consistent.
+pub fn claim_matches(claimed: &str, detected: &str) -> bool {
+  let claimed = canonical_mime(claimed);
+  if claimed.is_empty() || claimed == "application/octet-stream" {
+    return true;
+  }
+
+  claimed == detected
+    // HEIC is a HEIF profile, phones label the same file either way
+    || matches!((claimed.as_str(), detected), (HEIC, HEIF) | (HEIF, HEIC))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn zip_entry(name: &str) -> Vec<u8> {
+    let mut entry = b"PK\x03\x04".to_vec();
+    entry.extend_from_slice(&[0; 22]);
+    entry.extend_from_slice(&(name.len() as u16).to_le_bytes());
+    entry.extend_from_slice(&[0; 2]);
+    entry.extend_from_slice(name.as_bytes());
+    entry
+  }
+
+  #[test]
+  fn images_mentioning_pdf_are_images() {
+    let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE1, 0x00, 0x10];
+    jpeg.extend_from_slice(b"Exif\0\0%PDF-1.7 scan");
+    assert_eq!(sniff_mime(&jpeg), Some(JPEG));
+
+    let mut pdf = b"junk\n".to_vec();
+    pdf.extend_from_slice(b"%PDF-1.7\n");
+    assert_eq!(sniff_mime(&pdf), Some(PDF));
+  }
+
+  #[test]
+  fn docx_needs_its_document_part() {
+    let mut docx = zip_entry("[Content_Types].xml");
+    docx.extend(zip_entry("word/document.xml"));
+    assert_eq!(sniff_mime(&docx), Some(DOCX));
+
+    let mut archive = zip_entry("password/notes.txt");
+    archive.extend(zip_entry("backup/word/styles.xml"));
+    assert_eq!(sniff_mime(&archive), Some(ZIP));
+  }
+
+  #[test]
+  fn text_starting_like_an_executable_is_not_one() {
+    assert!(!is_executable(b"MZ Properties lease notes"));
+    assert!(!is_executable(b"#!/bin/sh\necho hi"));
+
+    let mut pe = vec![0; 0x84];
+    pe[..2].copy_from_slice(b"MZ");
+    pe[0x3C..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+    pe[0x80..0x84].copy_from_slice(b"PE\0\0");
+    assert!(is_executable(&pe));
+    assert_eq!(sniff_mime(&pe), Some(EXECUTABLE));
+  }
+}
@@ -1 +1,9 @@
+pub mod images;
+pub mod mime;
 pub mod pdf_docs;
+pub mod pdf_forms;
+pub mod pdf_size;
+pub mod pdf_text;
+pub mod redact;
+pub mod resume_pdf;
+pub mod sase_api;
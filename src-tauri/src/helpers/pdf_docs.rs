@@ -1,23 +1,197 @@
+use super::images::{auto_crop_borders, decode_pages};
+use super::pdf_size::{apply_size_budget, fit_pdfs_to_size, PdfSizeReport};
+use super::sase_api::{MergePdfsRequest, SaseApiClient, UploadProgressFn};
 use anyhow::Result;
-use std::fs;
-use std::path::Path;
+use image::DynamicImage;
+use lopdf::{dictionary, Bookmark, Object, ObjectId};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 // Import symbols from our Symbol Plan
 use printpdf::{
   deserialize::PdfWarnMsg,
-  image::{RawImage, RawImageData, RawImageFormat},
+  image::{ImageCompression, ImageOptimizationOptions, RawImage, RawImageData, RawImageFormat},
   ops::{Op, PdfPage},
   serialize::PdfSaveOptions,
   units::Mm,
   xobject::XObjectTransform,
-  PdfDocument,
+  BuiltinFont, Color, LinePoint, PaintMode, PdfDocument, Point, Polygon, PolygonRing, Pt, Rgb,
+  TextItem, WindingOrder,
 };
 
-/// Convert image blobs to a multi-page PDF, one image per page
-pub fn blobs_to_pdf(blobs: &[Vec<u8>], out: &Path) -> Result<()> {
+/// Highlight applied to a cell in a table rendered by [`table_to_pdf`]
+pub enum CellHighlight {
+  None,
+  Best,
+  Worst,
+}
+
+pub struct PdfTableRow {
+  pub label: String,
+  pub cells: Vec<(String, CellHighlight)>,
+}
+
+/// A PDF to merge with [`merge_pdfs`]
+pub struct PdfPart {
+  pub bytes: Vec<u8>,
+  /// Title of an outline entry pointing at the first page of this part
+  pub bookmark: Option<String>,
+}
+
+/// Page attributes a page may inherit from its ancestors in the page tree
+const INHERITABLE_PAGE_ATTRIBUTES: [&str; 4] = ["Resources", "MediaBox", "CropBox", "Rotate"];
+
+/// Number of pages in a PDF, or `None` if it cannot be parsed
+pub fn pdf_page_count(pdf_bytes: &[u8]) -> Option<u32> {
+  lopdf::Document::load_mem(pdf_bytes)
+    .ok()
+    .map(|doc| doc.get_pages().len() as u32)
+}
+
+/// Paper size of pages generated from images
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PageSize {
+  #[default]
+  A4,
+  Letter,
+  Legal,
+  /// Each page is sized to its image plus the margins
+  Image,
+}
+
+impl PageSize {
+  /// Portrait width and height in mm. Generated pages without an image of their own use A4
+  /// for [`PageSize::Image`].
+  pub fn portrait_mm(self) -> (f32, f32) {
+    match self {
+      PageSize::A4 | PageSize::Image => (210.0, 297.0),
+      PageSize::Letter => (215.9, 279.4),
+      PageSize::Legal => (215.9, 355.6),
+    }
+  }
+}
+
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PageOrientation {
+  /// Landscape for images wider than they are tall
+  #[default]
+  Auto,
+  Portrait,
+  Landscape,
+}
+
+/// How an image is sized on its page
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFit {
+  /// Scale to fit inside the margins, keeping the whole image visible
+  #[default]
+  Fit,
+  /// Scale to cover the whole page, cropping what falls off its edges
+  Fill,
+  /// Print at `dpi`, cropped by the page if it is larger
+  ActualSize,
+}
+
+/// How images are rendered into PDF pages, and how large the resulting PDF may get
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct PdfRenderOptions {
+  pub page_size: PageSize,
+  pub orientation: PageOrientation,
+  /// Space between the image and the page edge, in mm
+  pub margin_mm: f32,
+  pub fit: ImageFit,
+  /// JPEG quality images are re-encoded with, 1-100
+  pub jpeg_quality: u8,
+  pub grayscale: bool,
+  /// Crop uniform background around photographed documents
+  pub auto_crop: bool,
+  /// Images are downscaled so their longest edge is at most this many pixels
+  pub max_image_edge: u32,
+  /// Resolution used for [`ImageFit::ActualSize`] and [`PageSize::Image`]
+  pub dpi: f32,
+  /// Upload limit of the portal the PDF is for. Images are downscaled and re-encoded until the
+  /// output fits, or the build fails saying which documents are too large.
+  pub target_size_bytes: Option<u64>,
+}
+
+impl Default for PdfRenderOptions {
+  fn default() -> Self {
+    PdfRenderOptions {
+      page_size: PageSize::A4,
+      orientation: PageOrientation::Auto,
+      margin_mm: 10.0,
+      fit: ImageFit::Fit,
+      jpeg_quality: 80,
+      grayscale: false,
+      auto_crop: false,
+      max_image_edge: 1920,
+      dpi: 150.0,
+      target_size_bytes: None,
+    }
+  }
+}
+
+impl PdfRenderOptions {
+  pub fn validate(&self) -> Result<()> {
+    if !(1..=100).contains(&self.jpeg_quality) {
+      return Err(anyhow::anyhow!(
+        "JPEG quality must be between 1 and 100, got {}",
+        self.jpeg_quality
+      ));
+    }
+    if !(0.0..=50.0).contains(&self.margin_mm) {
+      return Err(anyhow::anyhow!(
+        "Margins must be between 0 and 50 mm, got {}",
+        self.margin_mm
+      ));
+    }
+    if self.dpi <= 0.0 || self.max_image_edge == 0 {
+      return Err(anyhow::anyhow!(
+        "DPI and maximum image size must be positive"
+      ));
+    }
+    if self.target_size_bytes == Some(0) {
+      return Err(anyhow::anyhow!("Target size must be positive"));
+    }
+    Ok(())
+  }
+
+  /// Page size in mm for an image of the given size in mm
+  fn page_for_image(&self, image_width: f32, image_height: f32) -> (f32, f32) {
+    if self.page_size == PageSize::Image {
+      return (
+        image_width + 2.0 * self.margin_mm,
+        image_height + 2.0 * self.margin_mm,
+      );
+    }
+
+    let (width, height) = self.page_size.portrait_mm();
+    let landscape = match self.orientation {
+      PageOrientation::Auto => image_width > image_height,
+      PageOrientation::Portrait => false,
+      PageOrientation::Landscape => true,
+    };
+    if landscape {
+      (height, width)
+    } else {
+      (width, height)
+    }
+  }
+}
+
+const MM_PER_INCH: f32 = 25.4;
+
+/// Convert image blobs to a multi-page PDF in memory, one page per image or per TIFF page.
+/// CPU heavy; call it from a blocking task.
+pub fn blobs_to_pdf(blobs: &[Vec<u8>], options: &PdfRenderOptions) -> Result<Vec<u8>> {
   if blobs.is_empty() {
     return Err(anyhow::anyhow!("No image blobs provided"));
   }
+  options.validate()?;
 
   let mut pdf_doc = PdfDocument::new("Generated PDF");
   let mut warnings = Vec::<PdfWarnMsg>::new();
@@ -31,239 +205,1025 @@ pub fn blobs_to_pdf(blobs: &[Vec<u8>], out: &Path) -> Result<()> {
       ));
     }
 
-    let dynamic_image = image::load_from_memory(blob)
-      .map_err(|e| anyhow::anyhow!("Failed to decode image {} with image crate: {}", i, e))?;
+    // Phone photos are often stored sideways with an EXIF orientation. Only pixels are
+    // embedded, so metadata such as GPS coordinates never reaches the PDF. Every page of a
+    // multi-page TIFF becomes its own PDF page.
+    let pages = decode_pages(blob).map_err(|e| anyhow::anyhow!("Image {}: {}", i, e))?;
+    for dynamic_image in pages {
+      let dynamic_image = if options.auto_crop {
+        auto_crop_borders(&dynamic_image)
+      } else {
+        dynamic_image
+      };
 
-    // Resize large images to reduce PDF size (max 1920px width/height)
-    let resized_image = if dynamic_image.width() > 1920 || dynamic_image.height() > 1920 {
-      println!(
-        "Resizing large image {} from {}x{}",
-        i,
-        dynamic_image.width(),
-        dynamic_image.height()
-      );
-      dynamic_image.resize(1920, 1920, image::imageops::FilterType::Lanczos3)
-    } else {
-      dynamic_image
-    };
+      // Resize large images to reduce PDF size
+      let max_edge = options.max_image_edge;
+      let resized_image = if dynamic_image.width() > max_edge || dynamic_image.height() > max_edge {
+        dynamic_image.resize(max_edge, max_edge, image::imageops::FilterType::Lanczos3)
+      } else {
+        dynamic_image
+      };
 
-    let rgba_image = resized_image.to_rgba8();
-    let width = rgba_image.width() as usize;
-    let height = rgba_image.height() as usize;
-    let raw_data = rgba_image.into_raw();
-
-    let raw_image = RawImage {
-      width: width,
-      height: height,
-      pixels: RawImageData::U8(raw_data),
-      data_format: RawImageFormat::RGBA8,
-      tag: Vec::new(),
-    };
+      let width = resized_image.width() as usize;
+      let height = resized_image.height() as usize;
+      // Documents have no use for transparency; dropping it lets the page be a plain JPEG
+      let raw_image = if options.grayscale {
+        RawImage {
+          width,
+          height,
+          pixels: RawImageData::U8(flatten_alpha(&resized_image).to_luma8().into_raw()),
+          data_format: RawImageFormat::R8,
+          tag: Vec::new(),
+        }
+      } else {
+        RawImage {
+          width,
+          height,
+          pixels: RawImageData::U8(flatten_alpha(&resized_image).to_rgb8().into_raw()),
+          data_format: RawImageFormat::RGB8,
+          tag: Vec::new(),
+        }
+      };
+
+      let image_id = pdf_doc.add_image(&raw_image);
+
+      // Size of the image at the configured resolution
+      let image_width_mm = width as f32 / options.dpi * MM_PER_INCH;
+      let image_height_mm = height as f32 / options.dpi * MM_PER_INCH;
+      let (page_width, page_height) = options.page_for_image(image_width_mm, image_height_mm);
+
+      let scale = match options.fit {
+        ImageFit::Fit => ((page_width - 2.0 * options.margin_mm) / image_width_mm)
+          .min((page_height - 2.0 * options.margin_mm) / image_height_mm),
+        ImageFit::Fill => (page_width / image_width_mm).max(page_height / image_height_mm),
+        ImageFit::ActualSize => 1.0,
+      };
+
+      let scaled_width = image_width_mm * scale;
+      let scaled_height = image_height_mm * scale;
+      let translate_x = (page_width - scaled_width) / 2.0;
+      let translate_y = (page_height - scaled_height) / 2.0;
 
-    let image_id = pdf_doc.add_image(&raw_image);
+      let transform = XObjectTransform {
+        translate_x: Some(Mm(translate_x).into()),
+        translate_y: Some(Mm(translate_y).into()),
+        rotate: None,
+        scale_x: Some(scale),
+        scale_y: Some(scale),
+        dpi: Some(options.dpi),
+      };
 
-    let a4_width = Mm(210.0);
-    let a4_height = Mm(297.0);
-    let image_width_mm = (width as f32) * 0.352778;
-    let image_height_mm = (height as f32) * 0.352778;
+      let ops = vec![Op::UseXobject {
+        id: image_id,
+        transform,
+      }];
 
-    let mut ops = Vec::new();
+      let page = PdfPage::new(Mm(page_width), Mm(page_height), ops);
+      pdf_doc.pages.push(page);
+    }
+  }
+
+  let save_options = PdfSaveOptions {
+    image_optimization: Some(ImageOptimizationOptions {
+      quality: Some(options.jpeg_quality as f32 / 100.0),
+      max_image_size: None,
+      dither_greyscale: Some(false),
+      convert_to_greyscale: Some(false),
+      auto_optimize: Some(false),
+      format: Some(ImageCompression::Jpeg),
+    }),
+    ..PdfSaveOptions::default()
+  };
+  Ok(pdf_doc.save(&save_options, &mut warnings))
+}
+
+/// Composite transparent images onto white, as they would look on paper
+fn flatten_alpha(image: &DynamicImage) -> DynamicImage {
+  if !image.color().has_alpha() {
+    return image.clone();
+  }
+
+  let mut flattened = image::RgbImage::new(image.width(), image.height());
+  for (target, source) in flattened.pixels_mut().zip(image.to_rgba8().pixels()) {
+    let alpha = source[3] as u32;
+    for channel in 0..3 {
+      target[channel] = ((source[channel] as u32 * alpha + 255 * (255 - alpha)) / 255) as u8;
+    }
+  }
+  DynamicImage::ImageRgb8(flattened)
+}
+
+/// Concatenate PDFs locally, in order. Every part is renumbered so object ids don't collide,
+/// all pages are moved into a single page tree and each part's bookmark becomes a top-level
+/// outline entry. Interactive form definitions and outlines of the parts are not carried over;
+/// filled-in fields still render through their widget appearances.
+pub fn merge_pdfs(parts: &[PdfPart]) -> Result<Vec<u8>> {
+  if parts.is_empty() {
+    return Err(anyhow::anyhow!("No PDFs to merge"));
+  }
 
-    // Calculate image scaling and positioning (centered with margins)
-    let scale_x = a4_width.0 / image_width_mm;
-    let scale_y = a4_height.0 / image_height_mm;
-    let scale = scale_x.min(scale_y) * 0.9;
+  let mut merged = lopdf::Document::with_version("1.7");
+  let mut next_id = 1;
+  let mut page_ids: Vec<ObjectId> = Vec::new();
+  let mut bookmarks: Vec<(String, ObjectId)> = Vec::new();
 
-    let scaled_width = image_width_mm * scale;
-    let scaled_height = image_height_mm * scale;
-    let translate_x = (a4_width.0 - scaled_width) / 2.0;
-    let translate_y = (a4_height.0 - scaled_height) / 2.0;
+  for (i, part) in parts.iter().enumerate() {
+    let mut doc = load_pdf(&part.bytes).map_err(|e| anyhow::anyhow!("PDF {}: {}", i + 1, e))?;
 
-    let transform = XObjectTransform {
-      translate_x: Some(Mm(translate_x).into()),
-      translate_y: Some(Mm(translate_y).into()),
-      rotate: None,
-      scale_x: Some(scale),
-      scale_y: Some(scale),
-      dpi: Some(72.0),
+    // Pages are moved out of their original tree, so they must carry inherited attributes
+    push_down_inherited_attributes(&mut doc);
+    doc.renumber_objects_with(next_id);
+    next_id = doc.max_id + 1;
+
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let Some(first_page) = pages.first() else {
+      return Err(anyhow::anyhow!("PDF {} has no pages", i + 1));
     };
+    if let Some(title) = &part.bookmark {
+      bookmarks.push((title.clone(), *first_page));
+    }
+    page_ids.extend(&pages);
 
-    ops.push(Op::UseXobject {
-      id: image_id,
-      transform,
-    });
+    for (id, object) in doc.objects {
+      match object.type_name().unwrap_or(b"") {
+        // Replaced by the merged document's own catalog and page tree
+        b"Catalog" | b"Pages" | b"Outlines" => {}
+        _ => {
+          merged.objects.insert(id, object);
+        }
+      }
+    }
+  }
+
+  let pages_id: ObjectId = (next_id, 0);
+  let catalog_id: ObjectId = (next_id + 1, 0);
+  merged.max_id = next_id + 1;
 
-    let page = PdfPage::new(a4_width, a4_height, ops);
-    pdf_doc.pages.push(page);
+  for page_id in &page_ids {
+    if let Some(Ok(page)) = merged.objects.get_mut(page_id).map(Object::as_dict_mut) {
+      page.set("Parent", pages_id);
+    }
   }
 
-  let save_options = PdfSaveOptions::default();
-  let pdf_bytes = pdf_doc.save(&save_options, &mut warnings);
+  merged.objects.insert(
+    pages_id,
+    Object::Dictionary(dictionary! {
+      "Type" => "Pages",
+      "Kids" => page_ids.iter().map(|id| Object::Reference(*id)).collect::<Vec<_>>(),
+      "Count" => page_ids.len() as i64,
+    }),
+  );
 
-  fs::write(out, pdf_bytes)
-    .map_err(|e| anyhow::anyhow!("Failed to write PDF to {}: {}", out.display(), e))?;
+  let mut catalog = dictionary! {
+    "Type" => "Catalog",
+    "Pages" => pages_id,
+  };
+  for (title, page_id) in bookmarks {
+    merged.add_bookmark(Bookmark::new(title, [0.0, 0.0, 0.0], 0, page_id), None);
+  }
+  if let Some(outline_id) = merged.build_outline() {
+    catalog.set("Outlines", outline_id);
+    catalog.set("PageMode", "UseOutlines");
+  }
+  merged
+    .objects
+    .insert(catalog_id, Object::Dictionary(catalog));
+  merged.trailer.set("Root", catalog_id);
 
-  Ok(())
+  // Drop leftovers of the old trees, such as outline items and encryption dictionaries
+  merged.prune_objects();
+  merged.compress();
+
+  let mut bytes = Vec::new();
+  merged
+    .save_to(&mut bytes)
+    .map_err(|e| anyhow::anyhow!("Failed to write merged PDF: {}", e))?;
+  Ok(bytes)
 }
 
-pub async fn merge_pdfs_via_sase_api(
-  base_pdf_bytes: Vec<u8>,
-  additional_pdf_bytes: Vec<Vec<u8>>,
-  headers: Vec<String>,
-  jwt_token: &str,
-) -> Result<Vec<u8>> {
-  let api_endpoint = "https://drakoindustries.com/api/sase/pdf/merge";
-
-  let client = reqwest::Client::new();
-  let mut form = reqwest::multipart::Form::new();
-
-  let base_part = reqwest::multipart::Part::bytes(base_pdf_bytes)
-    .file_name("base-document.pdf")
-    .mime_str("application/pdf")
-    .map_err(|e| anyhow::anyhow!("Failed to create base PDF part: {}", e))?;
-  form = form.part("basePdf", base_part);
-
-  for (i, pdf_bytes) in additional_pdf_bytes.iter().enumerate() {
-    let filename = format!("additional-{}.pdf", i + 1);
-    let part = reqwest::multipart::Part::bytes(pdf_bytes.clone())
-      .file_name(filename)
-      .mime_str("application/pdf")
-      .map_err(|e| anyhow::anyhow!("Failed to create additional PDF part {}: {}", i, e))?;
-    form = form.part("additionalPdfs", part);
-  }
-
-  // Add headers as JSON
-  if !headers.is_empty() {
-    let headers_json = serde_json::to_string(&headers)
-      .map_err(|e| anyhow::anyhow!("Failed to serialize headers to JSON: {}", e))?;
-    form = form.text("headers", headers_json);
-    println!("Sending headers to API: {:?}", headers);
-  }
-  let response = client
-    .post(api_endpoint)
-    .header("Authorization", format!("Bearer {}", jwt_token))
-    .multipart(form)
-    .send()
-    .await
-    .map_err(|e| anyhow::anyhow!("Failed to send merge request: {}", e))?;
-
-  if !response.status().is_success() {
-    let status = response.status();
-    let error_text = response
-      .text()
-      .await
-      .unwrap_or_else(|_| "Unknown error".to_string());
-
-    match status.as_u16() {
-      401 => {
-        return Err(anyhow::anyhow!(
-          "Authentication failed: Invalid or expired JWT token"
-        ))
+/// Parse a PDF, rejecting encrypted files that cannot be opened without a password
+pub(crate) fn load_pdf(bytes: &[u8]) -> Result<lopdf::Document> {
+  let doc =
+    lopdf::Document::load_mem(bytes).map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+
+  if doc.is_encrypted() && doc.encryption_state.is_none() {
+    return Err(anyhow::anyhow!(
+      "PDF is password protected; remove the password and upload it again"
+    ));
+  }
+
+  Ok(doc)
+}
+
+/// Copy attributes every page inherits from its page tree ancestors onto the page itself
+pub(crate) fn push_down_inherited_attributes(doc: &mut lopdf::Document) {
+  for page_id in doc.get_pages().into_values() {
+    let mut inherited = Vec::new();
+
+    for key in INHERITABLE_PAGE_ATTRIBUTES {
+      let Ok(page) = doc.get_dictionary(page_id) else {
+        break;
+      };
+      if page.has(key.as_bytes()) {
+        continue;
       }
-      403 => return Err(anyhow::anyhow!("Access denied: Insufficient permissions")),
-      413 => {
-        return Err(anyhow::anyhow!(
-          "Request too large: PDF files exceed size limit"
-        ))
+
+      // Walk up the tree to the nearest ancestor defining the attribute
+      let mut parent = page.get(b"Parent").and_then(Object::as_reference).ok();
+      while let Some(parent_id) = parent {
+        let Ok(node) = doc.get_dictionary(parent_id) else {
+          break;
+        };
+        if let Ok(value) = node.get(key.as_bytes()) {
+          inherited.push((key, value.clone()));
+          break;
+        }
+        parent = node.get(b"Parent").and_then(Object::as_reference).ok();
       }
-      _ => {
-        return Err(anyhow::anyhow!(
-          "SASE API returned error {}: {}",
-          status,
-          error_text
-        ))
+    }
+
+    if let Ok(page) = doc.get_dictionary_mut(page_id) {
+      for (key, value) in inherited {
+        page.set(key, value);
       }
     }
   }
+}
 
-  let merged_pdf_bytes = response
-    .bytes()
-    .await
-    .map_err(|e| anyhow::anyhow!("Failed to read response bytes: {}", e))?;
+/// Text stamped onto every page of a finished PDF with [`stamp_pdf`]
+pub struct PdfStamp {
+  /// Running header at the top of each page, e.g. the applicant name and target address
+  pub header: Option<String>,
+  /// "Page X of Y" footer
+  pub page_numbers: bool,
+  /// Faint text across the diagonal of each page
+  pub watermark: Option<String>,
+}
+
+const STAMP_FONT: &str = "StampHelvetica";
+const STAMP_GRAPHICS_STATE: &str = "StampWatermark";
+
+/// Overlay headers, page numbers and a watermark on every page of a PDF. The existing page
+/// content is wrapped in its own graphics state, so whatever state it leaves behind cannot
+/// shift or hide the stamp. Stamps are drawn upright relative to how pages are displayed.
+pub fn stamp_pdf(bytes: &[u8], stamp: &PdfStamp) -> Result<Vec<u8>> {
+  let mut doc = load_pdf(bytes)?;
+  // Pages get their own resources below, so inherited ones must be on the page first
+  push_down_inherited_attributes(&mut doc);
+
+  let font_id = doc.add_object(dictionary! {
+    "Type" => "Font",
+    "Subtype" => "Type1",
+    "BaseFont" => "Helvetica",
+    "Encoding" => "WinAnsiEncoding",
+  });
+  let graphics_state_id = doc.add_object(dictionary! {
+    "Type" => "ExtGState",
+    "ca" => 0.15,
+    "CA" => 0.15,
+  });
+
+  let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+  let page_count = pages.len();
+
+  for (i, page_id) in pages.into_iter().enumerate() {
+    let geometry = page_geometry(&doc, page_id)?;
+    let resources = stamp_resources(&doc, page_id, font_id, graphics_state_id)?;
+    let content = stamp_content(stamp, &geometry, i + 1, page_count);
+
+    let mut contents = vec![Object::Reference(doc.add_object(lopdf::Stream::new(
+      lopdf::Dictionary::new(),
+      b"q\n".to_vec(),
+    )))];
+    contents.extend(
+      doc
+        .get_page_contents(page_id)
+        .into_iter()
+        .map(Object::Reference),
+    );
+    contents.push(Object::Reference(
+      doc.add_object(lopdf::Stream::new(
+        lopdf::Dictionary::new(),
+        content
+          .encode()
+          .map_err(|e| anyhow::anyhow!("Failed to encode stamp: {}", e))?,
+      )),
+    ));
+
+    let page = doc.get_dictionary_mut(page_id)?;
+    page.set("Resources", resources);
+    page.set("Contents", contents);
+  }
+
+  doc.compress();
+
+  let mut bytes = Vec::new();
+  doc
+    .save_to(&mut bytes)
+    .map_err(|e| anyhow::anyhow!("Failed to write stamped PDF: {}", e))?;
+  Ok(bytes)
+}
+
+/// Visible area of a page in points, and its clockwise display rotation in degrees
+pub(crate) struct PageGeometry {
+  x: f32,
+  y: f32,
+  width: f32,
+  height: f32,
+  rotate: i64,
+}
+
+impl PageGeometry {
+  /// Width and height of the page as it is displayed, and the matrix mapping those display
+  /// coordinates (origin at the bottom left) onto the page's own coordinate system
+  pub(crate) fn display_transform(&self) -> (f32, f32, [f32; 6]) {
+    let (x, y, width, height) = (self.x, self.y, self.width, self.height);
+    match self.rotate {
+      90 => (height, width, [0.0, 1.0, -1.0, 0.0, x + width, y]),
+      180 => (width, height, [-1.0, 0.0, 0.0, -1.0, x + width, y + height]),
+      270 => (height, width, [0.0, -1.0, 1.0, 0.0, x, y + height]),
+      _ => (width, height, [1.0, 0.0, 0.0, 1.0, x, y]),
+    }
+  }
+}
+
+pub(crate) fn page_geometry(doc: &lopdf::Document, page_id: ObjectId) -> Result<PageGeometry> {
+  let page = doc.get_dictionary(page_id)?;
+
+  let bounds = ["CropBox", "MediaBox"]
+    .into_iter()
+    .filter_map(|key| page.get(key.as_bytes()).ok())
+    .filter_map(|object| doc.dereference(object).ok()?.1.as_array().ok())
+    .find_map(|values| {
+      let values: Vec<f32> = values.iter().filter_map(|v| v.as_float().ok()).collect();
+      (values.len() == 4).then_some(values)
+    })
+    // US Letter, the default when a page states no size
+    .unwrap_or_else(|| vec![0.0, 0.0, 612.0, 792.0]);
+
+  let rotate = page
+    .get(b"Rotate")
+    .and_then(Object::as_i64)
+    .unwrap_or(0)
+    .rem_euclid(360);
+
+  Ok(PageGeometry {
+    x: bounds[0].min(bounds[2]),
+    y: bounds[1].min(bounds[3]),
+    width: (bounds[2] - bounds[0]).abs(),
+    height: (bounds[3] - bounds[1]).abs(),
+    rotate,
+  })
+}
+
+/// The page's resources plus the stamp font and graphics state, as a standalone dictionary
+fn stamp_resources(
+  doc: &lopdf::Document,
+  page_id: ObjectId,
+  font_id: ObjectId,
+  graphics_state_id: ObjectId,
+) -> Result<lopdf::Dictionary> {
+  let page = doc.get_dictionary(page_id)?;
+  let mut resources = match page.get(b"Resources") {
+    Ok(object) => doc.dereference(object)?.1.as_dict()?.clone(),
+    Err(_) => lopdf::Dictionary::new(),
+  };
+
+  for (category, name, id) in [
+    ("Font", STAMP_FONT, font_id),
+    ("ExtGState", STAMP_GRAPHICS_STATE, graphics_state_id),
+  ] {
+    let mut entries = match resources.get(category.as_bytes()) {
+      Ok(object) => doc.dereference(object)?.1.as_dict()?.clone(),
+      Err(_) => lopdf::Dictionary::new(),
+    };
+    entries.set(name, id);
+    resources.set(category, entries);
+  }
+
+  Ok(resources)
+}
+
+fn stamp_content(
+  stamp: &PdfStamp,
+  page: &PageGeometry,
+  page_number: usize,
+  page_count: usize,
+) -> lopdf::content::Content {
+  use lopdf::content::Operation;
+
+  // Draw in coordinates as the page is displayed
+  let (width, height, matrix) = page.display_transform();
+
+  let mut operations = vec![
+    Operation::new("Q", vec![]),
+    Operation::new("q", vec![]),
+    Operation::new("cm", matrix.into_iter().map(Object::Real).collect()),
+  ];
+
+  let margin = 24.0;
+  if let Some(header) = &stamp.header {
+    let size = 8.0;
+    let text = fit_text(header, (width - 2.0 * margin) / MM_TO_PT, size);
+    push_stamp_text(&mut operations, &text, margin, height - margin, size, 0.35);
+  }
+
+  if stamp.page_numbers {
+    let size = 8.0;
+    let text = format!("Page {} of {}", page_number, page_count);
+    let x = (width - text_width_mm(&text, size) * MM_TO_PT) / 2.0;
+    push_stamp_text(&mut operations, &text, x, margin - size / 2.0, size, 0.35);
+  }
 
-  Ok(merged_pdf_bytes.to_vec())
+  if let Some(watermark) = &stamp.watermark {
+    let diagonal = (width * width + height * height).sqrt();
+    let angle = height.atan2(width);
+    // Span most of the diagonal, without becoming huge for short texts
+    let size = (diagonal * 0.8 / (text_width_mm(watermark, 1.0) * MM_TO_PT)).min(48.0);
+    let text_width = text_width_mm(watermark, size) * MM_TO_PT;
+
+    operations.push(Operation::new("q", vec![]));
+    operations.push(Operation::new(
+      "gs",
+      vec![Object::Name(STAMP_GRAPHICS_STATE.into())],
+    ));
+    operations.push(Operation::new(
+      "cm",
+      vec![
+        angle.cos().into(),
+        angle.sin().into(),
+        (-angle.sin()).into(),
+        angle.cos().into(),
+        (width / 2.0).into(),
+        (height / 2.0).into(),
+      ],
+    ));
+    push_stamp_text(
+      &mut operations,
+      watermark,
+      -text_width / 2.0,
+      -size / 3.0,
+      size,
+      0.5,
+    );
+    operations.push(Operation::new("Q", vec![]));
+  }
+
+  operations.push(Operation::new("Q", vec![]));
+
+  lopdf::content::Content { operations }
+}
+
+const MM_TO_PT: f32 = 72.0 / 25.4;
+
+fn push_stamp_text(
+  operations: &mut Vec<lopdf::content::Operation>,
+  text: &str,
+  x: f32,
+  y: f32,
+  size: f32,
+  gray: f32,
+) {
+  use lopdf::content::Operation;
+
+  operations.extend([
+    Operation::new("BT", vec![]),
+    Operation::new("Tf", vec![Object::Name(STAMP_FONT.into()), size.into()]),
+    Operation::new("g", vec![gray.into()]),
+    Operation::new("Td", vec![x.into(), y.into()]),
+    Operation::new("Tj", vec![Object::string_literal(win_ansi(text))]),
+    Operation::new("ET", vec![]),
+  ]);
+}
+
+/// Encode text for a standard font with WinAnsiEncoding; characters it lacks become '?'
+pub(crate) fn win_ansi(text: &str) -> Vec<u8> {
+  text
+    .chars()
+    .map(|c| match c {
+      '\u{20}'..='\u{7e}' | '\u{a0}'..='\u{ff}' => c as u8,
+      '\u{2013}' => 0x96,
+      '\u{2014}' => 0x97,
+      '\u{2018}' => 0x91,
+      '\u{2019}' => 0x92,
+      '\u{201c}' => 0x93,
+      '\u{201d}' => 0x94,
+      '\u{2022}' => 0x95,
+      '\u{2026}' => 0x85,
+      '\u{20ac}' => 0x80,
+      _ => b'?',
+    })
+    .collect()
+}
+
+/// Contents of a document added to a packet
+pub enum PacketContent {
+  Pdf(Vec<u8>),
+  /// Any image format the `image` crate decodes, placed on its own page
+  Image(Vec<u8>),
+}
+
+pub struct PacketDocument {
+  pub title: String,
+  /// Shown under the title on the divider page, e.g. the document type
+  pub subtitle: Option<String>,
+  pub content: PacketContent,
+}
+
+/// Cover page of an application packet
+pub struct PacketCover {
+  pub title: String,
+  /// Label and value lines, such as the applicant's name and contact details
+  pub fields: Vec<(String, String)>,
 }
 
-pub async fn merge_mixed_to_pdf_via_sase_api_with_pdfs(
+/// Stage of a long PDF build, reported to the UI as the build goes
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "stage", rename_all = "snake_case")]
+pub enum PdfBuildStage {
+  /// Reading document `index` (1-based) of `total` from the vault
+  LoadingDocument { index: usize, total: usize },
+  /// Rendering image document `index` of `total` image documents to PDF pages
+  ConvertingImage { index: usize, total: usize },
+  /// Joining everything into one PDF
+  Merging,
+  /// Adding page numbers, headers and watermarks
+  Stamping,
+  /// Sending PDFs to the SASE merge API
+  Uploading { sent: u64, total: u64 },
+  /// Trying compression level `attempt` of `attempts` to get under the size budget
+  Compressing { attempt: usize, attempts: usize },
+  /// The output fits the size budget
+  SizeReport(PdfSizeReport),
+}
+
+/// Receives progress from long PDF builds and tells them when to stop
+pub trait PdfBuildMonitor: Send + Sync {
+  fn report(&self, stage: PdfBuildStage);
+  /// Fails once the build has been cancelled. Builds check it between steps.
+  fn check_cancelled(&self) -> Result<()>;
+}
+
+const PAGE_MARGIN: f32 = 20.0;
+/// Distance of the first table of contents entry from the top of the page
+const TOC_TOP_OFFSET: f32 = 45.0;
+const TOC_LINE_HEIGHT: f32 = 8.0;
+
+/// Append documents to a base PDF in the given order, each with an outline entry
+pub fn merge_documents(
   first_pdf: Vec<u8>,
-  additional_blobs: Vec<Vec<u8>>,
-  additional_pdfs: Vec<Vec<u8>>,
-  headers: Vec<String>,
-  jwt_token: &str,
+  documents: Vec<PacketDocument>,
+  options: &PdfRenderOptions,
+  monitor: &dyn PdfBuildMonitor,
 ) -> Result<Vec<u8>> {
-  use std::env;
+  if documents.is_empty() {
+    return Ok(first_pdf);
+  }
 
-  let temp_dir = env::temp_dir();
-  let mut pdf_files_to_merge = Vec::new();
+  let mut parts = vec![PdfPart {
+    bytes: first_pdf,
+    bookmark: None,
+  }];
+  for (title, _, bytes, _) in convert_documents(documents, options, monitor)? {
+    parts.push(PdfPart {
+      bytes,
+      bookmark: Some(title),
+    });
+  }
+
+  monitor.check_cancelled()?;
+  monitor.report(PdfBuildStage::Merging);
+  merge_pdfs(&parts)
+}
 
-  let base_size_mb = first_pdf.len() as f64 / (1024.0 * 1024.0);
-  println!("Base PDF size: {:.2} MB", base_size_mb);
+/// Build an application packet: a cover page, a table of contents with page numbers, then
+/// every document in the given order behind a divider page. Each divider gets an outline entry.
+pub fn build_packet(
+  cover: &PacketCover,
+  documents: Vec<PacketDocument>,
+  options: &PdfRenderOptions,
+  monitor: &dyn PdfBuildMonitor,
+) -> Result<Vec<u8>> {
+  if documents.is_empty() {
+    return Err(anyhow::anyhow!("A packet needs at least one document"));
+  }
 
-  let mut total_size = first_pdf.len();
+  let sections = convert_documents(documents, options, monitor)?;
+  monitor.check_cancelled()?;
+  monitor.report(PdfBuildStage::Merging);
 
-  if !additional_blobs.is_empty() {
-    println!(
-      "Converting {} images to individual PDFs",
-      additional_blobs.len()
+  // Generated pages match the paper size of the image pages
+  let page = options.page_size.portrait_mm();
+
+  // Page numbers are known up front: cover, contents, then a divider and the document pages
+  let entries_per_page =
+    ((page.1 - TOC_TOP_OFFSET - PAGE_MARGIN) / TOC_LINE_HEIGHT).floor() as usize;
+  let toc_pages = sections.len().div_ceil(entries_per_page);
+  let total_pages = 1 + toc_pages + sections.iter().map(|s| 1 + s.3).sum::<u32>() as usize;
+  let mut next_page = 1 + toc_pages + 1;
+  let mut toc_entries = Vec::new();
+  for (title, _, _, pages) in &sections {
+    toc_entries.push((title.clone(), next_page));
+    next_page += 1 + *pages as usize;
+  }
+
+  let mut parts = vec![
+    PdfPart {
+      bytes: render_pages(
+        &cover.title,
+        page,
+        vec![cover_page_ops(page, cover, sections.len(), total_pages)],
+      ),
+      bookmark: Some("Cover".to_string()),
+    },
+    PdfPart {
+      bytes: render_pages(
+        "Contents",
+        page,
+        toc_pages_ops(page, &toc_entries, entries_per_page),
+      ),
+      bookmark: Some("Contents".to_string()),
+    },
+  ];
+
+  let section_count = sections.len();
+  for (i, (title, subtitle, bytes, pages)) in sections.into_iter().enumerate() {
+    let divider = divider_page_ops(
+      page,
+      &title,
+      subtitle.as_deref(),
+      i + 1,
+      section_count,
+      pages,
     );
+    parts.push(PdfPart {
+      bytes: render_pages(&title, page, vec![divider]),
+      bookmark: Some(title),
+    });
+    parts.push(PdfPart {
+      bytes,
+      bookmark: None,
+    });
+  }
 
-    for (i, blob) in additional_blobs.iter().enumerate() {
-      let size_mb = blob.len() as f64 / (1024.0 * 1024.0);
-      println!("Image {}: {:.2} MB", i + 1, size_mb);
-      total_size += blob.len();
+  merge_pdfs(&parts)
+}
 
-      let temp_image_pdf = temp_dir.join(format!("temp_sase_api_image_{}.pdf", i));
-      blobs_to_pdf(&[blob.clone()], &temp_image_pdf)?;
+/// A packet document converted to PDF: title, subtitle, PDF bytes and page count
+type ConvertedDocument = (String, Option<String>, Vec<u8>, u32);
 
-      let image_pdf_bytes = fs::read(&temp_image_pdf)
-        .map_err(|e| anyhow::anyhow!("Failed to read generated image PDF {}: {}", i, e))?;
+/// Convert packet documents to PDFs, reporting each image conversion and stopping between
+/// documents once cancelled
+fn convert_documents(
+  documents: Vec<PacketDocument>,
+  options: &PdfRenderOptions,
+  monitor: &dyn PdfBuildMonitor,
+) -> Result<Vec<ConvertedDocument>> {
+  let is_image = |document: &PacketDocument| matches!(document.content, PacketContent::Image(_));
+  let image_count = documents.iter().filter(|d| is_image(d)).count();
+  let mut image_index = 0;
 
-      let image_pdf_size_mb = image_pdf_bytes.len() as f64 / (1024.0 * 1024.0);
-      println!(
-        "Generated image PDF {} size: {:.2} MB",
-        i + 1,
-        image_pdf_size_mb
-      );
+  let mut converted = Vec::new();
+  for (i, document) in documents.into_iter().enumerate() {
+    monitor.check_cancelled()?;
+    if is_image(&document) {
+      image_index += 1;
+      monitor.report(PdfBuildStage::ConvertingImage {
+        index: image_index,
+        total: image_count,
+      });
+    }
+
+    let (bytes, pages) = packet_document_pdf(document.content, options)
+      .map_err(|e| anyhow::anyhow!("Document '{}' ({}): {}", document.title, i + 1, e))?;
+    converted.push((document.title, document.subtitle, bytes, pages));
+  }
+
+  Ok(converted)
+}
 
-      pdf_files_to_merge.push(image_pdf_bytes);
-      let _ = fs::remove_file(&temp_image_pdf);
+/// A packet document as PDF bytes, with its page count
+fn packet_document_pdf(
+  content: PacketContent,
+  options: &PdfRenderOptions,
+) -> Result<(Vec<u8>, u32)> {
+  match content {
+    PacketContent::Pdf(bytes) => {
+      let pages = load_pdf(&bytes)?.get_pages().len() as u32;
+      if pages == 0 {
+        return Err(anyhow::anyhow!("PDF has no pages"));
+      }
+      Ok((bytes, pages))
+    }
+    PacketContent::Image(bytes) => {
+      let pdf = images_to_pdfs(&[bytes], options)?
+        .pop()
+        .ok_or_else(|| anyhow::anyhow!("Failed to convert image"))?;
+      let pages = load_pdf(&pdf)?.get_pages().len() as u32;
+      Ok((pdf, pages))
     }
   }
+}
 
-  let pdf_headers = headers;
+fn render_pages(title: &str, (width, height): (f32, f32), pages: Vec<Vec<Op>>) -> Vec<u8> {
+  let mut pdf_doc = PdfDocument::new(title);
+  let mut warnings = Vec::<PdfWarnMsg>::new();
 
-  for (i, pdf) in additional_pdfs.iter().enumerate() {
-    let size_mb = pdf.len() as f64 / (1024.0 * 1024.0);
-    println!("Additional PDF {}: {:.2} MB", i + 1, size_mb);
-    total_size += pdf.len();
+  for ops in pages {
+    pdf_doc.pages.push(PdfPage::new(Mm(width), Mm(height), ops));
   }
-  pdf_files_to_merge.extend(additional_pdfs);
 
-  let total_size_mb = total_size as f64 / (1024.0 * 1024.0);
-  println!("Total size to merge: {:.2} MB", total_size_mb);
+  pdf_doc.save(&PdfSaveOptions::default(), &mut warnings)
+}
 
-  if pdf_files_to_merge.is_empty() {
-    return Ok(first_pdf);
+fn cover_page_ops(
+  (page_width, page_height): (f32, f32),
+  cover: &PacketCover,
+  documents: usize,
+  total_pages: usize,
+) -> Vec<Op> {
+  let mut ops = Vec::new();
+  let text_width = page_width - 2.0 * PAGE_MARGIN;
+
+  push_filled_rect(
+    &mut ops,
+    0.0,
+    page_height - 75.0,
+    page_width,
+    75.0,
+    (0.93, 0.95, 0.98),
+  );
+  push_text(
+    &mut ops,
+    &fit_text(&cover.title, text_width, 24.0),
+    PAGE_MARGIN,
+    page_height - 45.0,
+    24.0,
+    BuiltinFont::HelveticaBold,
+  );
+  push_text(
+    &mut ops,
+    &format!("{} documents, {} pages", documents, total_pages),
+    PAGE_MARGIN,
+    page_height - 58.0,
+    11.0,
+    BuiltinFont::Helvetica,
+  );
+
+  let label_width = 45.0;
+  let mut y = page_height - 100.0;
+  for (label, value) in &cover.fields {
+    push_text(
+      &mut ops,
+      &fit_text(label, label_width, 11.0),
+      PAGE_MARGIN,
+      y,
+      11.0,
+      BuiltinFont::HelveticaBold,
+    );
+    push_text(
+      &mut ops,
+      &fit_text(value, text_width - label_width, 11.0),
+      PAGE_MARGIN + label_width,
+      y,
+      11.0,
+      BuiltinFont::Helvetica,
+    );
+    y -= 9.0;
+    if y < PAGE_MARGIN {
+      break;
+    }
   }
 
-  if total_size_mb > 20.0 {
-    println!(
-      "⚠️  WARNING: Large file size ({:.2} MB) - API may reject request",
-      total_size_mb
+  ops
+}
+
+fn toc_pages_ops(
+  (page_width, page_height): (f32, f32),
+  entries: &[(String, usize)],
+  entries_per_page: usize,
+) -> Vec<Vec<Op>> {
+  let number_width = 15.0;
+  let title_width = page_width - 2.0 * PAGE_MARGIN - number_width;
+
+  entries
+    .chunks(entries_per_page)
+    .enumerate()
+    .map(|(chunk, entries)| {
+      let mut ops = Vec::new();
+      push_text(
+        &mut ops,
+        "Contents",
+        PAGE_MARGIN,
+        page_height - 30.0,
+        18.0,
+        BuiltinFont::HelveticaBold,
+      );
+
+      let mut y = page_height - TOC_TOP_OFFSET;
+      for (i, (title, page)) in entries.iter().enumerate() {
+        let number = chunk * entries_per_page + i + 1;
+        push_text(
+          &mut ops,
+          &fit_text(&format!("{}. {}", number, title), title_width, 11.0),
+          PAGE_MARGIN,
+          y,
+          11.0,
+          BuiltinFont::Helvetica,
+        );
+        let page = page.to_string();
+        push_text(
+          &mut ops,
+          &page,
+          page_width - PAGE_MARGIN - text_width_mm(&page, 11.0),
+          y,
+          11.0,
+          BuiltinFont::Helvetica,
+        );
+        y -= TOC_LINE_HEIGHT;
+      }
+
+      ops
+    })
+    .collect()
+}
+
+fn divider_page_ops(
+  (page_width, page_height): (f32, f32),
+  title: &str,
+  subtitle: Option<&str>,
+  number: usize,
+  count: usize,
+  pages: u32,
+) -> Vec<Op> {
+  let mut ops = Vec::new();
+  let text_width = page_width - 2.0 * PAGE_MARGIN;
+  let mut y = page_height / 2.0 + 20.0;
+
+  push_text(
+    &mut ops,
+    &format!("Section {} of {}", number, count),
+    PAGE_MARGIN,
+    y,
+    11.0,
+    BuiltinFont::Helvetica,
+  );
+  y -= 8.0;
+  push_filled_rect(&mut ops, PAGE_MARGIN, y, text_width, 0.6, (0.2, 0.2, 0.2));
+  y -= 14.0;
+  push_text(
+    &mut ops,
+    &fit_text(title, text_width, 22.0),
+    PAGE_MARGIN,
+    y,
+    22.0,
+    BuiltinFont::HelveticaBold,
+  );
+  if let Some(subtitle) = subtitle {
+    y -= 10.0;
+    push_text(
+      &mut ops,
+      &fit_text(subtitle, text_width, 12.0),
+      PAGE_MARGIN,
+      y,
+      12.0,
+      BuiltinFont::Helvetica,
     );
-    println!("💡 Consider reducing image quality or splitting into smaller batches");
+  }
+  y -= 10.0;
+  push_text(
+    &mut ops,
+    &format!("{} {}", pages, if pages == 1 { "page" } else { "pages" }),
+    PAGE_MARGIN,
+    y,
+    10.0,
+    BuiltinFont::HelveticaOblique,
+  );
+
+  ops
+}
+
+/// Convert each image to its own PDF, with a page per TIFF page
+fn images_to_pdfs(blobs: &[Vec<u8>], options: &PdfRenderOptions) -> Result<Vec<Vec<u8>>> {
+  let mut pdfs = Vec::new();
+
+  for (i, blob) in blobs.iter().enumerate() {
+    let image_pdf_bytes = blobs_to_pdf(std::slice::from_ref(blob), options)
+      .map_err(|e| anyhow::anyhow!("Failed to convert image {}: {}", i + 1, e))?;
+    pdfs.push(image_pdf_bytes);
+  }
+
+  Ok(pdfs)
+}
+
+pub async fn merge_pdfs_via_sase_api(
+  base_pdf_bytes: Vec<u8>,
+  additional_pdf_bytes: Vec<Vec<u8>>,
+  headers: Vec<String>,
+  jwt_token: &str,
+  monitor: Arc<dyn PdfBuildMonitor>,
+) -> Result<Vec<u8>> {
+  let progress: UploadProgressFn = Arc::new(move |sent, total| {
+    monitor.report(PdfBuildStage::Uploading { sent, total });
+  });
+  let request = MergePdfsRequest {
+    base_pdf: base_pdf_bytes,
+    additional_pdfs: additional_pdf_bytes,
+    headers,
+  };
+  let merged_pdf_bytes = SaseApiClient::shared()?
+    .merge_pdfs(request, jwt_token, Some(progress))
+    .await?;
+
+  Ok(merged_pdf_bytes)
+}
+
+/// Append documents to a base PDF in the given order using the SASE merge API
+pub async fn merge_documents_via_sase_api(
+  first_pdf: Vec<u8>,
+  documents: Vec<PacketDocument>,
+  options: &PdfRenderOptions,
+  jwt_token: &str,
+  monitor: Arc<dyn PdfBuildMonitor>,
+) -> Result<Vec<u8>> {
+  let target_size_bytes = options.target_size_bytes;
+  if documents.is_empty() {
+    return tokio::task::spawn_blocking(move || {
+      apply_size_budget(first_pdf, target_size_bytes, monitor.as_ref())
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("Compression task failed: {}", e))?;
   }
 
-  println!("PDF merge headers: {:?}", pdf_headers);
+  // Image conversion is CPU bound, keep it off the async runtime
+  let options = options.clone();
+  let conversion_monitor = monitor.clone();
+  let converted = tokio::task::spawn_blocking(move || {
+    convert_documents(documents, &options, conversion_monitor.as_ref())
+  })
+  .await
+  .map_err(|e| anyhow::anyhow!("Image conversion task failed: {}", e))??;
+  monitor.check_cancelled()?;
+
+  // The API merges what it is sent, so each upload is shrunk to fit the budget together
+  let (first_pdf, converted) = match target_size_bytes {
+    Some(target) => {
+      let pdfs = std::iter::once(("Base document".to_string(), first_pdf))
+        .chain(
+          converted
+            .into_iter()
+            .map(|(title, _, bytes, _)| (title, bytes)),
+        )
+        .collect();
+      let fit_monitor = monitor.clone();
+      let (mut pdfs, report) =
+        tokio::task::spawn_blocking(move || fit_pdfs_to_size(pdfs, target, fit_monitor.as_ref()))
+          .await
+          .map_err(|e| anyhow::anyhow!("Compression task failed: {}", e))??;
+      monitor.report(PdfBuildStage::SizeReport(report));
+
+      let (_, first_pdf) = pdfs.remove(0);
+      (first_pdf, pdfs)
+    }
+    None => (
+      first_pdf,
+      converted
+        .into_iter()
+        .map(|(title, _, bytes, _)| (title, bytes))
+        .collect(),
+    ),
+  };
+
+  let mut total_size = first_pdf.len();
+  let mut pdf_files_to_merge = Vec::new();
+  let mut pdf_headers = Vec::new();
+  for (title, bytes) in converted {
+    total_size += bytes.len();
+    pdf_files_to_merge.push(bytes);
+    pdf_headers.push(title);
+  }
+
+  println!(
+    "Merging {} documents, {:.2} MB",
+    pdf_files_to_merge.len() + 1,
+    total_size as f64 / (1024.0 * 1024.0)
+  );
 
   // Use SASE API to merge all PDFs
-  let merged_bytes =
-    merge_pdfs_via_sase_api(first_pdf, pdf_files_to_merge, pdf_headers, jwt_token).await?;
+  let merged_bytes = merge_pdfs_via_sase_api(
+    first_pdf,
+    pdf_files_to_merge,
+    pdf_headers,
+    jwt_token,
+    monitor,
+  )
+  .await?;
 
   Ok(merged_bytes)
 }
 
-pub fn create_test_pdf(out: &Path) -> Result<()> {
+/// A blank A4 page, to check that PDF generation works
+pub fn create_test_pdf() -> Vec<u8> {
   let mut pdf_doc = PdfDocument::new("Test PDF");
   let mut warnings = Vec::<PdfWarnMsg>::new();
 
@@ -275,10 +1235,191 @@ pub fn create_test_pdf(out: &Path) -> Result<()> {
   pdf_doc.pages.push(page);
 
   let save_options = PdfSaveOptions::default();
-  let pdf_bytes = pdf_doc.save(&save_options, &mut warnings);
+  pdf_doc.save(&save_options, &mut warnings)
+}
 
-  fs::write(out, pdf_bytes)
-    .map_err(|e| anyhow::anyhow!("Failed to write test PDF to {}: {}", out.display(), e))?;
+/// Render a simple comparison table (one column per item, one row per field) on landscape A4 pages
+pub fn table_to_pdf(title: &str, columns: &[String], rows: &[PdfTableRow]) -> Result<Vec<u8>> {
+  if columns.is_empty() {
+    return Err(anyhow::anyhow!("No columns provided"));
+  }
 
-  Ok(())
+  let mut pdf_doc = PdfDocument::new(title);
+  let mut warnings = Vec::<PdfWarnMsg>::new();
+
+  let page_width = 297.0;
+  let page_height = 210.0;
+  let margin = 12.0;
+  let label_width = 48.0;
+  let row_height = 8.0;
+  let font_size = 8.0;
+  let column_width = (page_width - 2.0 * margin - label_width) / columns.len() as f32;
+
+  let header_ops = |ops: &mut Vec<Op>| {
+    push_text(
+      ops,
+      title,
+      margin,
+      page_height - margin,
+      14.0,
+      BuiltinFont::HelveticaBold,
+    );
+    let y = page_height - margin - 10.0;
+    for (i, column) in columns.iter().enumerate() {
+      let x = margin + label_width + i as f32 * column_width;
+      push_text(
+        ops,
+        &fit_text(column, column_width, font_size),
+        x + 1.0,
+        y,
+        font_size,
+        BuiltinFont::HelveticaBold,
+      );
+    }
+    y - row_height
+  };
+
+  let mut ops = Vec::new();
+  let mut y = header_ops(&mut ops);
+
+  for row in rows {
+    if y < margin {
+      pdf_doc
+        .pages
+        .push(PdfPage::new(Mm(page_width), Mm(page_height), ops));
+      ops = Vec::new();
+      y = header_ops(&mut ops);
+    }
+
+    push_text(
+      &mut ops,
+      &fit_text(&row.label, label_width, font_size),
+      margin,
+      y,
+      font_size,
+      BuiltinFont::HelveticaBold,
+    );
+
+    for (i, (text, highlight)) in row.cells.iter().enumerate() {
+      let x = margin + label_width + i as f32 * column_width;
+      let fill = match highlight {
+        CellHighlight::Best => Some((0.85, 0.95, 0.85)),
+        CellHighlight::Worst => Some((0.98, 0.87, 0.87)),
+        CellHighlight::None => None,
+      };
+      if let Some((r, g, b)) = fill {
+        push_filled_rect(
+          &mut ops,
+          x,
+          y - 2.5,
+          column_width - 1.0,
+          row_height - 1.0,
+          (r, g, b),
+        );
+      }
+      push_text(
+        &mut ops,
+        &fit_text(text, column_width, font_size),
+        x + 1.0,
+        y,
+        font_size,
+        BuiltinFont::Helvetica,
+      );
+    }
+
+    y -= row_height;
+  }
+
+  pdf_doc
+    .pages
+    .push(PdfPage::new(Mm(page_width), Mm(page_height), ops));
+
+  let save_options = PdfSaveOptions::default();
+  Ok(pdf_doc.save(&save_options, &mut warnings))
+}
+
+/// Write a single line of text with a builtin font, `x`/`y` in mm from the bottom-left corner
+pub fn push_text(ops: &mut Vec<Op>, text: &str, x: f32, y: f32, size: f32, font: BuiltinFont) {
+  ops.push(Op::StartTextSection);
+  ops.push(Op::SetFillColor {
+    col: Color::Rgb(Rgb::new(0.0, 0.0, 0.0, None)),
+  });
+  ops.push(Op::SetFontSizeBuiltinFont {
+    size: Pt(size),
+    font,
+  });
+  ops.push(Op::SetTextCursor {
+    pos: Point::new(Mm(x), Mm(y)),
+  });
+  ops.push(Op::WriteTextBuiltinFont {
+    items: vec![TextItem::Text(text.to_string())],
+    font,
+  });
+  ops.push(Op::EndTextSection);
+}
+
+/// Fill a rectangle, `x`/`y` in mm from the bottom-left corner
+pub fn push_filled_rect(
+  ops: &mut Vec<Op>,
+  x: f32,
+  y: f32,
+  width: f32,
+  height: f32,
+  (r, g, b): (f32, f32, f32),
+) {
+  let corner = |x: f32, y: f32| LinePoint {
+    p: Point::new(Mm(x), Mm(y)),
+    bezier: false,
+  };
+
+  ops.push(Op::SaveGraphicsState);
+  ops.push(Op::SetFillColor {
+    col: Color::Rgb(Rgb::new(r, g, b, None)),
+  });
+  ops.push(Op::DrawPolygon {
+    polygon: Polygon {
+      rings: vec![PolygonRing {
+        points: vec![
+          corner(x, y),
+          corner(x + width, y),
+          corner(x + width, y + height),
+          corner(x, y + height),
+        ],
+      }],
+      mode: PaintMode::Fill,
+      winding_order: WindingOrder::NonZero,
+    },
+  });
+  ops.push(Op::RestoreGraphicsState);
+}
+
+/// Approximate width of a line of text in mm, using an average Helvetica glyph width
+pub fn text_width_mm(text: &str, font_size: f32) -> f32 {
+  text.chars().count() as f32 * font_size * 0.5 * 0.352778
+}
+
+/// Truncate text so it fits in `width_mm` at `font_size`, using an average Helvetica glyph width
+pub fn fit_text(text: &str, width_mm: f32, font_size: f32) -> String {
+  let char_width_mm = font_size * 0.5 * 0.352778;
+  let max_chars = (width_mm / char_width_mm).floor().max(1.0) as usize;
+
+  // Builtin fonts only cover WinAnsi, so drop anything they cannot draw
+  let text: String = text
+    .chars()
+    .map(|c| {
+      if c.is_ascii() && !c.is_ascii_control() {
+        c
+      } else {
+        '?'
+      }
+    })
+    .collect();
+
+  if text.chars().count() <= max_chars {
+    text
+  } else {
+    let mut truncated: String = text.chars().take(max_chars.saturating_sub(3)).collect();
+    truncated.push_str("...");
+    truncated
+  }
 }
@@ -0,0 +1,1039 @@
+use super::pdf_docs::{load_pdf, push_down_inherited_attributes, win_ansi};
+use anyhow::Result;
+use lopdf::content::{Content, Operation};
+use lopdf::{decode_text_string, dictionary, text_string, Dictionary, Document, Object, ObjectId};
+use lopdf::{Stream, StringFormat};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+
+/// Field flags, PDF 32000-1 tables 221, 226 and 228
+const FLAG_READ_ONLY: i64 = 1;
+const FLAG_MULTILINE: i64 = 1 << 12;
+const FLAG_RADIO: i64 = 1 << 15;
+const FLAG_PUSHBUTTON: i64 = 1 << 16;
+/// Annotation flag for widgets that are not shown
+const ANNOTATION_HIDDEN: i64 = 1 << 1;
+
+const FIELD_FONT: &str = "FormHelvetica";
+const DEFAULT_FONT_SIZE: f32 = 10.0;
+const MIN_FONT_SIZE: f32 = 4.0;
+/// Padding between a field's border and its text, in points
+const FIELD_PADDING: f32 = 2.0;
+
+/// Values that tick a checkbox
+const CHECKED_VALUES: [&str; 7] = ["yes", "y", "true", "1", "on", "x", "checked"];
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FormFieldKind {
+  Text,
+  Checkbox,
+  Radio,
+  Choice,
+  Signature,
+  /// Push buttons hold no value
+  Button,
+}
+
+impl FormFieldKind {
+  /// Whether the field takes a value that can be filled in
+  pub fn is_fillable(self) -> bool {
+    !matches!(self, FormFieldKind::Signature | FormFieldKind::Button)
+  }
+}
+
+/// A terminal AcroForm field, named by its fully qualified name
+#[derive(Serialize, Clone, Debug)]
+pub struct FormField {
+  pub name: String,
+  pub kind: FormFieldKind,
+  pub value: Option<String>,
+  /// States of checkboxes and radio buttons, or the choices of a choice field
+  pub options: Vec<String>,
+  pub read_only: bool,
+}
+
+/// The fields of a fillable PDF
+pub struct PdfForm {
+  pub fields: Vec<FormField>,
+  pub page_count: u32,
+  /// Identifies the form across uploads, see [`form_fingerprint`]
+  pub fingerprint: String,
+}
+
+/// Read the AcroForm fields of a PDF
+pub fn read_form(pdf: &[u8]) -> Result<PdfForm> {
+  let doc = load_pdf(pdf)?;
+  let nodes = field_nodes(&doc)?;
+  if nodes.is_empty() {
+    return Err(anyhow::anyhow!("This PDF has no fillable form fields"));
+  }
+
+  let fields: Vec<FormField> = nodes
+    .iter()
+    .map(|node| FormField {
+      name: node.name.clone(),
+      kind: node.kind,
+      value: field_value(&doc, node.id),
+      options: field_options(&doc, node),
+      read_only: node.flags & FLAG_READ_ONLY != 0,
+    })
+    .collect();
+  let page_count = doc.get_pages().len() as u32;
+  let fingerprint = form_fingerprint(fields.iter().map(|f| f.name.as_str()), page_count);
+
+  Ok(PdfForm {
+    fields,
+    page_count,
+    fingerprint,
+  })
+}
+
+/// Hash of the sorted field names and the page count. Different copies of the same form get the
+/// same fingerprint whatever has been filled in.
+pub fn form_fingerprint<'a>(names: impl Iterator<Item = &'a str>, page_count: u32) -> String {
+  let mut names: Vec<&str> = names.collect();
+  names.sort_unstable();
+  names.dedup();
+
+  let mut hasher = Sha256::new();
+  for name in names {
+    hasher.update(name.as_bytes());
+    hasher.update(b"\n");
+  }
+  hasher.update(format!("pages={}", page_count).as_bytes());
+  hex::encode(hasher.finalize())
+}
+
+/// Write values into form fields by fully qualified name and give every filled widget an
+/// appearance. Unknown, read-only and signature fields are left alone. When `flatten` is set the
+/// widgets are drawn into the page content and the form is removed, so nothing stays editable.
+pub fn fill_form(pdf: &[u8], values: &HashMap<String, String>, flatten: bool) -> Result<Vec<u8>> {
+  let mut doc = load_pdf(pdf)?;
+  let nodes = field_nodes(&doc)?;
+  let acro_form_da = acro_form(&doc)
+    .and_then(|form| form.get(b"DA").ok())
+    .and_then(|da| decode_text_string(da).ok());
+  let font_id = doc.add_object(dictionary! {
+    "Type" => "Font",
+    "Subtype" => "Type1",
+    "BaseFont" => "Helvetica",
+    "Encoding" => "WinAnsiEncoding",
+  });
+
+  for node in &nodes {
+    let Some(value) = values.get(&node.name) else {
+      continue;
+    };
+    if !node.kind.is_fillable() || node.flags & FLAG_READ_ONLY != 0 {
+      continue;
+    }
+
+    match node.kind {
+      FormFieldKind::Checkbox | FormFieldKind::Radio => set_button_state(&mut doc, node, value)?,
+      _ => {
+        let value = match node.max_len {
+          Some(max_len) => value.chars().take(max_len).collect(),
+          None => value.clone(),
+        };
+        doc
+          .get_object_mut(node.id)?
+          .as_dict_mut()?
+          .set("V", text_string(&value));
+        let da = node.da.clone().or_else(|| acro_form_da.clone());
+        for widget_id in &node.widgets {
+          let appearance = text_appearance(&doc, *widget_id, node, da.as_deref(), &value, font_id)?;
+          let appearance_id = doc.add_object(appearance);
+          doc.get_object_mut(*widget_id)?.as_dict_mut()?.set(
+            "AP",
+            dictionary! {
+              "N" => appearance_id,
+            },
+          );
+        }
+      }
+    }
+  }
+
+  if flatten {
+    flatten_form(&mut doc)?;
+  } else if let Some(form_id) = acro_form_id(&doc) {
+    let form = match form_id {
+      Some(id) => doc.get_object_mut(id)?.as_dict_mut()?,
+      None => doc.catalog_mut()?.get_mut(b"AcroForm")?.as_dict_mut()?,
+    };
+    // Viewers that can render the fields themselves should; XFA would override the values
+    form.set("NeedAppearances", true);
+    form.remove(b"XFA");
+  }
+
+  doc.compress();
+  let mut bytes = Vec::new();
+  doc
+    .save_to(&mut bytes)
+    .map_err(|e| anyhow::anyhow!("Failed to write filled PDF: {}", e))?;
+  Ok(bytes)
+}
+
+/// A terminal field with the attributes it inherits from its ancestors
+struct FieldNode {
+  id: ObjectId,
+  name: String,
+  kind: FormFieldKind,
+  flags: i64,
+  da: Option<String>,
+  quadding: i64,
+  max_len: Option<usize>,
+  widgets: Vec<ObjectId>,
+}
+
+/// Inheritable field attributes, PDF 32000-1 section 12.7.3.1
+#[derive(Clone, Default)]
+struct Inherited {
+  name: String,
+  field_type: Option<Vec<u8>>,
+  flags: i64,
+  da: Option<String>,
+  quadding: i64,
+  max_len: Option<usize>,
+}
+
+fn acro_form(doc: &Document) -> Option<&Dictionary> {
+  let form = doc.catalog().ok()?.get(b"AcroForm").ok()?;
+  doc.dereference(form).ok()?.1.as_dict().ok()
+}
+
+/// Where the AcroForm dictionary lives: its own object, or inline in the catalog (`None`)
+fn acro_form_id(doc: &Document) -> Option<Option<ObjectId>> {
+  match doc.catalog().ok()?.get(b"AcroForm").ok()? {
+    Object::Reference(id) => Some(Some(*id)),
+    Object::Dictionary(_) => Some(None),
+    _ => None,
+  }
+}
+
+fn field_nodes(doc: &Document) -> Result<Vec<FieldNode>> {
+  let Some(form) = acro_form(doc) else {
+    return Ok(Vec::new());
+  };
+  let fields = match form.get(b"Fields") {
+    Ok(fields) => doc.dereference(fields)?.1.as_array()?.clone(),
+    Err(_) => return Ok(Vec::new()),
+  };
+
+  let mut nodes = Vec::new();
+  let mut visited = HashSet::new();
+  for field in fields {
+    if let Ok(id) = field.as_reference() {
+      collect_fields(doc, id, &Inherited::default(), &mut visited, &mut nodes);
+    }
+  }
+  Ok(nodes)
+}
+
+fn collect_fields(
+  doc: &Document,
+  id: ObjectId,
+  parent: &Inherited,
+  visited: &mut HashSet<ObjectId>,
+  nodes: &mut Vec<FieldNode>,
+) {
+  if !visited.insert(id) {
+    return;
+  }
+  let Ok(dict) = doc.get_dictionary(id) else {
+    return;
+  };
+
+  let mut inherited = parent.clone();
+  if let Some(partial) = dict.get(b"T").ok().and_then(|t| decode_text_string(t).ok()) {
+    inherited.name = if parent.name.is_empty() {
+      partial
+    } else {
+      format!("{}.{}", parent.name, partial)
+    };
+  }
+  if let Ok(field_type) = dict.get(b"FT").and_then(Object::as_name) {
+    inherited.field_type = Some(field_type.to_vec());
+  }
+  if let Ok(flags) = dict.get(b"Ff").and_then(Object::as_i64) {
+    inherited.flags = flags;
+  }
+  if let Some(da) = dict
+    .get(b"DA")
+    .ok()
+    .and_then(|da| decode_text_string(da).ok())
+  {
+    inherited.da = Some(da);
+  }
+  if let Ok(quadding) = dict.get(b"Q").and_then(Object::as_i64) {
+    inherited.quadding = quadding;
+  }
+  if let Ok(max_len) = dict.get(b"MaxLen").and_then(Object::as_i64) {
+    inherited.max_len = usize::try_from(max_len).ok();
+  }
+
+  let kids: Vec<ObjectId> = dict
+    .get(b"Kids")
+    .and_then(|kids| doc.dereference(kids))
+    .and_then(|(_, kids)| kids.as_array())
+    .map(|kids| kids.iter().filter_map(|k| k.as_reference().ok()).collect())
+    .unwrap_or_default();
+
+  // Kids with a name are fields of their own; kids without one are this field's widgets
+  let (child_fields, widgets): (Vec<ObjectId>, Vec<ObjectId>) = kids.into_iter().partition(|kid| {
+    doc
+      .get_dictionary(*kid)
+      .map(|kid| kid.has(b"T"))
+      .unwrap_or(false)
+  });
+
+  if !child_fields.is_empty() {
+    for kid in child_fields {
+      collect_fields(doc, kid, &inherited, visited, nodes);
+    }
+    return;
+  }
+  if inherited.name.is_empty() {
+    return;
+  }
+
+  let mut widgets = widgets;
+  if dict.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Widget".as_slice()) {
+    widgets.insert(0, id);
+  }
+
+  let kind = match inherited.field_type.as_deref() {
+    Some(b"Tx") => FormFieldKind::Text,
+    Some(b"Ch") => FormFieldKind::Choice,
+    Some(b"Sig") => FormFieldKind::Signature,
+    Some(b"Btn") if inherited.flags & FLAG_PUSHBUTTON != 0 => FormFieldKind::Button,
+    Some(b"Btn") if inherited.flags & FLAG_RADIO != 0 => FormFieldKind::Radio,
+    Some(b"Btn") => FormFieldKind::Checkbox,
+    _ => return,
+  };
+
+  nodes.push(FieldNode {
+    id,
+    name: inherited.name,
+    kind,
+    flags: inherited.flags,
+    da: inherited.da,
+    quadding: inherited.quadding,
+    max_len: inherited.max_len,
+    widgets,
+  });
+}
+
+fn field_value(doc: &Document, id: ObjectId) -> Option<String> {
+  let value = doc.get_dictionary(id).ok()?.get(b"V").ok()?;
+  let value = doc.dereference(value).ok()?.1;
+  let value = match value {
+    Object::Name(name) => String::from_utf8_lossy(name).into_owned(),
+    Object::String(..) => decode_field_text(value)?,
+    _ => return None,
+  };
+  (!value.is_empty() && value != "Off").then_some(value)
+}
+
+/// Like `decode_text_string`, but keeps the tabs and line breaks of multiline fields, which
+/// lopdf's PDFDocEncoding table leaves out
+fn decode_field_text(value: &Object) -> Option<String> {
+  let bytes = value.as_str().ok()?;
+  if bytes.starts_with(b"\xFE\xFF") || bytes.starts_with(b"\xEF\xBB\xBF") {
+    return decode_text_string(value).ok();
+  }
+
+  let mut text = String::new();
+  for chunk in bytes.split_inclusive(|b| matches!(b, b'\t' | b'\n' | b'\r')) {
+    let (run, separator) = match chunk.split_last() {
+      Some((last, run)) if matches!(last, b'\t' | b'\n' | b'\r') => (run, Some(*last as char)),
+      _ => (chunk, None),
+    };
+    text.push_str(&decode_text_string(&Object::string_literal(run)).ok()?);
+    text.extend(separator);
+  }
+  Some(text)
+}
+
+fn field_options(doc: &Document, node: &FieldNode) -> Vec<String> {
+  match node.kind {
+    FormFieldKind::Checkbox | FormFieldKind::Radio => {
+      let mut states: Vec<String> = node
+        .widgets
+        .iter()
+        .filter_map(|widget| on_state(doc, *widget))
+        .map(|state| String::from_utf8_lossy(&state).into_owned())
+        .collect();
+      states.dedup();
+      states
+    }
+    FormFieldKind::Choice => doc
+      .get_dictionary(node.id)
+      .and_then(|dict| dict.get(b"Opt"))
+      .and_then(|opt| doc.dereference(opt))
+      .and_then(|(_, opt)| opt.as_array())
+      .map(|options| {
+        options
+          .iter()
+          .filter_map(|option| match option {
+            // [export value, display text]
+            Object::Array(pair) => pair.first().and_then(|v| decode_text_string(v).ok()),
+            option => decode_text_string(option).ok(),
+          })
+          .collect()
+      })
+      .unwrap_or_default(),
+    _ => Vec::new(),
+  }
+}
+
+/// Name of the appearance state a checkbox or radio widget shows when it is selected
+fn on_state(doc: &Document, widget_id: ObjectId) -> Option<Vec<u8>> {
+  let widget = doc.get_dictionary(widget_id).ok()?;
+  let appearances = doc
+    .dereference(widget.get(b"AP").ok()?)
+    .ok()?
+    .1
+    .as_dict()
+    .ok()?;
+  let normal = doc
+    .dereference(appearances.get(b"N").ok()?)
+    .ok()?
+    .1
+    .as_dict()
+    .ok()?;
+  normal
+    .iter()
+    .map(|(state, _)| state)
+    .find(|state| state.as_slice() != b"Off")
+    .cloned()
+}
+
+fn set_button_state(doc: &mut Document, node: &FieldNode, value: &str) -> Result<()> {
+  let value = value.trim();
+  let states: Vec<(ObjectId, Vec<u8>)> = node
+    .widgets
+    .iter()
+    .map(|widget| {
+      let state = on_state(doc, *widget).unwrap_or_else(|| b"Yes".to_vec());
+      (*widget, state)
+    })
+    .collect();
+
+  let selected: Option<Vec<u8>> = match node.kind {
+    FormFieldKind::Checkbox => {
+      let checked = CHECKED_VALUES.contains(&value.to_lowercase().as_str())
+        || states
+          .iter()
+          .any(|(_, state)| String::from_utf8_lossy(state).eq_ignore_ascii_case(value));
+      checked
+        .then(|| states.first().map(|(_, state)| state.clone()))
+        .flatten()
+    }
+    _ => states
+      .iter()
+      .find(|(_, state)| String::from_utf8_lossy(state).eq_ignore_ascii_case(value))
+      .map(|(_, state)| state.clone()),
+  };
+
+  let field_value = selected.clone().unwrap_or_else(|| b"Off".to_vec());
+  doc
+    .get_object_mut(node.id)?
+    .as_dict_mut()?
+    .set("V", Object::Name(field_value));
+  for (widget_id, state) in states {
+    let shown = match &selected {
+      Some(selected) if *selected == state => state,
+      _ => b"Off".to_vec(),
+    };
+    doc
+      .get_object_mut(widget_id)?
+      .as_dict_mut()?
+      .set("AS", Object::Name(shown));
+  }
+  Ok(())
+}
+
+fn widget_rect(doc: &Document, widget_id: ObjectId) -> Result<[f32; 4]> {
+  let widget = doc.get_dictionary(widget_id)?;
+  let values: Vec<f32> = doc
+    .dereference(widget.get(b"Rect")?)?
+    .1
+    .as_array()?
+    .iter()
+    .filter_map(|v| v.as_float().ok())
+    .collect();
+  if values.len() != 4 {
+    return Err(anyhow::anyhow!(
+      "Form field widget has an invalid rectangle"
+    ));
+  }
+  Ok([
+    values[0].min(values[2]),
+    values[1].min(values[3]),
+    values[0].max(values[2]),
+    values[1].max(values[3]),
+  ])
+}
+
+/// Font size and fill color operation from a default appearance string such as
+/// `/Helv 0 Tf 0 g`. A size of 0 means the text is sized to fit.
+fn parse_default_appearance(da: &str) -> (f32, Option<Operation>) {
+  let tokens: Vec<&str> = da.split_whitespace().collect();
+  let mut size = 0.0;
+  let mut color = None;
+  for (i, token) in tokens.iter().enumerate() {
+    let operands = |count: usize| -> Option<Vec<Object>> {
+      (i >= count)
+        .then(|| {
+          tokens[i - count..i]
+            .iter()
+            .filter_map(|t| t.parse::<f32>().ok())
+            .map(Object::Real)
+            .collect::<Vec<_>>()
+        })
+        .filter(|operands| operands.len() == count)
+    };
+    match *token {
+      "Tf" => {
+        size = operands(1)
+          .and_then(|o| o[0].as_float().ok())
+          .unwrap_or(0.0);
+      }
+      "g" => color = operands(1).map(|o| Operation::new("g", o)),
+      "rg" => color = operands(3).map(|o| Operation::new("rg", o)),
+      "k" => color = operands(4).map(|o| Operation::new("k", o)),
+      _ => {}
+    }
+  }
+  (size, color)
+}
+
+/// Average Helvetica glyph width as a fraction of the font size
+const AVERAGE_GLYPH_WIDTH: f32 = 0.5;
+
+fn text_width(text: &str, size: f32) -> f32 {
+  text.chars().count() as f32 * size * AVERAGE_GLYPH_WIDTH
+}
+
+/// Break text into lines no wider than `width` at `size`
+fn wrap_lines(text: &str, width: f32, size: f32) -> Vec<String> {
+  let mut lines = Vec::new();
+  for paragraph in text.lines() {
+    let mut line = String::new();
+    for word in paragraph.split_whitespace() {
+      let candidate = if line.is_empty() {
+        word.to_string()
+      } else {
+        format!("{} {}", line, word)
+      };
+      if !line.is_empty() && text_width(&candidate, size) > width {
+        lines.push(std::mem::replace(&mut line, word.to_string()));
+      } else {
+        line = candidate;
+      }
+    }
+    lines.push(line);
+  }
+  lines
+}
+
+/// Appearance stream showing `value` in a text or choice widget
+fn text_appearance(
+  doc: &Document,
+  widget_id: ObjectId,
+  node: &FieldNode,
+  da: Option<&str>,
+  value: &str,
+  font_id: ObjectId,
+) -> Result<Stream> {
+  let rect = widget_rect(doc, widget_id)?;
+  let (width, height) = (rect[2] - rect[0], rect[3] - rect[1]);
+  let inner_width = (width - 2.0 * FIELD_PADDING).max(1.0);
+  let (da_size, color) = parse_default_appearance(da.unwrap_or_default());
+  let multiline = node.kind == FormFieldKind::Text && node.flags & FLAG_MULTILINE != 0;
+
+  let (size, lines) = if multiline {
+    let size = if da_size > 0.0 {
+      da_size
+    } else {
+      DEFAULT_FONT_SIZE
+    };
+    (size, wrap_lines(value, inner_width, size))
+  } else {
+    let line = value.replace(['\r', '\n'], " ");
+    let mut size = if da_size > 0.0 {
+      da_size
+    } else {
+      ((height - 2.0 * FIELD_PADDING) * 0.75).clamp(MIN_FONT_SIZE, 12.0)
+    };
+    // Shrink long values until they fit rather than cutting them off
+    let needed = text_width(&line, size);
+    if needed > inner_width {
+      size = (size * inner_width / needed).max(MIN_FONT_SIZE);
+    }
+    (size, vec![line])
+  };
+
+  let leading = size * 1.15;
+  let mut operations = vec![
+    Operation::new("BMC", vec![Object::Name(b"Tx".to_vec())]),
+    Operation::new("q", vec![]),
+    // Clip to the field so nothing is drawn over its neighbours
+    Operation::new(
+      "re",
+      vec![
+        FIELD_PADDING.into(),
+        FIELD_PADDING.into(),
+        inner_width.into(),
+        (height - 2.0 * FIELD_PADDING).max(1.0).into(),
+      ],
+    ),
+    Operation::new("W", vec![]),
+    Operation::new("n", vec![]),
+    Operation::new("BT", vec![]),
+    Operation::new("Tf", vec![Object::Name(FIELD_FONT.into()), size.into()]),
+    color.unwrap_or_else(|| Operation::new("g", vec![0.into()])),
+  ];
+
+  let first_baseline = if multiline {
+    height - FIELD_PADDING - size
+  } else {
+    // Vertically centred, allowing for the descender
+    (height - size) / 2.0 + size * 0.22
+  };
+  for (i, line) in lines.iter().enumerate() {
+    let line_width = text_width(line, size);
+    let x = match node.quadding {
+      1 => (width - line_width) / 2.0,
+      2 => width - FIELD_PADDING - line_width,
+      _ => FIELD_PADDING,
+    };
+    let y = first_baseline - i as f32 * leading;
+    operations.push(Operation::new(
+      "Tm",
+      vec![1.into(), 0.into(), 0.into(), 1.into(), x.into(), y.into()],
+    ));
+    operations.push(Operation::new(
+      "Tj",
+      vec![Object::String(win_ansi(line), StringFormat::Literal)],
+    ));
+  }
+  operations.extend([
+    Operation::new("ET", vec![]),
+    Operation::new("Q", vec![]),
+    Operation::new("EMC", vec![]),
+  ]);
+
+  let content = Content { operations }
+    .encode()
+    .map_err(|e| anyhow::anyhow!("Failed to encode field appearance: {}", e))?;
+  Ok(Stream::new(
+    dictionary! {
+      "Type" => "XObject",
+      "Subtype" => "Form",
+      "BBox" => vec![0.into(), 0.into(), width.into(), height.into()],
+      "Resources" => dictionary! {
+        "Font" => dictionary! {
+          FIELD_FONT => font_id,
+        },
+      },
+    },
+    content,
+  ))
+}
+
+/// The appearance a widget currently shows: its normal appearance, or for checkboxes and radio
+/// buttons the one for its current state
+fn current_appearance(doc: &Document, widget: &Dictionary) -> Option<ObjectId> {
+  let appearances = doc
+    .dereference(widget.get(b"AP").ok()?)
+    .ok()?
+    .1
+    .as_dict()
+    .ok()?;
+  match appearances.get(b"N").ok()? {
+    Object::Reference(id) => match doc.get_object(*id).ok()? {
+      Object::Stream(_) => Some(*id),
+      Object::Dictionary(states) => {
+        let state = widget.get(b"AS").and_then(Object::as_name).ok()?;
+        states.get(state).and_then(Object::as_reference).ok()
+      }
+      _ => None,
+    },
+    Object::Dictionary(states) => {
+      let state = widget.get(b"AS").and_then(Object::as_name).ok()?;
+      states.get(state).and_then(Object::as_reference).ok()
+    }
+    _ => None,
+  }
+}
+
+/// Matrix that draws an appearance stream into a widget rectangle, PDF 32000-1 section 12.5.5
+fn appearance_matrix(doc: &Document, appearance_id: ObjectId, rect: [f32; 4]) -> Option<[f32; 6]> {
+  let stream = doc.get_object(appearance_id).ok()?.as_stream().ok()?;
+  let numbers = |key: &[u8]| -> Option<Vec<f32>> {
+    let values = doc
+      .dereference(stream.dict.get(key).ok()?)
+      .ok()?
+      .1
+      .as_array()
+      .ok()?;
+    Some(values.iter().filter_map(|v| v.as_float().ok()).collect())
+  };
+  let bbox = numbers(b"BBox").filter(|b| b.len() == 4)?;
+  let matrix = numbers(b"Matrix")
+    .filter(|m| m.len() == 6)
+    .unwrap_or_else(|| vec![1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+  let corners = [
+    (bbox[0], bbox[1]),
+    (bbox[2], bbox[1]),
+    (bbox[0], bbox[3]),
+    (bbox[2], bbox[3]),
+  ]
+  .map(|(x, y)| {
+    (
+      matrix[0] * x + matrix[2] * y + matrix[4],
+      matrix[1] * x + matrix[3] * y + matrix[5],
+    )
+  });
+  let min_x = corners.iter().map(|c| c.0).fold(f32::INFINITY, f32::min);
+  let max_x = corners
+    .iter()
+    .map(|c| c.0)
+    .fold(f32::NEG_INFINITY, f32::max);
+  let min_y = corners.iter().map(|c| c.1).fold(f32::INFINITY, f32::min);
+  let max_y = corners
+    .iter()
+    .map(|c| c.1)
+    .fold(f32::NEG_INFINITY, f32::max);
+  if max_x - min_x <= 0.0 || max_y - min_y <= 0.0 {
+    return None;
+  }
+
+  let scale_x = (rect[2] - rect[0]) / (max_x - min_x);
+  let scale_y = (rect[3] - rect[1]) / (max_y - min_y);
+  Some([
+    scale_x,
+    0.0,
+    0.0,
+    scale_y,
+    rect[0] - min_x * scale_x,
+    rect[1] - min_y * scale_y,
+  ])
+}
+
+/// Draw every visible widget into its page and remove the form
+fn flatten_form(doc: &mut Document) -> Result<()> {
+  // Pages get their own resources below, so inherited ones must be on the page first
+  push_down_inherited_attributes(doc);
+
+  for page_id in doc.get_pages().into_values().collect::<Vec<_>>() {
+    let annotations: Vec<Object> = match doc.get_dictionary(page_id)?.get(b"Annots") {
+      Ok(annotations) => doc.dereference(annotations)?.1.as_array()?.clone(),
+      Err(_) => continue,
+    };
+
+    let mut kept = Vec::new();
+    let mut draws = Vec::new();
+    for annotation in annotations {
+      let widget = annotation
+        .as_reference()
+        .ok()
+        .and_then(|id| doc.get_dictionary(id).ok())
+        .filter(|a| a.get(b"Subtype").and_then(Object::as_name).ok() == Some(b"Widget".as_slice()));
+      let Some(widget) = widget else {
+        kept.push(annotation);
+        continue;
+      };
+
+      let hidden = widget
+        .get(b"F")
+        .and_then(Object::as_i64)
+        .is_ok_and(|flags| flags & ANNOTATION_HIDDEN != 0);
+      if hidden {
+        continue;
+      }
+      let Some(appearance_id) = current_appearance(doc, widget) else {
+        continue;
+      };
+      let Ok(rect) = widget_rect(doc, annotation.as_reference()?) else {
+        continue;
+      };
+      if let Some(matrix) = appearance_matrix(doc, appearance_id, rect) {
+        draws.push((appearance_id, matrix));
+      }
+    }
+
+    if !draws.is_empty() {
+      let page = doc.get_dictionary(page_id)?;
+      let mut resources = match page.get(b"Resources") {
+        Ok(object) => doc.dereference(object)?.1.as_dict()?.clone(),
+        Err(_) => Dictionary::new(),
+      };
+      let mut xobjects = match resources.get(b"XObject") {
+        Ok(object) => doc.dereference(object)?.1.as_dict()?.clone(),
+        Err(_) => Dictionary::new(),
+      };
+
+      let mut operations = vec![Operation::new("Q", vec![])];
+      for (i, (appearance_id, matrix)) in draws.into_iter().enumerate() {
+        let name = format!("FlatField{}", i + 1);
+        xobjects.set(name.as_bytes(), appearance_id);
+        operations.extend([
+          Operation::new("q", vec![]),
+          Operation::new("cm", matrix.iter().map(|v| (*v).into()).collect()),
+          Operation::new("Do", vec![Object::Name(name.into_bytes())]),
+          Operation::new("Q", vec![]),
+        ]);
+      }
+      resources.set("XObject", xobjects);
+
+      // Wrap the existing content in its own graphics state, as stamp_pdf does
+      let mut contents = vec![Object::Reference(
+        doc.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec())),
+      )];
+      contents.extend(
+        doc
+          .get_page_contents(page_id)
+          .into_iter()
+          .map(Object::Reference),
+      );
+      let content = Content { operations }
+        .encode()
+        .map_err(|e| anyhow::anyhow!("Failed to encode flattened fields: {}", e))?;
+      contents.push(Object::Reference(
+        doc.add_object(Stream::new(Dictionary::new(), content)),
+      ));
+
+      let page = doc.get_dictionary_mut(page_id)?;
+      page.set("Resources", resources);
+      page.set("Contents", contents);
+    }
+
+    let page = doc.get_dictionary_mut(page_id)?;
+    if kept.is_empty() {
+      page.remove(b"Annots");
+    } else {
+      page.set("Annots", kept);
+    }
+  }
+
+  doc.catalog_mut()?.remove(b"AcroForm");
+  doc.prune_objects();
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn rect(rect: [i64; 4]) -> Vec<Object> {
+    rect.into_iter().map(Object::from).collect()
+  }
+
+  /// One page form with a text field, a nested multiline field, a checkbox and a radio group.
+  /// The page's font comes from the /Pages node.
+  fn form_fixture() -> Vec<u8> {
+    let mut doc = Document::with_version("1.7");
+    let pages_id = doc.new_object_id();
+    let page_id = doc.new_object_id();
+    let square = |doc: &mut Document, content: &[u8]| {
+      doc.add_object(Stream::new(
+        dictionary! {
+          "Type" => "XObject",
+          "Subtype" => "Form",
+          "BBox" => rect([0, 0, 12, 12]),
+        },
+        content.to_vec(),
+      ))
+    };
+    let on = square(&mut doc, b"0 0 12 12 re f");
+    let off = square(&mut doc, b"");
+    let widget = |bounds: [i64; 4]| {
+      dictionary! {
+        "Type" => "Annot",
+        "Subtype" => "Widget",
+        "Rect" => rect(bounds),
+        "P" => page_id,
+      }
+    };
+
+    let mut first_name = widget([50, 700, 250, 720]);
+    first_name.extend(&dictionary! {
+      "FT" => "Tx",
+      "T" => text_string("FirstName"),
+      "DA" => text_string("/Helv 0 Tf 0 g"),
+    });
+    let first_name = doc.add_object(first_name);
+
+    let applicant = doc.new_object_id();
+    let mut comments = widget([50, 500, 250, 600]);
+    comments.extend(&dictionary! {
+      "FT" => "Tx",
+      "T" => text_string("Comments"),
+      "Ff" => FLAG_MULTILINE,
+      "Parent" => applicant,
+    });
+    let comments = doc.add_object(comments);
+    doc.objects.insert(
+      applicant,
+      Object::Dictionary(dictionary! {
+        "T" => text_string("applicant"),
+        "Kids" => vec![comments.into()],
+      }),
+    );
+
+    let mut pets = widget([50, 450, 62, 462]);
+    pets.extend(&dictionary! {
+      "FT" => "Btn",
+      "T" => text_string("Pets"),
+      "AS" => "Off",
+      "AP" => dictionary! { "N" => dictionary! { "Yes" => on, "Off" => off } },
+    });
+    let pets = doc.add_object(pets);
+
+    let smoker = doc.new_object_id();
+    let mut radios = Vec::new();
+    for (state, x) in [("Yes", 50), ("No", 80)] {
+      let mut radio = widget([x, 400, x + 12, 412]);
+      radio.extend(&dictionary! {
+        "Parent" => smoker,
+        "AS" => "Off",
+        "AP" => dictionary! { "N" => dictionary! { state => on, "Off" => off } },
+      });
+      radios.push(Object::from(doc.add_object(radio)));
+    }
+    doc.objects.insert(
+      smoker,
+      Object::Dictionary(dictionary! {
+        "FT" => "Btn",
+        "Ff" => FLAG_RADIO,
+        "T" => text_string("Smoker"),
+        "Kids" => radios.clone(),
+      }),
+    );
+
+    let content = doc.add_object(Stream::new(
+      Dictionary::new(),
+      b"BT /F1 12 Tf 50 750 Td (Application) Tj ET".to_vec(),
+    ));
+    let mut annotations = vec![first_name.into(), comments.into(), pets.into()];
+    annotations.extend(radios);
+    doc.objects.insert(
+      page_id,
+      Object::Dictionary(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "Contents" => content,
+        "Annots" => annotations,
+      }),
+    );
+    let font = doc.add_object(dictionary! {
+      "Type" => "Font",
+      "Subtype" => "Type1",
+      "BaseFont" => "Helvetica",
+    });
+    doc.objects.insert(
+      pages_id,
+      Object::Dictionary(dictionary! {
+        "Type" => "Pages",
+        "Kids" => vec![page_id.into()],
+        "Count" => 1,
+        "MediaBox" => rect([0, 0, 612, 792]),
+        "Resources" => dictionary! { "Font" => dictionary! { "F1" => font } },
+      }),
+    );
+    let form = doc.add_object(dictionary! {
+      "Fields" => vec![first_name.into(), applicant.into(), pets.into(), smoker.into()],
+    });
+    let catalog = doc.add_object(dictionary! {
+      "Type" => "Catalog",
+      "Pages" => pages_id,
+      "AcroForm" => form,
+    });
+    doc.trailer.set("Root", catalog);
+
+    let mut bytes = Vec::new();
+    doc.save_to(&mut bytes).unwrap();
+    bytes
+  }
+
+  fn values() -> HashMap<String, String> {
+    [
+      ("FirstName", "Jörg"),
+      ("applicant.Comments", "Quiet tenant\nNo parties"),
+      ("Pets", "yes"),
+      ("Smoker", "no"),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value.to_string()))
+    .collect()
+  }
+
+  #[test]
+  fn filled_values_read_back() {
+    let pdf = form_fixture();
+    let form = read_form(&pdf).unwrap();
+    let kinds: Vec<(&str, FormFieldKind)> = form
+      .fields
+      .iter()
+      .map(|f| (f.name.as_str(), f.kind))
+      .collect();
+    assert_eq!(
+      kinds,
+      [
+        ("FirstName", FormFieldKind::Text),
+        ("applicant.Comments", FormFieldKind::Text),
+        ("Pets", FormFieldKind::Checkbox),
+        ("Smoker", FormFieldKind::Radio),
+      ]
+    );
+    assert!(form.fields.iter().all(|f| f.value.is_none()));
+
+    let filled = read_form(&fill_form(&pdf, &values(), false).unwrap()).unwrap();
+    assert_eq!(filled.fingerprint, form.fingerprint);
+    let filled: HashMap<String, Option<String>> = filled
+      .fields
+      .into_iter()
+      .map(|f| (f.name, f.value))
+      .collect();
+    assert_eq!(filled["FirstName"].as_deref(), Some("Jörg"));
+    assert_eq!(
+      filled["applicant.Comments"].as_deref(),
+      Some("Quiet tenant\nNo parties")
+    );
+    assert_eq!(filled["Pets"].as_deref(), Some("Yes"));
+    assert_eq!(filled["Smoker"].as_deref(), Some("No"));
+  }
+
+  #[test]
+  fn flattening_keeps_inherited_resources() {
+    let flat = fill_form(&form_fixture(), &values(), true).unwrap();
+    assert!(read_form(&flat).is_err());
+
+    let doc = Document::load_mem(&flat).unwrap();
+    let page_id = doc.get_pages()[&1];
+    let page = doc.get_dictionary(page_id).unwrap();
+    assert!(page.get(b"Annots").is_err());
+    let resources = doc
+      .dereference(page.get(b"Resources").unwrap())
+      .unwrap()
+      .1
+      .as_dict()
+      .unwrap();
+    let fonts = doc
+      .dereference(resources.get(b"Font").unwrap())
+      .unwrap()
+      .1
+      .as_dict()
+      .unwrap();
+    assert!(fonts.has(b"F1"));
+    let xobjects = resources.get(b"XObject").unwrap().as_dict().unwrap();
+    // Both text fields, the ticked checkbox and the two radio buttons
+    assert_eq!(xobjects.len(), 5);
+  }
+}
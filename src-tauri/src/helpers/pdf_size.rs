@@ -0,0 +1,592 @@
+//! Shrinking PDFs to fit upload limits. Every attempt starts again from the original so images
+//! are only re-encoded once: first the file is compressed losslessly, then embedded images are
+//! downscaled and re-encoded as JPEG at falling sizes and qualities until the result fits.
+
+use super::pdf_docs::{load_pdf, PdfBuildMonitor, PdfBuildStage};
+use anyhow::Result;
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+use lopdf::{Dictionary, Document, Object, ObjectId, SaveOptions, Stream};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+/// Bytes a document takes up in a PDF
+#[derive(Serialize, Clone, Debug)]
+pub struct DocumentSize {
+  pub title: String,
+  pub bytes: u64,
+}
+
+/// Outcome of fitting a PDF to a size budget
+#[derive(Serialize, Clone, Debug)]
+pub struct PdfSizeReport {
+  pub target_bytes: u64,
+  pub achieved_bytes: u64,
+  /// Estimated contribution of each document, largest first. Resources shared between documents
+  /// count towards the first one that uses them.
+  pub documents: Vec<DocumentSize>,
+}
+
+/// Image re-encoding applied by an attempt
+#[derive(Clone, Copy)]
+struct ImageLevel {
+  max_edge: u32,
+  quality: u8,
+}
+
+/// Attempts in order: lossless first, then smaller and lower quality images
+const LEVELS: [Option<ImageLevel>; 7] = [
+  None,
+  Some(ImageLevel {
+    max_edge: 2000,
+    quality: 75,
+  }),
+  Some(ImageLevel {
+    max_edge: 1600,
+    quality: 65,
+  }),
+  Some(ImageLevel {
+    max_edge: 1280,
+    quality: 55,
+  }),
+  Some(ImageLevel {
+    max_edge: 1024,
+    quality: 45,
+  }),
+  Some(ImageLevel {
+    max_edge: 800,
+    quality: 40,
+  }),
+  Some(ImageLevel {
+    max_edge: 640,
+    quality: 35,
+  }),
+];
+
+/// Pages before the first outline entry, such as an application form the documents follow
+const UNTITLED_PAGES: &str = "Other pages";
+
+/// Fit `pdf` under `target_bytes` when a target is set, reporting the outcome to `monitor`
+pub fn apply_size_budget(
+  pdf: Vec<u8>,
+  target_bytes: Option<u64>,
+  monitor: &dyn PdfBuildMonitor,
+) -> Result<Vec<u8>> {
+  let Some(target_bytes) = target_bytes else {
+    return Ok(pdf);
+  };
+
+  let (pdf, report) = fit_pdf_to_size(&pdf, target_bytes, monitor)?;
+  println!(
+    "PDF fits the size budget: {} of {}",
+    format_size(report.achieved_bytes),
+    format_size(report.target_bytes)
+  );
+  monitor.report(PdfBuildStage::SizeReport(report));
+  Ok(pdf)
+}
+
+/// Compress a PDF until it is at most `target_bytes`. Documents are told apart by the top level
+/// outline entries [`merge_pdfs`](super::pdf_docs::merge_pdfs) adds for them.
+pub fn fit_pdf_to_size(
+  pdf: &[u8],
+  target_bytes: u64,
+  monitor: &dyn PdfBuildMonitor,
+) -> Result<(Vec<u8>, PdfSizeReport)> {
+  let original = load_pdf(pdf)?;
+  if pdf.len() as u64 <= target_bytes {
+    let report = PdfSizeReport {
+      target_bytes,
+      achieved_bytes: pdf.len() as u64,
+      documents: document_sizes(&original),
+    };
+    return Ok((pdf.to_vec(), report));
+  }
+
+  let mut smallest: Option<(Vec<u8>, Document)> = None;
+  for (attempt, level) in LEVELS.iter().enumerate() {
+    monitor.check_cancelled()?;
+    monitor.report(PdfBuildStage::Compressing {
+      attempt: attempt + 1,
+      attempts: LEVELS.len(),
+    });
+
+    let mut doc = original.clone();
+    let bytes = shrink(&mut doc, *level)?;
+    let fits = bytes.len() as u64 <= target_bytes;
+    if smallest
+      .as_ref()
+      .is_none_or(|(best, _)| bytes.len() < best.len())
+    {
+      smallest = Some((bytes, doc));
+    }
+    if fits {
+      break;
+    }
+  }
+
+  let (bytes, doc) = smallest.ok_or_else(|| anyhow::anyhow!("No compression attempts made"))?;
+  let report = PdfSizeReport {
+    target_bytes,
+    achieved_bytes: bytes.len() as u64,
+    documents: document_sizes(&doc),
+  };
+  if report.achieved_bytes > target_bytes {
+    return Err(too_large(&report));
+  }
+  Ok((bytes, report))
+}
+
+/// A PDF and the title it is reported under
+pub type TitledPdf = (String, Vec<u8>);
+
+/// Compress separate PDFs until together they are at most `target_bytes`, for uploads that
+/// send each document on its own
+pub fn fit_pdfs_to_size(
+  pdfs: Vec<TitledPdf>,
+  target_bytes: u64,
+  monitor: &dyn PdfBuildMonitor,
+) -> Result<(Vec<TitledPdf>, PdfSizeReport)> {
+  let total = |pdfs: &[TitledPdf]| pdfs.iter().map(|(_, b)| b.len() as u64).sum::<u64>();
+  let report = |pdfs: &[TitledPdf]| {
+    let mut documents: Vec<DocumentSize> = pdfs
+      .iter()
+      .map(|(title, bytes)| DocumentSize {
+        title: title.clone(),
+        bytes: bytes.len() as u64,
+      })
+      .collect();
+    documents.sort_by_key(|document| Reverse(document.bytes));
+    PdfSizeReport {
+      target_bytes,
+      achieved_bytes: total(pdfs),
+      documents,
+    }
+  };
+
+  if total(&pdfs) <= target_bytes {
+    let report = report(&pdfs);
+    return Ok((pdfs, report));
+  }
+
+  let originals = pdfs
+    .iter()
+    .map(|(title, bytes)| {
+      load_pdf(bytes)
+        .map(|doc| (title.clone(), doc))
+        .map_err(|e| anyhow::anyhow!("{}: {}", title, e))
+    })
+    .collect::<Result<Vec<_>>>()?;
+
+  let mut smallest = pdfs;
+  for (attempt, level) in LEVELS.iter().enumerate() {
+    monitor.check_cancelled()?;
+    monitor.report(PdfBuildStage::Compressing {
+      attempt: attempt + 1,
+      attempts: LEVELS.len(),
+    });
+
+    let mut shrunk = Vec::new();
+    for (title, original) in &originals {
+      let mut doc = original.clone();
+      shrunk.push((title.clone(), shrink(&mut doc, *level)?));
+    }
+
+    let fits = total(&shrunk) <= target_bytes;
+    if total(&shrunk) < total(&smallest) {
+      smallest = shrunk;
+    }
+    if fits {
+      break;
+    }
+  }
+
+  let report = report(&smallest);
+  if report.achieved_bytes > target_bytes {
+    return Err(too_large(&report));
+  }
+  Ok((smallest, report))
+}
+
+fn too_large(report: &PdfSizeReport) -> anyhow::Error {
+  let largest = report
+    .documents
+    .iter()
+    .take(3)
+    .map(|d| format!("{} ({})", d.title, format_size(d.bytes)))
+    .collect::<Vec<_>>()
+    .join(", ");
+  anyhow::anyhow!(
+    "The PDF cannot be made smaller than {}, over the {} limit, even with images downscaled to \
+     {} pixels at JPEG quality {}. Largest documents: {}. Leave out or split the largest \
+     documents, or scan them at a lower resolution.",
+    format_size(report.achieved_bytes),
+    format_size(report.target_bytes),
+    LEVELS[LEVELS.len() - 1].map_or(0, |l| l.max_edge),
+    LEVELS[LEVELS.len() - 1].map_or(0, |l| l.quality),
+    largest
+  )
+}
+
+fn format_size(bytes: u64) -> String {
+  if bytes < 1024 * 1024 {
+    return format!("{:.0} KB", (bytes as f64 / 1024.0).ceil());
+  }
+  format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Compress a document in place and serialize it
+fn shrink(doc: &mut Document, level: Option<ImageLevel>) -> Result<Vec<u8>> {
+  if let Some(level) = level {
+    recompress_images(doc, level);
+  }
+  deduplicate_objects(doc);
+  doc.prune_objects();
+  doc.compress();
+  doc.renumber_objects();
+
+  // Object streams need PDF 1.5
+  if doc.version.as_str() < "1.5" {
+    doc.version = "1.5".to_string();
+  }
+  let mut bytes = Vec::new();
+  doc
+    .save_with_options(
+      &mut bytes,
+      SaveOptions::builder()
+        .use_object_streams(true)
+        .use_xref_streams(true)
+        .compression_level(9)
+        .build(),
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to write compressed PDF: {}", e))?;
+  Ok(bytes)
+}
+
+/// Downscale and re-encode 8-bit RGB and grayscale images as JPEG where that makes them
+/// smaller. Images that cannot be decoded are left alone.
+fn recompress_images(doc: &mut Document, level: ImageLevel) {
+  let mut replacements = Vec::new();
+  for (&id, object) in &doc.objects {
+    let Object::Stream(stream) = object else {
+      continue;
+    };
+    if stream.dict.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Image".as_slice()) {
+      continue;
+    }
+    if let Some(recompressed) = recompress_image(doc, stream, level) {
+      replacements.push((id, recompressed));
+    }
+  }
+
+  for (id, stream) in replacements {
+    doc.objects.insert(id, Object::Stream(stream));
+  }
+}
+
+fn recompress_image(doc: &Document, stream: &Stream, level: ImageLevel) -> Option<Stream> {
+  let dict = &stream.dict;
+  // Stencil masks, inverted samples and pre-blended soft masks don't survive as plain JPEGs
+  for key in ["ImageMask", "Decode", "Matte"] {
+    if dict.has(key.as_bytes()) {
+      return None;
+    }
+  }
+  if dict
+    .get(b"BitsPerComponent")
+    .and_then(Object::as_i64)
+    .ok()?
+    != 8
+  {
+    return None;
+  }
+  let width = dict.get(b"Width").and_then(Object::as_i64).ok()? as u32;
+  let height = dict.get(b"Height").and_then(Object::as_i64).ok()? as u32;
+  let components = image_components(doc, dict.get(b"ColorSpace").ok()?)?;
+
+  let filters = stream.filters().unwrap_or_default();
+  let image = if filters.as_slice() == [b"DCTDecode".as_slice()] {
+    let decoded = image::load_from_memory_with_format(&stream.content, ImageFormat::Jpeg).ok()?;
+    if components == 1 {
+      DynamicImage::ImageLuma8(decoded.to_luma8())
+    } else {
+      DynamicImage::ImageRgb8(decoded.to_rgb8())
+    }
+  } else {
+    if filters
+      .iter()
+      .any(|f| !matches!(*f, b"FlateDecode" | b"LZWDecode" | b"ASCII85Decode"))
+    {
+      return None;
+    }
+    let mut samples = if filters.is_empty() {
+      stream.content.clone()
+    } else {
+      stream.decompressed_content().ok()?
+    };
+    samples.truncate(width as usize * height as usize * components as usize);
+    if components == 1 {
+      DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, samples)?)
+    } else {
+      DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, samples)?)
+    }
+  };
+
+  let image = if width > level.max_edge || height > level.max_edge {
+    image.resize(
+      level.max_edge,
+      level.max_edge,
+      image::imageops::FilterType::Triangle,
+    )
+  } else {
+    image
+  };
+
+  let mut encoded = Vec::new();
+  let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut encoded, level.quality);
+  let result = if components == 1 {
+    encoder.encode_image(&image.to_luma8())
+  } else {
+    encoder.encode_image(&image.to_rgb8())
+  };
+  result.ok()?;
+  if encoded.len() >= stream.content.len() {
+    return None;
+  }
+
+  let mut recompressed = dict.clone();
+  for key in ["Filter", "DecodeParms", "Length"] {
+    recompressed.remove(key.as_bytes());
+  }
+  recompressed.set("Width", image.width() as i64);
+  recompressed.set("Height", image.height() as i64);
+  recompressed.set(
+    "ColorSpace",
+    if components == 1 {
+      "DeviceGray"
+    } else {
+      "DeviceRGB"
+    },
+  );
+  recompressed.set("Filter", "DCTDecode");
+  Some(Stream::new(recompressed, encoded).with_compression(false))
+}
+
+/// Color components of an image color space that can be re-encoded as gray or RGB JPEG
+fn image_components(doc: &Document, color_space: &Object) -> Option<u32> {
+  let (_, color_space) = doc.dereference(color_space).ok()?;
+  match color_space {
+    Object::Name(name) => match name.as_slice() {
+      b"DeviceGray" => Some(1),
+      b"DeviceRGB" => Some(3),
+      _ => None,
+    },
+    // ICC profiles are dropped; the device space is close enough for scanned documents
+    Object::Array(items) if items.first()?.as_name().ok()? == b"ICCBased" => {
+      let (_, profile) = doc.dereference(items.get(1)?).ok()?;
+      match profile
+        .as_stream()
+        .ok()?
+        .dict
+        .get(b"N")
+        .and_then(Object::as_i64)
+        .ok()?
+      {
+        1 => Some(1),
+        3 => Some(3),
+        _ => None,
+      }
+    }
+    _ => None,
+  }
+}
+
+/// Dictionary types that are safe to share once identical, unlike pages
+const SHAREABLE_TYPES: [&[u8]; 4] = [b"Font", b"FontDescriptor", b"ExtGState", b"Encoding"];
+
+/// Point references at a single copy of identical streams, such as the same font or logo in
+/// every merged document, and of identical font and graphics state dictionaries
+fn deduplicate_objects(doc: &mut Document) {
+  // Dictionaries only become identical once the streams they point at have been merged
+  for _ in 0..3 {
+    let mut first_by_key: HashMap<Vec<u8>, ObjectId> = HashMap::new();
+    let mut replacements: HashMap<ObjectId, ObjectId> = HashMap::new();
+    for (&id, object) in &doc.objects {
+      let Some(key) = dedup_key(object) else {
+        continue;
+      };
+      match first_by_key.entry(key) {
+        Entry::Occupied(first) => {
+          replacements.insert(id, *first.get());
+        }
+        Entry::Vacant(slot) => {
+          slot.insert(id);
+        }
+      }
+    }
+    if replacements.is_empty() {
+      return;
+    }
+
+    for object in doc.objects.values_mut() {
+      replace_references(object, &replacements);
+    }
+    for value in doc.trailer.iter_mut().map(|(_, value)| value) {
+      replace_references(value, &replacements);
+    }
+    for id in replacements.keys() {
+      doc.objects.remove(id);
+    }
+  }
+}
+
+fn dedup_key(object: &Object) -> Option<Vec<u8>> {
+  let mut hasher = Sha256::new();
+  match object {
+    Object::Stream(stream) => {
+      if matches!(
+        stream.dict.get(b"Type").and_then(Object::as_name).ok(),
+        Some(b"XRef" | b"ObjStm")
+      ) {
+        return None;
+      }
+      hasher.update(b"stream");
+      hasher.update(format!("{:?}", stream.dict).as_bytes());
+      hasher.update(&stream.content);
+    }
+    Object::Dictionary(dict) => {
+      let kind = dict.get(b"Type").and_then(Object::as_name).ok()?;
+      if !SHAREABLE_TYPES.contains(&kind) {
+        return None;
+      }
+      hasher.update(b"dict");
+      hasher.update(format!("{:?}", dict).as_bytes());
+    }
+    _ => return None,
+  }
+  Some(hasher.finalize().to_vec())
+}
+
+fn replace_references(object: &mut Object, replacements: &HashMap<ObjectId, ObjectId>) {
+  match object {
+    Object::Reference(id) => {
+      if let Some(replacement) = replacements.get(id) {
+        *id = *replacement;
+      }
+    }
+    Object::Array(items) => {
+      for item in items {
+        replace_references(item, replacements);
+      }
+    }
+    Object::Dictionary(dict) => replace_dict_references(dict, replacements),
+    Object::Stream(stream) => replace_dict_references(&mut stream.dict, replacements),
+    _ => {}
+  }
+}
+
+fn replace_dict_references(dict: &mut Dictionary, replacements: &HashMap<ObjectId, ObjectId>) {
+  for (_, value) in dict.iter_mut() {
+    replace_references(value, replacements);
+  }
+}
+
+/// Estimated bytes per document, attributing every page to the top level outline entry it
+/// falls under
+fn document_sizes(doc: &Document) -> Vec<DocumentSize> {
+  let mut starts: Vec<(u32, String)> = doc
+    .get_toc()
+    .map(|toc| {
+      toc
+        .toc
+        .into_iter()
+        .filter(|entry| entry.level == 1)
+        .map(|entry| (entry.page as u32, entry.title))
+        .collect()
+    })
+    .unwrap_or_default();
+  starts.sort_by_key(|(page, _)| *page);
+
+  let mut seen = HashSet::new();
+  let mut sizes: Vec<DocumentSize> = Vec::new();
+  for (number, page_id) in doc.get_pages() {
+    let title = starts
+      .iter()
+      .rev()
+      .find(|(start, _)| *start <= number)
+      .map_or(UNTITLED_PAGES, |(_, title)| title.as_str());
+    let bytes = page_size(doc, page_id, &mut seen);
+    match sizes.last_mut() {
+      Some(last) if last.title == title => last.bytes += bytes,
+      _ => sizes.push(DocumentSize {
+        title: title.to_string(),
+        bytes,
+      }),
+    }
+  }
+
+  sizes.sort_by_key(|size| Reverse(size.bytes));
+  sizes
+}
+
+/// Rough serialized size of a page and everything it uses that hasn't been counted yet
+fn page_size(doc: &Document, page_id: ObjectId, seen: &mut HashSet<ObjectId>) -> u64 {
+  // Overhead of an object header, dictionary and cross reference entry
+  const OBJECT_OVERHEAD: u64 = 64;
+
+  let mut bytes = 0;
+  let mut pending = vec![page_id];
+  while let Some(id) = pending.pop() {
+    let Ok(object) = doc.get_object(id) else {
+      continue;
+    };
+    let dict = match object {
+      Object::Stream(stream) => Some(&stream.dict),
+      Object::Dictionary(dict) => Some(dict),
+      _ => None,
+    };
+    // Links and parents lead to other pages, which are counted on their own
+    if id != page_id
+      && matches!(
+        dict.and_then(|d| d.get(b"Type").and_then(Object::as_name).ok()),
+        Some(b"Page" | b"Pages")
+      )
+    {
+      continue;
+    }
+    if !seen.insert(id) {
+      continue;
+    }
+
+    bytes += OBJECT_OVERHEAD;
+    if let Object::Stream(stream) = object {
+      bytes += stream.content.len() as u64;
+    }
+    match (object, dict) {
+      (Object::Array(items), _) => items
+        .iter()
+        .for_each(|item| collect_references(item, &mut pending)),
+      (_, Some(dict)) => dict
+        .iter()
+        .filter(|(key, _)| key.as_slice() != b"Parent")
+        .for_each(|(_, item)| collect_references(item, &mut pending)),
+      _ => {}
+    }
+  }
+  bytes
+}
+
+fn collect_references(value: &Object, pending: &mut Vec<ObjectId>) {
+  match value {
+    Object::Reference(id) => pending.push(*id),
+    Object::Array(items) => items
+      .iter()
+      .for_each(|item| collect_references(item, pending)),
+    Object::Dictionary(dict) => dict
+      .iter()
+      .for_each(|(_, item)| collect_references(item, pending)),
+    _ => {}
+  }
+}
@@ -0,0 +1,78 @@
+use super::pdf_docs::load_pdf;
+use anyhow::Result;
+use lopdf::{Document, Object, ObjectId};
+use std::collections::HashSet;
+
+/// CMap header entries lopdf's ToUnicode parser rejects. printpdf writes both, so without
+/// dropping them the text of every PDF this app generates would be unreadable.
+const UNSUPPORTED_CMAP_ENTRIES: [&str; 2] = ["/CMapVersion", "/WMode"];
+
+/// Text of every page of a PDF, in page order. Pages without a text layer, such as scans,
+/// come back empty. Text in fonts that cannot be decoded is skipped rather than failing the
+/// whole document.
+pub fn extract_pdf_text(bytes: &[u8]) -> Result<Vec<String>> {
+  let mut doc = load_pdf(bytes)?;
+  clean_to_unicode_cmaps(&mut doc);
+
+  let page_count = doc.get_pages().len() as u32;
+  Ok(
+    (1..=page_count)
+      .map(|page| {
+        let chunks: Vec<String> = doc
+          .extract_text_chunks(&[page])
+          .into_iter()
+          .filter_map(|chunk| chunk.ok())
+          .collect();
+        normalize_whitespace(&chunks.concat())
+      })
+      .collect(),
+  )
+}
+
+/// Whether any page has text. PDFs of scanned paper only hold images.
+pub fn has_text_layer(pages: &[String]) -> bool {
+  pages
+    .iter()
+    .any(|page| page.chars().any(char::is_alphanumeric))
+}
+
+fn clean_to_unicode_cmaps(doc: &mut Document) {
+  let cmap_ids: HashSet<ObjectId> = doc
+    .objects
+    .values()
+    .filter_map(|object| match object {
+      Object::Dictionary(dict) => dict.get(b"ToUnicode").ok()?.as_reference().ok(),
+      _ => None,
+    })
+    .collect();
+
+  for id in cmap_ids {
+    let Ok(stream) = doc.get_object_mut(id).and_then(Object::as_stream_mut) else {
+      continue;
+    };
+    let Ok(content) = stream.get_plain_content() else {
+      continue;
+    };
+    let content = String::from_utf8_lossy(&content).into_owned();
+    let cleaned: Vec<&str> = content
+      .lines()
+      .filter(|line| {
+        let line = line.trim_start();
+        !UNSUPPORTED_CMAP_ENTRIES
+          .iter()
+          .any(|entry| line.starts_with(entry))
+      })
+      .collect();
+    stream.set_plain_content(cleaned.join("\n").into_bytes());
+  }
+}
+
+/// One space between words and one line break between lines, without blank lines
+fn normalize_whitespace(text: &str) -> String {
+  text
+    .lines()
+    .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+    .filter(|line| !line.is_empty())
+    .collect::<Vec<_>>()
+    .join("\n")
+}
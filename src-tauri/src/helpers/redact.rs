@@ -0,0 +1,1054 @@
+//! Burning redactions into documents. Redacted content is removed rather than covered: image
+//! pixels are overwritten, and on PDF pages the text and image data under a region is deleted
+//! before an opaque box is drawn over it, so nothing can be copied or recovered from beneath.
+
+use super::images::{decode_pages, encode_jpeg, encode_tiff_pages};
+use super::mime::{sniff_mime, TIFF};
+use super::pdf_docs::{load_pdf, page_geometry, push_down_inherited_attributes};
+use anyhow::Result;
+use image::{DynamicImage, ImageFormat, Rgba};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Dictionary, Object, ObjectId, Stream};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Cursor;
+
+/// Area to redact, in fractions of the page as displayed, measured from its top left corner
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct RedactionRegion {
+  /// 1-based page number. Images have a single page, except multi-page TIFFs.
+  pub page: u32,
+  pub x: f32,
+  pub y: f32,
+  pub width: f32,
+  pub height: f32,
+}
+
+/// Rectangle in PDF user space
+#[derive(Clone, Copy)]
+struct Rect {
+  x0: f32,
+  y0: f32,
+  x1: f32,
+  y1: f32,
+}
+
+impl Rect {
+  fn bounding(points: impl IntoIterator<Item = (f32, f32)>) -> Rect {
+    let mut rect = Rect {
+      x0: f32::INFINITY,
+      y0: f32::INFINITY,
+      x1: f32::NEG_INFINITY,
+      y1: f32::NEG_INFINITY,
+    };
+    for (x, y) in points {
+      rect.x0 = rect.x0.min(x);
+      rect.y0 = rect.y0.min(y);
+      rect.x1 = rect.x1.max(x);
+      rect.y1 = rect.y1.max(y);
+    }
+    rect
+  }
+
+  fn transformed(&self, m: &[f32; 6]) -> Rect {
+    Rect::bounding([
+      apply(m, self.x0, self.y0),
+      apply(m, self.x1, self.y0),
+      apply(m, self.x0, self.y1),
+      apply(m, self.x1, self.y1),
+    ])
+  }
+
+  fn intersects(&self, other: &Rect) -> bool {
+    self.x0 < other.x1 && other.x0 < self.x1 && self.y0 < other.y1 && other.y0 < self.y1
+  }
+}
+
+const IDENTITY: [f32; 6] = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Matrix applying `a`, then `b`
+fn multiply(a: &[f32; 6], b: &[f32; 6]) -> [f32; 6] {
+  [
+    a[0] * b[0] + a[1] * b[2],
+    a[0] * b[1] + a[1] * b[3],
+    a[2] * b[0] + a[3] * b[2],
+    a[2] * b[1] + a[3] * b[3],
+    a[4] * b[0] + a[5] * b[2] + b[4],
+    a[4] * b[1] + a[5] * b[3] + b[5],
+  ]
+}
+
+fn apply(m: &[f32; 6], x: f32, y: f32) -> (f32, f32) {
+  (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+fn invert(m: &[f32; 6]) -> Option<[f32; 6]> {
+  let det = m[0] * m[3] - m[1] * m[2];
+  if det.abs() < f32::EPSILON {
+    return None;
+  }
+  let (a, b, c, d) = (m[3] / det, -m[1] / det, -m[2] / det, m[0] / det);
+  Some([a, b, c, d, -(m[4] * a + m[5] * c), -(m[4] * b + m[5] * d)])
+}
+
+/// Check that regions are non-empty and lie on the page
+fn validate_regions(regions: &[RedactionRegion]) -> Result<()> {
+  if regions.is_empty() {
+    return Err(anyhow::anyhow!("No regions to redact"));
+  }
+
+  for region in regions {
+    let in_range = |v: f32| (0.0..=1.0).contains(&v);
+    if region.page == 0
+      || !in_range(region.x)
+      || !in_range(region.y)
+      || region.width <= 0.0
+      || region.height <= 0.0
+      || region.x + region.width > 1.0 + f32::EPSILON
+      || region.y + region.height > 1.0 + f32::EPSILON
+    {
+      return Err(anyhow::anyhow!("Invalid redaction region {:?}", region));
+    }
+  }
+
+  Ok(())
+}
+
+/// Black out regions of an image. JPEGs stay JPEGs, multi-page TIFFs stay multi-page TIFFs;
+/// everything else is saved as PNG. The result is upright and carries no metadata.
+pub fn redact_image(blob: &[u8], regions: &[RedactionRegion]) -> Result<Vec<u8>> {
+  validate_regions(regions)?;
+
+  let format = image::guess_format(blob).ok();
+  let mut pages: Vec<_> = decode_pages(blob)?
+    .into_iter()
+    .map(|page| page.to_rgba8())
+    .collect();
+  if let Some(region) = regions.iter().find(|r| r.page as usize > pages.len()) {
+    return Err(anyhow::anyhow!(
+      "Page {} does not exist; the image has {} pages",
+      region.page,
+      pages.len()
+    ));
+  }
+
+  for region in regions {
+    let pixels = &mut pages[region.page as usize - 1];
+    let (width, height) = pixels.dimensions();
+    let x0 = (region.x * width as f32).floor() as u32;
+    let y0 = (region.y * height as f32).floor() as u32;
+    let x1 = (((region.x + region.width) * width as f32).ceil() as u32).min(width);
+    let y1 = (((region.y + region.height) * height as f32).ceil() as u32).min(height);
+    for y in y0..y1 {
+      for x in x0..x1 {
+        pixels.put_pixel(x, y, Rgba([0, 0, 0, 255]));
+      }
+    }
+  }
+
+  if pages.len() > 1 && sniff_mime(blob) == Some(TIFF) {
+    let pages: Vec<DynamicImage> = pages.into_iter().map(DynamicImage::ImageRgba8).collect();
+    return encode_tiff_pages(&pages);
+  }
+
+  let image = DynamicImage::ImageRgba8(pages.swap_remove(0));
+  if format == Some(ImageFormat::Jpeg) {
+    return encode_jpeg(&image, 90);
+  }
+
+  let mut bytes = Vec::new();
+  image
+    .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+    .map_err(|e| anyhow::anyhow!("Failed to encode PNG: {}", e))?;
+  Ok(bytes)
+}
+
+/// Remove text, image data and annotations under the regions of a PDF and cover them with
+/// opaque boxes. Text runs touching a region are removed whole, measured with their font's
+/// glyph widths, and the text around them keeps its place. Fonts without widths, such as the
+/// standard 14, cannot be measured, so all text of a text object using one is removed when any
+/// of it may reach a region.
+pub fn redact_pdf(bytes: &[u8], regions: &[RedactionRegion]) -> Result<Vec<u8>> {
+  validate_regions(regions)?;
+
+  let mut doc = load_pdf(bytes)?;
+  // Pages get their own resources below, so inherited ones must be on the page first
+  push_down_inherited_attributes(&mut doc);
+  let pages = doc.get_pages();
+
+  let mut page_numbers: Vec<u32> = regions.iter().map(|r| r.page).collect();
+  page_numbers.sort_unstable();
+  page_numbers.dedup();
+
+  for page_number in page_numbers {
+    let page_id = *pages.get(&page_number).ok_or_else(|| {
+      anyhow::anyhow!(
+        "Page {} does not exist; the PDF has {} pages",
+        page_number,
+        pages.len()
+      )
+    })?;
+
+    let (width, height, matrix) = page_geometry(&doc, page_id)?.display_transform();
+    let rects: Vec<Rect> = regions
+      .iter()
+      .filter(|r| r.page == page_number)
+      .map(|r| {
+        Rect {
+          x0: r.x * width,
+          y0: (1.0 - r.y - r.height) * height,
+          x1: (r.x + r.width) * width,
+          y1: (1.0 - r.y) * height,
+        }
+        .transformed(&matrix)
+      })
+      .collect();
+
+    redact_page(&mut doc, page_id, &rects)
+      .map_err(|e| anyhow::anyhow!("Page {}: {}", page_number, e))?;
+  }
+
+  // Drops the original content streams and images
+  doc.prune_objects();
+  doc.compress();
+
+  let mut bytes = Vec::new();
+  doc
+    .save_to(&mut bytes)
+    .map_err(|e| anyhow::anyhow!("Failed to write redacted PDF: {}", e))?;
+  Ok(bytes)
+}
+
+/// Text state needed to work out where text is drawn
+struct TextState {
+  matrix: [f32; 6],
+  line_matrix: [f32; 6],
+  /// Set after text in a font without widths, until the next line starts
+  position_unknown: bool,
+}
+
+/// Text parameters, which are part of the graphics state saved by `q`
+#[derive(Clone)]
+struct TextParams {
+  font: Option<Vec<u8>>,
+  font_size: f32,
+  leading: f32,
+  horizontal_scale: f32,
+  char_spacing: f32,
+  word_spacing: f32,
+}
+
+/// Glyph widths of a font, in thousandths of text space units
+enum FontWidths {
+  /// Single-byte codes, from the `/Widths` array
+  Simple {
+    first_char: usize,
+    widths: Vec<f32>,
+    missing_width: f32,
+  },
+  /// Two-byte codes of an `Identity-H` Type0 font, from its descendant's `/W` array
+  Identity {
+    widths: HashMap<u32, f32>,
+    default_width: f32,
+  },
+}
+
+/// How far text reaching to the right of where it starts is assumed to extend, in text
+/// space units. Any page is narrower.
+const UNMEASURED_TEXT_WIDTH: f32 = 100_000.0;
+
+fn redact_page(doc: &mut lopdf::Document, page_id: ObjectId, rects: &[Rect]) -> Result<()> {
+  let content = doc.get_and_decode_page_content(page_id)?;
+
+  let page = doc.get_dictionary(page_id)?;
+  let mut resources = match page.get(b"Resources") {
+    Ok(object) => doc.dereference(object)?.1.as_dict()?.clone(),
+    Err(_) => Dictionary::new(),
+  };
+  let mut xobjects = match resources.get(b"XObject") {
+    Ok(object) => doc.dereference(object)?.1.as_dict()?.clone(),
+    Err(_) => Dictionary::new(),
+  };
+  let color_spaces = match resources.get(b"ColorSpace") {
+    Ok(object) => doc.dereference(object)?.1.as_dict()?.clone(),
+    Err(_) => Dictionary::new(),
+  };
+  let fonts = page_font_widths(doc, &resources);
+
+  let mut ctm = IDENTITY;
+  let mut params = TextParams {
+    font: None,
+    font_size: 0.0,
+    leading: 0.0,
+    horizontal_scale: 1.0,
+    char_spacing: 0.0,
+    word_spacing: 0.0,
+  };
+  let mut state_stack = Vec::new();
+  let mut text = TextState {
+    matrix: IDENTITY,
+    line_matrix: IDENTITY,
+    position_unknown: false,
+  };
+
+  let mut operations = vec![Operation::new("q", vec![])];
+  let mut inline_images = 0;
+  // Where the current text object starts in `operations`, and whether all of its text has to
+  // go because text that could not be measured may reach a region
+  let mut text_object_start = None;
+  let mut remove_text_object = false;
+
+  for operation in content.operations {
+    let numbers: Vec<f32> = operation
+      .operands
+      .iter()
+      .filter_map(|o| o.as_float().ok())
+      .collect();
+
+    match operation.operator.as_str() {
+      "q" => state_stack.push((ctm, params.clone())),
+      "Q" => {
+        if let Some((saved_ctm, saved_params)) = state_stack.pop() {
+          ctm = saved_ctm;
+          params = saved_params;
+        } else {
+          ctm = IDENTITY;
+        }
+      }
+      "cm" if numbers.len() == 6 => {
+        let m = [
+          numbers[0], numbers[1], numbers[2], numbers[3], numbers[4], numbers[5],
+        ];
+        ctm = multiply(&m, &ctm);
+      }
+      "BT" => {
+        text.matrix = IDENTITY;
+        text.line_matrix = IDENTITY;
+        text.position_unknown = false;
+        text_object_start = Some(operations.len());
+        remove_text_object = false;
+      }
+      "ET" => {
+        if let Some(start) = text_object_start.take() {
+          if remove_text_object {
+            // Text objects start at the text space origin, so the text after this one keeps
+            // its place. The spacing set by " outlasts the text object, so it stays.
+            let text_object = operations.split_off(start);
+            for op in text_object {
+              match op.operator.as_str() {
+                "\"" if op.operands.len() == 3 => {
+                  operations.push(Operation::new("Tw", vec![op.operands[0].clone()]));
+                  operations.push(Operation::new("Tc", vec![op.operands[1].clone()]));
+                }
+                operator if is_text_showing(operator) => {}
+                _ => operations.push(op),
+              }
+            }
+          }
+        }
+      }
+      "Tf" if operation.operands.len() == 2 => {
+        params.font = operation.operands[0].as_name().ok().map(<[u8]>::to_vec);
+        params.font_size = operation.operands[1].as_float().unwrap_or(0.0);
+      }
+      "TL" if numbers.len() == 1 => params.leading = numbers[0],
+      "Tz" if numbers.len() == 1 => params.horizontal_scale = numbers[0] / 100.0,
+      "Tc" if numbers.len() == 1 => params.char_spacing = numbers[0],
+      "Tw" if numbers.len() == 1 => params.word_spacing = numbers[0],
+      "Td" if numbers.len() == 2 => move_text_line(&mut text, numbers[0], numbers[1]),
+      "TD" if numbers.len() == 2 => {
+        params.leading = -numbers[1];
+        move_text_line(&mut text, numbers[0], numbers[1]);
+      }
+      "Tm" if numbers.len() == 6 => {
+        text.matrix = [
+          numbers[0], numbers[1], numbers[2], numbers[3], numbers[4], numbers[5],
+        ];
+        text.line_matrix = text.matrix;
+        text.position_unknown = false;
+      }
+      "T*" => move_text_line(&mut text, 0.0, -params.leading),
+      operator if is_text_showing(operator) => {
+        let mut prefix = Vec::new();
+        if operator == "\"" && numbers.len() >= 2 {
+          params.word_spacing = numbers[0];
+          params.char_spacing = numbers[1];
+          prefix.push(Operation::new("Tw", vec![operation.operands[0].clone()]));
+          prefix.push(Operation::new("Tc", vec![operation.operands[1].clone()]));
+        }
+        if operator == "'" || operator == "\"" {
+          move_text_line(&mut text, 0.0, -params.leading);
+          prefix.push(Operation::new("T*", vec![]));
+        }
+
+        let font = params
+          .font
+          .as_ref()
+          .and_then(|name| fonts.get(name)?.as_ref());
+        let advance = font.map(|font| text_advance(&operation.operands, font, &params));
+        // Where text in an unmeasured font ends, and so where any text after it on the same
+        // line starts, is unknown. It may reach anywhere to the right.
+        let reach = match advance {
+          Some(advance) if !text.position_unknown => advance * params.horizontal_scale,
+          _ => UNMEASURED_TEXT_WIDTH,
+        };
+        let bounds = Rect {
+          x0: 0.0,
+          y0: -0.25 * params.font_size,
+          x1: reach,
+          y1: params.font_size,
+        }
+        .transformed(&multiply(&text.matrix, &ctm));
+        let hit = rects.iter().any(|r| r.intersects(&bounds));
+
+        match advance {
+          Some(advance) => {
+            if hit && !text.position_unknown {
+              operations.extend(prefix);
+              // Keep the text position moving as if the text had been drawn
+              if params.font_size.abs() > f32::EPSILON {
+                operations.push(Operation::new(
+                  "TJ",
+                  vec![Object::Array(vec![Object::Real(
+                    -advance * 1000.0 / params.font_size,
+                  )])],
+                ));
+              }
+            } else {
+              remove_text_object |= hit;
+              operations.push(operation);
+            }
+            text.matrix = multiply(
+              &[1.0, 0.0, 0.0, 1.0, advance * params.horizontal_scale, 0.0],
+              &text.matrix,
+            );
+          }
+          None => {
+            remove_text_object |= hit;
+            text.position_unknown = true;
+            operations.push(operation);
+          }
+        }
+        continue;
+      }
+      "BI" => {
+        // Inline images become image XObjects so they can be redacted like any other image
+        let Some(Object::Stream(stream)) = operation.operands.into_iter().next() else {
+          continue;
+        };
+        inline_images += 1;
+        let name = format!("RedactedInline{}", inline_images);
+        let stream = inline_image_to_xobject(stream, &color_spaces);
+        let id = doc.add_object(stream);
+        xobjects.set(name.as_str(), id);
+        redact_xobject(doc, &mut xobjects, name.as_bytes(), &ctm, rects)?;
+        operations.push(Operation::new("Do", vec![Object::Name(name.into_bytes())]));
+        continue;
+      }
+      "Do" => {
+        if let Some(Ok(name)) = operation.operands.first().map(Object::as_name) {
+          let name = name.to_vec();
+          redact_xobject(doc, &mut xobjects, &name, &ctm, rects)?;
+        }
+      }
+      _ => {}
+    }
+
+    operations.push(operation);
+  }
+
+  // Opaque boxes over everything drawn on the page
+  operations.push(Operation::new("Q", vec![]));
+  operations.push(Operation::new("q", vec![]));
+  operations.push(Operation::new("g", vec![Object::Integer(0)]));
+  for rect in rects {
+    operations.push(Operation::new(
+      "re",
+      vec![
+        Object::Real(rect.x0),
+        Object::Real(rect.y0),
+        Object::Real(rect.x1 - rect.x0),
+        Object::Real(rect.y1 - rect.y0),
+      ],
+    ));
+  }
+  operations.push(Operation::new("f", vec![]));
+  operations.push(Operation::new("Q", vec![]));
+
+  let content = Content { operations }
+    .encode()
+    .map_err(|e| anyhow::anyhow!("Failed to encode page content: {}", e))?;
+  let content_id = doc.add_object(Stream::new(Dictionary::new(), content));
+
+  resources.set("XObject", xobjects);
+  let annotations = remaining_annotations(doc, page_id, rects);
+
+  let page = doc.get_dictionary_mut(page_id)?;
+  page.set("Resources", resources);
+  page.set("Contents", content_id);
+  if let Some(annotations) = annotations {
+    page.set("Annots", annotations);
+  }
+
+  Ok(())
+}
+
+fn move_text_line(text: &mut TextState, x: f32, y: f32) {
+  text.line_matrix = multiply(&[1.0, 0.0, 0.0, 1.0, x, y], &text.line_matrix);
+  text.matrix = text.line_matrix;
+  text.position_unknown = false;
+}
+
+fn is_text_showing(operator: &str) -> bool {
+  matches!(operator, "Tj" | "TJ" | "'" | "\"")
+}
+
+/// Horizontal advance of a text-showing operation in text space, before horizontal scaling
+fn text_advance(operands: &[Object], font: &FontWidths, params: &TextParams) -> f32 {
+  let string_advance = |bytes: &[u8]| -> f32 {
+    let glyphs: Vec<(f32, bool)> = match font {
+      FontWidths::Simple {
+        first_char,
+        widths,
+        missing_width,
+      } => bytes
+        .iter()
+        .map(|&code| {
+          let width = (code as usize)
+            .checked_sub(*first_char)
+            .and_then(|index| widths.get(index))
+            .copied()
+            .unwrap_or(*missing_width);
+          (width, code == b' ')
+        })
+        .collect(),
+      // Word spacing only applies to single-byte codes
+      FontWidths::Identity {
+        widths,
+        default_width,
+      } => bytes
+        .chunks(2)
+        .map(|code| {
+          let cid = code.iter().fold(0, |cid, &byte| cid << 8 | byte as u32);
+          (widths.get(&cid).copied().unwrap_or(*default_width), false)
+        })
+        .collect(),
+    };
+    glyphs
+      .into_iter()
+      .map(|(width, is_space)| {
+        let word_spacing = if is_space { params.word_spacing } else { 0.0 };
+        width / 1000.0 * params.font_size + params.char_spacing + word_spacing
+      })
+      .sum()
+  };
+
+  // The string of ' and " is their last operand
+  let shown = match operands {
+    [.., Object::Array(items)] => items.as_slice(),
+    [.., last] => std::slice::from_ref(last),
+    [] => &[],
+  };
+  shown
+    .iter()
+    .map(|item| match item {
+      Object::String(bytes, _) => string_advance(bytes),
+      other => -other.as_float().unwrap_or(0.0) / 1000.0 * params.font_size,
+    })
+    .sum()
+}
+
+/// Glyph widths of the fonts in a page's resources, by resource name. Fonts whose widths are
+/// not in the file, or whose codes this cannot split into glyphs, map to `None`.
+fn page_font_widths(
+  doc: &lopdf::Document,
+  resources: &Dictionary,
+) -> HashMap<Vec<u8>, Option<FontWidths>> {
+  let Some(fonts) = resources
+    .get(b"Font")
+    .ok()
+    .and_then(|fonts| doc.dereference(fonts).ok()?.1.as_dict().ok())
+  else {
+    return HashMap::new();
+  };
+
+  fonts
+    .iter()
+    .map(|(name, font)| {
+      let widths = doc
+        .dereference(font)
+        .ok()
+        .and_then(|(_, font)| font.as_dict().ok())
+        .and_then(|font| font_widths(doc, font));
+      (name.clone(), widths)
+    })
+    .collect()
+}
+
+fn font_widths(doc: &lopdf::Document, font: &Dictionary) -> Option<FontWidths> {
+  let numbers = |object: &Object| -> Option<Vec<f32>> {
+    resolve(doc, object)?
+      .as_array()
+      .ok()?
+      .iter()
+      .map(|value| resolve(doc, value)?.as_float().ok())
+      .collect()
+  };
+
+  match font.get(b"Subtype").and_then(Object::as_name).ok()? {
+    b"Type1" | b"MMType1" | b"TrueType" => {
+      let first_char = resolve(doc, font.get(b"FirstChar").ok()?)?.as_i64().ok()?;
+      let widths = numbers(font.get(b"Widths").ok()?)?;
+      let missing_width = font
+        .get(b"FontDescriptor")
+        .ok()
+        .and_then(|object| resolve(doc, object))
+        .and_then(|descriptor| descriptor.as_dict().ok())
+        .and_then(|descriptor| resolve(doc, descriptor.get(b"MissingWidth").ok()?))
+        .and_then(|width| width.as_float().ok())
+        .unwrap_or(0.0);
+      Some(FontWidths::Simple {
+        first_char: usize::try_from(first_char).ok()?,
+        widths,
+        missing_width,
+      })
+    }
+    b"Type0" => {
+      // Other CMaps map codes of varying length, and vertical text advances downwards
+      if resolve(doc, font.get(b"Encoding").ok()?)?.as_name().ok()? != b"Identity-H" {
+        return None;
+      }
+      let descendant = resolve(doc, font.get(b"DescendantFonts").ok()?)?
+        .as_array()
+        .ok()?
+        .first()
+        .and_then(|object| resolve(doc, object))?
+        .as_dict()
+        .ok()?;
+      let default_width = descendant
+        .get(b"DW")
+        .ok()
+        .and_then(|object| resolve(doc, object))
+        .and_then(|width| width.as_float().ok())
+        .unwrap_or(1000.0);
+
+      // Entries are either `first [w1 w2 ...]` or `first last w`
+      let mut widths = HashMap::new();
+      if let Ok(entries) = descendant.get(b"W") {
+        let entries: Vec<&Object> = resolve(doc, entries)?
+          .as_array()
+          .ok()?
+          .iter()
+          .filter_map(|entry| resolve(doc, entry))
+          .collect();
+        let mut i = 0;
+        while i + 1 < entries.len() {
+          let first = entries[i].as_i64().ok()? as u32;
+          if let Ok(run) = entries[i + 1].as_array() {
+            for (offset, width) in run.iter().enumerate() {
+              widths.insert(first + offset as u32, resolve(doc, width)?.as_float().ok()?);
+            }
+            i += 2;
+          } else {
+            let last = entries[i + 1].as_i64().ok()? as u32;
+            let width = entries.get(i + 2)?.as_float().ok()?;
+            for cid in first..=last.min(first.saturating_add(0xFFFF)) {
+              widths.insert(cid, width);
+            }
+            i += 3;
+          }
+        }
+      }
+      Some(FontWidths::Identity {
+        widths,
+        default_width,
+      })
+    }
+    // Type3 glyphs are measured in their own glyph space
+    _ => None,
+  }
+}
+
+fn resolve<'a>(doc: &'a lopdf::Document, object: &'a Object) -> Option<&'a Object> {
+  doc.dereference(object).ok().map(|(_, object)| object)
+}
+
+/// Overwrite the parts of an image XObject drawn under a region. The redacted image is stored
+/// as a new object used only by this page, since the original may be shared with other pages.
+fn redact_xobject(
+  doc: &mut lopdf::Document,
+  xobjects: &mut Dictionary,
+  name: &[u8],
+  ctm: &[f32; 6],
+  rects: &[Rect],
+) -> Result<()> {
+  let Ok(object) = xobjects.get(name) else {
+    return Ok(());
+  };
+  let Ok(stream) = doc.dereference(object)?.1.as_stream() else {
+    return Ok(());
+  };
+
+  let subtype = stream
+    .dict
+    .get(b"Subtype")
+    .and_then(Object::as_name)
+    .map(<[u8]>::to_vec)
+    .unwrap_or_default();
+
+  match subtype.as_slice() {
+    b"Image" => {
+      // Images are drawn into the unit square
+      let placement = Rect {
+        x0: 0.0,
+        y0: 0.0,
+        x1: 1.0,
+        y1: 1.0,
+      }
+      .transformed(ctm);
+      let hits: Vec<Rect> = rects
+        .iter()
+        .filter(|r| r.intersects(&placement))
+        .copied()
+        .collect();
+      if hits.is_empty() {
+        return Ok(());
+      }
+
+      let inverse =
+        invert(ctm).ok_or_else(|| anyhow::anyhow!("Image has a degenerate placement"))?;
+      let unit_rects: Vec<Rect> = hits.iter().map(|r| r.transformed(&inverse)).collect();
+
+      // Soft masks and stencil masks cover the same unit square, and keep the outline of
+      // what was drawn. A /Mask array is a range of colors rather than an image.
+      let masks = ["SMask", "Mask"]
+        .into_iter()
+        .filter_map(|key| {
+          let mask_id = stream.dict.get(key.as_bytes()).ok()?.as_reference().ok()?;
+          Some((key, mask_id))
+        })
+        .map(|(key, mask_id)| {
+          let mask = doc.get_object(mask_id)?.as_stream()?;
+          Ok((key, redact_image_stream(doc, mask, &unit_rects)?))
+        })
+        .collect::<Result<Vec<_>>>()?;
+      let mut redacted = redact_image_stream(doc, stream, &unit_rects)?;
+      for (key, mask) in masks {
+        let mask_id = doc.add_object(mask);
+        redacted.dict.set(key, mask_id);
+      }
+
+      let id = doc.add_object(redacted);
+      xobjects.set(name.to_vec(), id);
+    }
+    b"Form" => {
+      let bbox: Vec<f32> = stream
+        .dict
+        .get(b"BBox")
+        .and_then(Object::as_array)
+        .map(|values| values.iter().filter_map(|v| v.as_float().ok()).collect())
+        .unwrap_or_default();
+      let matrix: Vec<f32> = stream
+        .dict
+        .get(b"Matrix")
+        .and_then(Object::as_array)
+        .map(|values| values.iter().filter_map(|v| v.as_float().ok()).collect())
+        .unwrap_or_default();
+      let matrix = match matrix[..] {
+        [a, b, c, d, e, f] => [a, b, c, d, e, f],
+        _ => IDENTITY,
+      };
+      let placement = match bbox[..] {
+        [x0, y0, x1, y1] => Rect { x0, y0, x1, y1 }.transformed(&multiply(&matrix, ctm)),
+        _ => return Ok(()),
+      };
+
+      if rects.iter().any(|r| r.intersects(&placement)) {
+        return Err(anyhow::anyhow!(
+          "a region covers embedded page content (a form XObject), which cannot be redacted"
+        ));
+      }
+    }
+    _ => {}
+  }
+
+  Ok(())
+}
+
+/// Copy of an image stream with the samples under `unit_rects` zeroed. Rectangles are in the
+/// image's unit square, where (0, 0) is the bottom left corner of the last row.
+fn redact_image_stream(
+  doc: &lopdf::Document,
+  stream: &Stream,
+  unit_rects: &[Rect],
+) -> Result<Stream> {
+  let dict = &stream.dict;
+  let width = dict.get(b"Width").and_then(Object::as_i64)? as usize;
+  let height = dict.get(b"Height").and_then(Object::as_i64)? as usize;
+
+  let pixel_bounds = |rect: &Rect| {
+    let x0 = (rect.x0.clamp(0.0, 1.0) * width as f32).floor() as usize;
+    let x1 = (rect.x1.clamp(0.0, 1.0) * width as f32).ceil() as usize;
+    let y0 = ((1.0 - rect.y1.clamp(0.0, 1.0)) * height as f32).floor() as usize;
+    let y1 = ((1.0 - rect.y0.clamp(0.0, 1.0)) * height as f32).ceil() as usize;
+    (x0, x1.min(width), y0, y1.min(height))
+  };
+
+  let filters: Vec<Vec<u8>> = stream
+    .filters()
+    .map(|filters| filters.into_iter().map(<[u8]>::to_vec).collect())
+    .unwrap_or_default();
+
+  if filters.iter().any(|f| f == b"DCTDecode") {
+    if filters.len() != 1 {
+      return Err(anyhow::anyhow!("Unsupported filter chain for a JPEG image"));
+    }
+
+    let decoded = image::load_from_memory_with_format(&stream.content, ImageFormat::Jpeg)
+      .map_err(|e| anyhow::anyhow!("Failed to decode image: {}", e))?;
+    // Soft masks have to stay grayscale
+    let gray = decoded.color().channel_count() == 1;
+    let mut pixels = decoded.to_rgb8();
+    for rect in unit_rects {
+      let (x0, x1, y0, y1) = pixel_bounds(rect);
+      for y in y0..y1 {
+        for x in x0..x1 {
+          pixels.put_pixel(x as u32, y as u32, image::Rgb([0, 0, 0]));
+        }
+      }
+    }
+
+    let mut redacted = dict.clone();
+    for key in ["Filter", "DecodeParms", "Decode"] {
+      redacted.remove(key.as_bytes());
+    }
+    let (color_space, jpeg) = if gray {
+      let mut jpeg = Vec::new();
+      image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 90)
+        .encode_image(&DynamicImage::ImageRgb8(pixels).to_luma8())
+        .map_err(|e| anyhow::anyhow!("Failed to encode JPEG: {}", e))?;
+      ("DeviceGray", jpeg)
+    } else {
+      (
+        "DeviceRGB",
+        encode_jpeg(&DynamicImage::ImageRgb8(pixels), 90)?,
+      )
+    };
+    redacted.set("Filter", "DCTDecode");
+    redacted.set("ColorSpace", color_space);
+    redacted.set("BitsPerComponent", 8);
+    return Ok(Stream::new(redacted, jpeg).with_compression(false));
+  }
+
+  if let Some(filter) = filters.iter().find(|f| {
+    !matches!(
+      f.as_slice(),
+      b"FlateDecode" | b"LZWDecode" | b"ASCII85Decode"
+    )
+  }) {
+    return Err(anyhow::anyhow!(
+      "images compressed with {} cannot be redacted",
+      String::from_utf8_lossy(filter)
+    ));
+  }
+
+  let components = if dict
+    .get(b"ImageMask")
+    .and_then(Object::as_bool)
+    .unwrap_or(false)
+  {
+    1
+  } else {
+    let color_space = dict
+      .get(b"ColorSpace")
+      .map_err(|_| anyhow::anyhow!("Image has no color space"))?;
+    color_space_components(doc, color_space)?
+  };
+  let bits = dict
+    .get(b"BitsPerComponent")
+    .and_then(Object::as_i64)
+    .unwrap_or(8) as usize;
+  let bits_per_pixel = components * bits;
+  let stride = (width * bits_per_pixel).div_ceil(8);
+
+  let mut data = if filters.is_empty() {
+    stream.content.clone()
+  } else {
+    stream.decompressed_content()?
+  };
+  if data.len() < stride * height {
+    return Err(anyhow::anyhow!("Image data is shorter than its dimensions"));
+  }
+
+  // The opaque box drawn over the region hides whatever the zeroed samples look like
+  for rect in unit_rects {
+    let (x0, x1, y0, y1) = pixel_bounds(rect);
+    for y in y0..y1 {
+      let row = &mut data[y * stride..(y + 1) * stride];
+      for bit in x0 * bits_per_pixel..x1 * bits_per_pixel {
+        row[bit / 8] &= !(0x80 >> (bit % 8));
+      }
+    }
+  }
+
+  let mut redacted = dict.clone();
+  redacted.remove(b"Filter");
+  redacted.remove(b"DecodeParms");
+  let mut redacted = Stream::new(redacted, data);
+  redacted.compress()?;
+  Ok(redacted)
+}
+
+fn color_space_components(doc: &lopdf::Document, color_space: &Object) -> Result<usize> {
+  let (_, color_space) = doc.dereference(color_space)?;
+
+  let name = match color_space {
+    Object::Name(name) => name.as_slice(),
+    Object::Array(items) => items
+      .first()
+      .and_then(|item| item.as_name().ok())
+      .unwrap_or_default(),
+    _ => b"",
+  };
+
+  match name {
+    b"DeviceGray" | b"CalGray" | b"Indexed" | b"Separation" => Ok(1),
+    b"DeviceRGB" | b"CalRGB" | b"Lab" => Ok(3),
+    b"DeviceCMYK" => Ok(4),
+    b"ICCBased" => {
+      let profile = color_space
+        .as_array()?
+        .get(1)
+        .ok_or_else(|| anyhow::anyhow!("ICC color space without a profile"))?;
+      let (_, profile) = doc.dereference(profile)?;
+      Ok(
+        profile
+          .as_stream()?
+          .dict
+          .get(b"N")
+          .and_then(Object::as_i64)? as usize,
+      )
+    }
+    b"DeviceN" => Ok(
+      color_space
+        .as_array()?
+        .get(1)
+        .and_then(|names| doc.dereference(names).ok()?.1.as_array().ok())
+        .map(Vec::len)
+        .unwrap_or(1),
+    ),
+    other => Err(anyhow::anyhow!(
+      "Unsupported image color space {}",
+      String::from_utf8_lossy(other)
+    )),
+  }
+}
+
+/// Expand an inline image's abbreviated keys and names into a regular image XObject
+fn inline_image_to_xobject(stream: Stream, color_spaces: &Dictionary) -> Stream {
+  let abbreviation = |name: &[u8]| -> Vec<u8> {
+    match name {
+      b"G" => b"DeviceGray".to_vec(),
+      b"RGB" => b"DeviceRGB".to_vec(),
+      b"CMYK" => b"DeviceCMYK".to_vec(),
+      b"I" => b"Indexed".to_vec(),
+      b"AHx" => b"ASCIIHexDecode".to_vec(),
+      b"A85" => b"ASCII85Decode".to_vec(),
+      b"LZW" => b"LZWDecode".to_vec(),
+      b"Fl" => b"FlateDecode".to_vec(),
+      b"RL" => b"RunLengthDecode".to_vec(),
+      b"CCF" => b"CCITTFaxDecode".to_vec(),
+      b"DCT" => b"DCTDecode".to_vec(),
+      other => other.to_vec(),
+    }
+  };
+  let expand = |value: &Object| match value {
+    Object::Name(name) => Object::Name(abbreviation(name)),
+    Object::Array(items) => Object::Array(
+      items
+        .iter()
+        .map(|item| match item {
+          Object::Name(name) => Object::Name(abbreviation(name)),
+          other => other.clone(),
+        })
+        .collect(),
+    ),
+    other => other.clone(),
+  };
+
+  let mut dict = dictionary! {
+    "Type" => "XObject",
+    "Subtype" => "Image",
+  };
+  for (key, value) in stream.dict.iter() {
+    let key: &[u8] = match key.as_slice() {
+      b"W" => b"Width",
+      b"H" => b"Height",
+      b"BPC" => b"BitsPerComponent",
+      b"CS" => b"ColorSpace",
+      b"F" => b"Filter",
+      b"DP" => b"DecodeParms",
+      b"IM" => b"ImageMask",
+      b"D" => b"Decode",
+      b"I" => b"Interpolate",
+      b"L" | b"Length" => continue,
+      other => other,
+    };
+
+    let value = match (key, value) {
+      // Named color spaces refer to the page's color space resources
+      (b"ColorSpace", Object::Name(name))
+        if !matches!(
+          name.as_slice(),
+          b"G" | b"RGB" | b"CMYK" | b"DeviceGray" | b"DeviceRGB" | b"DeviceCMYK"
+        ) =>
+      {
+        color_spaces
+          .get(name)
+          .cloned()
+          .unwrap_or_else(|_| expand(value))
+      }
+      (b"ColorSpace", _) | (b"Filter", _) => expand(value),
+      _ => value.clone(),
+    };
+    dict.set(key.to_vec(), value);
+  }
+
+  Stream::new(dict, stream.content).with_compression(false)
+}
+
+/// The page's annotations that don't overlap a region, or `None` if the page has none
+fn remaining_annotations(
+  doc: &lopdf::Document,
+  page_id: ObjectId,
+  rects: &[Rect],
+) -> Option<Vec<Object>> {
+  let page = doc.get_dictionary(page_id).ok()?;
+  let (_, annotations) = doc.dereference(page.get(b"Annots").ok()?).ok()?;
+
+  Some(
+    annotations
+      .as_array()
+      .ok()?
+      .iter()
+      .filter(|annotation| {
+        let bounds: Vec<f32> = doc
+          .dereference(annotation)
+          .ok()
+          .and_then(|(_, a)| a.as_dict().ok())
+          .and_then(|a| a.get(b"Rect").ok())
+          .and_then(|r| r.as_array().ok())
+          .map(|values| values.iter().filter_map(|v| v.as_float().ok()).collect())
+          .unwrap_or_default();
+        match bounds[..] {
+          [x0, y0, x1, y1] => {
+            let bounds = Rect::bounding([(x0, y0), (x1, y1)]);
+            !rects.iter().any(|r| r.intersects(&bounds))
+          }
+          _ => true,
+        }
+      })
+      .cloned()
+      .collect(),
+  )
+}
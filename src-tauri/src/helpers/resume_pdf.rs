@@ -0,0 +1,643 @@
+use super::pdf_docs::{push_filled_rect, PageSize};
+use anyhow::{anyhow, Result};
+use printpdf::{
+  deserialize::PdfWarnMsg,
+  ops::{Op, PdfPage},
+  serialize::PdfSaveOptions,
+  units::Mm,
+  Color, FontId, Line, LinePoint, ParsedFont, PdfDocument, Point, Pt, Rgb, TextItem,
+};
+use serde::Deserialize;
+
+// DejaVu fonts are embedded so the resume looks the same in every viewer and covers accented
+// names, which the builtin PDF fonts cannot
+const SANS: &[u8] = include_bytes!("../../fonts/DejaVuSans.ttf");
+const SANS_BOLD: &[u8] = include_bytes!("../../fonts/DejaVuSans-Bold.ttf");
+const SERIF: &[u8] = include_bytes!("../../fonts/DejaVuSerif.ttf");
+const SERIF_BOLD: &[u8] = include_bytes!("../../fonts/DejaVuSerif-Bold.ttf");
+
+const PT_TO_MM: f32 = 0.352778;
+const FOOTER_SIZE: f32 = 8.0;
+const MUTED: (f32, f32, f32) = (0.4, 0.4, 0.4);
+const TEXT: (f32, f32, f32) = (0.1, 0.1, 0.1);
+
+/// Look of a rental resume
+#[derive(Deserialize, Clone, Copy, Default, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResumeTheme {
+  /// Sans-serif with a colored banner
+  #[default]
+  Modern,
+  /// Serif with a centered header and ruled section headings
+  Classic,
+  /// Small type and tight spacing, to keep long histories on one page
+  Compact,
+}
+
+struct ThemeStyle {
+  serif: bool,
+  accent: (f32, f32, f32),
+  banner: bool,
+  centered: bool,
+  margin: f32,
+  name_size: f32,
+  heading_size: f32,
+  body_size: f32,
+  /// Extra space between lines, as a fraction of the font size
+  leading: f32,
+  section_gap: f32,
+  label_width: f32,
+}
+
+impl ResumeTheme {
+  fn style(self) -> ThemeStyle {
+    match self {
+      ResumeTheme::Modern => ThemeStyle {
+        serif: false,
+        accent: (0.12, 0.36, 0.52),
+        banner: true,
+        centered: false,
+        margin: 18.0,
+        name_size: 22.0,
+        heading_size: 12.0,
+        body_size: 10.0,
+        leading: 0.45,
+        section_gap: 7.0,
+        label_width: 40.0,
+      },
+      ResumeTheme::Classic => ThemeStyle {
+        serif: true,
+        accent: (0.15, 0.15, 0.15),
+        banner: false,
+        centered: true,
+        margin: 22.0,
+        name_size: 22.0,
+        heading_size: 12.0,
+        body_size: 10.5,
+        leading: 0.45,
+        section_gap: 7.0,
+        label_width: 42.0,
+      },
+      ResumeTheme::Compact => ThemeStyle {
+        serif: false,
+        accent: (0.3, 0.3, 0.3),
+        banner: false,
+        centered: false,
+        margin: 14.0,
+        name_size: 16.0,
+        heading_size: 10.0,
+        body_size: 8.5,
+        leading: 0.3,
+        section_gap: 4.5,
+        label_width: 34.0,
+      },
+    }
+  }
+}
+
+/// Everything on a rental resume, already formatted for display
+pub struct RentalResume {
+  pub name: String,
+  /// Phone, email, address and the like, shown under the name
+  pub contact: Vec<String>,
+  /// The listing the resume is for
+  pub applying_for: Option<String>,
+  pub sections: Vec<ResumeSection>,
+  /// Shown in the footer
+  pub generated_on: String,
+}
+
+pub struct ResumeSection {
+  pub title: String,
+  pub entries: Vec<ResumeEntry>,
+}
+
+/// An employer, a previous home, a reference or just a list of fields
+#[derive(Default)]
+pub struct ResumeEntry {
+  pub heading: Option<String>,
+  /// Shown right-aligned next to the heading, such as dates
+  pub detail: Option<String>,
+  pub fields: Vec<(String, String)>,
+}
+
+/// How a rental resume is rendered
+#[derive(Deserialize, Clone, Copy, Default, Debug)]
+#[serde(default)]
+pub struct ResumeOptions {
+  pub theme: ResumeTheme,
+  pub page_size: PageSize,
+}
+
+/// Lay out a rental resume as a PDF with embedded fonts. Sections flow onto as many pages as
+/// they need, but keep to one or two for a typical applicant.
+pub fn render_resume(resume: &RentalResume, options: &ResumeOptions) -> Result<Vec<u8>> {
+  let style = options.theme.style();
+  let margin = style.margin;
+  let (width, height) = options.page_size.portrait_mm();
+
+  let mut doc = PdfDocument::new(&format!("Rental resume - {}", resume.name));
+  let (regular, bold) = if style.serif {
+    (SERIF, SERIF_BOLD)
+  } else {
+    (SANS, SANS_BOLD)
+  };
+  let regular = ResumeFont::load(&mut doc, regular)?;
+  let bold = ResumeFont::load(&mut doc, bold)?;
+
+  let mut layout = Layout {
+    style,
+    regular,
+    bold,
+    width,
+    height,
+    pages: Vec::new(),
+    ops: Vec::new(),
+    y: height - margin,
+  };
+  layout.header(resume);
+  for section in &resume.sections {
+    layout.section(section);
+  }
+  let pages = layout.finish(&resume.name, &resume.generated_on);
+
+  for ops in pages {
+    doc.pages.push(PdfPage::new(Mm(width), Mm(height), ops));
+  }
+  let mut warnings = Vec::<PdfWarnMsg>::new();
+  Ok(doc.save(&PdfSaveOptions::default(), &mut warnings))
+}
+
+struct ResumeFont {
+  id: FontId,
+  parsed: ParsedFont,
+}
+
+impl ResumeFont {
+  fn load(doc: &mut PdfDocument, bytes: &[u8]) -> Result<ResumeFont> {
+    let mut warnings = Vec::new();
+    let parsed = ParsedFont::from_bytes(bytes, 0, &mut warnings)
+      .ok_or_else(|| anyhow!("Failed to load the resume font"))?;
+    Ok(ResumeFont {
+      id: doc.add_font(&parsed),
+      parsed,
+    })
+  }
+
+  /// Text with characters the font has no glyph for replaced
+  fn drawable(&self, text: &str) -> String {
+    text
+      .chars()
+      .map(|c| match c {
+        '\t' | '\r' | '\n' => ' ',
+        c if self.parsed.lookup_glyph_index(c as u32).is_some() => c,
+        _ => '?',
+      })
+      .collect()
+  }
+
+  fn width_mm(&self, text: &str, size: f32) -> f32 {
+    // Glyphs without an outline, such as the space, report no advance of their own
+    let space = self.parsed.get_space_width().unwrap_or_default() as u32;
+    let units: u32 = text
+      .chars()
+      .filter_map(|c| self.parsed.lookup_glyph_index(c as u32))
+      .map(
+        |glyph| match self.parsed.get_horizontal_advance(glyph) as u32 {
+          0 => space,
+          advance => advance,
+        },
+      )
+      .sum();
+    units as f32 / self.parsed.font_metrics.units_per_em as f32 * size * PT_TO_MM
+  }
+
+  /// Break text into lines no wider than `width` mm, keeping the line breaks it has
+  fn wrap(&self, text: &str, size: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+      let mut line = String::new();
+      for word in paragraph.split_whitespace() {
+        let candidate = if line.is_empty() {
+          word.to_string()
+        } else {
+          format!("{} {}", line, word)
+        };
+        if self.width_mm(&candidate, size) <= width {
+          line = candidate;
+          continue;
+        }
+        if !line.is_empty() {
+          lines.push(std::mem::take(&mut line));
+        }
+        // Words longer than the line are split wherever they overflow
+        for c in word.chars() {
+          if !line.is_empty() && self.width_mm(&format!("{}{}", line, c), size) > width {
+            lines.push(std::mem::take(&mut line));
+          }
+          line.push(c);
+        }
+      }
+      if !line.is_empty() {
+        lines.push(line);
+      }
+    }
+    if lines.is_empty() {
+      lines.push(String::new());
+    }
+    lines
+  }
+}
+
+#[derive(Clone, Copy)]
+enum Align {
+  Left,
+  Center,
+  Right,
+}
+
+/// Places text top to bottom, starting a new page when the current one is full
+struct Layout {
+  style: ThemeStyle,
+  regular: ResumeFont,
+  bold: ResumeFont,
+  width: f32,
+  height: f32,
+  pages: Vec<Vec<Op>>,
+  ops: Vec<Op>,
+  /// Top of the next line, in mm from the bottom of the page
+  y: f32,
+}
+
+impl Layout {
+  fn line_height(&self, size: f32) -> f32 {
+    size * PT_TO_MM * (1.0 + self.style.leading)
+  }
+
+  fn content_width(&self) -> f32 {
+    self.width - 2.0 * self.style.margin
+  }
+
+  /// Lowest a line may reach, leaving room for the footer
+  fn bottom(&self) -> f32 {
+    self.style.margin + FOOTER_SIZE * PT_TO_MM * 2.0
+  }
+
+  /// Start a new page unless `needed` mm still fit on this one
+  fn reserve(&mut self, needed: f32) {
+    if self.y - needed >= self.bottom() {
+      return;
+    }
+    self.pages.push(std::mem::take(&mut self.ops));
+    self.y = self.height - self.style.margin;
+  }
+
+  fn text(
+    &mut self,
+    text: &str,
+    bold: bool,
+    size: f32,
+    x: f32,
+    align: Align,
+    color: (f32, f32, f32),
+  ) {
+    let font = if bold { &self.bold } else { &self.regular };
+    let text = font.drawable(text);
+    let x = match align {
+      Align::Left => x,
+      Align::Center => x - font.width_mm(&text, size) / 2.0,
+      Align::Right => x - font.width_mm(&text, size),
+    };
+    // Baseline sits below the top of the line by roughly the ascent
+    let baseline = self.y - size * PT_TO_MM * 0.8;
+    let font = font.id.clone();
+    let (r, g, b) = color;
+
+    self.ops.extend([
+      Op::StartTextSection,
+      Op::SetFillColor {
+        col: Color::Rgb(Rgb::new(r, g, b, None)),
+      },
+      Op::SetFontSize {
+        size: Pt(size),
+        font: font.clone(),
+      },
+      Op::SetTextCursor {
+        pos: Point::new(Mm(x), Mm(baseline)),
+      },
+      Op::WriteText {
+        items: vec![TextItem::Text(text)],
+        font,
+      },
+      Op::EndTextSection,
+    ]);
+  }
+
+  fn rule(&mut self, y: f32, thickness: f32, color: (f32, f32, f32)) {
+    let (r, g, b) = color;
+    let point = |x: f32| LinePoint {
+      p: Point::new(Mm(x), Mm(y)),
+      bezier: false,
+    };
+    let line = Line {
+      points: vec![
+        point(self.style.margin),
+        point(self.width - self.style.margin),
+      ],
+      is_closed: false,
+    };
+
+    self.ops.extend([
+      Op::SaveGraphicsState,
+      Op::SetOutlineColor {
+        col: Color::Rgb(Rgb::new(r, g, b, None)),
+      },
+      Op::SetOutlineThickness { pt: Pt(thickness) },
+      Op::DrawLine { line },
+      Op::RestoreGraphicsState,
+    ]);
+  }
+
+  fn header(&mut self, resume: &RentalResume) {
+    let style = &self.style;
+    let (name_size, body_size, margin, accent) =
+      (style.name_size, style.body_size, style.margin, style.accent);
+    let contact = resume.contact.join("  |  ");
+    let contact_lines = self.regular.wrap(&contact, body_size, self.content_width());
+
+    if self.style.banner {
+      let banner_height = margin
+        + self.line_height(name_size)
+        + self.line_height(body_size) * contact_lines.len() as f32
+        + 6.0;
+      push_filled_rect(
+        &mut self.ops,
+        0.0,
+        self.height - banner_height,
+        self.width,
+        banner_height,
+        accent,
+      );
+      self.text(
+        &resume.name,
+        true,
+        name_size,
+        margin,
+        Align::Left,
+        (1.0, 1.0, 1.0),
+      );
+      self.y -= self.line_height(name_size);
+      for line in &contact_lines {
+        self.text(
+          line,
+          false,
+          body_size,
+          margin,
+          Align::Left,
+          (0.9, 0.93, 0.96),
+        );
+        self.y -= self.line_height(body_size);
+      }
+      self.y = self.height - banner_height - 6.0;
+    } else {
+      let (x, align) = if self.style.centered {
+        (self.width / 2.0, Align::Center)
+      } else {
+        (margin, Align::Left)
+      };
+      self.text(&resume.name, true, name_size, x, align, TEXT);
+      self.y -= self.line_height(name_size);
+      for line in &contact_lines {
+        self.text(line, false, body_size, x, align, MUTED);
+        self.y -= self.line_height(body_size);
+      }
+      self.y -= 2.0;
+      self.rule(self.y, 1.0, accent);
+      self.y -= 5.0;
+    }
+
+    if let Some(applying_for) = &resume.applying_for {
+      let text = format!("Applying for: {}", applying_for);
+      for line in self.bold.wrap(&text, body_size, self.content_width()) {
+        self.text(&line, true, body_size, margin, Align::Left, accent);
+        self.y -= self.line_height(body_size);
+      }
+      self.y -= 2.0;
+    }
+  }
+
+  fn section(&mut self, section: &ResumeSection) {
+    if section.entries.is_empty() {
+      return;
+    }
+    let (heading_size, body_size, margin, accent) = (
+      self.style.heading_size,
+      self.style.body_size,
+      self.style.margin,
+      self.style.accent,
+    );
+
+    // Keep the heading together with at least the first lines of the section
+    self.reserve(self.line_height(heading_size) + 3.0 * self.line_height(body_size));
+    let title = if self.style.banner {
+      section.title.clone()
+    } else {
+      section.title.to_uppercase()
+    };
+    self.text(&title, true, heading_size, margin, Align::Left, accent);
+    self.y -= self.line_height(heading_size);
+    self.rule(self.y + 0.8, 0.5, accent);
+    self.y -= 2.0;
+
+    for (i, entry) in section.entries.iter().enumerate() {
+      if i > 0 {
+        self.y -= self.line_height(body_size) * 0.4;
+      }
+      self.entry(entry);
+    }
+    self.y -= self.style.section_gap;
+  }
+
+  fn entry(&mut self, entry: &ResumeEntry) {
+    let (body_size, margin, label_width) = (
+      self.style.body_size,
+      self.style.margin,
+      self.style.label_width,
+    );
+    let line_height = self.line_height(body_size);
+    let right = self.width - margin;
+
+    let detail_width = entry
+      .detail
+      .as_deref()
+      .map(|detail| self.regular.width_mm(detail, body_size) + 4.0)
+      .unwrap_or(0.0);
+    let heading = entry
+      .heading
+      .as_deref()
+      .map(|heading| {
+        self
+          .bold
+          .wrap(heading, body_size, self.content_width() - detail_width)
+      })
+      .unwrap_or_default();
+
+    let indent = if entry.heading.is_some() { 4.0 } else { 0.0 };
+    let value_x = margin + indent + label_width;
+    let fields: Vec<(Vec<String>, Vec<String>)> = entry
+      .fields
+      .iter()
+      .map(|(label, value)| {
+        (
+          self.bold.wrap(label, body_size, label_width - 2.0),
+          self.regular.wrap(value, body_size, right - value_x),
+        )
+      })
+      .collect();
+
+    // Move the whole entry to the next page rather than splitting it, unless it is longer
+    // than a page
+    let rows: usize = fields
+      .iter()
+      .map(|(labels, values)| labels.len().max(values.len()))
+      .sum();
+    let entry_height = line_height * (heading.len() + rows) as f32;
+    let page_height = self.height - self.style.margin - self.bottom();
+    if entry_height <= page_height {
+      self.reserve(entry_height);
+    }
+
+    for (i, line) in heading.iter().enumerate() {
+      self.reserve(line_height * 2.0);
+      if let (0, Some(detail)) = (i, &entry.detail) {
+        self.text(detail, false, body_size, right, Align::Right, MUTED);
+      }
+      self.text(line, true, body_size, margin, Align::Left, TEXT);
+      self.y -= line_height;
+    }
+    for (labels, values) in &fields {
+      for row in 0..labels.len().max(values.len()) {
+        self.reserve(line_height);
+        if let Some(label) = labels.get(row) {
+          self.text(label, true, body_size, margin + indent, Align::Left, MUTED);
+        }
+        if let Some(value) = values.get(row) {
+          self.text(value, false, body_size, value_x, Align::Left, TEXT);
+        }
+        self.y -= line_height;
+      }
+    }
+  }
+
+  /// Close the last page and number them all
+  fn finish(mut self, name: &str, generated_on: &str) -> Vec<Vec<Op>> {
+    self.pages.push(std::mem::take(&mut self.ops));
+    let count = self.pages.len();
+    let margin = self.style.margin;
+
+    let mut pages = std::mem::take(&mut self.pages);
+    for (i, page) in pages.iter_mut().enumerate() {
+      self.y = margin + FOOTER_SIZE * PT_TO_MM;
+      self.text(
+        &format!("{} - generated {}", name, generated_on),
+        false,
+        FOOTER_SIZE,
+        margin,
+        Align::Left,
+        MUTED,
+      );
+      self.text(
+        &format!("Page {} of {}", i + 1, count),
+        false,
+        FOOTER_SIZE,
+        self.width - margin,
+        Align::Right,
+        MUTED,
+      );
+      page.append(&mut self.ops);
+    }
+    pages
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn empty_applicant() -> RentalResume {
+    RentalResume {
+      name: String::new(),
+      contact: Vec::new(),
+      applying_for: None,
+      sections: Vec::new(),
+      generated_on: String::new(),
+    }
+  }
+
+  /// Enough history to run onto several pages, with accents and long unbroken values
+  fn long_applicant() -> RentalResume {
+    let field = |label: &str, value: &str| (label.to_string(), value.to_string());
+    let homes = (0..30)
+      .map(|i| ResumeEntry {
+        heading: Some(format!(
+          "{} Elm Street, Apt {}, Springfield, IL 62704",
+          100 + i,
+          i
+        )),
+        detail: Some("2019-06 – 2023-05".to_string()),
+        fields: vec![
+          field("Landlord", "Zoë Müller-Łukasiewicz, landlord@example.com"),
+          field("Rent", "$1,450.00 / month"),
+          field(
+            "Reason for leaving",
+            &"The owner sold the building. ".repeat(12),
+          ),
+          field("Notes", &"x".repeat(300)),
+        ],
+      })
+      .collect();
+    RentalResume {
+      name: "Zoë Martínez-O'Brien".to_string(),
+      contact: vec![
+        "+1 (555) 123-4567".to_string(),
+        "zoe@example.com".to_string(),
+      ],
+      applying_for: Some("88 Lakeview Ave, Unit 3B, Chicago, IL 60614".to_string()),
+      sections: vec![
+        ResumeSection {
+          title: "Rental history".to_string(),
+          entries: homes,
+        },
+        ResumeSection {
+          title: "Additional information".to_string(),
+          entries: vec![ResumeEntry {
+            fields: vec![field("Pets", "One cat"), field("Smoker", "No")],
+            ..Default::default()
+          }],
+        },
+      ],
+      generated_on: "2026-10-19".to_string(),
+    }
+  }
+
+  #[test]
+  fn every_theme_renders_a_loadable_pdf() {
+    for theme in [
+      ResumeTheme::Modern,
+      ResumeTheme::Classic,
+      ResumeTheme::Compact,
+    ] {
+      let options = ResumeOptions {
+        theme,
+        page_size: PageSize::Letter,
+      };
+      let empty = render_resume(&empty_applicant(), &options).unwrap();
+      let empty = lopdf::Document::load_mem(&empty).unwrap();
+      assert_eq!(empty.get_pages().len(), 1, "{:?}", theme);
+
+      let long = render_resume(&long_applicant(), &options).unwrap();
+      let long = lopdf::Document::load_mem(&long).unwrap();
+      assert!(long.get_pages().len() > 1, "{:?}", theme);
+    }
+  }
+}
@@ -0,0 +1,504 @@
+use reqwest::multipart::{Form, Part};
+use reqwest::StatusCode;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+/// Base URL of the SASE API when `SASE_API_URL` is not set
+pub const DEFAULT_SASE_API_URL: &str = "https://drakoindustries.com/api/sase";
+
+/// Size of the chunks PDFs are streamed to the SASE API in, and so of upload progress steps
+const UPLOAD_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Client shared by every request so connections to the API are reused
+static SASE_API: LazyLock<Result<SaseApiClient, String>> =
+  LazyLock::new(|| SaseApiClient::new(SaseApiConfig::from_env()).map_err(|e| e.to_string()));
+
+/// Called with the bytes sent so far and the total size of an upload
+pub type UploadProgressFn = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// Where the SASE API lives and how patiently to talk to it
+#[derive(Clone, Debug)]
+pub struct SaseApiConfig {
+  pub base_url: String,
+  pub connect_timeout: Duration,
+  /// Limit for a whole request, including the upload and reading the response
+  pub request_timeout: Duration,
+  /// Retries after the first attempt for server and network errors
+  pub max_retries: u32,
+  /// Wait before the first retry, doubled for every further one
+  pub initial_backoff: Duration,
+}
+
+impl Default for SaseApiConfig {
+  fn default() -> Self {
+    SaseApiConfig {
+      base_url: DEFAULT_SASE_API_URL.to_string(),
+      connect_timeout: Duration::from_secs(15),
+      request_timeout: Duration::from_secs(300),
+      max_retries: 3,
+      initial_backoff: Duration::from_millis(500),
+    }
+  }
+}
+
+impl SaseApiConfig {
+  /// The default configuration, with the base URL taken from `SASE_API_URL` and the request
+  /// timeout from `SASE_API_TIMEOUT_SECS` when they are set
+  pub fn from_env() -> Self {
+    let mut config = SaseApiConfig::default();
+    if let Ok(base_url) = std::env::var("SASE_API_URL") {
+      if !base_url.trim().is_empty() {
+        config.base_url = base_url.trim().to_string();
+      }
+    }
+    if let Some(secs) = std::env::var("SASE_API_TIMEOUT_SECS")
+      .ok()
+      .and_then(|secs| secs.trim().parse::<u64>().ok())
+      .filter(|secs| *secs > 0)
+    {
+      config.request_timeout = Duration::from_secs(secs);
+    }
+    config
+  }
+}
+
+/// Why a SASE API request failed
+#[derive(Debug)]
+pub enum SaseApiError {
+  /// 401: the JWT token is missing, invalid or expired
+  Unauthorized,
+  /// 403: the account may not use this endpoint
+  Forbidden,
+  /// 413: the upload is over the API's size limit
+  PayloadTooLarge,
+  /// Any other 4xx, which retrying will not fix
+  Rejected { status: u16, message: String },
+  /// 5xx, still failing after every retry
+  Server { status: u16, message: String },
+  /// No response within the configured timeout
+  Timeout,
+  /// The request could not be sent or the response could not be read
+  Network(reqwest::Error),
+  /// The client or the request could not be built
+  InvalidRequest(String),
+}
+
+impl SaseApiError {
+  /// Server and network errors may go away on their own; everything else needs a change first
+  pub fn is_retryable(&self) -> bool {
+    matches!(
+      self,
+      SaseApiError::Server { .. } | SaseApiError::Timeout | SaseApiError::Network(_)
+    )
+  }
+
+  fn from_status(status: StatusCode, message: String) -> Self {
+    match status {
+      StatusCode::UNAUTHORIZED => SaseApiError::Unauthorized,
+      StatusCode::FORBIDDEN => SaseApiError::Forbidden,
+      StatusCode::PAYLOAD_TOO_LARGE => SaseApiError::PayloadTooLarge,
+      status if status.is_server_error() => SaseApiError::Server {
+        status: status.as_u16(),
+        message,
+      },
+      status => SaseApiError::Rejected {
+        status: status.as_u16(),
+        message,
+      },
+    }
+  }
+
+  fn from_reqwest(error: reqwest::Error) -> Self {
+    if error.is_timeout() {
+      SaseApiError::Timeout
+    } else if error.is_builder() {
+      SaseApiError::InvalidRequest(error.to_string())
+    } else {
+      SaseApiError::Network(error)
+    }
+  }
+}
+
+impl fmt::Display for SaseApiError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      SaseApiError::Unauthorized => {
+        write!(f, "Authentication failed: Invalid or expired JWT token")
+      }
+      SaseApiError::Forbidden => write!(f, "Access denied: Insufficient permissions"),
+      SaseApiError::PayloadTooLarge => write!(f, "Request too large: PDF files exceed size limit"),
+      SaseApiError::Rejected { status, message } => {
+        write!(f, "SASE API rejected the request ({}): {}", status, message)
+      }
+      SaseApiError::Server { status, message } => {
+        write!(f, "SASE API returned error {}: {}", status, message)
+      }
+      SaseApiError::Timeout => write!(f, "SASE API did not respond in time"),
+      SaseApiError::Network(e) => write!(f, "Could not reach the SASE API: {}", e),
+      SaseApiError::InvalidRequest(message) => write!(f, "Invalid SASE API request: {}", message),
+    }
+  }
+}
+
+impl std::error::Error for SaseApiError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      SaseApiError::Network(e) => Some(e),
+      _ => None,
+    }
+  }
+}
+
+/// PDFs for the merge endpoint: the base document followed by the others, each with a header
+pub struct MergePdfsRequest {
+  pub base_pdf: Vec<u8>,
+  pub additional_pdfs: Vec<Vec<u8>>,
+  pub headers: Vec<String>,
+}
+
+pub struct SaseApiClient {
+  config: SaseApiConfig,
+  http: reqwest::Client,
+}
+
+impl SaseApiClient {
+  pub fn new(config: SaseApiConfig) -> Result<Self, SaseApiError> {
+    let http = reqwest::Client::builder()
+      .connect_timeout(config.connect_timeout)
+      .timeout(config.request_timeout)
+      .pool_idle_timeout(Duration::from_secs(90))
+      .build()
+      .map_err(|e| SaseApiError::InvalidRequest(format!("Failed to create HTTP client: {}", e)))?;
+    Ok(SaseApiClient { config, http })
+  }
+
+  /// The client configured from the environment, created on first use
+  pub fn shared() -> Result<&'static SaseApiClient, SaseApiError> {
+    SASE_API
+      .as_ref()
+      .map_err(|e| SaseApiError::InvalidRequest(e.clone()))
+  }
+
+  fn endpoint(&self, path: &str) -> String {
+    format!(
+      "{}/{}",
+      self.config.base_url.trim_end_matches('/'),
+      path.trim_start_matches('/')
+    )
+  }
+
+  /// Merge PDFs into one, in order. Uploads are streamed so `progress` follows them; a retried
+  /// upload starts counting from zero again.
+  pub async fn merge_pdfs(
+    &self,
+    request: MergePdfsRequest,
+    jwt_token: &str,
+    progress: Option<UploadProgressFn>,
+  ) -> Result<Vec<u8>, SaseApiError> {
+    let headers_json = if request.headers.is_empty() {
+      None
+    } else {
+      Some(serde_json::to_string(&request.headers).map_err(|e| {
+        SaseApiError::InvalidRequest(format!("Failed to serialize headers to JSON: {}", e))
+      })?)
+    };
+    let base_pdf = Arc::new(request.base_pdf);
+    let additional_pdfs: Vec<Arc<Vec<u8>>> =
+      request.additional_pdfs.into_iter().map(Arc::new).collect();
+    let total =
+      (base_pdf.len() + additional_pdfs.iter().map(|pdf| pdf.len()).sum::<usize>()) as u64;
+
+    self
+      .post_multipart("pdf/merge", jwt_token, || {
+        let upload = Upload {
+          sent: Arc::new(AtomicU64::new(0)),
+          total,
+          progress: progress.clone(),
+        };
+
+        let mut form = Form::new().part(
+          "basePdf",
+          upload.pdf_part(base_pdf.clone(), "base-document.pdf".to_string())?,
+        );
+        for (i, pdf) in additional_pdfs.iter().enumerate() {
+          let part = upload.pdf_part(pdf.clone(), format!("additional-{}.pdf", i + 1))?;
+          form = form.part("additionalPdfs", part);
+        }
+        if let Some(headers_json) = &headers_json {
+          form = form.text("headers", headers_json.clone());
+        }
+        Ok(form)
+      })
+      .await
+  }
+
+  /// POST a multipart form, retrying server and network errors with exponential backoff.
+  /// Streamed bodies can only be sent once, so `build_form` is called for every attempt.
+  pub async fn post_multipart(
+    &self,
+    path: &str,
+    jwt_token: &str,
+    build_form: impl Fn() -> Result<Form, SaseApiError>,
+  ) -> Result<Vec<u8>, SaseApiError> {
+    let url = self.endpoint(path);
+    let mut backoff = self.config.initial_backoff;
+    let mut attempt = 0;
+
+    loop {
+      attempt += 1;
+      match self.try_post(&url, jwt_token, build_form()?).await {
+        Ok(bytes) => return Ok(bytes),
+        Err(e) if e.is_retryable() && attempt <= self.config.max_retries => {
+          println!(
+            "SASE API request to {} failed (attempt {} of {}): {}. Retrying in {:?}",
+            url,
+            attempt,
+            self.config.max_retries + 1,
+            e,
+            backoff
+          );
+          tokio::time::sleep(backoff).await;
+          backoff *= 2;
+        }
+        Err(e) => return Err(e),
+      }
+    }
+  }
+
+  async fn try_post(
+    &self,
+    url: &str,
+    jwt_token: &str,
+    form: Form,
+  ) -> Result<Vec<u8>, SaseApiError> {
+    let response = self
+      .http
+      .post(url)
+      .bearer_auth(jwt_token)
+      .multipart(form)
+      .send()
+      .await
+      .map_err(SaseApiError::from_reqwest)?;
+
+    let status = response.status();
+    if !status.is_success() {
+      let message = response
+        .text()
+        .await
+        .unwrap_or_else(|_| "Unknown error".to_string());
+      return Err(SaseApiError::from_status(status, message));
+    }
+
+    let bytes = response.bytes().await.map_err(SaseApiError::from_reqwest)?;
+    Ok(bytes.to_vec())
+  }
+}
+
+/// Bytes sent so far across all parts of one upload attempt
+struct Upload {
+  sent: Arc<AtomicU64>,
+  total: u64,
+  progress: Option<UploadProgressFn>,
+}
+
+impl Upload {
+  /// A PDF part streamed in chunks, reporting progress as reqwest reads it
+  fn pdf_part(&self, bytes: Arc<Vec<u8>>, file_name: String) -> Result<Part, SaseApiError> {
+    let length = bytes.len() as u64;
+    let sent = self.sent.clone();
+    let total = self.total;
+    let progress = self.progress.clone();
+    let chunks = (0..bytes.len())
+      .step_by(UPLOAD_CHUNK_SIZE)
+      .map(move |start| {
+        let chunk = bytes[start..(start + UPLOAD_CHUNK_SIZE).min(bytes.len())].to_vec();
+        let sent = sent.fetch_add(chunk.len() as u64, Ordering::Relaxed) + chunk.len() as u64;
+        if let Some(progress) = &progress {
+          progress(sent, total);
+        }
+        Ok::<_, std::io::Error>(chunk)
+      });
+    let body = reqwest::Body::wrap_stream(futures_util::stream::iter(chunks));
+
+    Part::stream_with_length(body, length)
+      .file_name(file_name)
+      .mime_str("application/pdf")
+      .map_err(|e| SaseApiError::InvalidRequest(format!("Failed to create PDF part: {}", e)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use wiremock::matchers::{header, method, path};
+  use wiremock::{Mock, MockServer, ResponseTemplate};
+
+  fn client(base_url: &str) -> SaseApiClient {
+    SaseApiClient::new(SaseApiConfig {
+      base_url: base_url.to_string(),
+      connect_timeout: Duration::from_secs(2),
+      request_timeout: Duration::from_secs(2),
+      max_retries: 2,
+      initial_backoff: Duration::from_millis(10),
+    })
+    .unwrap()
+  }
+
+  fn request() -> MergePdfsRequest {
+    MergePdfsRequest {
+      base_pdf: b"%PDF-1.7 base".to_vec(),
+      additional_pdfs: vec![vec![7; UPLOAD_CHUNK_SIZE + 10], b"%PDF-1.7 two".to_vec()],
+      headers: vec!["Pay stub".to_string(), "ID".to_string()],
+    }
+  }
+
+  async fn respond_with(status: u16, expected_requests: u64) -> (MockServer, SaseApiError) {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+      .and(path("/pdf/merge"))
+      .respond_with(ResponseTemplate::new(status).set_body_string("nope"))
+      .expect(expected_requests)
+      .mount(&server)
+      .await;
+
+    let error = client(&server.uri())
+      .merge_pdfs(request(), "token", None)
+      .await
+      .unwrap_err();
+    (server, error)
+  }
+
+  #[tokio::test]
+  async fn returns_the_merged_pdf() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+      .and(path("/pdf/merge"))
+      .and(header("authorization", "Bearer token"))
+      .respond_with(ResponseTemplate::new(200).set_body_bytes(b"%PDF-1.7 merged".to_vec()))
+      .expect(1)
+      .mount(&server)
+      .await;
+
+    let reports = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let recorded = reports.clone();
+    let progress: UploadProgressFn = Arc::new(move |sent, total| {
+      recorded.lock().unwrap().push((sent, total));
+    });
+
+    let merged = client(&format!("{}/", server.uri()))
+      .merge_pdfs(request(), "token", Some(progress))
+      .await
+      .unwrap();
+    assert_eq!(merged, b"%PDF-1.7 merged");
+
+    let requests = server.received_requests().await.unwrap();
+    let body = String::from_utf8_lossy(&requests[0].body);
+    assert!(body.contains("name=\"basePdf\"; filename=\"base-document.pdf\""));
+    assert_eq!(body.matches("name=\"additionalPdfs\"").count(), 2);
+    assert!(body.contains(r#"["Pay stub","ID"]"#));
+
+    let total = request().base_pdf.len() as u64
+      + request()
+        .additional_pdfs
+        .iter()
+        .map(|pdf| pdf.len() as u64)
+        .sum::<u64>();
+    assert_eq!(reports.lock().unwrap().last(), Some(&(total, total)));
+  }
+
+  #[tokio::test]
+  async fn does_not_retry_unauthorized() {
+    let (_server, error) = respond_with(401, 1).await;
+    assert!(matches!(error, SaseApiError::Unauthorized));
+  }
+
+  #[tokio::test]
+  async fn does_not_retry_forbidden() {
+    let (_server, error) = respond_with(403, 1).await;
+    assert!(matches!(error, SaseApiError::Forbidden));
+  }
+
+  #[tokio::test]
+  async fn does_not_retry_payload_too_large() {
+    let (_server, error) = respond_with(413, 1).await;
+    assert!(matches!(error, SaseApiError::PayloadTooLarge));
+  }
+
+  #[tokio::test]
+  async fn does_not_retry_other_client_errors() {
+    let (_server, error) = respond_with(400, 1).await;
+    assert!(matches!(
+      error,
+      SaseApiError::Rejected { status: 400, ref message } if message == "nope"
+    ));
+  }
+
+  #[tokio::test]
+  async fn gives_up_on_server_errors_after_the_retries() {
+    let (_server, error) = respond_with(502, 3).await;
+    assert!(matches!(
+      error,
+      SaseApiError::Server { status: 502, ref message } if message == "nope"
+    ));
+  }
+
+  #[tokio::test]
+  async fn retries_server_errors_until_one_succeeds() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+      .and(path("/pdf/merge"))
+      .respond_with(ResponseTemplate::new(503))
+      .up_to_n_times(2)
+      .expect(2)
+      .mount(&server)
+      .await;
+    Mock::given(method("POST"))
+      .and(path("/pdf/merge"))
+      .respond_with(ResponseTemplate::new(200).set_body_bytes(b"%PDF-1.7 merged".to_vec()))
+      .expect(1)
+      .mount(&server)
+      .await;
+
+    let merged = client(&server.uri())
+      .merge_pdfs(request(), "token", None)
+      .await
+      .unwrap();
+    assert_eq!(merged, b"%PDF-1.7 merged");
+  }
+
+  #[tokio::test]
+  async fn retries_timeouts() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+      .and(path("/pdf/merge"))
+      .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+      .expect(3)
+      .mount(&server)
+      .await;
+
+    let error = client(&server.uri())
+      .merge_pdfs(request(), "token", None)
+      .await
+      .unwrap_err();
+    assert!(matches!(error, SaseApiError::Timeout));
+  }
+
+  #[tokio::test]
+  async fn retries_connection_failures() {
+    // Nothing listens on the port once the listener is dropped
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+      .unwrap()
+      .local_addr()
+      .unwrap()
+      .port();
+
+    let error = client(&format!("http://127.0.0.1:{}", port))
+      .merge_pdfs(request(), "token", None)
+      .await
+      .unwrap_err();
+    assert!(matches!(error, SaseApiError::Network(_)));
+    assert!(error.is_retryable());
+  }
+}
@@ -1,8 +1,22 @@
+mod blobs;
 mod checklist;
+mod comparison;
+mod costs;
 mod document;
+mod document_text;
+mod document_validity;
+mod document_versions;
+mod form_fill;
+mod form_templates;
 mod helpers;
 mod listings;
+mod packet;
+mod pdf_jobs;
+mod photos;
+mod places;
 mod profile;
+mod protocol;
+mod resume;
 
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 use anyhow::Result;
@@ -63,6 +77,23 @@ async fn open_db_with_password(args: OpenArgs) -> Result<serde_json::Value, Stri
     .await
     .map_err(|e| format!("Failed to create tables: {}", e))?;
 
+  document::backfill_document_metadata(&pool)
+    .await
+    .map_err(|e| format!("Failed to backfill document metadata: {}", e))?;
+
+  blobs::migrate_inline_data(&pool)
+    .await
+    .map_err(|e| format!("Failed to migrate file contents: {}", e))?;
+
+  // Extraction is slow for large vaults, so documents stored before the text index existed
+  // are indexed in the background
+  let index_pool = pool.clone();
+  tauri::async_runtime::spawn(async move {
+    if let Err(e) = document_text::backfill_document_text(&index_pool).await {
+      println!("Failed to index document text: {}", e);
+    }
+  });
+
   println!("Setting DB_POOL...");
   {
     let mut pool_guard = DB_POOL.write().await;
@@ -103,6 +134,8 @@ async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
       furnishing TEXT,
       notes TEXT,
       favorite BOOLEAN DEFAULT 0,
+      latitude REAL,
+      longitude REAL,
       created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
       updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
     )
@@ -111,6 +144,9 @@ async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
   .execute(pool)
   .await?;
 
+  add_column_if_missing(pool, "listings", "latitude", "REAL").await?;
+  add_column_if_missing(pool, "listings", "longitude", "REAL").await?;
+
   // Create Profile table
   sqlx::query(
     r#"
@@ -162,6 +198,50 @@ async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
   // .execute(pool)
   // .await?;
 
+  // File contents shared by documents, document versions and listing photos
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS blobs (
+      hash TEXT PRIMARY KEY, -- SHA-256 of data, hex encoded
+      data BLOB NOT NULL,
+      size INTEGER NOT NULL,
+      ref_count INTEGER NOT NULL DEFAULT 0, -- maintained by triggers
+      created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  // Which PDF blobs have had their text extracted into blob_text_pages
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS blob_text (
+      hash TEXT PRIMARY KEY, -- references blobs
+      page_count INTEGER NOT NULL DEFAULT 0,
+      has_text_layer BOOLEAN, -- false for scans that only hold images, unset if unreadable
+      error TEXT, -- why the text could not be extracted
+      indexed_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  // Full-text index of every page of every PDF blob
+  sqlx::query(
+    r#"
+    CREATE VIRTUAL TABLE IF NOT EXISTS blob_text_pages USING fts5(
+      text,
+      hash UNINDEXED,
+      page UNINDEXED, -- 1-based
+      tokenize = 'porter unicode61 remove_diacritics 2'
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
   // Create Documents table
   sqlx::query(
     r#"
@@ -170,8 +250,17 @@ async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
       name TEXT NOT NULL,
       document_type TEXT NOT NULL,
       reminder_date DATETIME,
-      mime_type TEXT,
-      data BLOB,
+      mime_type TEXT, -- detected from the contents
+      claimed_mime_type TEXT, -- as labeled by the uploader
+      data BLOB, -- only set by older versions of the app, contents live in blobs
+      size INTEGER,
+      page_count INTEGER,
+      hash TEXT, -- SHA-256 of the contents, hex encoded; references blobs
+      version INTEGER DEFAULT 1, -- version number of the current contents
+      version_note TEXT,
+      version_uploaded_at DATETIME,
+      issued_on DATE,
+      expires_on DATE, -- overrides the validity rule for the document type
       updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
     )
     "#,
@@ -179,6 +268,92 @@ async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
   .execute(pool)
   .await?;
 
+  add_column_if_missing(pool, "documents", "claimed_mime_type", "TEXT").await?;
+  add_column_if_missing(pool, "documents", "size", "INTEGER").await?;
+  add_column_if_missing(pool, "documents", "page_count", "INTEGER").await?;
+  add_column_if_missing(pool, "documents", "hash", "TEXT").await?;
+  add_column_if_missing(pool, "documents", "version", "INTEGER DEFAULT 1").await?;
+  add_column_if_missing(pool, "documents", "version_note", "TEXT").await?;
+  add_column_if_missing(pool, "documents", "version_uploaded_at", "DATETIME").await?;
+  // Documents uploaded before the upload time was recorded. updated_at is the closest there
+  // is; new documents set version_uploaded_at when they are added, so this only runs once.
+  sqlx::query(
+    "UPDATE documents SET version_uploaded_at = updated_at WHERE version_uploaded_at IS NULL",
+  )
+  .execute(pool)
+  .await?;
+  add_column_if_missing(pool, "documents", "issued_on", "DATE").await?;
+  add_column_if_missing(pool, "documents", "expires_on", "DATE").await?;
+
+  // How long documents of a type stay valid after they were issued
+  let rules_exist: bool = sqlx::query_scalar(
+    "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'document_validity_rules'",
+  )
+  .fetch_one(pool)
+  .await?;
+
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS document_validity_rules (
+      document_type TEXT PRIMARY KEY COLLATE NOCASE,
+      valid_days INTEGER NOT NULL,
+      updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  // Seed defaults only once, so rules removed by the user stay removed
+  if !rules_exist {
+    for (document_type, valid_days) in document_validity::DEFAULT_VALIDITY_RULES {
+      sqlx::query("INSERT INTO document_validity_rules (document_type, valid_days) VALUES (?, ?)")
+        .bind(document_type)
+        .bind(valid_days)
+        .execute(pool)
+        .await?;
+    }
+  }
+
+  // Previous versions of documents; the current version is the one documents refers to
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS document_versions (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      document_id INTEGER NOT NULL,
+      version INTEGER NOT NULL,
+      mime_type TEXT,
+      claimed_mime_type TEXT,
+      data BLOB, -- only set by older versions of the app, contents live in blobs
+      size INTEGER,
+      page_count INTEGER,
+      hash TEXT, -- references blobs
+      note TEXT,
+      uploaded_at DATETIME,
+      UNIQUE(document_id, version),
+      FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  // Document versions pinned by a listing instead of following the latest one
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS listing_document_pins (
+      listing_id INTEGER NOT NULL,
+      document_id INTEGER NOT NULL,
+      version INTEGER NOT NULL,
+      PRIMARY KEY(listing_id, document_id),
+      FOREIGN KEY(listing_id) REFERENCES listings(id) ON DELETE CASCADE,
+      FOREIGN KEY(document_id) REFERENCES documents(id) ON DELETE CASCADE
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
   // Create Checklists table
   sqlx::query(
     r#"
@@ -210,6 +385,132 @@ async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
   .execute(pool)
   .await?;
 
+  // Listing cost model (one row per listing)
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS listing_costs (
+      listing_id INTEGER PRIMARY KEY,
+      security_deposit DECIMAL(10,2),
+      first_month_due BOOLEAN DEFAULT 1,
+      last_month_due BOOLEAN DEFAULT 0,
+      application_fee DECIMAL(10,2),
+      broker_fee DECIMAL(10,2),
+      pet_deposit DECIMAL(10,2),
+      pet_rent DECIMAL(10,2),
+      parking_monthly DECIMAL(10,2),
+      utility_costs TEXT, -- JSON object of utility name to estimated monthly cost
+      lease_length_months INTEGER DEFAULT 12,
+      months_free DECIMAL(4,2), -- concession, e.g. 1 for "1 month free"
+      updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+      FOREIGN KEY(listing_id) REFERENCES listings(id) ON DELETE CASCADE
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  // Listing photos, with thumbnails generated on import
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS listing_photos (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      listing_id INTEGER NOT NULL,
+      caption TEXT,
+      sort_order INTEGER NOT NULL DEFAULT 0,
+      mime_type TEXT NOT NULL,
+      width INTEGER, -- after EXIF orientation is applied
+      height INTEGER,
+      hash TEXT NOT NULL, -- original upload; references blobs
+      thumbnail_small BLOB NOT NULL, -- JPEG
+      thumbnail_medium BLOB NOT NULL, -- JPEG
+      created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+      updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+      FOREIGN KEY(listing_id) REFERENCES listings(id) ON DELETE CASCADE
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  sqlx::query(
+    "CREATE INDEX IF NOT EXISTS idx_listing_photos_listing ON listing_photos(listing_id, sort_order)",
+  )
+  .execute(pool)
+  .await?;
+
+  // Places the user cares about (work, school, family) for distance filtering
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS places (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      name TEXT NOT NULL,
+      kind TEXT, -- work, school, family, other
+      address TEXT,
+      latitude REAL NOT NULL,
+      longitude REAL NOT NULL,
+      created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+      updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  // Homes the applicant rented before, for the rental resume
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS rental_history (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      address TEXT NOT NULL,
+      landlord_name TEXT,
+      landlord_contact TEXT,
+      monthly_rent DECIMAL(10,2),
+      moved_in DATE,
+      moved_out DATE, -- NULL while the applicant still lives there
+      reason_for_leaving TEXT,
+      created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+      updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  // People who vouch for the applicant, for the rental resume
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS personal_references (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      name TEXT NOT NULL,
+      relationship TEXT,
+      phone TEXT,
+      email TEXT,
+      created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+      updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  // Field mappings the user approved for fillable PDF forms, by form fingerprint
+  sqlx::query(
+    r#"
+    CREATE TABLE IF NOT EXISTS form_templates (
+      fingerprint TEXT PRIMARY KEY, -- SHA-256 of the field names and page count, hex encoded
+      name TEXT NOT NULL,
+      page_count INTEGER NOT NULL,
+      field_names TEXT NOT NULL, -- JSON array of fully qualified field names
+      mappings TEXT NOT NULL, -- JSON object of field name to mapping
+      auto_fill BOOLEAN DEFAULT 1, -- fill copies of the form as soon as they are uploaded
+      created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+      updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
   // Insert a default profile if none exists
   sqlx::query(
     r#"
@@ -273,10 +574,166 @@ async fn create_tables(pool: &SqlitePool) -> Result<(), sqlx::Error> {
   .execute(pool)
   .await?;
 
+  sqlx::query(
+    r#"
+    CREATE TRIGGER IF NOT EXISTS update_listing_costs_updated_at
+    AFTER UPDATE ON listing_costs
+    FOR EACH ROW
+    BEGIN
+      UPDATE listing_costs SET updated_at = CURRENT_TIMESTAMP WHERE listing_id = NEW.listing_id;
+    END
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  sqlx::query(
+    r#"
+    CREATE TRIGGER IF NOT EXISTS update_places_updated_at
+    AFTER UPDATE ON places
+    FOR EACH ROW
+    BEGIN
+      UPDATE places SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+    END
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  sqlx::query(
+    r#"
+    CREATE TRIGGER IF NOT EXISTS update_listing_photos_updated_at
+    AFTER UPDATE ON listing_photos
+    FOR EACH ROW
+    BEGIN
+      UPDATE listing_photos SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+    END
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  sqlx::query(
+    r#"
+    CREATE TRIGGER IF NOT EXISTS update_document_validity_rules_updated_at
+    AFTER UPDATE ON document_validity_rules
+    FOR EACH ROW
+    BEGIN
+      UPDATE document_validity_rules SET updated_at = CURRENT_TIMESTAMP
+      WHERE document_type = NEW.document_type;
+    END
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  create_blob_reference_triggers(pool).await?;
+
   Ok(())
 }
 
-#[derive(Serialize, Deserialize)]
+/// Keep `blobs.ref_count` in step with the rows referring to each blob, and delete blobs
+/// and their extracted text once nothing refers to them
+async fn create_blob_reference_triggers(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+  for table in ["documents", "document_versions", "listing_photos"] {
+    sqlx::query(&format!(
+      r#"
+      CREATE TRIGGER IF NOT EXISTS {table}_blob_insert
+      AFTER INSERT ON {table}
+      FOR EACH ROW WHEN NEW.hash IS NOT NULL
+      BEGIN
+        UPDATE blobs SET ref_count = ref_count + 1 WHERE hash = NEW.hash;
+      END
+      "#
+    ))
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+      r#"
+      CREATE TRIGGER IF NOT EXISTS {table}_blob_update
+      AFTER UPDATE OF hash ON {table}
+      FOR EACH ROW WHEN OLD.hash IS NOT NEW.hash
+      BEGIN
+        UPDATE blobs SET ref_count = ref_count + 1 WHERE hash = NEW.hash;
+        UPDATE blobs SET ref_count = ref_count - 1 WHERE hash = OLD.hash;
+      END
+      "#
+    ))
+    .execute(pool)
+    .await?;
+
+    sqlx::query(&format!(
+      r#"
+      CREATE TRIGGER IF NOT EXISTS {table}_blob_delete
+      AFTER DELETE ON {table}
+      FOR EACH ROW WHEN OLD.hash IS NOT NULL
+      BEGIN
+        UPDATE blobs SET ref_count = ref_count - 1 WHERE hash = OLD.hash;
+      END
+      "#
+    ))
+    .execute(pool)
+    .await?;
+  }
+
+  sqlx::query(
+    r#"
+    CREATE TRIGGER IF NOT EXISTS blobs_release_unreferenced
+    AFTER UPDATE OF ref_count ON blobs
+    FOR EACH ROW WHEN NEW.ref_count <= 0
+    BEGIN
+      DELETE FROM blobs WHERE hash = NEW.hash;
+    END
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  sqlx::query(
+    r#"
+    CREATE TRIGGER IF NOT EXISTS blobs_release_text
+    AFTER DELETE ON blobs
+    FOR EACH ROW
+    BEGIN
+      DELETE FROM blob_text_pages WHERE hash = OLD.hash;
+      DELETE FROM blob_text WHERE hash = OLD.hash;
+    END
+    "#,
+  )
+  .execute(pool)
+  .await?;
+
+  Ok(())
+}
+
+/// Add a column to a table created by an older version of the app
+async fn add_column_if_missing(
+  pool: &SqlitePool,
+  table: &str,
+  column: &str,
+  definition: &str,
+) -> Result<(), sqlx::Error> {
+  let exists: bool =
+    sqlx::query_scalar("SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?")
+      .bind(table)
+      .bind(column)
+      .fetch_one(pool)
+      .await?;
+
+  if !exists {
+    sqlx::query(&format!(
+      "ALTER TABLE {} ADD COLUMN {} {}",
+      table, column, definition
+    ))
+    .execute(pool)
+    .await?;
+  }
+
+  Ok(())
+}
+
+#[derive(Serialize, Deserialize, Default)]
 struct Listing {
   id: Option<i64>,
   address: String,
@@ -302,6 +759,51 @@ struct Listing {
   furnishing: Option<String>,
   notes: Option<String>,
   favorite: Option<bool>,
+  latitude: Option<f64>,
+  longitude: Option<f64>,
+  created_at: Option<String>,
+  updated_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ListingCosts {
+  listing_id: i64,
+  security_deposit: Option<f64>,
+  first_month_due: Option<bool>,
+  last_month_due: Option<bool>,
+  application_fee: Option<f64>,
+  broker_fee: Option<f64>,
+  pet_deposit: Option<f64>,
+  pet_rent: Option<f64>,
+  parking_monthly: Option<f64>,
+  utility_costs: Option<String>,
+  lease_length_months: Option<i32>,
+  months_free: Option<f64>,
+  updated_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ListingPhoto {
+  id: Option<i64>,
+  listing_id: i64,
+  caption: Option<String>,
+  sort_order: i64,
+  mime_type: String,
+  width: Option<i64>,
+  height: Option<i64>,
+  size: Option<i64>,
+  created_at: Option<String>,
+  updated_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Place {
+  id: Option<i64>,
+  name: String,
+  kind: Option<String>,
+  address: Option<String>,
+  latitude: f64,
+  longitude: f64,
   created_at: Option<String>,
   updated_at: Option<String>,
 }
@@ -327,9 +829,51 @@ struct Document {
   reminder_date: Option<String>,
   mime_type: Option<String>,
   data: Option<Vec<u8>>,
+  issued_on: Option<String>,
+  expires_on: Option<String>,
   updated_at: Option<String>,
 }
 
+/// Document row without its `data` blob
+#[derive(Serialize, Deserialize)]
+struct DocumentMeta {
+  id: i64,
+  name: String,
+  document_type: String,
+  reminder_date: Option<String>,
+  mime_type: Option<String>,
+  claimed_mime_type: Option<String>,
+  size: Option<i64>,
+  page_count: Option<i64>,
+  hash: Option<String>,
+  issued_on: Option<String>,
+  expires_on: Option<String>,
+  updated_at: Option<String>,
+  /// Whether a PDF has searchable text; false for scans. Unset for other files, PDFs that have
+  /// not been indexed yet and PDFs whose text could not be read.
+  has_text_layer: Option<bool>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DocumentValidityRule {
+  document_type: String,
+  valid_days: i64,
+  updated_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DocumentVersion {
+  document_id: i64,
+  version: i64,
+  mime_type: Option<String>,
+  size: Option<i64>,
+  page_count: Option<i64>,
+  hash: Option<String>,
+  note: Option<String>,
+  uploaded_at: Option<String>,
+  is_current: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 struct IncomeSource {
   id: Option<i64>,
@@ -358,6 +902,31 @@ struct Checklist {
   updated_at: Option<String>,
 }
 
+#[derive(Serialize, Deserialize)]
+struct RentalHistoryEntry {
+  id: Option<i64>,
+  address: String,
+  landlord_name: Option<String>,
+  landlord_contact: Option<String>,
+  monthly_rent: Option<f64>,
+  moved_in: Option<String>,
+  moved_out: Option<String>,
+  reason_for_leaving: Option<String>,
+  created_at: Option<String>,
+  updated_at: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersonalReference {
+  id: Option<i64>,
+  name: String,
+  relationship: Option<String>,
+  phone: Option<String>,
+  email: Option<String>,
+  created_at: Option<String>,
+  updated_at: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 struct AdditionalInfoItem {
   id: String,
@@ -525,6 +1094,9 @@ pub fn run() {
     .plugin(tauri_plugin_dialog::init())
     .plugin(tauri_plugin_opener::init())
     .plugin(tauri_plugin_http::init())
+    .register_asynchronous_uri_scheme_protocol(protocol::SCHEME, |_ctx, request, responder| {
+      protocol::handle_request(request, responder)
+    })
     .invoke_handler(tauri::generate_handler![
       get_environment_variable,
       greet,
@@ -543,6 +1115,15 @@ pub fn run() {
       profile::get_additional_info,
       profile::set_additional_info,
       profile::delete_additional_info,
+      profile::get_rental_history,
+      profile::add_rental_history,
+      profile::update_rental_history,
+      profile::delete_rental_history,
+      profile::get_personal_references,
+      profile::add_personal_reference,
+      profile::update_personal_reference,
+      profile::delete_personal_reference,
+      resume::generate_rental_resume,
       listings::add_listing,
       listings::get_listings,
       listings::get_listing,
@@ -552,12 +1133,58 @@ pub fn run() {
       listings::update_listing,
       listings::toggle_listing_favorite,
       listings::set_listing_favorite,
-      document::get_documents,
+      photos::add_listing_photo,
+      photos::get_listing_photos,
+      photos::get_listing_photo_thumbnail,
+      photos::get_listing_photo_data,
+      photos::set_listing_photo_caption,
+      photos::reorder_listing_photos,
+      photos::delete_listing_photo,
+      places::get_places,
+      places::add_place,
+      places::update_place,
+      places::delete_place,
+      places::set_listing_location,
+      places::listings_within,
+      places::get_listings_with_distances,
+      places::export_listings_geojson,
+      places::import_listing_coordinates,
+      costs::get_listing_costs,
+      costs::set_listing_costs,
+      costs::compute_listing_costs,
+      comparison::compare_listings,
+      comparison::export_listing_comparison_pdf,
+      document::list_documents,
+      document::get_document_data,
       document::add_document,
       document::read_file_as_blob,
       document::delete_document,
+      document_text::search_document_text,
+      document_versions::replace_document,
+      document_versions::list_document_versions,
+      document_versions::get_document_version_data,
+      document_versions::restore_document_version,
+      document_versions::redact_document,
+      document_versions::prune_document_versions,
+      document_versions::pin_listing_document_version,
+      document_versions::get_listing_document_pins,
+      document_validity::set_document_dates,
+      document_validity::get_document_validity_rules,
+      document_validity::set_document_validity_rule,
+      document_validity::get_expiring_documents,
+      blobs::get_blob_stats,
+      blobs::collect_garbage_blobs,
       document::test_pdf_generation,
       document::build_pdf_with_sase_api,
+      packet::build_application_packet,
+      pdf_jobs::cancel_pdf_job,
+      form_fill::inspect_pdf_form,
+      form_fill::fill_pdf_form,
+      form_templates::get_form_templates,
+      form_templates::save_form_template,
+      form_templates::delete_form_template,
+      form_templates::export_form_template,
+      form_templates::import_form_template,
       checklist::get_checklists,
       checklist::add_checklist,
       checklist::update_checklist,
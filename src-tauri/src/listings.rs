@@ -1,6 +1,9 @@
+use crate::places::validate_location;
 use crate::Listing;
 use crate::DB_POOL;
+use sqlx::sqlite::SqliteRow;
 use sqlx::Row;
+use sqlx::SqlitePool;
 
 /// Add a new listing
 #[tauri::command]
@@ -8,14 +11,16 @@ pub async fn add_listing(listing: Listing) -> Result<i64, String> {
   println!("add_listing called with address: {}", listing.address);
   let pool_guard = DB_POOL.read().await;
   let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+  validate_location(listing.latitude, listing.longitude)?;
+
   let result = sqlx::query(
     r#"
     INSERT INTO listings (
       address, contact_email, contact_phone, contact_other, source_link, price_rent,
       housing_type, lease_type, upfront_fees, utilities, credit_score_min, minimum_income,
       references_required, reference_document_ids, bedrooms, bathrooms, square_footage, layout_description,
-      amenities, pet_policy, furnishing, notes, favorite
-    ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+      amenities, pet_policy, furnishing, notes, favorite, latitude, longitude
+    ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
     "#,
   )
   .bind(&listing.address)
@@ -41,6 +46,8 @@ pub async fn add_listing(listing: Listing) -> Result<i64, String> {
   .bind(&listing.furnishing)
   .bind(&listing.notes)
   .bind(listing.favorite)
+  .bind(listing.latitude)
+  .bind(listing.longitude)
   .execute(pool)
   .await
   .map_err(|e| format!("Failed to insert listing: {}", e))?;
@@ -62,7 +69,7 @@ pub async fn get_listings() -> Result<Vec<Listing>, String> {
       price_rent, housing_type, lease_type, upfront_fees, utilities, 
       credit_score_min, minimum_income, references_required, reference_document_ids, bedrooms, 
       bathrooms, square_footage, layout_description, amenities, pet_policy, 
-      furnishing, notes, favorite, created_at, updated_at
+      furnishing, notes, favorite, latitude, longitude, created_at, updated_at
     FROM listings 
     ORDER BY created_at DESC
     "#,
@@ -71,37 +78,7 @@ pub async fn get_listings() -> Result<Vec<Listing>, String> {
   .await
   .map_err(|e| format!("Failed to fetch listings: {}", e))?;
 
-  let mut listings = Vec::new();
-  for row in rows {
-    listings.push(Listing {
-      id: row.try_get("id").ok(),
-      address: row.try_get("address").unwrap_or_default(),
-      contact_email: row.try_get("contact_email").ok(),
-      contact_phone: row.try_get("contact_phone").ok(),
-      contact_other: row.try_get("contact_other").ok(),
-      source_link: row.try_get("source_link").unwrap_or_default(),
-      price_rent: row.try_get("price_rent").unwrap_or(0.0),
-      housing_type: row.try_get("housing_type").ok(),
-      lease_type: row.try_get("lease_type").ok(),
-      upfront_fees: row.try_get("upfront_fees").ok(),
-      utilities: row.try_get("utilities").ok(),
-      credit_score_min: row.try_get("credit_score_min").ok(),
-      minimum_income: row.try_get("minimum_income").ok(),
-      references_required: row.try_get("references_required").ok(),
-      reference_document_ids: row.try_get("reference_document_ids").ok(),
-      bedrooms: row.try_get("bedrooms").ok(),
-      bathrooms: row.try_get("bathrooms").ok(),
-      square_footage: row.try_get("square_footage").ok(),
-      layout_description: row.try_get("layout_description").ok(),
-      amenities: row.try_get("amenities").ok(),
-      pet_policy: row.try_get("pet_policy").ok(),
-      furnishing: row.try_get("furnishing").ok(),
-      notes: row.try_get("notes").ok(),
-      favorite: row.try_get("favorite").ok(),
-      created_at: row.try_get("created_at").ok(),
-      updated_at: row.try_get("updated_at").ok(),
-    });
-  }
+  let listings = rows.iter().map(listing_from_row).collect();
   Ok(listings)
 }
 
@@ -118,7 +95,7 @@ pub async fn get_listing(id: i64) -> Result<Listing, String> {
       price_rent, housing_type, lease_type, upfront_fees, utilities, 
       credit_score_min, minimum_income, references_required, reference_document_ids, bedrooms, 
       bathrooms, square_footage, layout_description, amenities, pet_policy, 
-      furnishing, notes, favorite, created_at, updated_at
+      furnishing, notes, favorite, latitude, longitude, created_at, updated_at
     FROM listings 
     WHERE id = ?
     "#,
@@ -128,36 +105,7 @@ pub async fn get_listing(id: i64) -> Result<Listing, String> {
   .await
   .map_err(|e| format!("Failed to fetch listing: {}", e))?;
 
-  let listing = Listing {
-    id: row.try_get("id").ok(),
-    address: row.try_get("address").unwrap_or_default(),
-    contact_email: row.try_get("contact_email").ok(),
-    contact_phone: row.try_get("contact_phone").ok(),
-    contact_other: row.try_get("contact_other").ok(),
-    source_link: row.try_get("source_link").unwrap_or_default(),
-    price_rent: row.try_get("price_rent").unwrap_or(0.0),
-    housing_type: row.try_get("housing_type").ok(),
-    lease_type: row.try_get("lease_type").ok(),
-    upfront_fees: row.try_get("upfront_fees").ok(),
-    utilities: row.try_get("utilities").ok(),
-    credit_score_min: row.try_get("credit_score_min").ok(),
-    minimum_income: row.try_get("minimum_income").ok(),
-    references_required: row.try_get("references_required").ok(),
-    reference_document_ids: row.try_get("reference_document_ids").ok(),
-    bedrooms: row.try_get("bedrooms").ok(),
-    bathrooms: row.try_get("bathrooms").ok(),
-    square_footage: row.try_get("square_footage").ok(),
-    layout_description: row.try_get("layout_description").ok(),
-    amenities: row.try_get("amenities").ok(),
-    pet_policy: row.try_get("pet_policy").ok(),
-    furnishing: row.try_get("furnishing").ok(),
-    notes: row.try_get("notes").ok(),
-    favorite: row.try_get("favorite").ok(),
-    created_at: row.try_get("created_at").ok(),
-    updated_at: row.try_get("updated_at").ok(),
-  };
-
-  Ok(listing)
+  Ok(listing_from_row(&row))
 }
 
 #[tauri::command]
@@ -358,3 +306,55 @@ pub async fn set_listing_favorite(listing_id: i64, favorite: bool) -> Result<(),
 
   Ok(())
 }
+
+/// Map a `listings` row onto a `Listing`
+pub(crate) fn listing_from_row(row: &SqliteRow) -> Listing {
+  Listing {
+    id: row.try_get("id").ok(),
+    address: row.try_get("address").unwrap_or_default(),
+    contact_email: row.try_get("contact_email").ok(),
+    contact_phone: row.try_get("contact_phone").ok(),
+    contact_other: row.try_get("contact_other").ok(),
+    source_link: row.try_get("source_link").unwrap_or_default(),
+    price_rent: row.try_get("price_rent").unwrap_or(0.0),
+    housing_type: row.try_get("housing_type").ok(),
+    lease_type: row.try_get("lease_type").ok(),
+    upfront_fees: row.try_get("upfront_fees").ok(),
+    utilities: row.try_get("utilities").ok(),
+    credit_score_min: row.try_get("credit_score_min").ok(),
+    minimum_income: row.try_get("minimum_income").ok(),
+    references_required: row.try_get("references_required").ok(),
+    reference_document_ids: row.try_get("reference_document_ids").ok(),
+    bedrooms: row.try_get("bedrooms").ok(),
+    bathrooms: row.try_get("bathrooms").ok(),
+    square_footage: row.try_get("square_footage").ok(),
+    layout_description: row.try_get("layout_description").ok(),
+    amenities: row.try_get("amenities").ok(),
+    pet_policy: row.try_get("pet_policy").ok(),
+    furnishing: row.try_get("furnishing").ok(),
+    notes: row.try_get("notes").ok(),
+    favorite: row.try_get("favorite").ok(),
+    latitude: row.try_get("latitude").ok(),
+    longitude: row.try_get("longitude").ok(),
+    created_at: row.try_get("created_at").ok(),
+    updated_at: row.try_get("updated_at").ok(),
+  }
+}
+
+/// Fetch the given listings, preserving the order of `ids`
+pub(crate) async fn fetch_listings_by_ids(
+  pool: &SqlitePool,
+  ids: &[i64],
+) -> Result<Vec<Listing>, String> {
+  let mut listings = Vec::with_capacity(ids.len());
+  for id in ids {
+    let row = sqlx::query("SELECT * FROM listings WHERE id = ?")
+      .bind(id)
+      .fetch_optional(pool)
+      .await
+      .map_err(|e| format!("Failed to fetch listing: {}", e))?
+      .ok_or_else(|| format!("No listing found with id {}", id))?;
+    listings.push(listing_from_row(&row));
+  }
+  Ok(listings)
+}
@@ -0,0 +1,224 @@
+use crate::document::load_packet_documents;
+use crate::helpers::pdf_docs::{
+  build_packet, stamp_pdf, PacketCover, PdfBuildMonitor, PdfBuildStage, PdfRenderOptions, PdfStamp,
+};
+use crate::helpers::pdf_size::apply_size_budget;
+use crate::pdf_jobs::PdfJob;
+use crate::DB_POOL;
+use serde::Deserialize;
+use sqlx::{Row, SqlitePool};
+use std::sync::Arc;
+
+/// What to stamp on every page of a packet
+#[derive(Deserialize, Default)]
+pub struct PacketStampOptions {
+  /// "Page X of Y" footers
+  #[serde(default)]
+  page_numbers: bool,
+  /// Header with the applicant name and the address applied for
+  #[serde(default)]
+  running_header: bool,
+  /// Landlord or agency the packet is given to. When set, every page carries a diagonal
+  /// "Provided to ... for ... on ... - not for other use" watermark.
+  watermark_recipient: Option<String>,
+}
+
+/// Build an application packet from the given documents, in that order: a cover page with the
+/// applicant's details, a table of contents, and a divider page before each document. With a
+/// listing, its pinned document versions are used and its address is shown on the cover.
+/// Progress is reported as `pdf-job-progress` events for `job_id`, which `cancel_pdf_job` stops.
+#[tauri::command]
+pub async fn build_application_packet(
+  app: tauri::AppHandle,
+  document_ids: Vec<i64>,
+  listing_id: Option<i64>,
+  title: Option<String>,
+  stamp: Option<PacketStampOptions>,
+  render_options: Option<PdfRenderOptions>,
+  job_id: Option<String>,
+) -> Result<Vec<u8>, String> {
+  let job = PdfJob::start(app, job_id)?;
+  let monitor: Arc<dyn PdfBuildMonitor> = job.clone();
+
+  job
+    .run(async move {
+      let pool_guard = DB_POOL.read().await;
+      let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+      let documents =
+        load_packet_documents(pool, &document_ids, listing_id, monitor.as_ref()).await?;
+      if documents.is_empty() {
+        return Err("No documents to include in the packet".to_string());
+      }
+
+      let details = packet_details(pool, listing_id).await?;
+      let stamp = packet_stamp(pool, &details, stamp.unwrap_or_default()).await?;
+      let cover = packet_cover(details, title);
+      let render_options = render_options.unwrap_or_default();
+      render_options.validate().map_err(|e| e.to_string())?;
+
+      tokio::task::spawn_blocking(move || {
+        let packet = build_packet(&cover, documents, &render_options, monitor.as_ref())?;
+        let packet = apply_stamp(packet, stamp.as_ref(), monitor.as_ref())?;
+        // Last, so the budget covers the stamps too
+        apply_size_budget(packet, render_options.target_size_bytes, monitor.as_ref())
+      })
+      .await
+      .map_err(|e| format!("Packet build task failed: {}", e))?
+      .map_err(|e| format!("Failed to build application packet: {}", e))
+    })
+    .await
+}
+
+/// Stamp for a PDF built without a packet cover, such as a resume with documents appended
+pub(crate) async fn listing_stamp(
+  pool: &SqlitePool,
+  listing_id: Option<i64>,
+  options: PacketStampOptions,
+) -> Result<Option<PdfStamp>, String> {
+  let details = packet_details(pool, listing_id).await?;
+  packet_stamp(pool, &details, options).await
+}
+
+/// Stamp every page of a finished PDF, if there is anything to stamp
+pub(crate) fn apply_stamp(
+  pdf: Vec<u8>,
+  stamp: Option<&PdfStamp>,
+  monitor: &dyn PdfBuildMonitor,
+) -> anyhow::Result<Vec<u8>> {
+  let Some(stamp) = stamp else {
+    return Ok(pdf);
+  };
+  monitor.check_cancelled()?;
+  monitor.report(PdfBuildStage::Stamping);
+  stamp_pdf(&pdf, stamp)
+}
+
+/// Applicant and listing details shown on a packet
+struct PacketDetails {
+  applicant: Option<String>,
+  listing_address: Option<String>,
+  fields: Vec<(String, String)>,
+}
+
+async fn packet_details(
+  pool: &SqlitePool,
+  listing_id: Option<i64>,
+) -> Result<PacketDetails, String> {
+  let mut fields = Vec::new();
+
+  let profile = sqlx::query("SELECT * FROM profile WHERE id = 1")
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch profile: {}", e))?;
+
+  let mut applicant = None;
+  if let Some(row) = profile {
+    applicant = row.try_get::<Option<String>, _>("fullname").ok().flatten();
+    for (label, column) in [
+      ("Applicant", "fullname"),
+      ("Date of birth", "date_of_birth"),
+      ("Phone", "phone"),
+      ("Email", "email"),
+      ("Current address", "address"),
+    ] {
+      if let Ok(Some(value)) = row.try_get::<Option<String>, _>(column) {
+        fields.push((label.to_string(), value));
+      }
+    }
+    if let Ok(Some(income)) = row.try_get::<Option<i64>, _>("monthly_income") {
+      if income > 0 {
+        fields.push(("Monthly income".to_string(), format!("${}", income)));
+      }
+    }
+  }
+
+  let mut listing_address = None;
+  if let Some(listing_id) = listing_id {
+    let listing = sqlx::query("SELECT address, price_rent FROM listings WHERE id = ?")
+      .bind(listing_id)
+      .fetch_optional(pool)
+      .await
+      .map_err(|e| format!("Failed to fetch listing: {}", e))?
+      .ok_or_else(|| format!("No listing found with id {}", listing_id))?;
+
+    let address: String = listing.try_get("address").unwrap_or_default();
+    fields.push(("Applying for".to_string(), address.clone()));
+    if let Ok(rent) = listing.try_get::<f64, _>("price_rent") {
+      fields.push(("Monthly rent".to_string(), format!("${:.2}", rent)));
+    }
+    listing_address = Some(address);
+  }
+
+  // Skip blank profile fields rather than printing empty lines
+  fields.retain(|(_, value)| !value.trim().is_empty());
+
+  Ok(PacketDetails {
+    applicant: applicant.filter(|a| !a.trim().is_empty()),
+    listing_address: listing_address.filter(|a| !a.trim().is_empty()),
+    fields,
+  })
+}
+
+fn packet_cover(details: PacketDetails, title: Option<String>) -> PacketCover {
+  let title = title
+    .filter(|t| !t.trim().is_empty())
+    .unwrap_or_else(|| match details.applicant {
+      Some(name) => format!("Rental Application - {}", name),
+      None => "Rental Application".to_string(),
+    });
+
+  PacketCover {
+    title,
+    fields: details.fields,
+  }
+}
+
+async fn packet_stamp(
+  pool: &SqlitePool,
+  details: &PacketDetails,
+  options: PacketStampOptions,
+) -> Result<Option<PdfStamp>, String> {
+  let header = if options.running_header {
+    match (&details.applicant, &details.listing_address) {
+      (Some(applicant), Some(address)) => {
+        Some(format!("{} - Application for {}", applicant, address))
+      }
+      (Some(applicant), None) => Some(format!("{} - Rental application", applicant)),
+      (None, Some(address)) => Some(format!("Rental application for {}", address)),
+      (None, None) => None,
+    }
+  } else {
+    None
+  };
+
+  let watermark = match options.watermark_recipient.filter(|r| !r.trim().is_empty()) {
+    Some(recipient) => {
+      let today: String = sqlx::query_scalar("SELECT date('now', 'localtime')")
+        .fetch_one(pool)
+        .await
+        .map_err(|e| format!("Failed to read the current date: {}", e))?;
+      let address = details
+        .listing_address
+        .as_deref()
+        .unwrap_or("a rental application");
+      Some(format!(
+        "Provided to {} for {} on {} \u{2014} not for other use",
+        recipient.trim(),
+        address,
+        today
+      ))
+    }
+    None => None,
+  };
+
+  if header.is_none() && !options.page_numbers && watermark.is_none() {
+    return Ok(None);
+  }
+
+  Ok(Some(PdfStamp {
+    header,
+    page_numbers: options.page_numbers,
+    watermark,
+  }))
+}
@@ -0,0 +1,120 @@
+use crate::helpers::pdf_docs::{PdfBuildMonitor, PdfBuildStage};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter};
+use tokio_util::sync::CancellationToken;
+
+/// Event emitted with a [`PdfJobProgress`] payload as PDF jobs move through their stages
+pub const PDF_JOB_PROGRESS_EVENT: &str = "pdf-job-progress";
+
+const CANCELLED: &str = "PDF job was cancelled";
+
+/// Cancellation tokens of running PDF jobs, by job id
+static PDF_JOBS: LazyLock<Mutex<HashMap<String, CancellationToken>>> =
+  LazyLock::new(|| Mutex::new(HashMap::new()));
+
+static NEXT_JOB: AtomicU64 = AtomicU64::new(1);
+
+#[derive(Serialize, Clone)]
+struct PdfJobProgress {
+  job_id: String,
+  #[serde(flatten)]
+  stage: PdfBuildStage,
+}
+
+/// A running PDF build. Its progress is emitted as [`PDF_JOB_PROGRESS_EVENT`] and it can be
+/// stopped with `cancel_pdf_job` until it is dropped.
+pub struct PdfJob {
+  id: String,
+  app: AppHandle,
+  token: CancellationToken,
+}
+
+impl PdfJob {
+  /// Register a job. The frontend picks the id so it can listen for progress and cancel the
+  /// job before the command returns; without one, an id is generated.
+  pub fn start(app: AppHandle, job_id: Option<String>) -> Result<Arc<PdfJob>, String> {
+    let id = job_id
+      .filter(|id| !id.trim().is_empty())
+      .unwrap_or_else(new_job_id);
+    let token = CancellationToken::new();
+
+    let mut jobs = PDF_JOBS
+      .lock()
+      .map_err(|_| "PDF job registry is unavailable".to_string())?;
+    if jobs.contains_key(&id) {
+      return Err(format!("A PDF job with id {} is already running", id));
+    }
+    jobs.insert(id.clone(), token.clone());
+
+    Ok(Arc::new(PdfJob { id, app, token }))
+  }
+
+  /// Run the async part of a job, abandoning it as soon as the job is cancelled. Dropping the
+  /// future aborts uploads in flight; blocking work stops at its next cancellation check.
+  pub async fn run<T>(&self, work: impl Future<Output = Result<T, String>>) -> Result<T, String> {
+    tokio::select! {
+      result = work => result,
+      _ = self.token.cancelled() => Err(CANCELLED.to_string()),
+    }
+  }
+}
+
+impl PdfBuildMonitor for PdfJob {
+  fn report(&self, stage: PdfBuildStage) {
+    let progress = PdfJobProgress {
+      job_id: self.id.clone(),
+      stage,
+    };
+    if let Err(e) = self.app.emit(PDF_JOB_PROGRESS_EVENT, progress) {
+      println!("Failed to emit progress for PDF job {}: {}", self.id, e);
+    }
+  }
+
+  fn check_cancelled(&self) -> anyhow::Result<()> {
+    if self.token.is_cancelled() {
+      return Err(anyhow::anyhow!(CANCELLED));
+    }
+    Ok(())
+  }
+}
+
+impl Drop for PdfJob {
+  fn drop(&mut self) {
+    if let Ok(mut jobs) = PDF_JOBS.lock() {
+      jobs.remove(&self.id);
+    }
+  }
+}
+
+fn new_job_id() -> String {
+  let millis = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_millis())
+    .unwrap_or_default();
+  format!(
+    "pdf-{}-{}",
+    millis,
+    NEXT_JOB.fetch_add(1, Ordering::Relaxed)
+  )
+}
+
+/// Cancel a running PDF job. Returns false when no job with that id is running, e.g. because it
+/// already finished.
+#[tauri::command]
+pub async fn cancel_pdf_job(job_id: String) -> Result<bool, String> {
+  let jobs = PDF_JOBS
+    .lock()
+    .map_err(|_| "PDF job registry is unavailable".to_string())?;
+  match jobs.get(&job_id) {
+    Some(token) => {
+      token.cancel();
+      Ok(true)
+    }
+    None => Ok(false),
+  }
+}
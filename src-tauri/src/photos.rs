@@ -0,0 +1,240 @@
+use crate::blobs::store_blob;
+use crate::helpers::images::{
+  decode_oriented, image_mime_type, jpeg_thumbnail, sanitize_image, THUMBNAIL_MEDIUM,
+  THUMBNAIL_SMALL,
+};
+use crate::ListingPhoto;
+use crate::DB_POOL;
+use sqlx::Row;
+
+/// Largest photo accepted on import
+const MAX_PHOTO_BYTES: usize = 30 * 1024 * 1024;
+
+/// Add a photo to a listing. Thumbnails are generated with the EXIF orientation applied.
+#[tauri::command]
+pub async fn add_listing_photo(
+  listing_id: i64,
+  data: Vec<u8>,
+  caption: Option<String>,
+) -> Result<i64, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  if data.len() > MAX_PHOTO_BYTES {
+    return Err(format!(
+      "Photo is too large: {} bytes (max {} MB)",
+      data.len(),
+      MAX_PHOTO_BYTES / (1024 * 1024)
+    ));
+  }
+  let mime_type = image_mime_type(&data).ok_or("Unsupported image format")?;
+
+  let (data, width, height, thumbnail_small, thumbnail_medium) =
+    tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
+      // Stored upright and without EXIF, so photos don't carry GPS coordinates around
+      let data = sanitize_image(&data)?;
+      let image = decode_oriented(&data)?;
+      let small = jpeg_thumbnail(&image, THUMBNAIL_SMALL)?;
+      let medium = jpeg_thumbnail(&image, THUMBNAIL_MEDIUM)?;
+      Ok((data, image.width(), image.height(), small, medium))
+    })
+    .await
+    .map_err(|e| format!("Thumbnail task failed: {}", e))?
+    .map_err(|e| format!("Failed to process photo: {}", e))?;
+  // Rotated images may have been re-encoded in another format
+  let mime_type = image_mime_type(&data).unwrap_or(mime_type);
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+  let hash = store_blob(&mut *tx, &data).await?;
+
+  let result = sqlx::query(
+    r#"
+    INSERT INTO listing_photos (
+      listing_id, caption, sort_order, mime_type, width, height, hash,
+      thumbnail_small, thumbnail_medium
+    ) VALUES (
+      ?, ?,
+      (SELECT COALESCE(MAX(sort_order) + 1, 0) FROM listing_photos WHERE listing_id = ?),
+      ?, ?, ?, ?, ?, ?
+    )
+    "#,
+  )
+  .bind(listing_id)
+  .bind(&caption)
+  .bind(listing_id)
+  .bind(mime_type)
+  .bind(width as i64)
+  .bind(height as i64)
+  .bind(&hash)
+  .bind(&thumbnail_small)
+  .bind(&thumbnail_medium)
+  .execute(&mut *tx)
+  .await
+  .map_err(|e| format!("Failed to insert listing photo: {}", e))?;
+
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to insert listing photo: {}", e))?;
+
+  Ok(result.last_insert_rowid())
+}
+
+/// Photo metadata for a listing in display order, without any image data
+#[tauri::command]
+pub async fn get_listing_photos(listing_id: i64) -> Result<Vec<ListingPhoto>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let rows = sqlx::query(
+    r#"
+    SELECT p.id, p.listing_id, p.caption, p.sort_order, p.mime_type, p.width, p.height,
+      b.size, p.created_at, p.updated_at
+    FROM listing_photos p
+    LEFT JOIN blobs b ON b.hash = p.hash
+    WHERE p.listing_id = ?
+    ORDER BY p.sort_order, p.id
+    "#,
+  )
+  .bind(listing_id)
+  .fetch_all(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch listing photos: {}", e))?;
+
+  let mut photos = Vec::new();
+  for row in rows {
+    photos.push(ListingPhoto {
+      id: row.try_get("id").ok(),
+      listing_id: row.try_get("listing_id").unwrap_or_default(),
+      caption: row.try_get("caption").ok(),
+      sort_order: row.try_get("sort_order").unwrap_or_default(),
+      mime_type: row.try_get("mime_type").unwrap_or_default(),
+      width: row.try_get("width").ok(),
+      height: row.try_get("height").ok(),
+      size: row.try_get("size").ok(),
+      created_at: row.try_get("created_at").ok(),
+      updated_at: row.try_get("updated_at").ok(),
+    });
+  }
+
+  Ok(photos)
+}
+
+/// JPEG thumbnail of a photo. `size` is "small" or "medium".
+#[tauri::command]
+pub async fn get_listing_photo_thumbnail(photo_id: i64, size: String) -> Result<Vec<u8>, String> {
+  let column = match size.as_str() {
+    "small" => "thumbnail_small",
+    "medium" => "thumbnail_medium",
+    _ => return Err(format!("Unknown thumbnail size: {}", size)),
+  };
+
+  fetch_photo_blob(photo_id, column).await
+}
+
+/// Full resolution photo as it was uploaded
+#[tauri::command]
+pub async fn get_listing_photo_data(photo_id: i64) -> Result<Vec<u8>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let data: Option<Vec<u8>> = sqlx::query_scalar(
+    "SELECT b.data FROM listing_photos p LEFT JOIN blobs b ON b.hash = p.hash WHERE p.id = ?",
+  )
+  .bind(photo_id)
+  .fetch_optional(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch listing photo: {}", e))?
+  .ok_or_else(|| format!("No photo found with id {}", photo_id))?;
+
+  data.ok_or_else(|| format!("Contents of photo {} are missing", photo_id))
+}
+
+#[tauri::command]
+pub async fn set_listing_photo_caption(
+  photo_id: i64,
+  caption: Option<String>,
+) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let result = sqlx::query("UPDATE listing_photos SET caption = ? WHERE id = ?")
+    .bind(&caption)
+    .bind(photo_id)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to update photo caption: {}", e))?;
+
+  if result.rows_affected() == 0 {
+    return Err(format!("No photo found with id {}", photo_id));
+  }
+
+  Ok(())
+}
+
+/// Reorder the photos of a listing. `photo_ids` lists the listing's photos in their new order.
+#[tauri::command]
+pub async fn reorder_listing_photos(listing_id: i64, photo_ids: Vec<i64>) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+
+  for (position, photo_id) in photo_ids.iter().enumerate() {
+    let result =
+      sqlx::query("UPDATE listing_photos SET sort_order = ? WHERE id = ? AND listing_id = ?")
+        .bind(position as i64)
+        .bind(photo_id)
+        .bind(listing_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| format!("Failed to reorder photos: {}", e))?;
+
+    if result.rows_affected() == 0 {
+      return Err(format!(
+        "Photo {} does not belong to listing {}",
+        photo_id, listing_id
+      ));
+    }
+  }
+
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to reorder photos: {}", e))?;
+
+  Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_listing_photo(photo_id: i64) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  sqlx::query("DELETE FROM listing_photos WHERE id = ?")
+    .bind(photo_id)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to delete listing photo: {}", e))?;
+
+  Ok(())
+}
+
+async fn fetch_photo_blob(photo_id: i64, column: &str) -> Result<Vec<u8>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  sqlx::query_scalar::<_, Vec<u8>>(&format!(
+    "SELECT {} FROM listing_photos WHERE id = ?",
+    column
+  ))
+  .bind(photo_id)
+  .fetch_optional(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch listing photo: {}", e))?
+  .ok_or_else(|| format!("No photo found with id {}", photo_id))
+}
@@ -0,0 +1,493 @@
+use crate::listings::listing_from_row;
+use crate::Listing;
+use crate::Place;
+use crate::DB_POOL;
+use serde::Serialize;
+use serde_json::json;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
+
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+
+/// How an imported GeoJSON feature names the listing it belongs to
+#[derive(Debug, PartialEq)]
+enum ListingMatch<'a> {
+  Id(i64),
+  Address(&'a str),
+}
+
+#[derive(Serialize)]
+pub struct PlaceDistance {
+  place_id: i64,
+  name: String,
+  distance_km: f64,
+}
+
+#[derive(Serialize)]
+pub struct ListingWithDistances {
+  #[serde(flatten)]
+  listing: Listing,
+  /// Distances to every saved place, nearest first. Empty when the listing has no coordinates.
+  distances: Vec<PlaceDistance>,
+}
+
+#[derive(Serialize)]
+pub struct ListingWithinDistance {
+  #[serde(flatten)]
+  listing: Listing,
+  distance_km: f64,
+}
+
+#[tauri::command]
+pub async fn get_places() -> Result<Vec<Place>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  fetch_places(pool).await
+}
+
+#[tauri::command]
+pub async fn add_place(place: Place) -> Result<i64, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  validate_coordinates(place.latitude, place.longitude)?;
+
+  let result = sqlx::query(
+    "INSERT INTO places (name, kind, address, latitude, longitude) VALUES (?, ?, ?, ?, ?)",
+  )
+  .bind(&place.name)
+  .bind(&place.kind)
+  .bind(&place.address)
+  .bind(place.latitude)
+  .bind(place.longitude)
+  .execute(pool)
+  .await
+  .map_err(|e| format!("Failed to insert place: {}", e))?;
+
+  Ok(result.last_insert_rowid())
+}
+
+#[tauri::command]
+pub async fn update_place(id: i64, place: Place) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  validate_coordinates(place.latitude, place.longitude)?;
+
+  let result = sqlx::query(
+    r#"
+    UPDATE places
+    SET name = ?, kind = ?, address = ?, latitude = ?, longitude = ?
+    WHERE id = ?
+    "#,
+  )
+  .bind(&place.name)
+  .bind(&place.kind)
+  .bind(&place.address)
+  .bind(place.latitude)
+  .bind(place.longitude)
+  .bind(id)
+  .execute(pool)
+  .await
+  .map_err(|e| format!("Failed to update place: {}", e))?;
+
+  if result.rows_affected() == 0 {
+    return Err(format!("No place found with id {}", id));
+  }
+
+  Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_place(id: i64) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  sqlx::query("DELETE FROM places WHERE id = ?")
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to delete place: {}", e))?;
+
+  Ok(())
+}
+
+/// Set or clear the coordinates of a listing
+#[tauri::command]
+pub async fn set_listing_location(
+  listing_id: i64,
+  latitude: Option<f64>,
+  longitude: Option<f64>,
+) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  validate_location(latitude, longitude)?;
+
+  let result = sqlx::query("UPDATE listings SET latitude = ?, longitude = ? WHERE id = ?")
+    .bind(latitude)
+    .bind(longitude)
+    .bind(listing_id)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to update listing location: {}", e))?;
+
+  if result.rows_affected() == 0 {
+    return Err(format!("No listing found with id {}", listing_id));
+  }
+
+  Ok(())
+}
+
+/// Listings within `km` of a saved place, nearest first
+#[tauri::command]
+pub async fn listings_within(place_id: i64, km: f64) -> Result<Vec<ListingWithinDistance>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let place = fetch_place(pool, place_id).await?;
+
+  let mut within: Vec<ListingWithinDistance> = fetch_located_listings(pool)
+    .await?
+    .into_iter()
+    .filter_map(|listing| {
+      let distance_km = haversine_km(
+        place.latitude,
+        place.longitude,
+        listing.latitude?,
+        listing.longitude?,
+      );
+      (distance_km <= km).then_some(ListingWithinDistance {
+        listing,
+        distance_km,
+      })
+    })
+    .collect();
+
+  within.sort_by(|a, b| a.distance_km.total_cmp(&b.distance_km));
+  Ok(within)
+}
+
+/// All listings with their distance to every saved place
+#[tauri::command]
+pub async fn get_listings_with_distances() -> Result<Vec<ListingWithDistances>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let places = fetch_places(pool).await?;
+  let rows = sqlx::query("SELECT * FROM listings ORDER BY created_at DESC")
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch listings: {}", e))?;
+
+  let listings = rows
+    .iter()
+    .map(listing_from_row)
+    .map(|listing| {
+      let mut distances: Vec<PlaceDistance> = match (listing.latitude, listing.longitude) {
+        (Some(lat), Some(lon)) => places
+          .iter()
+          .map(|place| PlaceDistance {
+            place_id: place.id.unwrap_or_default(),
+            name: place.name.clone(),
+            distance_km: haversine_km(place.latitude, place.longitude, lat, lon),
+          })
+          .collect(),
+        _ => Vec::new(),
+      };
+      distances.sort_by(|a, b| a.distance_km.total_cmp(&b.distance_km));
+      ListingWithDistances { listing, distances }
+    })
+    .collect();
+
+  Ok(listings)
+}
+
+/// Export listings (and optionally saved places) with coordinates as a GeoJSON FeatureCollection
+#[tauri::command]
+pub async fn export_listings_geojson(include_places: bool) -> Result<String, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let mut features: Vec<serde_json::Value> = fetch_located_listings(pool)
+    .await?
+    .iter()
+    .filter_map(|listing| {
+      Some(json!({
+        "type": "Feature",
+        // GeoJSON positions are [longitude, latitude]
+        "geometry": { "type": "Point", "coordinates": [listing.longitude?, listing.latitude?] },
+        "properties": {
+          "kind": "listing",
+          "listing_id": listing.id,
+          "address": listing.address,
+          "price_rent": listing.price_rent,
+          "bedrooms": listing.bedrooms,
+          "bathrooms": listing.bathrooms,
+          "favorite": listing.favorite,
+          "source_link": listing.source_link,
+        },
+      }))
+    })
+    .collect();
+
+  if include_places {
+    features.extend(fetch_places(pool).await?.iter().map(|place| {
+      json!({
+        "type": "Feature",
+        "geometry": { "type": "Point", "coordinates": [place.longitude, place.latitude] },
+        "properties": {
+          "kind": "place",
+          "place_id": place.id,
+          "name": place.name,
+          "place_kind": place.kind,
+          "address": place.address,
+        },
+      })
+    }));
+  }
+
+  serde_json::to_string_pretty(&json!({
+    "type": "FeatureCollection",
+    "features": features,
+  }))
+  .map_err(|e| format!("Failed to serialize GeoJSON: {}", e))
+}
+
+/// Import listing coordinates from a GeoJSON FeatureCollection of points. Features are
+/// matched to listings by a `listing_id` property, or by an exact `address` property.
+/// Returns the number of listings updated.
+#[tauri::command]
+pub async fn import_listing_coordinates(geojson: String) -> Result<u64, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let collection: serde_json::Value =
+    serde_json::from_str(&geojson).map_err(|e| format!("Invalid GeoJSON: {}", e))?;
+  // Check every feature before changing anything, so a bad one doesn't leave half the
+  // listings updated
+  let updates = coordinate_updates(&collection)?;
+
+  let mut tx = pool
+    .begin()
+    .await
+    .map_err(|e| format!("Failed to start transaction: {}", e))?;
+  let mut updated = 0;
+  for (listing, lat, lon) in updates {
+    let query = match listing {
+      ListingMatch::Id(id) => {
+        sqlx::query("UPDATE listings SET latitude = ?, longitude = ? WHERE id = ?")
+          .bind(lat)
+          .bind(lon)
+          .bind(id)
+      }
+      ListingMatch::Address(address) => {
+        sqlx::query("UPDATE listings SET latitude = ?, longitude = ? WHERE address = ?")
+          .bind(lat)
+          .bind(lon)
+          .bind(address)
+      }
+    };
+    updated += query
+      .execute(&mut *tx)
+      .await
+      .map_err(|e| format!("Failed to update listing location: {}", e))?
+      .rows_affected();
+  }
+  tx.commit()
+    .await
+    .map_err(|e| format!("Failed to update listing locations: {}", e))?;
+
+  Ok(updated)
+}
+
+/// The listing and coordinates of each point feature that names a listing. Fails on the first
+/// feature with out of range coordinates.
+fn coordinate_updates(
+  collection: &serde_json::Value,
+) -> Result<Vec<(ListingMatch<'_>, f64, f64)>, String> {
+  let features = collection["features"]
+    .as_array()
+    .ok_or("GeoJSON must be a FeatureCollection")?;
+
+  let mut updates = Vec::new();
+  for feature in features {
+    if feature["geometry"]["type"] != "Point" {
+      continue;
+    }
+    let coordinates = &feature["geometry"]["coordinates"];
+    let (Some(lon), Some(lat)) = (coordinates[0].as_f64(), coordinates[1].as_f64()) else {
+      continue;
+    };
+    validate_coordinates(lat, lon)?;
+
+    let properties = &feature["properties"];
+    if let Some(id) = properties["listing_id"].as_i64() {
+      updates.push((ListingMatch::Id(id), lat, lon));
+    } else if let Some(address) = properties["address"].as_str() {
+      updates.push((ListingMatch::Address(address), lat, lon));
+    }
+  }
+  Ok(updates)
+}
+
+/// Great-circle distance between two coordinates in kilometres
+pub(crate) fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+  let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+  let d_lat = lat2 - lat1;
+  let d_lon = (lon2 - lon1).to_radians();
+
+  let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+  2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+/// Coordinates of a listing, which may be unset but only both at once
+pub(crate) fn validate_location(
+  latitude: Option<f64>,
+  longitude: Option<f64>,
+) -> Result<(), String> {
+  match (latitude, longitude) {
+    (Some(lat), Some(lon)) => validate_coordinates(lat, lon),
+    (None, None) => Ok(()),
+    _ => Err("Latitude and longitude must be set together".to_string()),
+  }
+}
+
+fn validate_coordinates(latitude: f64, longitude: f64) -> Result<(), String> {
+  if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+    return Err(format!(
+      "Invalid coordinates: {}, {} (latitude must be within ±90, longitude within ±180)",
+      latitude, longitude
+    ));
+  }
+  Ok(())
+}
+
+async fn fetch_places(pool: &SqlitePool) -> Result<Vec<Place>, String> {
+  let rows = sqlx::query("SELECT * FROM places ORDER BY name")
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch places: {}", e))?;
+
+  Ok(rows.iter().map(place_from_row).collect())
+}
+
+async fn fetch_place(pool: &SqlitePool, id: i64) -> Result<Place, String> {
+  let row = sqlx::query("SELECT * FROM places WHERE id = ?")
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch place: {}", e))?
+    .ok_or_else(|| format!("No place found with id {}", id))?;
+
+  Ok(place_from_row(&row))
+}
+
+async fn fetch_located_listings(pool: &SqlitePool) -> Result<Vec<Listing>, String> {
+  let rows =
+    sqlx::query("SELECT * FROM listings WHERE latitude IS NOT NULL AND longitude IS NOT NULL")
+      .fetch_all(pool)
+      .await
+      .map_err(|e| format!("Failed to fetch listings: {}", e))?;
+
+  Ok(rows.iter().map(listing_from_row).collect())
+}
+
+fn place_from_row(row: &SqliteRow) -> Place {
+  Place {
+    id: row.try_get("id").ok(),
+    name: row.try_get("name").unwrap_or_default(),
+    kind: row.try_get("kind").ok(),
+    address: row.try_get("address").ok(),
+    latitude: row.try_get("latitude").unwrap_or_default(),
+    longitude: row.try_get("longitude").unwrap_or_default(),
+    created_at: row.try_get("created_at").ok(),
+    updated_at: row.try_get("updated_at").ok(),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn point(lon: f64, lat: f64, properties: serde_json::Value) -> serde_json::Value {
+    json!({
+      "type": "Feature",
+      "geometry": { "type": "Point", "coordinates": [lon, lat] },
+      "properties": properties,
+    })
+  }
+
+  #[test]
+  fn haversine_matches_known_distances() {
+    assert_eq!(haversine_km(51.5, -0.12, 51.5, -0.12), 0.0);
+    // London to Paris
+    let london_paris = haversine_km(51.5074, -0.1278, 48.8566, 2.3522);
+    assert!((london_paris - 343.6).abs() < 1.0, "{}", london_paris);
+    // New York to Los Angeles
+    let ny_la = haversine_km(40.7128, -74.0060, 34.0522, -118.2437);
+    assert!((ny_la - 3936.0).abs() < 5.0, "{}", ny_la);
+    // A degree of latitude, and the same distance either way
+    assert!((haversine_km(0.0, 0.0, 1.0, 0.0) - 111.2).abs() < 0.1);
+    assert_eq!(
+      haversine_km(10.0, 20.0, -30.0, 40.0),
+      haversine_km(-30.0, 40.0, 10.0, 20.0)
+    );
+    // Across the antimeridian
+    assert!((haversine_km(0.0, 179.5, 0.0, -179.5) - 111.2).abs() < 0.1);
+  }
+
+  #[test]
+  fn locations_must_be_in_range_and_complete() {
+    assert!(validate_location(None, None).is_ok());
+    assert!(validate_location(Some(90.0), Some(-180.0)).is_ok());
+    assert!(validate_location(Some(-33.87), Some(151.21)).is_ok());
+    assert!(validate_location(Some(90.5), Some(0.0)).is_err());
+    assert!(validate_location(Some(0.0), Some(180.5)).is_err());
+    assert!(validate_location(Some(-91.0), Some(-181.0)).is_err());
+    assert!(validate_location(Some(f64::NAN), Some(0.0)).is_err());
+    assert!(validate_location(Some(40.0), None).is_err());
+    assert!(validate_location(None, Some(-74.0)).is_err());
+  }
+
+  #[test]
+  fn geojson_points_name_their_listing() {
+    let collection = json!({
+      "type": "FeatureCollection",
+      "features": [
+        point(-87.63, 41.88, json!({ "listing_id": 3, "address": "ignored" })),
+        point(-122.42, 37.77, json!({ "address": "1 Main St" })),
+        point(2.35, 48.86, json!({ "name": "no listing" })),
+        {
+          "type": "Feature",
+          "geometry": { "type": "LineString", "coordinates": [[0.0, 0.0], [1.0, 1.0]] },
+          "properties": { "listing_id": 4 },
+        },
+      ],
+    });
+    assert_eq!(
+      coordinate_updates(&collection).unwrap(),
+      [
+        (ListingMatch::Id(3), 41.88, -87.63),
+        (ListingMatch::Address("1 Main St"), 37.77, -122.42),
+      ]
+    );
+  }
+
+  #[test]
+  fn geojson_with_bad_coordinates_is_rejected() {
+    // One bad feature rejects the whole import
+    let out_of_range = json!({
+      "type": "FeatureCollection",
+      "features": [
+        point(-87.63, 41.88, json!({ "listing_id": 1 })),
+        point(-187.63, 41.88, json!({ "listing_id": 2 })),
+      ],
+    });
+    assert!(coordinate_updates(&out_of_range).is_err());
+
+    assert!(coordinate_updates(&point(0.0, 0.0, json!({ "listing_id": 1 }))).is_err());
+    assert!(coordinate_updates(&json!([])).is_err());
+  }
+}
@@ -4,9 +4,12 @@
 // Adjust the path as necessary
 use crate::AdditionalInfoItem;
 use crate::IncomeSource;
+use crate::PersonalReference;
+use crate::RentalHistoryEntry;
 // use crate::MonthlyIncome;
 use crate::DB_POOL;
-use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, SqlitePool};
 
 // Income Sources
 #[tauri::command]
@@ -78,6 +81,7 @@ pub async fn delete_income_source(id: i64) -> Result<(), String> {
 
 // Monthly Income
 #[tauri::command]
+#[rustfmt::skip]
 pub async fn get_monthly_income(profile_id: i64) -> Result<Option<i64>, String> {
     let pool_guard = DB_POOL.read().await;
     let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
@@ -261,3 +265,214 @@ pub async fn delete_additional_info(id: i64) -> Result<(), String> {
 }
 
 // End Additional Info
+
+// Rental History
+#[tauri::command]
+pub async fn get_rental_history() -> Result<Vec<RentalHistoryEntry>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  fetch_rental_history(pool).await
+}
+
+#[tauri::command]
+pub async fn add_rental_history(entry: RentalHistoryEntry) -> Result<i64, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let result = sqlx::query(
+    r#"
+    INSERT INTO rental_history
+      (address, landlord_name, landlord_contact, monthly_rent, moved_in, moved_out, reason_for_leaving)
+    VALUES (?, ?, ?, ?, ?, ?, ?)
+    "#,
+  )
+  .bind(&entry.address)
+  .bind(&entry.landlord_name)
+  .bind(&entry.landlord_contact)
+  .bind(entry.monthly_rent)
+  .bind(&entry.moved_in)
+  .bind(&entry.moved_out)
+  .bind(&entry.reason_for_leaving)
+  .execute(pool)
+  .await
+  .map_err(|e| format!("Failed to insert rental history: {}", e))?;
+
+  Ok(result.last_insert_rowid())
+}
+
+#[tauri::command]
+pub async fn update_rental_history(id: i64, entry: RentalHistoryEntry) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let result = sqlx::query(
+    r#"
+    UPDATE rental_history
+    SET address = ?, landlord_name = ?, landlord_contact = ?, monthly_rent = ?,
+        moved_in = ?, moved_out = ?, reason_for_leaving = ?, updated_at = CURRENT_TIMESTAMP
+    WHERE id = ?
+    "#,
+  )
+  .bind(&entry.address)
+  .bind(&entry.landlord_name)
+  .bind(&entry.landlord_contact)
+  .bind(entry.monthly_rent)
+  .bind(&entry.moved_in)
+  .bind(&entry.moved_out)
+  .bind(&entry.reason_for_leaving)
+  .bind(id)
+  .execute(pool)
+  .await
+  .map_err(|e| format!("Failed to update rental history: {}", e))?;
+
+  if result.rows_affected() == 0 {
+    return Err(format!("No rental history found with id {}", id));
+  }
+
+  Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_rental_history(id: i64) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  sqlx::query("DELETE FROM rental_history WHERE id = ?")
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to delete rental history: {}", e))?;
+
+  Ok(())
+}
+
+/// Rental history, current home first
+pub(crate) async fn fetch_rental_history(
+  pool: &SqlitePool,
+) -> Result<Vec<RentalHistoryEntry>, String> {
+  let rows = sqlx::query(
+    "SELECT * FROM rental_history ORDER BY moved_out IS NOT NULL, moved_out DESC, moved_in DESC",
+  )
+  .fetch_all(pool)
+  .await
+  .map_err(|e| format!("Failed to fetch rental history: {}", e))?;
+
+  Ok(rows.iter().map(rental_history_from_row).collect())
+}
+
+fn rental_history_from_row(row: &SqliteRow) -> RentalHistoryEntry {
+  RentalHistoryEntry {
+    id: row.try_get("id").ok(),
+    address: row.try_get("address").unwrap_or_default(),
+    landlord_name: row.try_get("landlord_name").ok().flatten(),
+    landlord_contact: row.try_get("landlord_contact").ok().flatten(),
+    monthly_rent: row.try_get("monthly_rent").ok().flatten(),
+    moved_in: row.try_get("moved_in").ok().flatten(),
+    moved_out: row.try_get("moved_out").ok().flatten(),
+    reason_for_leaving: row.try_get("reason_for_leaving").ok().flatten(),
+    created_at: row.try_get("created_at").ok(),
+    updated_at: row.try_get("updated_at").ok(),
+  }
+}
+
+// End Rental History
+
+// References
+#[tauri::command]
+pub async fn get_personal_references() -> Result<Vec<PersonalReference>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  fetch_personal_references(pool).await
+}
+
+#[tauri::command]
+pub async fn add_personal_reference(reference: PersonalReference) -> Result<i64, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let result = sqlx::query(
+    "INSERT INTO personal_references (name, relationship, phone, email) VALUES (?, ?, ?, ?)",
+  )
+  .bind(&reference.name)
+  .bind(&reference.relationship)
+  .bind(&reference.phone)
+  .bind(&reference.email)
+  .execute(pool)
+  .await
+  .map_err(|e| format!("Failed to insert reference: {}", e))?;
+
+  Ok(result.last_insert_rowid())
+}
+
+#[tauri::command]
+pub async fn update_personal_reference(
+  id: i64,
+  reference: PersonalReference,
+) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let result = sqlx::query(
+    r#"
+    UPDATE personal_references
+    SET name = ?, relationship = ?, phone = ?, email = ?, updated_at = CURRENT_TIMESTAMP
+    WHERE id = ?
+    "#,
+  )
+  .bind(&reference.name)
+  .bind(&reference.relationship)
+  .bind(&reference.phone)
+  .bind(&reference.email)
+  .bind(id)
+  .execute(pool)
+  .await
+  .map_err(|e| format!("Failed to update reference: {}", e))?;
+
+  if result.rows_affected() == 0 {
+    return Err(format!("No reference found with id {}", id));
+  }
+
+  Ok(())
+}
+
+#[tauri::command]
+pub async fn delete_personal_reference(id: i64) -> Result<(), String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  sqlx::query("DELETE FROM personal_references WHERE id = ?")
+    .bind(id)
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Failed to delete reference: {}", e))?;
+
+  Ok(())
+}
+
+pub(crate) async fn fetch_personal_references(
+  pool: &SqlitePool,
+) -> Result<Vec<PersonalReference>, String> {
+  let rows = sqlx::query("SELECT * FROM personal_references ORDER BY id")
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch references: {}", e))?;
+
+  Ok(
+    rows
+      .iter()
+      .map(|row| PersonalReference {
+        id: row.try_get("id").ok(),
+        name: row.try_get("name").unwrap_or_default(),
+        relationship: row.try_get("relationship").ok().flatten(),
+        phone: row.try_get("phone").ok().flatten(),
+        email: row.try_get("email").ok().flatten(),
+        created_at: row.try_get("created_at").ok(),
+        updated_at: row.try_get("updated_at").ok(),
+      })
+      .collect(),
+  )
+}
+
+// End References
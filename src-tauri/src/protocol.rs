@@ -0,0 +1,407 @@
+//! `sase://` URI scheme serving decrypted documents and photos straight from the vault, so the
+//! webview can use plain `<img>`/`<iframe>` URLs instead of shipping bytes over IPC.
+//!
+//! - `sase://document/<id>` — document contents, with `Range` support
+//! - `sase://thumbnail/<photo_id>?size=small|medium` — listing photo thumbnail
+//! - `sase://photo/<photo_id>` — full resolution listing photo
+//!
+//! On Windows and Android the same paths are served from `http://sase.localhost/...`.
+
+use crate::DB_POOL;
+use sqlx::{Row, SqlitePool};
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::UriSchemeResponder;
+
+pub const SCHEME: &str = "sase";
+
+type HttpResponse = Response<Vec<u8>>;
+
+/// Entry point registered with `register_asynchronous_uri_scheme_protocol`
+pub fn handle_request(request: Request<Vec<u8>>, responder: UriSchemeResponder) {
+  tauri::async_runtime::spawn(async move {
+    responder.respond(serve(&request).await);
+  });
+}
+
+/// A blob to serve, without its contents loaded
+struct Resource {
+  /// Contents stored in `blobs` under this hash. Otherwise they are read from `column` of the
+  /// row `id` in `table`.
+  blob_hash: Option<String>,
+  table: &'static str,
+  column: &'static str,
+  id: i64,
+  mime_type: String,
+  etag: String,
+  filename: Option<String>,
+}
+
+async fn serve(request: &Request<Vec<u8>>) -> HttpResponse {
+  let pool_guard = DB_POOL.read().await;
+  let Some(pool) = pool_guard.as_ref() else {
+    return error(StatusCode::FORBIDDEN, "Vault is locked");
+  };
+
+  let segments = path_segments(request);
+  let query = request.uri().query().unwrap_or("");
+  let resource = match segments.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+    ["document", id] => match id.parse() {
+      Ok(id) => find_document(pool, id).await,
+      Err(_) => return error(StatusCode::BAD_REQUEST, "Invalid document id"),
+    },
+    ["thumbnail", id] => {
+      let column = if query.split('&').any(|p| p == "size=medium") {
+        "thumbnail_medium"
+      } else {
+        "thumbnail_small"
+      };
+      match id.parse() {
+        Ok(id) => find_photo(pool, id, column).await,
+        Err(_) => return error(StatusCode::BAD_REQUEST, "Invalid photo id"),
+      }
+    }
+    ["photo", id] => match id.parse() {
+      Ok(id) => find_photo(pool, id, "data").await,
+      Err(_) => return error(StatusCode::BAD_REQUEST, "Invalid photo id"),
+    },
+    _ => return error(StatusCode::NOT_FOUND, "Unknown resource"),
+  };
+
+  let resource = match resource {
+    Ok(Some(resource)) => resource,
+    Ok(None) => return error(StatusCode::NOT_FOUND, "Not found"),
+    Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+  };
+
+  let etag = format!("\"{}\"", resource.etag);
+  let not_modified = request
+    .headers()
+    .get(header::IF_NONE_MATCH)
+    .and_then(|v| v.to_str().ok())
+    .is_some_and(|v| v.split(',').any(|tag| tag.trim() == etag));
+  if not_modified {
+    return Response::builder()
+      .status(StatusCode::NOT_MODIFIED)
+      .header(header::ETAG, &etag)
+      .body(Vec::new())
+      .unwrap_or_default();
+  }
+
+  let total = match blob_length(pool, &resource).await {
+    Ok(total) => total,
+    Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+  };
+
+  let range = request
+    .headers()
+    .get(header::RANGE)
+    .and_then(|v| v.to_str().ok());
+
+  let (status, start, end) = match select_range(range, total) {
+    Some(selected) => selected,
+    None => {
+      return Response::builder()
+        .status(StatusCode::RANGE_NOT_SATISFIABLE)
+        .header(header::CONTENT_RANGE, format!("bytes */{}", total))
+        .body(Vec::new())
+        .unwrap_or_default();
+    }
+  };
+
+  let body = if total == 0 {
+    Vec::new()
+  } else {
+    match read_blob_range(pool, &resource, start, end - start + 1).await {
+      Ok(body) => body,
+      Err(e) => return error(StatusCode::INTERNAL_SERVER_ERROR, &e),
+    }
+  };
+
+  let mut builder = Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, &resource.mime_type)
+    .header(header::CONTENT_LENGTH, body.len())
+    .header(header::ACCEPT_RANGES, "bytes")
+    .header(header::ETAG, &etag)
+    // Decrypted content must never end up in a shared or on-disk cache
+    .header(header::CACHE_CONTROL, "private, no-store")
+    .header("X-Content-Type-Options", "nosniff");
+
+  if status == StatusCode::PARTIAL_CONTENT {
+    builder = builder.header(
+      header::CONTENT_RANGE,
+      format!("bytes {}-{}/{}", start, end, total),
+    );
+  }
+  if let Some(filename) = &resource.filename {
+    // Header values must be ASCII; non-ASCII characters are replaced
+    let ascii: String = filename
+      .chars()
+      .map(|c| {
+        if c.is_ascii_graphic() || c == ' ' {
+          c
+        } else {
+          '_'
+        }
+      })
+      .filter(|c| *c != '"')
+      .collect();
+    builder = builder.header(
+      header::CONTENT_DISPOSITION,
+      format!("inline; filename=\"{}\"", ascii),
+    );
+  }
+
+  builder
+    .body(body)
+    .unwrap_or_else(|e| error(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))
+}
+
+/// Path segments of the request. `sase://document/5` has the resource kind as its host,
+/// while `http://sase.localhost/document/5` (and `sase://localhost/document%2F5` as produced
+/// by `convertFileSrc`) carry it in the path.
+fn path_segments(request: &Request<Vec<u8>>) -> Vec<String> {
+  let uri = request.uri();
+  let mut segments = Vec::new();
+
+  if let Some(host) = uri.host() {
+    if host != "localhost" && host != "sase.localhost" {
+      segments.push(host.to_string());
+    }
+  }
+
+  let path = uri.path().replace("%2F", "/").replace("%2f", "/");
+  segments.extend(
+    path
+      .split('/')
+      .filter(|s| !s.is_empty())
+      .map(str::to_string),
+  );
+  segments
+}
+
+/// Status and inclusive byte span to serve for an optional `Range` header. Returns `None` when
+/// the range cannot be satisfied.
+fn select_range(range: Option<&str>, total: u64) -> Option<(StatusCode, u64, u64)> {
+  match range {
+    None => Some((StatusCode::OK, 0, total.saturating_sub(1))),
+    Some(range) => {
+      let (start, end) = parse_range(range, total)?;
+      Some((StatusCode::PARTIAL_CONTENT, start, end))
+    }
+  }
+}
+
+/// Parse a single `bytes=start-end` range. Returns `None` when it cannot be satisfied.
+fn parse_range(value: &str, total: u64) -> Option<(u64, u64)> {
+  let spec = value.trim().strip_prefix("bytes=")?;
+  // Multiple ranges are not supported, serve the first one
+  let spec = spec.split(',').next()?.trim();
+  let (start, end) = spec.split_once('-')?;
+
+  if total == 0 {
+    return None;
+  }
+
+  let (start, end) = match (start.trim(), end.trim()) {
+    // Suffix range: the last N bytes
+    ("", suffix) => {
+      let suffix: u64 = suffix.parse().ok()?;
+      if suffix == 0 {
+        return None;
+      }
+      (total.saturating_sub(suffix), total - 1)
+    }
+    (start, "") => (start.parse().ok()?, total - 1),
+    (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?.min(total - 1)),
+  };
+
+  (start <= end && start < total).then_some((start, end))
+}
+
+async fn find_document(pool: &SqlitePool, id: i64) -> Result<Option<Resource>, String> {
+  let row = sqlx::query("SELECT name, mime_type, hash, updated_at FROM documents WHERE id = ?")
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch document: {}", e))?;
+
+  Ok(row.map(|row| {
+    let hash: Option<String> = row.try_get("hash").ok();
+    let updated_at: Option<String> = row.try_get("updated_at").ok();
+    Resource {
+      blob_hash: hash.clone(),
+      table: "documents",
+      column: "data",
+      id,
+      mime_type: row
+        .try_get::<Option<String>, _>("mime_type")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "application/octet-stream".to_string()),
+      etag: hash.unwrap_or_else(|| format!("document-{}-{}", id, updated_at.unwrap_or_default())),
+      filename: row.try_get("name").ok(),
+    }
+  }))
+}
+
+async fn find_photo(
+  pool: &SqlitePool,
+  id: i64,
+  column: &'static str,
+) -> Result<Option<Resource>, String> {
+  let row = sqlx::query("SELECT mime_type, hash, updated_at FROM listing_photos WHERE id = ?")
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch photo: {}", e))?;
+
+  Ok(row.map(|row| {
+    let updated_at: String = row.try_get("updated_at").unwrap_or_default();
+    // Originals live in blobs, thumbnails in the photo row
+    let blob_hash: Option<String> = if column == "data" {
+      row.try_get("hash").ok()
+    } else {
+      None
+    };
+    Resource {
+      etag: match &blob_hash {
+        Some(hash) => hash.clone(),
+        None => format!("photo-{}-{}-{}", id, column, updated_at.replace(' ', "T")),
+      },
+      blob_hash,
+      table: "listing_photos",
+      column,
+      id,
+      // Thumbnails are always generated as JPEG
+      mime_type: if column == "data" {
+        row.try_get("mime_type").unwrap_or_default()
+      } else {
+        "image/jpeg".to_string()
+      },
+      filename: None,
+    }
+  }))
+}
+
+async fn blob_length(pool: &SqlitePool, resource: &Resource) -> Result<u64, String> {
+  let length: Option<i64> = match &resource.blob_hash {
+    Some(hash) => {
+      sqlx::query_scalar("SELECT size FROM blobs WHERE hash = ?")
+        .bind(hash)
+        .fetch_optional(pool)
+        .await
+    }
+    None => sqlx::query_scalar(&format!(
+      "SELECT length({}) FROM {} WHERE id = ?",
+      resource.column, resource.table
+    ))
+    .bind(resource.id)
+    .fetch_one(pool)
+    .await
+    .map(Some),
+  }
+  .map_err(|e| format!("Failed to read blob length: {}", e))?
+  .flatten();
+
+  Ok(length.unwrap_or(0).max(0) as u64)
+}
+
+async fn read_blob_range(
+  pool: &SqlitePool,
+  resource: &Resource,
+  start: u64,
+  length: u64,
+) -> Result<Vec<u8>, String> {
+  // substr() is 1-based and reads only the requested part of the blob
+  let data: Option<Vec<u8>> = match &resource.blob_hash {
+    Some(hash) => {
+      sqlx::query_scalar("SELECT substr(data, ?, ?) FROM blobs WHERE hash = ?")
+        .bind(start as i64 + 1)
+        .bind(length as i64)
+        .bind(hash)
+        .fetch_optional(pool)
+        .await
+    }
+    None => sqlx::query_scalar(&format!(
+      "SELECT substr({}, ?, ?) FROM {} WHERE id = ?",
+      resource.column, resource.table
+    ))
+    .bind(start as i64 + 1)
+    .bind(length as i64)
+    .bind(resource.id)
+    .fetch_one(pool)
+    .await
+    .map(Some),
+  }
+  .map_err(|e| format!("Failed to read blob: {}", e))?
+  .flatten();
+
+  Ok(data.unwrap_or_default())
+}
+
+fn error(status: StatusCode, message: &str) -> HttpResponse {
+  Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+    .header(header::CACHE_CONTROL, "no-store")
+    .body(message.as_bytes().to_vec())
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ranges_are_clamped_to_the_content() {
+    assert_eq!(parse_range("bytes=0-", 100), Some((0, 99)));
+    assert_eq!(parse_range("bytes=10-19", 100), Some((10, 19)));
+    assert_eq!(parse_range("bytes=-10", 100), Some((90, 99)));
+    assert_eq!(parse_range("bytes=-500", 100), Some((0, 99)));
+    assert_eq!(parse_range("bytes=90-500", 100), Some((90, 99)));
+    // Only the first of several ranges is served
+    assert_eq!(parse_range("bytes=0-9, 50-59", 100), Some((0, 9)));
+  }
+
+  #[test]
+  fn unsatisfiable_and_malformed_ranges_are_rejected() {
+    for range in [
+      "bytes=5-3",
+      "bytes=100-",
+      "bytes=-0",
+      "bytes=abc-",
+      "bytes=1-x",
+      "bytes=5",
+      "items=0-9",
+      "",
+    ] {
+      assert_eq!(parse_range(range, 100), None, "{}", range);
+    }
+    assert_eq!(parse_range("bytes=0-", 0), None);
+  }
+
+  #[test]
+  fn ranges_map_to_partial_content_or_not_satisfiable() {
+    assert_eq!(select_range(None, 100), Some((StatusCode::OK, 0, 99)));
+    assert_eq!(select_range(None, 0), Some((StatusCode::OK, 0, 0)));
+    assert_eq!(
+      select_range(Some("bytes=0-"), 100),
+      Some((StatusCode::PARTIAL_CONTENT, 0, 99))
+    );
+    assert_eq!(
+      select_range(Some("bytes=-10"), 100),
+      Some((StatusCode::PARTIAL_CONTENT, 90, 99))
+    );
+    assert_eq!(
+      select_range(Some("bytes=90-500"), 100),
+      Some((StatusCode::PARTIAL_CONTENT, 90, 99))
+    );
+    assert_eq!(
+      select_range(Some("bytes=0-9,50-59"), 100),
+      Some((StatusCode::PARTIAL_CONTENT, 0, 9))
+    );
+    assert_eq!(select_range(Some("bytes=5-3"), 100), None);
+    assert_eq!(select_range(Some("bytes=oops"), 100), None);
+  }
+}
@@ -0,0 +1,250 @@
+use crate::helpers::resume_pdf::{
+  render_resume, RentalResume, ResumeEntry, ResumeOptions, ResumeSection,
+};
+use crate::profile::{fetch_personal_references, fetch_rental_history};
+use crate::DB_POOL;
+use sqlx::{Row, SqlitePool};
+
+/// Render the applicant's rental resume from their profile, income sources, rental history,
+/// references and additional info. With a listing, its address and rent are shown as the home
+/// applied for. Rendered locally with embedded fonts, so it works offline.
+#[tauri::command]
+pub async fn generate_rental_resume(
+  listing_id: Option<i64>,
+  options: Option<ResumeOptions>,
+) -> Result<Vec<u8>, String> {
+  let pool_guard = DB_POOL.read().await;
+  let pool = pool_guard.as_ref().ok_or("Database not initialized")?;
+
+  let resume = load_resume(pool, listing_id).await?;
+  let options = options.unwrap_or_default();
+
+  tokio::task::spawn_blocking(move || render_resume(&resume, &options))
+    .await
+    .map_err(|e| format!("Resume render task failed: {}", e))?
+    .map_err(|e| format!("Failed to render rental resume: {}", e))
+}
+
+async fn load_resume(pool: &SqlitePool, listing_id: Option<i64>) -> Result<RentalResume, String> {
+  let profile = sqlx::query("SELECT * FROM profile WHERE id = 1")
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch profile: {}", e))?;
+  let text = |column: &str| {
+    profile
+      .as_ref()
+      .and_then(|row| row.try_get::<Option<String>, _>(column).ok().flatten())
+      .and_then(non_blank)
+  };
+
+  let name = text("fullname").unwrap_or_else(|| "Rental applicant".to_string());
+  let contact = ["phone", "email", "address"]
+    .into_iter()
+    .filter_map(&text)
+    .collect();
+
+  let mut applicant = ResumeEntry::default();
+  push_field(&mut applicant, "Date of birth", text("date_of_birth"));
+  let income = profile
+    .as_ref()
+    .and_then(|row| {
+      row
+        .try_get::<Option<i64>, _>("monthly_income")
+        .ok()
+        .flatten()
+    })
+    .filter(|income| *income > 0);
+  push_field(
+    &mut applicant,
+    "Monthly income",
+    income.map(|income| format!("${}", income)),
+  );
+
+  let mut sections = Vec::new();
+  if !applicant.fields.is_empty() {
+    sections.push(ResumeSection {
+      title: "Applicant".to_string(),
+      entries: vec![applicant],
+    });
+  }
+  sections.extend(income_sections(pool).await?);
+  sections.extend(rental_history_section(pool).await?);
+  sections.extend(references_section(pool).await?);
+  sections.extend(additional_info_section(pool).await?);
+
+  let applying_for = match listing_id {
+    Some(listing_id) => Some(listing_summary(pool, listing_id).await?),
+    None => None,
+  };
+  let generated_on: String = sqlx::query_scalar("SELECT date('now', 'localtime')")
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Failed to read the current date: {}", e))?;
+
+  Ok(RentalResume {
+    name,
+    contact,
+    applying_for,
+    sections,
+    generated_on,
+  })
+}
+
+/// Income sources with an employer or job title as employment, the rest as other income
+async fn income_sections(pool: &SqlitePool) -> Result<Vec<ResumeSection>, String> {
+  let rows = sqlx::query("SELECT * FROM income_sources ORDER BY id")
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch income sources: {}", e))?;
+  let text = |row: &sqlx::sqlite::SqliteRow, column: &str| {
+    row
+      .try_get::<Option<String>, _>(column)
+      .ok()
+      .flatten()
+      .and_then(non_blank)
+  };
+
+  let mut income = ResumeEntry::default();
+  let mut employment = Vec::new();
+  for row in &rows {
+    let source = text(row, "source");
+    let heading = match (text(row, "employer_name"), text(row, "job_title")) {
+      (Some(employer), Some(job_title)) => format!("{} - {}", employer, job_title),
+      (Some(heading), None) | (None, Some(heading)) => heading,
+      (None, None) => {
+        push_field(&mut income, "Income source", source);
+        continue;
+      }
+    };
+
+    let mut entry = ResumeEntry {
+      heading: Some(heading),
+      detail: text(row, "employment_length"),
+      ..Default::default()
+    };
+    push_field(&mut entry, "Contact", text(row, "employer_contact"));
+    push_field(&mut entry, "Income source", source);
+    employment.push(entry);
+  }
+
+  let mut sections = Vec::new();
+  if !employment.is_empty() {
+    sections.push(ResumeSection {
+      title: "Employment".to_string(),
+      entries: employment,
+    });
+  }
+  if !income.fields.is_empty() {
+    sections.push(ResumeSection {
+      title: "Other income".to_string(),
+      entries: vec![income],
+    });
+  }
+  Ok(sections)
+}
+
+async fn rental_history_section(pool: &SqlitePool) -> Result<Option<ResumeSection>, String> {
+  let entries: Vec<ResumeEntry> = fetch_rental_history(pool)
+    .await?
+    .into_iter()
+    .filter_map(|home| {
+      let address = non_blank(home.address)?;
+      let moved_in = home.moved_in.and_then(non_blank);
+      let moved_out = home.moved_out.and_then(non_blank);
+      let detail = match (moved_in, moved_out) {
+        (Some(moved_in), Some(moved_out)) => Some(format!("{} - {}", moved_in, moved_out)),
+        (Some(moved_in), None) => Some(format!("{} - present", moved_in)),
+        (None, Some(moved_out)) => Some(format!("until {}", moved_out)),
+        (None, None) => None,
+      };
+
+      let mut entry = ResumeEntry {
+        heading: Some(address),
+        detail,
+        ..Default::default()
+      };
+      push_field(&mut entry, "Landlord", home.landlord_name);
+      push_field(&mut entry, "Landlord contact", home.landlord_contact);
+      push_field(
+        &mut entry,
+        "Monthly rent",
+        home.monthly_rent.map(|rent| format!("${:.2}", rent)),
+      );
+      push_field(&mut entry, "Reason for leaving", home.reason_for_leaving);
+      Some(entry)
+    })
+    .collect();
+
+  Ok((!entries.is_empty()).then(|| ResumeSection {
+    title: "Rental history".to_string(),
+    entries,
+  }))
+}
+
+async fn references_section(pool: &SqlitePool) -> Result<Option<ResumeSection>, String> {
+  let entries: Vec<ResumeEntry> = fetch_personal_references(pool)
+    .await?
+    .into_iter()
+    .filter_map(|reference| {
+      let mut entry = ResumeEntry {
+        heading: Some(non_blank(reference.name)?),
+        detail: reference.relationship.and_then(non_blank),
+        ..Default::default()
+      };
+      push_field(&mut entry, "Phone", reference.phone);
+      push_field(&mut entry, "Email", reference.email);
+      Some(entry)
+    })
+    .collect();
+
+  Ok((!entries.is_empty()).then(|| ResumeSection {
+    title: "References".to_string(),
+    entries,
+  }))
+}
+
+async fn additional_info_section(pool: &SqlitePool) -> Result<Option<ResumeSection>, String> {
+  let rows = sqlx::query("SELECT label, value FROM additional_info ORDER BY id")
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch additional info: {}", e))?;
+
+  let mut entry = ResumeEntry::default();
+  for row in rows {
+    if let Some(label) = row.try_get::<String, _>("label").ok().and_then(non_blank) {
+      push_field(&mut entry, &label, row.try_get("value").ok());
+    }
+  }
+
+  Ok((!entry.fields.is_empty()).then(|| ResumeSection {
+    title: "Additional information".to_string(),
+    entries: vec![entry],
+  }))
+}
+
+async fn listing_summary(pool: &SqlitePool, listing_id: i64) -> Result<String, String> {
+  let listing = sqlx::query("SELECT address, price_rent FROM listings WHERE id = ?")
+    .bind(listing_id)
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Failed to fetch listing: {}", e))?
+    .ok_or_else(|| format!("No listing found with id {}", listing_id))?;
+
+  let address: String = listing.try_get("address").unwrap_or_default();
+  Ok(match listing.try_get::<f64, _>("price_rent") {
+    Ok(rent) if rent > 0.0 => format!("{} (${:.2} / month)", address, rent),
+    _ => address,
+  })
+}
+
+/// Add a field, skipping blank values rather than printing empty lines
+fn push_field(entry: &mut ResumeEntry, label: &str, value: Option<String>) {
+  if let Some(value) = value.and_then(non_blank) {
+    entry.fields.push((label.to_string(), value));
+  }
+}
+
+fn non_blank(value: String) -> Option<String> {
+  let trimmed = value.trim();
+  (!trimmed.is_empty()).then(|| trimmed.to_string())
+}
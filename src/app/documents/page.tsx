@@ -33,6 +33,7 @@ import { Calendar } from "@/components/ui/calendar";
 import { ChevronDownIcon, Search, X, Filter } from "lucide-react";
 import {
     addDocument,
+    isDocumentError,
     readFileAsBlob,
     getDocuments,
     Document,
@@ -194,7 +195,21 @@ const Documents = () => {
                 is_completed: false,
             };
 
-            await addDocument(documentData);
+            try {
+                await addDocument(documentData);
+            } catch (error) {
+                if (!isDocumentError(error) || error.kind !== "duplicate") {
+                    throw error;
+                }
+                // Identical file already stored: link the existing document unless the
+                // user wants a separate entry (contents are stored only once either way)
+                const useExisting = window.confirm(
+                    `${error.message}. Use the existing document instead of adding a copy?`
+                );
+                if (!useExisting) {
+                    await addDocument(documentData, { allowDuplicate: true });
+                }
+            }
 
             const updatedDocs = await getDocuments();
             setDocuments(updatedDocs);
@@ -375,6 +390,27 @@ const Documents = () => {
                                         <SelectItem value="Listing">
                                             Listing
                                         </SelectItem>
+                                        <SelectItem value="Pay Stub">
+                                            Pay Stub
+                                        </SelectItem>
+                                        <SelectItem value="Proof of Income">
+                                            Proof of Income
+                                        </SelectItem>
+                                        <SelectItem value="Bank Statement">
+                                            Bank Statement
+                                        </SelectItem>
+                                        <SelectItem value="Credit Report">
+                                            Credit Report
+                                        </SelectItem>
+                                        <SelectItem value="Employment Verification">
+                                            Employment Verification
+                                        </SelectItem>
+                                        <SelectItem value="Background Check">
+                                            Background Check
+                                        </SelectItem>
+                                        <SelectItem value="ID">
+                                            ID
+                                        </SelectItem>
                                         <SelectItem value="General">
                                             General
                                         </SelectItem>
@@ -495,6 +531,27 @@ const Documents = () => {
                                     <SelectItem value="Listing">
                                         Listing
                                     </SelectItem>
+                                    <SelectItem value="Pay Stub">
+                                        Pay Stub
+                                    </SelectItem>
+                                    <SelectItem value="Proof of Income">
+                                        Proof of Income
+                                    </SelectItem>
+                                    <SelectItem value="Bank Statement">
+                                        Bank Statement
+                                    </SelectItem>
+                                    <SelectItem value="Credit Report">
+                                        Credit Report
+                                    </SelectItem>
+                                    <SelectItem value="Employment Verification">
+                                        Employment Verification
+                                    </SelectItem>
+                                    <SelectItem value="Background Check">
+                                        Background Check
+                                    </SelectItem>
+                                    <SelectItem value="ID">
+                                        ID
+                                    </SelectItem>
                                     <SelectItem value="General">
                                         General
                                     </SelectItem>
@@ -6,6 +6,7 @@ import React, { useState, useEffect } from "react";
 import {
     Document,
     createDocumentBlobUrl,
+    documentUrl,
     isImageDocument,
     isPdfDocument,
     formatFileSize,
@@ -56,19 +57,15 @@ const DocumentPreview: React.FC<DocumentPreviewProps> = ({
     onDownload,
     onDelete,
 }) => {
-    const [blobUrl, setBlobUrl] = useState<string | null>(null);
+    const [previewUrl, setPreviewUrl] = useState<string | null>(null);
     const [isImage, setIsImage] = useState(false);
 
     useEffect(() => {
-        const url = createDocumentBlobUrl(document);
-        setBlobUrl(url);
-        setIsImage(isImageDocument(document));
-
-        return () => {
-            if (url) {
-                URL.revokeObjectURL(url);
-            }
-        };
+        const image = isImageDocument(document);
+        setIsImage(image);
+        setPreviewUrl(
+            image && document.id !== undefined ? documentUrl(document.id) : null
+        );
     }, [document]);
 
     const getFileIcon = () => {
@@ -79,8 +76,9 @@ const DocumentPreview: React.FC<DocumentPreviewProps> = ({
     };
 
     const getFileSize = () => {
-        if (!document.data) return "Unknown size";
-        return formatFileSize(document.data.length);
+        const size = document.size ?? document.data?.length;
+        if (size === undefined) return "Unknown size";
+        return formatFileSize(size);
     };
 
     return (
@@ -132,13 +130,13 @@ const DocumentPreview: React.FC<DocumentPreviewProps> = ({
 
             <CardContent className="pt-0 flex-1 flex flex-col">
                 {/* Image Preview */}
-                {isImage && blobUrl && (
+                {isImage && previewUrl && (
                     <div className="mb-3">
                         <img
-                            src={blobUrl}
+                            src={previewUrl}
                             alt={document.name}
                             className="w-full h-32 object-cover rounded-md border"
-                            onError={() => setBlobUrl(null)}
+                            onError={() => setPreviewUrl(null)}
                         />
                     </div>
                 )}
@@ -198,27 +196,16 @@ const DocumentModal: React.FC<{
     document: Document | null;
     onClose: () => void;
 }> = ({ document, onClose }) => {
-    const [blobUrl, setBlobUrl] = useState<string | null>(null);
-
-    useEffect(() => {
-        if (document) {
-            const url = createDocumentBlobUrl(document);
-            setBlobUrl(url);
-
-            return () => {
-                if (url) {
-                    URL.revokeObjectURL(url);
-                }
-            };
-        }
-    }, [document]);
+    const viewUrl =
+        document?.id !== undefined ? documentUrl(document.id) : null;
 
     if (!document) return null;
 
     const isImage = isImageDocument(document);
     const isPdf = isPdfDocument(document);
 
-    const handleDownload = () => {
+    const handleDownload = async () => {
+        const blobUrl = await createDocumentBlobUrl(document);
         if (blobUrl) {
             const link = window.document.createElement("a");
             link.href = blobUrl;
@@ -226,6 +213,7 @@ const DocumentModal: React.FC<{
             window.document.body.appendChild(link);
             link.click();
             window.document.body.removeChild(link);
+            URL.revokeObjectURL(blobUrl);
 
             toast.success("File downloaded successfully!", {
                 description: `${document.name} has been saved to your Downloads folder`,
@@ -243,7 +231,7 @@ const DocumentModal: React.FC<{
                     <DialogTitle className="flex items-center justify-between mr-4">
                         <span>{document.name}</span>
                         <Button
-                            onClick={handleDownload}
+                            onClick={() => void handleDownload()}
                             size="sm"
                             className="cursor-pointer"
                         >
@@ -254,15 +242,15 @@ const DocumentModal: React.FC<{
                 </DialogHeader>
 
                 <div className="flex-1 overflow-auto">
-                    {isImage && blobUrl ? (
+                    {isImage && viewUrl ? (
                         <img
-                            src={blobUrl}
+                            src={viewUrl}
                             alt={document.name}
                             className="w-full h-auto max-h-[70vh] object-contain"
                         />
-                    ) : isPdf && blobUrl ? (
+                    ) : isPdf && viewUrl ? (
                         <iframe
-                            src={blobUrl}
+                            src={viewUrl}
                             className="w-full h-[70vh] border rounded"
                             title={document.name}
                         />
@@ -298,8 +286,8 @@ export const DocumentViewer: React.FC<DocumentViewerProps> = ({
         onDocumentSelect?.(document);
     };
 
-    const handleDownloadDocument = (document: Document) => {
-        const blobUrl = createDocumentBlobUrl(document);
+    const handleDownloadDocument = async (document: Document) => {
+        const blobUrl = await createDocumentBlobUrl(document);
         if (blobUrl) {
             const link = window.document.createElement("a");
             link.href = blobUrl;
@@ -372,7 +360,7 @@ export const DocumentViewer: React.FC<DocumentViewerProps> = ({
                         key={document.id}
                         document={document}
                         onView={() => handleViewDocument(document)}
-                        onDownload={() => handleDownloadDocument(document)}
+                        onDownload={() => void handleDownloadDocument(document)}
                         onDelete={() => handleDeleteDocument(document)}
                     />
                 ))}
@@ -8,11 +8,15 @@ import {
     getListing,
     getDocuments,
     type Document,
-    fetchPdfFromApi,
+    generateRentalResume,
     buildCombinedPdfWithSaseApi,
+    cancelPdfJob,
+    describePdfJobProgress,
     downloadPdf,
+    newPdfJobId,
+    onPdfJobProgress,
+    getExpiringDocuments,
 } from "@/utils/database";
-import { createClient } from "@/utils/supabase/client";
 import { useDatabaseContextSafe } from "@/components/DatabaseInitializer";
 import {
     Bed,
@@ -58,6 +62,8 @@ const ListingDetails: React.FC<ListingDetailsProps> = ({ listingId }) => {
         []
     );
     const [isPdfGenerating, setIsPdfGenerating] = useState(false);
+    const [pdfJobId, setPdfJobId] = useState<string | null>(null);
+    const [pdfProgress, setPdfProgress] = useState<string | null>(null);
 
     useEffect(() => {
         const fetchListing = async () => {
@@ -123,80 +129,57 @@ const ListingDetails: React.FC<ListingDetailsProps> = ({ listingId }) => {
             return;
         }
 
+        // Landlords reject stale documents; let the user back out before building the packet
+        const referencedIds = listing.reference_document_ids ?? [];
+        const expiring = await getExpiringDocuments(0).catch((error) => {
+            console.error(error);
+            return [];
+        });
+        const expired = expiring.filter(
+            (doc) => doc.expired && referencedIds.includes(doc.document_id)
+        );
+        if (
+            expired.length > 0 &&
+            !window.confirm(
+                `These documents are out of date:\n${expired
+                    .map((doc) => `- ${doc.name} (expired ${doc.valid_until})`)
+                    .join("\n")}\n\nInclude them anyway?`
+            )
+        ) {
+            return;
+        }
+
         setIsPdfGenerating(true);
 
         try {
-            const supabase = createClient();
-            const {
-                data: { session },
-            } = await supabase.auth.getSession();
-
-            if (!session?.access_token) {
-                throw new Error("No access token available");
-            }
-
-            console.log("Starting PDF generation process...");
-
-            // Parse address for better formatting
-            const parsedAddress = parseAddress(listing.address);
-            const addressFormatted = parsedAddress
-                ? `${parsedAddress.street}, ${parsedAddress.city}, ${parsedAddress.state} ${parsedAddress.zip}`
-                : listing.address;
-
-            // Mock personal data with listing information
-            const pdfData = {
-                full_name: "John Doe",
-                date_of_birth: "1990-05-15",
-                gender: "Male",
-                phone: "+1 (555) 123-4567",
-                email: "john.doe@example.com",
-                profile_address: "123 Main St, Los Angeles, CA 90012",
-                profile_created_at: "2025-01-10T14:32:00Z",
-                profile_updated_at: "2025-09-15T09:18:00Z",
-                listing_id: `LST-${listing.id?.toString().padStart(8, "0")}`,
-                listing_address: addressFormatted,
-                contact_email: listing.contact_email ?? "N/A",
-                contact_phone: listing.contact_phone ?? "N/A",
-                contact_other: listing.contact_other ?? "N/A",
-                source_link: listing.source_link,
-                price_rent: `$${formatRent(listing.price_rent)} / month`,
-                housing_type: listing.housing_type ?? "N/A",
-                lease_type: listing.lease_type ?? "N/A",
-                upfront_fees: listing.upfront_fees?.toString() ?? "N/A",
-                utilities: listing.utilities ?? "N/A",
-                credit_score_min: listing.credit_score_min?.toString() ?? "N/A",
-                minimum_income: listing.minimum_income
-                    ? `$${listing.minimum_income.toLocaleString()} / month`
-                    : "N/A",
-                references_required: listing.references_required ? "Yes" : "No",
-                bedrooms: listing.bedrooms?.toString() ?? "N/A",
-                bathrooms: listing.bathrooms?.toString() ?? "N/A",
-                square_footage: listing.square_footage?.toString() ?? "N/A",
-                layout_description: listing.layout_description ?? "N/A",
-                amenities: listing.amenities ?? "N/A",
-                pet_policy: listing.pet_policy ?? "N/A",
-                furnishing: listing.furnishing ?? "N/A",
-                listing_notes: listing.notes ?? "N/A",
-                listing_created_at:
-                    listing.created_at ?? new Date().toISOString(),
-                listing_updated_at:
-                    listing.updated_at ?? new Date().toISOString(),
-            };
-
-            console.log("Fetching PDF from external API...");
-            const apiPdfData = await fetchPdfFromApi(
-                pdfData,
-                session.access_token
-            );
+            console.log("Generating rental resume...");
+            const resumePdf = await generateRentalResume(listing.id, {
+                page_size: "letter",
+            });
 
             console.log("Combining with documents...");
             // Use referenced document IDs, or fallback to empty array
             const documentIds = listing.reference_document_ids ?? [];
-            const combinedPdfData = await buildCombinedPdfWithSaseApi(
-                apiPdfData,
-                documentIds,
-                session.access_token
-            );
+            const jobId = newPdfJobId();
+            setPdfJobId(jobId);
+            const unlisten = await onPdfJobProgress(jobId, (progress) => {
+                setPdfProgress(describePdfJobProgress(progress));
+            });
+            let combinedPdfData: Uint8Array;
+            try {
+                combinedPdfData = await buildCombinedPdfWithSaseApi(
+                    resumePdf,
+                    documentIds,
+                    undefined,
+                    listing.id,
+                    false,
+                    { page_size: "letter" },
+                    jobId,
+                    { page_numbers: true, running_header: true }
+                );
+            } finally {
+                unlisten();
+            }
 
             const timestamp = new Date().toISOString().replace(/[:.]/g, "-");
             const filename = `listing-${listing.id}-application-${timestamp}.pdf`;
@@ -204,14 +187,18 @@ const ListingDetails: React.FC<ListingDetailsProps> = ({ listingId }) => {
 
             console.log("PDF generation and download completed successfully!");
         } catch (error) {
+            const message =
+                error instanceof Error ? error.message : "Unknown error";
+            if (message.includes("PDF job was cancelled")) {
+                console.log("PDF generation cancelled");
+                return;
+            }
             console.error("Error generating PDF:", error);
-            alert(
-                `Error generating PDF: ${
-                    error instanceof Error ? error.message : "Unknown error"
-                }`
-            );
+            alert(`Error generating PDF: ${message}`);
         } finally {
             setIsPdfGenerating(false);
+            setPdfJobId(null);
+            setPdfProgress(null);
         }
     }, [listing]);
 
@@ -543,7 +530,7 @@ const ListingDetails: React.FC<ListingDetailsProps> = ({ listingId }) => {
                                 {isPdfGenerating ? (
                                     <>
                                         <div className="animate-spin rounded-full h-4 w-4 border-b-2 border-white"></div>
-                                        Generating PDF...
+                                        {pdfProgress ?? "Generating PDF..."}
                                     </>
                                 ) : (
                                     <>
@@ -552,6 +539,17 @@ const ListingDetails: React.FC<ListingDetailsProps> = ({ listingId }) => {
                                     </>
                                 )}
                             </Button>
+                            {pdfJobId && (
+                                <Button
+                                    onClick={() => {
+                                        void cancelPdfJob(pdfJobId);
+                                    }}
+                                    variant="outline"
+                                    className="w-full mt-2"
+                                >
+                                    Cancel
+                                </Button>
+                            )}
                         </div>
                     </div>
 
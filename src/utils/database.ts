@@ -1,7 +1,8 @@
 "use client";
 
 /* eslint-disable @typescript-eslint/restrict-template-expressions */
-import { invoke } from "@tauri-apps/api/core";
+import { convertFileSrc, invoke } from "@tauri-apps/api/core";
+import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 import { createClient } from "./supabase/client";
 import { CheckedState } from "@radix-ui/react-checkbox";
 
@@ -30,6 +31,19 @@ export interface Listing {
     furnishing?: "furnished" | "unfurnished" | "semi-furnished";
     notes?: string;
     favorite?: boolean;
+    latitude?: number;
+    longitude?: number;
+    created_at?: string;
+    updated_at?: string;
+}
+
+export interface Place {
+    id?: number;
+    name: string;
+    kind?: "work" | "school" | "family" | "other";
+    address?: string;
+    latitude: number;
+    longitude: number;
     created_at?: string;
     updated_at?: string;
 }
@@ -54,8 +68,23 @@ export interface Document {
     document_type: string;
     reminder_date?: string;
     mime_type?: string;
+    claimed_mime_type?: string;
     data?: Uint8Array;
+    size?: number;
+    page_count?: number;
+    hash?: string;
+    issued_on?: string;
+    expires_on?: string;
     updated_at?: string;
+    /** False for scanned PDFs without searchable text; unset for other files and unreadable PDFs */
+    has_text_layer?: boolean;
+}
+
+export interface DocumentQuery {
+    search?: string;
+    document_type?: string;
+    limit?: number;
+    offset?: number;
 }
 
 export interface Checklist {
@@ -268,21 +297,391 @@ export async function setListingFavorite(
     }
 }
 
-export async function getDocuments(): Promise<Document[]> {
+export interface ListingPhoto {
+    id: number;
+    listing_id: number;
+    caption?: string;
+    sort_order: number;
+    mime_type: string;
+    width?: number;
+    height?: number;
+    size?: number;
+    created_at?: string;
+    updated_at?: string;
+}
+
+export async function addListingPhoto(
+    listingId: number,
+    data: Uint8Array,
+    caption?: string
+): Promise<number> {
     try {
-        const result = await invoke<Document[]>("get_documents");
-        return result.map((doc) => ({
-            ...doc,
-            data: doc.data
-                ? new Uint8Array(doc.data as unknown as number[])
-                : undefined,
-        }));
+        return await invoke<number>("add_listing_photo", {
+            listingId,
+            data: Array.from(data),
+            caption,
+        });
+    } catch (error) {
+        console.error("Failed to add listing photo:", error);
+        throw new Error(`Failed to add listing photo: ${error}`);
+    }
+}
+
+export async function getListingPhotos(
+    listingId: number
+): Promise<ListingPhoto[]> {
+    try {
+        return await invoke<ListingPhoto[]>("get_listing_photos", {
+            listingId,
+        });
+    } catch (error) {
+        console.error("Failed to get listing photos:", error);
+        throw new Error(`Failed to get listing photos: ${error}`);
+    }
+}
+
+export async function getListingPhotoThumbnail(
+    photoId: number,
+    size: "small" | "medium" = "small"
+): Promise<Uint8Array> {
+    try {
+        const result = await invoke<number[]>("get_listing_photo_thumbnail", {
+            photoId,
+            size,
+        });
+        return new Uint8Array(result);
+    } catch (error) {
+        console.error("Failed to get listing photo thumbnail:", error);
+        throw new Error(`Failed to get listing photo thumbnail: ${error}`);
+    }
+}
+
+export async function getListingPhotoData(
+    photoId: number
+): Promise<Uint8Array> {
+    try {
+        const result = await invoke<number[]>("get_listing_photo_data", {
+            photoId,
+        });
+        return new Uint8Array(result);
+    } catch (error) {
+        console.error("Failed to get listing photo:", error);
+        throw new Error(`Failed to get listing photo: ${error}`);
+    }
+}
+
+export async function setListingPhotoCaption(
+    photoId: number,
+    caption?: string
+): Promise<void> {
+    try {
+        await invoke("set_listing_photo_caption", { photoId, caption });
+    } catch (error) {
+        console.error("Failed to set listing photo caption:", error);
+        throw new Error(`Failed to set listing photo caption: ${error}`);
+    }
+}
+
+export async function reorderListingPhotos(
+    listingId: number,
+    photoIds: number[]
+): Promise<void> {
+    try {
+        await invoke("reorder_listing_photos", { listingId, photoIds });
+    } catch (error) {
+        console.error("Failed to reorder listing photos:", error);
+        throw new Error(`Failed to reorder listing photos: ${error}`);
+    }
+}
+
+export async function deleteListingPhoto(photoId: number): Promise<void> {
+    try {
+        await invoke("delete_listing_photo", { photoId });
+    } catch (error) {
+        console.error("Failed to delete listing photo:", error);
+        throw new Error(`Failed to delete listing photo: ${error}`);
+    }
+}
+
+export async function getPlaces(): Promise<Place[]> {
+    try {
+        return await invoke<Place[]>("get_places");
+    } catch (error) {
+        console.error("Failed to get places:", error);
+        throw new Error(`Failed to get places: ${error}`);
+    }
+}
+
+export async function addPlace(
+    place: Omit<Place, "id" | "created_at" | "updated_at">
+): Promise<number> {
+    try {
+        return await invoke<number>("add_place", { place });
+    } catch (error) {
+        console.error("Failed to add place:", error);
+        throw new Error(`Failed to add place: ${error}`);
+    }
+}
+
+export async function updatePlace(
+    id: number,
+    place: Omit<Place, "id" | "created_at" | "updated_at">
+): Promise<void> {
+    try {
+        await invoke("update_place", { id, place });
+    } catch (error) {
+        console.error("Failed to update place:", error);
+        throw new Error(`Failed to update place: ${error}`);
+    }
+}
+
+export async function deletePlace(id: number): Promise<void> {
+    try {
+        await invoke("delete_place", { id });
+    } catch (error) {
+        console.error("Failed to delete place:", error);
+        throw new Error(`Failed to delete place: ${error}`);
+    }
+}
+
+export async function setListingLocation(
+    listingId: number,
+    latitude: number | null,
+    longitude: number | null
+): Promise<void> {
+    try {
+        await invoke("set_listing_location", {
+            listingId,
+            latitude,
+            longitude,
+        });
+    } catch (error) {
+        console.error("Failed to set listing location:", error);
+        throw new Error(`Failed to set listing location: ${error}`);
+    }
+}
+
+export async function listingsWithin(
+    placeId: number,
+    km: number
+): Promise<(Listing & { distance_km: number })[]> {
+    try {
+        return await invoke<(Listing & { distance_km: number })[]>(
+            "listings_within",
+            { placeId, km }
+        );
+    } catch (error) {
+        console.error("Failed to get listings within distance:", error);
+        throw new Error(`Failed to get listings within distance: ${error}`);
+    }
+}
+
+export async function getListingsWithDistances(): Promise<
+    (Listing & {
+        distances: { place_id: number; name: string; distance_km: number }[];
+    })[]
+> {
+    try {
+        return await invoke("get_listings_with_distances");
+    } catch (error) {
+        console.error("Failed to get listings with distances:", error);
+        throw new Error(`Failed to get listings with distances: ${error}`);
+    }
+}
+
+export async function exportListingsGeoJson(
+    includePlaces = true
+): Promise<string> {
+    try {
+        return await invoke<string>("export_listings_geojson", {
+            includePlaces,
+        });
+    } catch (error) {
+        console.error("Failed to export listings as GeoJSON:", error);
+        throw new Error(`Failed to export listings as GeoJSON: ${error}`);
+    }
+}
+
+export async function importListingCoordinates(
+    geojson: string
+): Promise<number> {
+    try {
+        return await invoke<number>("import_listing_coordinates", {
+            geojson,
+        });
+    } catch (error) {
+        console.error("Failed to import listing coordinates:", error);
+        throw new Error(`Failed to import listing coordinates: ${error}`);
+    }
+}
+
+export interface ListingCosts {
+    listing_id: number;
+    security_deposit?: number;
+    first_month_due?: boolean;
+    last_month_due?: boolean;
+    application_fee?: number;
+    broker_fee?: number;
+    pet_deposit?: number;
+    pet_rent?: number;
+    parking_monthly?: number;
+    utility_costs?: string; // JSON object of utility name to monthly cost
+    lease_length_months?: number;
+    months_free?: number;
+    updated_at?: string;
+}
+
+export interface ListingCostSummary {
+    listing_id: number;
+    rent: number;
+    monthly_recurring: number;
+    concession_monthly_credit: number;
+    effective_monthly: number;
+    move_in_total: number;
+    refundable_deposits: number;
+    first_year_total: number;
+    lease_length_months: number;
+    has_cost_model: boolean;
+    line_items: {
+        label: string;
+        amount: number;
+        kind: "move_in" | "monthly" | "concession";
+        refundable: boolean;
+    }[];
+}
+
+export async function getListingCosts(
+    listingId: number
+): Promise<ListingCosts | null> {
+    try {
+        return await invoke<ListingCosts | null>("get_listing_costs", {
+            listingId,
+        });
+    } catch (error) {
+        console.error("Failed to get listing costs:", error);
+        throw new Error(`Failed to get listing costs: ${error}`);
+    }
+}
+
+export async function setListingCosts(costs: ListingCosts): Promise<void> {
+    try {
+        await invoke("set_listing_costs", { costs });
+    } catch (error) {
+        console.error("Failed to set listing costs:", error);
+        throw new Error(`Failed to set listing costs: ${error}`);
+    }
+}
+
+export async function computeListingCosts(
+    listingId: number
+): Promise<ListingCostSummary> {
+    try {
+        return await invoke<ListingCostSummary>("compute_listing_costs", {
+            listingId,
+        });
+    } catch (error) {
+        console.error("Failed to compute listing costs:", error);
+        throw new Error(`Failed to compute listing costs: ${error}`);
+    }
+}
+
+export interface ListingComparisonCell {
+    listing_id: number;
+    value: number | string | null;
+    display: string;
+    best: boolean;
+    worst: boolean;
+}
+
+export interface ListingComparisonRow {
+    field: string;
+    label: string;
+    cells: ListingComparisonCell[];
+    all_equal: boolean;
+}
+
+export interface ListingSetComparison {
+    common: string[];
+    all: string[];
+    unique: { listing_id: number; items: string[] }[];
+    missing: { listing_id: number; items: string[] }[];
+}
+
+export interface ListingComparison {
+    listings: { id: number; address: string }[];
+    rows: ListingComparisonRow[];
+    amenities: ListingSetComparison;
+    utilities: ListingSetComparison;
+    affordability: {
+        listing_id: number;
+        monthly_total: number;
+        monthly_income?: number;
+        rent_to_income_ratio?: number;
+        meets_minimum_income?: boolean;
+        meets_three_times_rent?: boolean;
+        affordable?: boolean;
+    }[];
+}
+
+export async function compareListings(
+    ids: number[]
+): Promise<ListingComparison> {
+    try {
+        return await invoke<ListingComparison>("compare_listings", { ids });
+    } catch (error) {
+        console.error("Failed to compare listings:", error);
+        throw new Error(`Failed to compare listings: ${error}`);
+    }
+}
+
+export async function exportListingComparisonPdf(
+    ids: number[]
+): Promise<Uint8Array> {
+    try {
+        const result = await invoke<number[]>(
+            "export_listing_comparison_pdf",
+            { ids }
+        );
+        return new Uint8Array(result);
+    } catch (error) {
+        console.error("Failed to export listing comparison:", error);
+        throw new Error(`Failed to export listing comparison: ${error}`);
+    }
+}
+
+/**
+ * List document metadata only; use getDocumentData to load the contents
+ */
+export async function getDocuments(query?: DocumentQuery): Promise<Document[]> {
+    try {
+        return await invoke<Document[]>("list_documents", { query });
     } catch (error) {
         console.error("Failed to get documents:", error);
         throw new Error(`Failed to get documents: ${error}`);
     }
 }
 
+/**
+ * Fetch the contents of a document, optionally only a byte range of it
+ */
+export async function getDocumentData(
+    documentId: number,
+    offset?: number,
+    length?: number
+): Promise<Uint8Array> {
+    try {
+        const result = await invoke<ArrayBuffer>("get_document_data", {
+            documentId,
+            offset,
+            length,
+        });
+        return new Uint8Array(result);
+    } catch (error) {
+        console.error("Failed to get document data:", error);
+        throw new Error(`Failed to get document data: ${error}`);
+    }
+}
+
 export async function deleteDocument(document_id: number): Promise<void> {
     try {
         await invoke("delete_document", { documentId: document_id });
@@ -292,8 +691,324 @@ export async function deleteDocument(document_id: number): Promise<void> {
     }
 }
 
+export interface DocumentTextMatch {
+    document_id: number;
+    name: string;
+    document_type: string;
+    /** 1-based */
+    page: number;
+    /** Text around the match, with the matched words in [brackets] */
+    snippet: string;
+}
+
+/**
+ * Searches the text of all PDF documents, best matches first. Pages match
+ * any of the words, so plain phrases like "statement showing the March
+ * deposit" work.
+ */
+export async function searchDocumentText(
+    query: string,
+    limit?: number
+): Promise<DocumentTextMatch[]> {
+    try {
+        return await invoke<DocumentTextMatch[]>("search_document_text", {
+            query,
+            limit,
+        });
+    } catch (error) {
+        console.error("Failed to search documents:", error);
+        throw new Error(`Failed to search documents: ${error}`);
+    }
+}
+
+export interface DocumentVersion {
+    document_id: number;
+    version: number;
+    mime_type?: string;
+    size?: number;
+    page_count?: number;
+    hash?: string;
+    note?: string;
+    uploaded_at?: string;
+    is_current: boolean;
+}
+
+export interface DocumentPin {
+    document_id: number;
+    version: number;
+}
+
+export async function replaceDocument(
+    documentId: number,
+    data: Uint8Array,
+    mimeType?: string,
+    note?: string
+): Promise<number> {
+    try {
+        return await invoke<number>("replace_document", {
+            documentId,
+            data: Array.from(data),
+            mimeType,
+            note,
+        });
+    } catch (error) {
+        console.error("Failed to replace document:", error);
+        if (isDocumentError(error)) {
+            throw error;
+        }
+        throw new Error(`Failed to replace document: ${error}`);
+    }
+}
+
+export async function listDocumentVersions(
+    documentId: number
+): Promise<DocumentVersion[]> {
+    try {
+        return await invoke<DocumentVersion[]>("list_document_versions", {
+            documentId,
+        });
+    } catch (error) {
+        console.error("Failed to list document versions:", error);
+        throw new Error(`Failed to list document versions: ${error}`);
+    }
+}
+
+export async function getDocumentVersionData(
+    documentId: number,
+    version: number
+): Promise<Uint8Array> {
+    try {
+        const result = await invoke<ArrayBuffer>("get_document_version_data", {
+            documentId,
+            version,
+        });
+        return new Uint8Array(result);
+    } catch (error) {
+        console.error("Failed to get document version data:", error);
+        throw new Error(`Failed to get document version data: ${error}`);
+    }
+}
+
+export async function restoreDocumentVersion(
+    documentId: number,
+    version: number
+): Promise<number> {
+    try {
+        return await invoke<number>("restore_document_version", {
+            documentId,
+            version,
+        });
+    } catch (error) {
+        console.error("Failed to restore document version:", error);
+        throw new Error(`Failed to restore document version: ${error}`);
+    }
+}
+
+/** Area to redact, in fractions (0-1) of the displayed page, from its top left corner */
+export interface RedactionRegion {
+    /** 1-based page number; images have a single page */
+    page: number;
+    x: number;
+    y: number;
+    width: number;
+    height: number;
+}
+
+/**
+ * Black out regions of a document and save the result as a new version.
+ * The unredacted original stays in the version history.
+ */
+export async function redactDocument(
+    documentId: number,
+    regions: RedactionRegion[],
+    note?: string
+): Promise<number> {
+    try {
+        return await invoke<number>("redact_document", {
+            documentId,
+            regions,
+            note: note ?? null,
+        });
+    } catch (error) {
+        console.error("Failed to redact document:", error);
+        throw new Error(`Failed to redact document: ${error}`);
+    }
+}
+
+export async function pruneDocumentVersions(
+    documentId: number,
+    keep: number
+): Promise<number> {
+    try {
+        return await invoke<number>("prune_document_versions", {
+            documentId,
+            keep,
+        });
+    } catch (error) {
+        console.error("Failed to prune document versions:", error);
+        throw new Error(`Failed to prune document versions: ${error}`);
+    }
+}
+
+export async function pinListingDocumentVersion(
+    listingId: number,
+    documentId: number,
+    version: number | null
+): Promise<void> {
+    try {
+        await invoke("pin_listing_document_version", {
+            listingId,
+            documentId,
+            version,
+        });
+    } catch (error) {
+        console.error("Failed to pin document version:", error);
+        throw new Error(`Failed to pin document version: ${error}`);
+    }
+}
+
+export async function getListingDocumentPins(
+    listingId: number
+): Promise<DocumentPin[]> {
+    try {
+        return await invoke<DocumentPin[]>("get_listing_document_pins", {
+            listingId,
+        });
+    } catch (error) {
+        console.error("Failed to get document pins:", error);
+        throw new Error(`Failed to get document pins: ${error}`);
+    }
+}
+
+export interface DocumentValidityRule {
+    document_type: string;
+    valid_days: number;
+    updated_at?: string;
+}
+
+export interface ExpiringDocument {
+    document_id: number;
+    name: string;
+    document_type: string;
+    issued_on?: string;
+    valid_until: string;
+    basis: "expires_on" | "issued_on" | "uploaded";
+    days_remaining: number;
+    expired: boolean;
+}
+
+export async function setDocumentDates(
+    documentId: number,
+    issuedOn: string | null,
+    expiresOn: string | null
+): Promise<void> {
+    try {
+        await invoke("set_document_dates", { documentId, issuedOn, expiresOn });
+    } catch (error) {
+        console.error("Failed to set document dates:", error);
+        throw new Error(`Failed to set document dates: ${error}`);
+    }
+}
+
+export async function getDocumentValidityRules(): Promise<
+    DocumentValidityRule[]
+> {
+    try {
+        return await invoke<DocumentValidityRule[]>(
+            "get_document_validity_rules"
+        );
+    } catch (error) {
+        console.error("Failed to get document validity rules:", error);
+        throw new Error(`Failed to get document validity rules: ${error}`);
+    }
+}
+
+export async function setDocumentValidityRule(
+    documentType: string,
+    validDays: number | null
+): Promise<void> {
+    try {
+        await invoke("set_document_validity_rule", { documentType, validDays });
+    } catch (error) {
+        console.error("Failed to set document validity rule:", error);
+        throw new Error(`Failed to set document validity rule: ${error}`);
+    }
+}
+
+export async function getExpiringDocuments(
+    withinDays: number
+): Promise<ExpiringDocument[]> {
+    try {
+        return await invoke<ExpiringDocument[]>("get_expiring_documents", {
+            withinDays,
+        });
+    } catch (error) {
+        console.error("Failed to get expiring documents:", error);
+        throw new Error(`Failed to get expiring documents: ${error}`);
+    }
+}
+
+export interface BlobStats {
+    blob_count: number;
+    stored_bytes: number;
+    referenced_bytes: number;
+}
+
+export async function getBlobStats(): Promise<BlobStats> {
+    try {
+        return await invoke<BlobStats>("get_blob_stats");
+    } catch (error) {
+        console.error("Failed to get storage stats:", error);
+        throw new Error(`Failed to get storage stats: ${error}`);
+    }
+}
+
+export async function collectGarbageBlobs(
+    vacuum = false
+): Promise<{ deleted: number; bytes_freed: number }> {
+    try {
+        return await invoke("collect_garbage_blobs", { vacuum });
+    } catch (error) {
+        console.error("Failed to clean up storage:", error);
+        throw new Error(`Failed to clean up storage: ${error}`);
+    }
+}
+
+/**
+ * Error returned by add_document when a file is rejected
+ */
+export interface DocumentError {
+    kind:
+        | "database"
+        | "empty_file"
+        | "executable"
+        | "unrecognized"
+        | "disallowed_type"
+        | "mime_mismatch"
+        | "duplicate";
+    message: string;
+    claimed?: string;
+    detected?: string;
+    /** Existing document with identical contents, for "duplicate" */
+    document_id?: number;
+}
+
+export function isDocumentError(error: unknown): error is DocumentError {
+    return (
+        typeof error === "object" &&
+        error !== null &&
+        "kind" in error &&
+        "message" in error
+    );
+}
+
+/**
+ * Adds a document. Identical files are rejected with a "duplicate" DocumentError
+ * carrying the existing document's id, unless `allowDuplicate` is set.
+ */
 export async function addDocument(
-    document: Omit<Document, "id" | "created_at" | "updated_at">
+    document: Omit<Document, "id" | "created_at" | "updated_at">,
+    options?: { allowDuplicate?: boolean }
 ): Promise<number> {
     try {
         console.log("Adding document to database:", {
@@ -303,12 +1018,19 @@ export async function addDocument(
             data_size: document.data?.length ?? 0,
             reminder_date: document.reminder_date,
         });
-        const result = await invoke<number>("add_document", { document });
+        const result = await invoke<number>("add_document", {
+            document,
+            allowDuplicate: options?.allowDuplicate ?? false,
+        });
         console.log("Document added successfully with ID:", result);
         return result;
     } catch (error) {
+        if (isDocumentError(error) && error.kind === "duplicate") {
+            throw error;
+        }
         console.error("Failed to add document:", error);
-        throw new Error(`Failed to add document: ${error}`);
+        const message = isDocumentError(error) ? error.message : error;
+        throw new Error(`Failed to add document: ${message}`);
     }
 }
 
@@ -390,13 +1112,16 @@ export const handleLogout = async (): Promise<void> => {
     }
 };
 
-export function createDocumentBlobUrl(document: Document): string | null {
-    if (!document.data || !document.mime_type) {
+export async function createDocumentBlobUrl(
+    document: Document
+): Promise<string | null> {
+    if (!document.mime_type || document.id === undefined) {
         return null;
     }
 
     try {
-        const blob = new Blob([Uint8Array.from(document.data)], {
+        const data = document.data ?? (await getDocumentData(document.id));
+        const blob = new Blob([Uint8Array.from(data)], {
             type: document.mime_type,
         });
         return URL.createObjectURL(blob);
@@ -406,6 +1131,23 @@ export function createDocumentBlobUrl(document: Document): string | null {
     }
 }
 
+/**
+ * URL serving a document straight from the vault through the sase:// protocol
+ */
+export function documentUrl(documentId: number): string {
+    return convertFileSrc(`document/${documentId}`, "sase");
+}
+
+/**
+ * URL serving a listing photo thumbnail through the sase:// protocol
+ */
+export function listingPhotoThumbnailUrl(
+    photoId: number,
+    size: "small" | "medium" = "small"
+): string {
+    return `${convertFileSrc(`thumbnail/${photoId}`, "sase")}?size=${size}`;
+}
+
 export function isImageDocument(document: Document): boolean {
     if (!document.mime_type) return false;
     return document.mime_type.startsWith("image/");
@@ -425,52 +1167,189 @@ export function formatFileSize(bytes: number): string {
     return `${parseFloat((bytes / Math.pow(k, i)).toFixed(2))} ${sizes[i]}`;
 }
 
+export interface RentalHistoryEntry {
+    id?: number;
+    address: string;
+    landlord_name?: string;
+    landlord_contact?: string;
+    monthly_rent?: number;
+    moved_in?: string;
+    /** Unset while the applicant still lives there */
+    moved_out?: string;
+    reason_for_leaving?: string;
+    created_at?: string;
+    updated_at?: string;
+}
+
+export interface PersonalReference {
+    id?: number;
+    name: string;
+    relationship?: string;
+    phone?: string;
+    email?: string;
+    created_at?: string;
+    updated_at?: string;
+}
+
+export async function getRentalHistory(): Promise<RentalHistoryEntry[]> {
+    try {
+        return await invoke<RentalHistoryEntry[]>("get_rental_history");
+    } catch (error) {
+        console.error("Failed to get rental history:", error);
+        throw new Error(`Failed to get rental history: ${error}`);
+    }
+}
+
+export async function addRentalHistory(
+    entry: Omit<RentalHistoryEntry, "id" | "created_at" | "updated_at">
+): Promise<number> {
+    try {
+        return await invoke<number>("add_rental_history", { entry });
+    } catch (error) {
+        console.error("Failed to add rental history:", error);
+        throw new Error(`Failed to add rental history: ${error}`);
+    }
+}
+
+export async function updateRentalHistory(
+    id: number,
+    entry: Omit<RentalHistoryEntry, "id" | "created_at" | "updated_at">
+): Promise<void> {
+    try {
+        await invoke("update_rental_history", { id, entry });
+    } catch (error) {
+        console.error("Failed to update rental history:", error);
+        throw new Error(`Failed to update rental history: ${error}`);
+    }
+}
+
+export async function deleteRentalHistory(id: number): Promise<void> {
+    try {
+        await invoke("delete_rental_history", { id });
+    } catch (error) {
+        console.error("Failed to delete rental history:", error);
+        throw new Error(`Failed to delete rental history: ${error}`);
+    }
+}
+
+export async function getPersonalReferences(): Promise<PersonalReference[]> {
+    try {
+        return await invoke<PersonalReference[]>("get_personal_references");
+    } catch (error) {
+        console.error("Failed to get references:", error);
+        throw new Error(`Failed to get references: ${error}`);
+    }
+}
+
+export async function addPersonalReference(
+    reference: Omit<PersonalReference, "id" | "created_at" | "updated_at">
+): Promise<number> {
+    try {
+        return await invoke<number>("add_personal_reference", { reference });
+    } catch (error) {
+        console.error("Failed to add reference:", error);
+        throw new Error(`Failed to add reference: ${error}`);
+    }
+}
+
+export async function updatePersonalReference(
+    id: number,
+    reference: Omit<PersonalReference, "id" | "created_at" | "updated_at">
+): Promise<void> {
+    try {
+        await invoke("update_personal_reference", { id, reference });
+    } catch (error) {
+        console.error("Failed to update reference:", error);
+        throw new Error(`Failed to update reference: ${error}`);
+    }
+}
+
+export async function deletePersonalReference(id: number): Promise<void> {
+    try {
+        await invoke("delete_personal_reference", { id });
+    } catch (error) {
+        console.error("Failed to delete reference:", error);
+        throw new Error(`Failed to delete reference: ${error}`);
+    }
+}
+
+export interface ResumeOptions {
+    /** Modern has a colored banner, classic is serif, compact fits more on a page */
+    theme?: "modern" | "classic" | "compact";
+    page_size?: "a4" | "letter" | "legal";
+}
+
 /**
- * Fetches PDF data from an external API
+ * Renders the rental resume from the profile, income sources, rental history,
+ * references and additional info. Works offline. With a listing, its address
+ * and rent are shown as the home applied for.
  */
-export async function fetchPdfFromApi(
-    pdfData: Record<string, any>,
-    accessToken: string
+export async function generateRentalResume(
+    listingId?: number,
+    options?: ResumeOptions
 ): Promise<Uint8Array> {
     try {
-        console.log("Fetching PDF from API with data:", pdfData);
-
-        const response = await fetch(
-            "https://drakoindustries.com/api/sase/pdf/generate",
-            {
-                method: "POST",
-                headers: {
-                    "Content-Type": "application/json",
-                    Authorization: `Bearer ${accessToken}`,
-                },
-                body: JSON.stringify(pdfData),
-            }
-        );
-
-        if (!response.ok) {
-            throw new Error(
-                `API request failed: ${response.status} ${response.statusText}`
-            );
-        }
-
-        const arrayBuffer = await response.arrayBuffer();
-        return new Uint8Array(arrayBuffer);
+        const result = await invoke<number[]>("generate_rental_resume", {
+            listingId,
+            options,
+        });
+        return new Uint8Array(result);
     } catch (error) {
-        console.error("Failed to fetch PDF from API:", error);
-        throw new Error(`Failed to fetch PDF from API: ${error}`);
+        console.error("Failed to generate rental resume:", error);
+        throw new Error(`Failed to generate rental resume: ${error}`);
     }
 }
 
+/** How images are laid out when they are turned into PDF pages */
+export interface PdfRenderOptions {
+    page_size?: "a4" | "letter" | "legal" | "image";
+    orientation?: "auto" | "portrait" | "landscape";
+    margin_mm?: number;
+    fit?: "fit" | "fill" | "actual_size";
+    /** 1-100 */
+    jpeg_quality?: number;
+    grayscale?: boolean;
+    /** Crop uniform background around photographed documents */
+    auto_crop?: boolean;
+    /** Longest image edge in pixels */
+    max_image_edge?: number;
+    dpi?: number;
+    /** Upload limit to compress the PDF under; the build fails if it cannot fit */
+    target_size_bytes?: number;
+}
+
+export interface PdfSizeReport {
+    target_bytes: number;
+    achieved_bytes: number;
+    /** Estimated bytes per document, largest first */
+    documents: { title: string; bytes: number }[];
+}
+
+/**
+ * Appends documents to `firstPdf`. The merge runs locally; pass `mergeRemotely`
+ * (with a JWT) to use the SASE merge API instead. `stamp` marks every page as
+ * `buildApplicationPacket` does.
+ */
 export async function buildCombinedPdfWithSaseApi(
     firstPdf: Uint8Array,
     documentIds: number[],
-    jwtToken: string
+    jwtToken?: string,
+    listingId?: number,
+    mergeRemotely = false,
+    renderOptions?: PdfRenderOptions,
+    jobId?: string,
+    stamp?: PacketStampOptions
 ): Promise<Uint8Array> {
     try {
         const result = await invoke("build_pdf_with_sase_api", {
             firstPdf: Array.from(firstPdf),
             idsInOrder: documentIds,
-            jwtToken: jwtToken,
+            jwtToken: jwtToken ?? null,
+            listingId: listingId ?? null,
+            mergeRemotely,
+            stamp: stamp ?? null,
+            renderOptions: renderOptions ?? null,
+            jobId: jobId ?? null,
         });
 
         if (result instanceof Array) {
@@ -499,6 +1378,278 @@ export async function buildCombinedPdfWithSaseApi(
     }
 }
 
+export interface PacketStampOptions {
+    /** "Page X of Y" footers */
+    page_numbers?: boolean;
+    /** Header with the applicant name and the address applied for */
+    running_header?: boolean;
+    /** Adds a diagonal "Provided to <recipient> ..." watermark to every page */
+    watermark_recipient?: string | null;
+}
+
+/**
+ * Build an application packet with a cover page, table of contents and a divider
+ * before each document, keeping the documents in the given order.
+ */
+export async function buildApplicationPacket(
+    documentIds: number[],
+    listingId?: number,
+    title?: string,
+    stamp?: PacketStampOptions,
+    renderOptions?: PdfRenderOptions,
+    jobId?: string
+): Promise<Uint8Array> {
+    try {
+        const result = await invoke<number[]>("build_application_packet", {
+            documentIds,
+            listingId: listingId ?? null,
+            title: title ?? null,
+            stamp: stamp ?? null,
+            renderOptions: renderOptions ?? null,
+            jobId: jobId ?? null,
+        });
+        return new Uint8Array(result);
+    } catch (error) {
+        console.error("Error building application packet:", error);
+        throw new Error(`Failed to build application packet: ${error}`);
+    }
+}
+
+export type PdfJobStage =
+    | { stage: "loading_document"; index: number; total: number }
+    | { stage: "converting_image"; index: number; total: number }
+    | { stage: "merging" }
+    | { stage: "stamping" }
+    | { stage: "uploading"; sent: number; total: number }
+    | { stage: "compressing"; attempt: number; attempts: number }
+    | ({ stage: "size_report" } & PdfSizeReport);
+
+export type PdfJobProgress = PdfJobStage & { job_id: string };
+
+/** Id for a PDF build, passed to the build so its progress can be followed and cancelled */
+export function newPdfJobId(): string {
+    return `pdf-${crypto.randomUUID()}`;
+}
+
+export async function onPdfJobProgress(
+    jobId: string,
+    callback: (progress: PdfJobProgress) => void
+): Promise<UnlistenFn> {
+    return listen<PdfJobProgress>("pdf-job-progress", (event) => {
+        if (event.payload.job_id === jobId) {
+            callback(event.payload);
+        }
+    });
+}
+
+export function describePdfJobProgress(progress: PdfJobStage): string {
+    switch (progress.stage) {
+        case "loading_document":
+            return `Loading document ${progress.index} of ${progress.total}...`;
+        case "converting_image":
+            return `Converting image ${progress.index} of ${progress.total}...`;
+        case "merging":
+            return "Merging documents...";
+        case "stamping":
+            return "Stamping pages...";
+        case "uploading": {
+            const percent =
+                progress.total > 0
+                    ? Math.round((progress.sent / progress.total) * 100)
+                    : 0;
+            return `Uploading... ${percent}%`;
+        }
+        case "compressing":
+            return `Compressing to fit the size limit (attempt ${progress.attempt} of ${progress.attempts})...`;
+        case "size_report":
+            return `Compressed to ${(progress.achieved_bytes / 1024 / 1024).toFixed(1)} MB`;
+    }
+}
+
+/** Returns false when the job already finished */
+export async function cancelPdfJob(jobId: string): Promise<boolean> {
+    try {
+        return await invoke<boolean>("cancel_pdf_job", { jobId });
+    } catch (error) {
+        console.error("Error cancelling PDF job:", error);
+        throw new Error(`Failed to cancel PDF job: ${error}`);
+    }
+}
+
+/**
+ * Where a form field gets its value from. Paths look like `profile.phone`,
+ * `income_sources[0].employer_name`, `additional_info.pets`, `listing.address` or `today`.
+ */
+export type FieldMapping =
+    | { kind: "path"; path: string; transform?: ValueTransform }
+    | { kind: "literal"; value: string }
+    | { kind: "skip" };
+
+/**
+ * How a value is rewritten before it goes into a field. Date formats are made of `YYYY`, `YY`,
+ * `MM`, `M`, `DD` and `D`; phone formats put the digits into each `#`, e.g. `(###) ###-####`.
+ */
+export type ValueTransform =
+    | { kind: "date"; format: string }
+    | { kind: "phone"; format: string }
+    | { kind: "uppercase" };
+
+export interface FormMappingProfile {
+    /** Listing the application is for, which `listing.*` paths read from */
+    listing_id?: number;
+    /** By fully qualified field name; these win over the saved template and guesses */
+    mappings?: Record<string, FieldMapping>;
+    /** Only fill fields with a mapping instead of guessing from field names */
+    skip_guesses?: boolean;
+    /** Draw the values into the pages so nothing stays editable */
+    flatten?: boolean;
+    /** Save `mappings` to the form's template so they apply whenever it is filled again */
+    remember?: boolean;
+    /** Name of the new document; defaults to the original name with "(filled)" */
+    name?: string;
+}
+
+export interface MappedFormField {
+    name: string;
+    kind: "text" | "checkbox" | "radio" | "choice" | "signature" | "button";
+    mapping: FieldMapping | null;
+    origin: "request" | "saved" | "guessed" | "unmapped";
+    /** Value that is or would be written; null leaves the field as it is */
+    value: string | null;
+}
+
+export interface FormPreview {
+    fingerprint: string;
+    page_count: number;
+    /** Name of the saved template for this form, if there is one */
+    template_name: string | null;
+    fields: MappedFormField[];
+}
+
+export interface FilledForm {
+    /** The new document holding the filled form */
+    document_id: number;
+    fingerprint: string;
+    fields: MappedFormField[];
+}
+
+/** List the fields of a fillable PDF with the values fillPdfForm would write */
+export async function inspectPdfForm(
+    documentId: number,
+    mappingProfile?: FormMappingProfile
+): Promise<FormPreview> {
+    try {
+        return await invoke<FormPreview>("inspect_pdf_form", {
+            documentId,
+            mappingProfile: mappingProfile ?? null,
+        });
+    } catch (error) {
+        console.error("Failed to inspect PDF form:", error);
+        throw new Error(`Failed to inspect PDF form: ${error}`);
+    }
+}
+
+/** Fill a PDF form from the profile and listing and save it as a new document */
+export async function fillPdfForm(
+    documentId: number,
+    mappingProfile?: FormMappingProfile
+): Promise<FilledForm> {
+    try {
+        return await invoke<FilledForm>("fill_pdf_form", {
+            documentId,
+            mappingProfile: mappingProfile ?? null,
+        });
+    } catch (error) {
+        console.error("Failed to fill PDF form:", error);
+        throw new Error(`Failed to fill PDF form: ${error}`);
+    }
+}
+
+/** Field mappings saved for a form, recognized by the fingerprint of its fields and page count */
+export interface FormTemplate {
+    fingerprint: string;
+    name: string;
+    page_count: number;
+    field_names: string[];
+    mappings: Record<string, FieldMapping>;
+    /** Fill copies of the form as soon as they are uploaded */
+    auto_fill: boolean;
+    updated_at: string | null;
+}
+
+/** Sent when an uploaded PDF was filled from the saved template for its form */
+export interface FormTemplateApplied {
+    document_id: number;
+    /** The new document holding the filled form */
+    filled_document_id: number;
+    fingerprint: string;
+    template_name: string;
+}
+
+export async function getFormTemplates(): Promise<FormTemplate[]> {
+    try {
+        return await invoke<FormTemplate[]>("get_form_templates");
+    } catch (error) {
+        console.error("Failed to get form templates:", error);
+        throw new Error(`Failed to get form templates: ${error}`);
+    }
+}
+
+/** Save the mappings for the form in a document as its template */
+export async function saveFormTemplate(
+    documentId: number,
+    mappings: Record<string, FieldMapping>,
+    options?: { name?: string; autoFill?: boolean }
+): Promise<FormTemplate> {
+    try {
+        return await invoke<FormTemplate>("save_form_template", {
+            documentId,
+            name: options?.name ?? null,
+            mappings,
+            autoFill: options?.autoFill ?? null,
+        });
+    } catch (error) {
+        console.error("Failed to save form template:", error);
+        throw new Error(`Failed to save form template: ${error}`);
+    }
+}
+
+export async function deleteFormTemplate(fingerprint: string): Promise<void> {
+    try {
+        await invoke("delete_form_template", { fingerprint });
+    } catch (error) {
+        console.error("Failed to delete form template:", error);
+        throw new Error(`Failed to delete form template: ${error}`);
+    }
+}
+
+/** A template as JSON, to share with others applying with the same form */
+export async function exportFormTemplate(fingerprint: string): Promise<string> {
+    try {
+        return await invoke<string>("export_form_template", { fingerprint });
+    } catch (error) {
+        console.error("Failed to export form template:", error);
+        throw new Error(`Failed to export form template: ${error}`);
+    }
+}
+
+export async function importFormTemplate(json: string): Promise<FormTemplate> {
+    try {
+        return await invoke<FormTemplate>("import_form_template", { json });
+    } catch (error) {
+        console.error("Failed to import form template:", error);
+        throw new Error(`Failed to import form template: ${error}`);
+    }
+}
+
+export async function onFormTemplateApplied(
+    callback: (applied: FormTemplateApplied) => void
+): Promise<UnlistenFn> {
+    return listen<FormTemplateApplied>("form-template-applied", (event) => {
+        callback(event.payload);
+    });
+}
+
 export function downloadPdf(pdfData: Uint8Array, filename: string): void {
     try {
         console.log("Downloading PDF:", filename);